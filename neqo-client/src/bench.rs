@@ -0,0 +1,174 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// What `--bench` measures: a single transfer of generated data to or from neqo-server,
+// which serves `/N` with N bytes and counts the body of a POST to `/upload`.
+
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process::exit;
+use std::time::{Duration, Instant};
+
+use neqo_common::metrics::{self, Metrics};
+use neqo_http3::Http3Client;
+
+use super::{Args, Res};
+
+/// The path that neqo-server counts uploads on.
+const UPLOAD_PATH: &str = "/upload";
+
+/// Point `args` at the benchmark endpoint of the server at the origin of the first URL.
+pub fn configure(args: &mut Args, size: usize) {
+    if args.use_old_http {
+        eprintln!("--bench only works with HTTP/3");
+        exit(1)
+    }
+    let url = match args.urls.first() {
+        Some(url) => url.clone(),
+        None => {
+            eprintln!("--bench needs the URL of a server");
+            exit(1)
+        }
+    };
+    let path = if args.upload {
+        args.method = String::from("POST");
+        String::from(UPLOAD_PATH)
+    } else {
+        format!("/{}", size)
+    };
+    args.urls = vec![url.join(&path).unwrap()];
+}
+
+/// The data that is uploaded. neqo-server sends the same for downloads.
+pub fn data(size: usize) -> Vec<u8> {
+    vec![b'a'; size]
+}
+
+/// Records what the connection reports while the benchmark runs.
+#[derive(Debug)]
+pub struct Recorder {
+    start: Instant,
+    handshake: Cell<Option<f64>>,
+    /// Samples of the smoothed RTT and of the congestion window, with the time since `start`.
+    samples: RefCell<Vec<(Duration, &'static str, f64)>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            handshake: Cell::new(None),
+            samples: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn values(&self, name: &str) -> Vec<f64> {
+        self.samples
+            .borrow()
+            .iter()
+            .filter(|(_, n, _)| *n == name)
+            .map(|&(_, _, v)| v)
+            .collect()
+    }
+
+    fn write_csv(&self, args: &Args) -> Res<()> {
+        if let Some(path) = &args.csv {
+            let mut f = BufWriter::new(File::create(path)?);
+            writeln!(f, "time,metric,value")?;
+            for (time, name, value) in self.samples.borrow().iter() {
+                writeln!(f, "{:.6},{},{}", time.as_secs_f64(), name, value)?;
+            }
+            f.flush()?;
+            println!("Wrote samples to {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Print the results of the benchmark. `transfer` is the size of the response and how long
+    /// the request took, if it completed.
+    pub fn report(
+        &self,
+        args: &Args,
+        size: usize,
+        client: &mut Http3Client,
+        transfer: Option<(usize, Duration)>,
+    ) -> Res<()> {
+        let direction = if args.upload {
+            "Uploaded"
+        } else {
+            "Downloaded"
+        };
+        match transfer {
+            Some((received, time)) if args.upload || received == size => {
+                let mbps = size as f64 * 8.0 / time.as_secs_f64() / 1e6;
+                println!(
+                    "{} {} bytes in {:.3} s: {:.2} Mbit/s",
+                    direction,
+                    size,
+                    time.as_secs_f64(),
+                    mbps
+                );
+            }
+            Some((received, _)) => {
+                println!("Downloaded {} bytes of {}", received, size);
+            }
+            None => println!("The transfer did not complete"),
+        }
+        if let Some(handshake) = self.handshake.get() {
+            println!("Handshake: {:.1} ms", handshake * 1e3);
+        }
+
+        let rtt = self.values(metrics::RTT);
+        if !rtt.is_empty() {
+            let min = rtt.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = rtt.iter().copied().fold(0.0, f64::max);
+            let mean = rtt.iter().sum::<f64>() / rtt.len() as f64;
+            println!(
+                "Smoothed RTT: min {:.1} ms, mean {:.1} ms, max {:.1} ms ({} samples)",
+                min * 1e3,
+                mean * 1e3,
+                max * 1e3,
+                rtt.len()
+            );
+        }
+
+        let stats = client.conn().stats();
+        println!(
+            "Packets: {} sent, {} received, {} lost",
+            stats.packets_tx, stats.packets_rx, stats.lost
+        );
+
+        // This is the congestion window of the client, which a download hardly uses.
+        // neqo-server records its own with `--csv`.
+        let cwnd = self.values(metrics::CWND);
+        if let (Some(first), Some(last)) = (cwnd.first(), cwnd.last()) {
+            let max = cwnd.iter().copied().fold(0.0, f64::max);
+            println!(
+                "Congestion window: {} bytes at first, {} at most, {} at the end",
+                first, max, last
+            );
+        }
+
+        self.write_csv(args)
+    }
+}
+
+impl Metrics for Recorder {
+    fn gauge_set(&self, name: &'static str, value: f64) {
+        if name == metrics::RTT || name == metrics::CWND {
+            self.samples
+                .borrow_mut()
+                .push((self.start.elapsed(), name, value));
+        }
+    }
+
+    fn histogram_observe(&self, name: &'static str, value: f64) {
+        if name == metrics::HANDSHAKE_DURATION {
+            self.handshake.set(Some(value));
+        }
+    }
+}
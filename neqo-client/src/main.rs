@@ -7,12 +7,10 @@
 #![cfg_attr(feature = "deny-warnings", deny(warnings))]
 #![warn(clippy::use_self)]
 
-use qlog::QlogStreamer;
-
-use neqo_common::{self as common, event::Provider, hex, qlog::NeqoQlog, Datagram, Role};
+use neqo_common::{event::Provider, hex, qlog::NeqoQlog, Datagram, Decoder, Encoder, Role};
 use neqo_crypto::{
     constants::{TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256},
-    init, AuthenticationStatus, Cipher, ResumptionToken,
+    init, set_key_log_file, AuthenticationStatus, Cipher, ResumptionToken, SecretAgentInfo,
 };
 use neqo_http3::{
     self, Error, Header, Http3Client, Http3ClientEvent, Http3Parameters, Http3State, Output,
@@ -20,22 +18,25 @@ use neqo_http3::{
 use neqo_qpack::QpackSettings;
 use neqo_transport::{
     CongestionControlAlgorithm, Connection, ConnectionId, Error as TransportError,
-    FixedConnectionIdManager as EmptyConnectionIdGenerator, QuicVersion,
+    FixedConnectionIdManager as EmptyConnectionIdGenerator, PacketDump, QuicVersion, ZeroRttState,
 };
 
 use std::cell::RefCell;
 use std::collections::{HashMap, VecDeque};
-use std::fs::{File, OpenOptions};
-use std::io::{self, ErrorKind, Write};
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
 use std::path::PathBuf;
 use std::process::exit;
 use std::rc::Rc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use structopt::StructOpt;
 use url::{Origin, Url};
 
+mod bench;
+
 #[derive(Debug)]
 pub enum ClientError {
     Http3Error(neqo_http3::Error),
@@ -117,12 +118,26 @@ pub struct Args {
 
     urls: Vec<Url>,
 
-    #[structopt(short = "m", default_value = "GET")]
+    #[structopt(short = "m", long, default_value = "GET")]
+    /// The request method.
     method: String,
 
     #[structopt(short = "h", long, number_of_values = 2)]
+    /// A request header, given as a name and a value.
     header: Vec<String>,
 
+    #[structopt(name = "request-header", short = "H", long, number_of_values = 1)]
+    /// A request header in the form `name: value`, as curl takes them.
+    request_header: Vec<String>,
+
+    #[structopt(name = "body", short = "d", long)]
+    /// Send the contents of this file as the request body; `-` reads standard input.
+    body: Option<PathBuf>,
+
+    #[structopt(name = "trailer", long, number_of_values = 1)]
+    /// A request trailer in the form `name: value`, sent after the body.
+    trailer: Vec<String>,
+
     #[structopt(name = "encoder-table-size", long, default_value = "16384")]
     max_table_size_encoder: u64,
 
@@ -152,16 +167,23 @@ pub struct Args {
     /// Output received data to stdout
     output_read_data: bool,
 
-    #[structopt(name = "qlog-dir", long)]
-    /// Enable QLOG logging and QLOG traces to this directory
+    #[structopt(name = "qlog-dir", long, env = "QLOGDIR")]
+    /// Write a qlog trace of each connection to `<original DCID>-client.qlog` in this directory
     qlog_dir: Option<PathBuf>,
 
+    #[structopt(name = "packet-dump-dir", long, parse(from_os_str))]
+    /// Write every packet of each connection, decrypted and with the fields of its frames,
+    /// to `<original DCID>-client.dump` in this directory. This is slow.
+    packet_dump_dir: Option<PathBuf>,
+
     #[structopt(name = "output-dir", long)]
     /// Save contents of fetched URLs to a directory
     output_dir: Option<PathBuf>,
 
-    #[structopt(name = "qns-test", long)]
-    /// Enable special behavior for use with QUIC Network Simulator
+    #[structopt(name = "qns-test", long, env = "TESTCASE")]
+    /// Enable special behavior for use with QUIC Network Simulator.
+    /// The URLs to fetch are taken from `REQUESTS` if none are given
+    /// and files are saved to `/downloads` unless `--output-dir` is set.
     qns_test: Option<String>,
 
     #[structopt(short = "r", long)]
@@ -169,6 +191,11 @@ pub struct Args {
     /// Use this for 0-RTT: the stack always attempts 0-RTT on resumption.
     resume: bool,
 
+    #[structopt(name = "resumption-file", long, parse(from_os_str))]
+    /// Resume with the token in this file, if it holds one for the server, and attempt 0-RTT.
+    /// The last resumption token from the server is saved to the file. HTTP/3 only.
+    resumption_file: Option<PathBuf>,
+
     #[structopt(name = "key-update", long)]
     /// Attempt to initiate a key update immediately after confirming the connection.
     key_update: bool,
@@ -177,6 +204,25 @@ pub struct Args {
     /// The set of TLS cipher suites to enable.
     /// From: TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256.
     ciphers: Vec<String>,
+
+    #[structopt(name = "key-log-file", long, env = "SSLKEYLOGFILE", parse(from_os_str))]
+    /// Write TLS secrets to this file in the NSS key log format.
+    key_log_file: Option<PathBuf>,
+
+    #[structopt(name = "bench", long)]
+    /// Download this many bytes from neqo-server at the origin of the first URL and print
+    /// goodput, handshake time, RTT, losses and the congestion window. HTTP/3 only.
+    bench: Option<usize>,
+
+    #[structopt(name = "upload", long, requires = "bench", conflicts_with = "body")]
+    /// With `--bench`, upload the bytes instead. The congestion window that is reported is
+    /// the client's, so it only says much about uploads; for a download, run neqo-server
+    /// with `--csv` to record the window of the sender.
+    upload: bool,
+
+    #[structopt(name = "csv", long, requires = "bench", parse(from_os_str))]
+    /// With `--bench`, write the samples of the RTT and the congestion window to this file.
+    csv: Option<PathBuf>,
 }
 
 impl Args {
@@ -225,13 +271,22 @@ fn get_output_file(
 
         eprintln!("Saving {} to {:?}", url.clone().into_string(), out_path);
 
+        if let Some(parent) = out_path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                eprintln!("Unable to create {}: {}", parent.display(), e);
+                return None;
+            }
+        }
         let f = match OpenOptions::new()
             .write(true)
             .create(true)
             .truncate(true)
             .open(&out_path)
         {
-            Err(_) => return None,
+            Err(e) => {
+                eprintln!("Unable to open {}: {}", out_path.display(), e);
+                return None;
+            }
             Ok(f) => f,
         };
 
@@ -305,12 +360,100 @@ fn process_loop(
     }
 }
 
+/// What is sent with every request, other than the method and the URL.
+struct RequestContent {
+    headers: Vec<Header>,
+    body: Vec<u8>,
+    trailers: Vec<Header>,
+}
+
+impl RequestContent {
+    fn new(args: &Args) -> Res<Self> {
+        let mut headers = to_headers(&args.header);
+        headers.extend(args.request_header.iter().map(|h| parse_header(h)));
+        let body = match &args.body {
+            Some(path) if path.as_os_str() == "-" => {
+                let mut body = Vec::new();
+                io::stdin().read_to_end(&mut body)?;
+                body
+            }
+            Some(path) => fs::read(path)?,
+            None => match args.bench {
+                Some(size) if args.upload => bench::data(size),
+                _ => Vec::new(),
+            },
+        };
+        Ok(Self {
+            headers,
+            body,
+            trailers: args.trailer.iter().map(|h| parse_header(h)).collect(),
+        })
+    }
+
+    /// Whether requests end right after their headers.
+    fn is_empty(&self) -> bool {
+        self.body.is_empty() && self.trailers.is_empty()
+    }
+}
+
+/// Parse a header field that is written as `name: value`.
+fn parse_header(field: &str) -> Header {
+    if let Some(i) = field.find(':') {
+        (
+            field[..i].trim().to_ascii_lowercase(),
+            field[i + 1..].trim().to_string(),
+        )
+    } else {
+        eprintln!("Invalid header field {}, expected name: value", field);
+        exit(1)
+    }
+}
+
+/// A request that is in progress, and what has been received in response to it.
+struct Download {
+    url: Url,
+    out_file: Option<File>,
+    /// How much of the request body has been sent, until the request is complete.
+    body_sent: Option<usize>,
+    status: Option<String>,
+    received: usize,
+    start: Instant,
+}
+
+impl Download {
+    fn new(url: Url, out_file: Option<File>) -> Self {
+        Self {
+            url,
+            out_file,
+            body_sent: None,
+            status: None,
+            received: 0,
+            start: Instant::now(),
+        }
+    }
+
+    /// Print a line that says how the request went.
+    fn report(&self, outcome: &str) {
+        println!(
+            "{} {}: {} bytes, {}",
+            self.status.as_deref().unwrap_or("---"),
+            self.url,
+            self.received,
+            outcome
+        );
+    }
+}
+
 struct Handler<'a> {
-    streams: HashMap<u64, Option<File>>,
+    streams: HashMap<u64, Download>,
     url_queue: VecDeque<Url>,
     all_paths: Vec<PathBuf>,
     args: &'a Args,
+    request: &'a RequestContent,
+    token: Option<ResumptionToken>,
     key_update: KeyUpdateState,
+    /// The size of the last complete response and how long it took from creating the request.
+    transfer: Option<(usize, Duration)>,
 }
 
 impl<'a> Handler<'a> {
@@ -343,18 +486,25 @@ impl<'a> Handler<'a> {
             &url.scheme(),
             &url.host_str().unwrap(),
             &url.path(),
-            &to_headers(&self.args.header),
+            &self.request.headers,
         ) {
             Ok(client_stream_id) => {
                 println!(
                     "Successfully created stream id {} for {}",
                     client_stream_id, url
                 );
-                let _ = client.stream_close_send(client_stream_id);
+                let mut download = Download::new(url, None);
+                if self.request.is_empty() {
+                    let _ = client.stream_close_send(client_stream_id);
+                } else {
+                    // The body and trailers are sent once the stream is writable.
+                    download.body_sent = Some(0);
+                }
 
-                let out_file = get_output_file(&url, &self.args.output_dir, &mut self.all_paths);
+                download.out_file =
+                    get_output_file(&download.url, &self.args.output_dir, &mut self.all_paths);
 
-                self.streams.insert(client_stream_id, out_file);
+                self.streams.insert(client_stream_id, download);
                 true
             }
             e @ Err(Error::TransportError(TransportError::StreamLimitError))
@@ -370,6 +520,33 @@ impl<'a> Handler<'a> {
         }
     }
 
+    /// Send as much of the request body as flow control allows. Once all of it is sent,
+    /// send the trailers, if there are any, and end the request.
+    fn send_body(&mut self, client: &mut Http3Client, stream_id: u64) -> Res<()> {
+        let download = match self.streams.get_mut(&stream_id) {
+            Some(download) => download,
+            None => return Ok(()),
+        };
+        let sent = match &mut download.body_sent {
+            Some(sent) => sent,
+            None => return Ok(()),
+        };
+        while *sent < self.request.body.len() {
+            let amount = client.send_request_body(stream_id, &self.request.body[*sent..])?;
+            if amount == 0 {
+                return Ok(());
+            }
+            *sent += amount;
+        }
+        if self.request.trailers.is_empty() {
+            client.stream_close_send(stream_id)?;
+        } else {
+            client.send_request_trailers(stream_id, &self.request.trailers)?;
+        }
+        download.body_sent = None;
+        Ok(())
+    }
+
     fn maybe_key_update(&mut self, c: &mut Http3Client) -> Res<()> {
         self.key_update.maybe_update(|| c.initiate_key_update())?;
         self.download_urls(c);
@@ -380,6 +557,23 @@ impl<'a> Handler<'a> {
         self.streams.is_empty() && self.url_queue.is_empty()
     }
 
+    /// Forget a stream whose response is complete or that has been reset, and start the next
+    /// downloads. Returns false when all downloads are done and the connection is closed.
+    fn stream_finished(&mut self, client: &mut Http3Client, stream_id: u64, outcome: &str) -> bool {
+        if let Some(download) = self.streams.remove(&stream_id) {
+            download.report(outcome);
+            if outcome == "complete" {
+                self.transfer = Some((download.received, download.start.elapsed()));
+            }
+        }
+        self.download_urls(client);
+        if self.done() {
+            client.close(Instant::now(), 0, "kthxbye!");
+            return false;
+        }
+        true
+    }
+
     fn handle(&mut self, client: &mut Http3Client) -> Res<bool> {
         while let Some(event) = client.next_event() {
             match event {
@@ -389,13 +583,22 @@ impl<'a> Handler<'a> {
                 Http3ClientEvent::HeaderReady {
                     stream_id,
                     headers,
+                    interim,
                     fin,
-                    ..
-                } => match self.streams.get(&stream_id) {
-                    Some(out_file) => {
-                        if out_file.is_none() {
+                } => match self.streams.get_mut(&stream_id) {
+                    Some(download) => {
+                        if !interim {
+                            download.status = headers
+                                .iter()
+                                .find(|(name, _)| name == ":status")
+                                .map(|(_, value)| value.clone());
+                        }
+                        if download.out_file.is_none() {
                             println!("READ HEADERS[{}]: fin={} {:?}", stream_id, fin, headers);
                         }
+                        if fin && !self.stream_finished(client, stream_id, "complete") {
+                            return Ok(false);
+                        }
                     }
                     None => {
                         println!("Data on unexpected stream: {}", stream_id);
@@ -409,16 +612,20 @@ impl<'a> Handler<'a> {
                             println!("Data on unexpected stream: {}", stream_id);
                             return Ok(false);
                         }
-                        Some(out_file) => loop {
+                        Some(download) => loop {
                             let mut data = vec![0; 4096];
                             let (sz, fin) = client
                                 .read_response_data(Instant::now(), stream_id, &mut data)
                                 .expect("Read should succeed");
+                            download.received += sz;
 
+                            let out_file = &mut download.out_file;
                             if let Some(out_file) = out_file {
                                 if sz > 0 {
                                     out_file.write_all(&data[..sz])?;
                                 }
+                            } else if self.args.bench.is_some() {
+                                // Printing every read would slow the benchmark down.
                             } else if !self.args.output_read_data {
                                 println!("READ[{}]: {} bytes", stream_id, sz);
                             } else if let Ok(txt) = String::from_utf8(data.clone()) {
@@ -441,19 +648,46 @@ impl<'a> Handler<'a> {
                         },
                     }
 
-                    if stream_done {
-                        self.streams.remove(&stream_id);
-                        self.download_urls(client);
-                        if self.done() {
-                            client.close(Instant::now(), 0, "kthxbye!");
-                            return Ok(false);
-                        }
+                    if stream_done && !self.stream_finished(client, stream_id, "complete") {
+                        return Ok(false);
+                    }
+                }
+                Http3ClientEvent::Reset {
+                    stream_id, error, ..
+                } => {
+                    println!("RESET[{}]: error={}", stream_id, error);
+                    if self.streams.contains_key(&stream_id)
+                        && !self.stream_finished(client, stream_id, "reset")
+                    {
+                        return Ok(false);
+                    }
+                }
+                Http3ClientEvent::DataWritable { stream_id } => {
+                    self.send_body(client, stream_id)?;
+                }
+                Http3ClientEvent::TrailersReady {
+                    stream_id,
+                    trailers,
+                } => {
+                    println!("READ TRAILERS[{}]: {:?}", stream_id, trailers);
+                    if self.streams.contains_key(&stream_id)
+                        && !self.stream_finished(client, stream_id, "complete")
+                    {
+                        return Ok(false);
                     }
                 }
+                Http3ClientEvent::StopSending { stream_id, error } => {
+                    // The request has been sent completely, only the response matters.
+                    println!("STOP_SENDING[{}]: error={}", stream_id, error);
+                }
                 Http3ClientEvent::StateChange(Http3State::Connected)
+                | Http3ClientEvent::StateChange(Http3State::ZeroRtt)
                 | Http3ClientEvent::RequestsCreatable => {
                     self.download_urls(client);
                 }
+                Http3ClientEvent::ResumptionToken(token) => {
+                    self.token = Some(token);
+                }
                 _ => {
                     println!("Unhandled event {:?}", event);
                 }
@@ -486,6 +720,7 @@ fn client(
     remote_addr: SocketAddr,
     hostname: &str,
     urls: &[Url],
+    request: &RequestContent,
 ) -> Res<()> {
     let quic_protocol = match args.alpn.as_str() {
         "h3-27" => QuicVersion::Draft27,
@@ -512,18 +747,42 @@ fn client(
     }
     let mut client = Http3Client::new_with_conn(
         transport,
-        &Http3Parameters {
-            qpack_settings: QpackSettings {
+        &Http3Parameters::new(
+            QpackSettings {
                 max_table_size_encoder: args.max_table_size_encoder,
                 max_table_size_decoder: args.max_table_size_decoder,
                 max_blocked_streams: args.max_blocked_streams,
             },
-            max_concurrent_push_streams: args.max_concurrent_push_streams,
-        },
+            args.max_concurrent_push_streams,
+        ),
     );
 
-    let qlog = qlog_new(args, hostname, client.connection_id())?;
+    let qlog = qlog_new(args, client.connection_id())?;
     client.set_qlog(qlog);
+    let dump = packet_dump_new(args, client.connection_id())?;
+    client.conn().set_packet_dump(dump);
+
+    let recorder = Rc::new(bench::Recorder::new());
+    if args.bench.is_some() {
+        client.set_metrics(Rc::clone(&recorder) as _);
+    }
+
+    let resuming = if let Some(token) = load_resumption_token(args, hostname) {
+        // A token that is corrupt or from an older version is no reason to give up.
+        match client.enable_resumption(Instant::now(), token) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!(
+                    "Warning: can't resume with the token in {}, doing a full handshake: {:?}",
+                    args.resumption_file.as_ref().unwrap().display(),
+                    e
+                );
+                false
+            }
+        }
+    } else {
+        false
+    };
 
     let key_update = KeyUpdateState(args.key_update);
     let mut h = Handler {
@@ -531,19 +790,84 @@ fn client(
         url_queue: VecDeque::from(urls.to_vec()),
         all_paths: Vec::new(),
         args: &args,
+        request,
+        token: None,
         key_update,
+        transfer: None,
     };
 
     process_loop(&local_addr, &socket, &mut client, &mut h)?;
 
+    if let Some(size) = args.bench {
+        recorder.report(args, size, &mut client, h.transfer)?;
+    }
+
+    if resuming {
+        report_resumption(&mut client);
+    }
+    if args.resumption_file.is_some() {
+        // Many servers don't send NEW_TOKEN, so take a token without one if there is no other.
+        let token = h
+            .token
+            .take()
+            .or_else(|| client.take_resumption_token(Instant::now()));
+        match token {
+            Some(token) => save_resumption_token(args, hostname, &token)?,
+            None => println!("No resumption token from {}", hostname),
+        }
+    }
+
     Ok(())
 }
 
-fn qlog_new(args: &Args, hostname: &str, cid: &ConnectionId) -> Res<NeqoQlog> {
+/// Read the token that `save_resumption_token` saved, if it is for `host`.
+fn load_resumption_token(args: &Args, host: &str) -> Option<Vec<u8>> {
+    let path = args.resumption_file.as_ref()?;
+    // There is nothing to resume on the first run.
+    let data = fs::read(path).ok()?;
+    let mut dec = Decoder::from(&data[..]);
+    if dec.decode_vvec()? != host.as_bytes() {
+        println!("{} has no resumption token for {}", path.display(), host);
+        return None;
+    }
+    Some(dec.decode_remainder().to_vec())
+}
+
+/// Save a resumption token, along with the host that it is for. The expiration time isn't
+/// saved; the server decides whether the ticket in the token is still good.
+fn save_resumption_token(args: &Args, host: &str, token: &ResumptionToken) -> Res<()> {
+    if let Some(path) = &args.resumption_file {
+        let mut enc = Encoder::default();
+        enc.encode_vvec(host.as_bytes());
+        enc.encode(token.as_ref());
+        fs::write(path, &enc[..])?;
+        println!("Saved resumption token for {} to {}", host, path.display());
+    }
+    Ok(())
+}
+
+/// Say whether the server accepted resumption and early data.
+fn report_resumption(client: &mut Http3Client) {
+    let resumed = client.tls_info().map_or(false, SecretAgentInfo::resumed);
+    let early_data = match client.conn().zero_rtt_state() {
+        ZeroRttState::AcceptedClient => "accepted",
+        ZeroRttState::Rejected => "rejected",
+        _ => "not sent",
+    };
+    println!(
+        "Resumption {}, early data {}",
+        if resumed { "accepted" } else { "rejected" },
+        early_data
+    );
+}
+
+/// Start a qlog trace for the connection with the original destination connection ID `odcid`.
+/// The file is named like those of the server, so that the traces of both ends sit together.
+fn qlog_new(args: &Args, odcid: &ConnectionId) -> Res<NeqoQlog> {
     if let Some(qlog_dir) = &args.qlog_dir {
+        fs::create_dir_all(qlog_dir)?;
         let mut qlog_path = qlog_dir.to_path_buf();
-        let filename = format!("{}-{}.qlog", hostname, cid);
-        qlog_path.push(filename);
+        qlog_path.push(format!("{}-client.qlog", odcid));
 
         let f = OpenOptions::new()
             .write(true)
@@ -551,28 +875,53 @@ fn qlog_new(args: &Args, hostname: &str, cid: &ConnectionId) -> Res<NeqoQlog> {
             .truncate(true)
             .open(&qlog_path)?;
 
-        let streamer = QlogStreamer::new(
-            qlog::QLOG_VERSION.to_string(),
-            Some("Example qlog".to_string()),
-            Some("Example qlog description".to_string()),
-            None,
-            std::time::Instant::now(),
-            common::qlog::new_trace(Role::Client),
-            Box::new(f),
-        );
-
-        Ok(NeqoQlog::enabled(streamer, qlog_path)?)
+        Ok(NeqoQlog::enabled_with_file(f, qlog_path, Role::Client)?)
     } else {
         Ok(NeqoQlog::disabled())
     }
 }
 
-fn main() -> Res<()> {
-    init();
+/// Start a packet dump for the connection with the original destination connection ID `odcid`,
+/// if `--packet-dump-dir` asks for one.
+fn packet_dump_new(args: &Args, odcid: &ConnectionId) -> Res<Option<PacketDump>> {
+    if let Some(dir) = &args.packet_dump_dir {
+        fs::create_dir_all(dir)?;
+        let mut path = dir.to_path_buf();
+        path.push(format!("{}-client.dump", odcid));
+        Ok(Some(PacketDump::new(File::create(&path)?)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The URLs that the QUIC Network Simulator asks for, which it puts in `REQUESTS`.
+fn qns_requests() -> Vec<Url> {
+    env::var("REQUESTS")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|u| {
+            Url::parse(u).unwrap_or_else(|e| {
+                eprintln!("Invalid URL {} in REQUESTS: {}", u, e);
+                exit(1)
+            })
+        })
+        .collect()
+}
 
+fn main() -> Res<()> {
     let mut args = Args::from_args();
+    if let Some(path) = &args.key_log_file {
+        set_key_log_file(path);
+    }
+    init();
 
     if let Some(testcase) = args.qns_test.as_ref() {
+        if args.urls.is_empty() {
+            args.urls = qns_requests();
+        }
+        if args.output_dir.is_none() {
+            args.output_dir = Some(PathBuf::from("/downloads"));
+        }
         match testcase.as_str() {
             "http3" => {}
             "handshake" | "transfer" | "retry" => {
@@ -604,6 +953,12 @@ fn main() -> Res<()> {
         }
     }
 
+    if let Some(size) = args.bench {
+        bench::configure(&mut args, size);
+    }
+
+    let request = RequestContent::new(&args)?;
+
     let mut urls_by_origin: HashMap<Origin, Vec<Url>> = HashMap::new();
     for url in &args.urls {
         let entry = urls_by_origin.entry(url.origin()).or_default();
@@ -650,6 +1005,7 @@ fn main() -> Res<()> {
                 remote_addr,
                 &format!("{}", host),
                 &urls,
+                &request,
             )?;
         } else if !args.download_in_series {
             let token = if args.resume {
@@ -717,7 +1073,7 @@ mod old {
 
     use url::Url;
 
-    use super::{qlog_new, KeyUpdateState, Res};
+    use super::{packet_dump_new, qlog_new, KeyUpdateState, Res};
 
     use neqo_common::{event::Provider, Datagram};
     use neqo_crypto::{AuthenticationStatus, ResumptionToken};
@@ -1011,7 +1367,8 @@ mod old {
             client.set_ciphers(&ciphers)?;
         }
 
-        client.set_qlog(qlog_new(args, origin, &client.odcid().unwrap())?);
+        client.set_qlog(qlog_new(args, &client.odcid().unwrap())?);
+        client.set_packet_dump(packet_dump_new(args, &client.odcid().unwrap())?);
 
         let key_update = KeyUpdateState(args.key_update);
         let mut h = HandlerOld {
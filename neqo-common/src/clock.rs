@@ -0,0 +1,126 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Sources of the current time.
+
+use std::cell::Cell;
+use std::fmt::Debug;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A source of the current time. Code that needs the time without being given it should ask
+/// a `Clock`, so that tests can control the time.
+pub trait Clock: Debug {
+    fn now(&self) -> Instant;
+
+    /// The wall-clock time, for times that are shared with other hosts.
+    fn system_time(&self) -> SystemTime;
+}
+
+pub type ClockRef = Rc<dyn Clock>;
+
+/// The time of the system, i.e. `Instant::now()`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when it is told to. Its system time starts at a fixed time, so
+/// that it is the same in every run.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    start: Instant,
+    now: Cell<Instant>,
+}
+
+impl MockClock {
+    /// The system time when the clock is at `start`.
+    pub const SYSTEM_START: Duration = Duration::from_secs(1_600_000_000);
+
+    #[must_use]
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            now: Cell::new(start),
+        }
+    }
+
+    /// Move the clock forward by `d`.
+    pub fn advance(&self, d: Duration) {
+        self.now.set(self.now.get() + d);
+    }
+
+    /// Set the time. This can move the clock backwards.
+    pub fn set(&self, t: Instant) {
+        self.now.set(t);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        let now = self.now.get();
+        if now >= self.start {
+            UNIX_EPOCH + Self::SYSTEM_START + (now - self.start)
+        } else {
+            UNIX_EPOCH + Self::SYSTEM_START - (self.start - now)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, ClockRef, MockClock, SystemClock};
+    use std::rc::Rc;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn mock() {
+        let start = Instant::now();
+        let clock = Rc::new(MockClock::new(start));
+        let shared: ClockRef = Rc::clone(&clock) as _;
+        assert_eq!(shared.now(), start);
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(shared.now(), start + Duration::from_millis(10));
+        clock.set(start);
+        assert_eq!(shared.now(), start);
+    }
+
+    #[test]
+    fn mock_system_time() {
+        let start = Instant::now() + Duration::from_secs(10);
+        let clock = MockClock::new(start);
+        let system_start = UNIX_EPOCH + MockClock::SYSTEM_START;
+        assert_eq!(clock.system_time(), system_start);
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(clock.system_time(), system_start + Duration::from_secs(3));
+        clock.set(start - Duration::from_secs(5));
+        assert_eq!(clock.system_time(), system_start - Duration::from_secs(5));
+    }
+
+    #[test]
+    fn system() {
+        let before = Instant::now();
+        let now = SystemClock.now();
+        assert!(now >= before);
+        assert!(now <= Instant::now());
+        let before = SystemTime::now();
+        let now = SystemClock.system_time();
+        assert!(now >= before);
+        assert!(now <= SystemTime::now());
+    }
+}
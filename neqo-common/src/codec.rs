@@ -10,6 +10,9 @@ use std::ops::{Deref, DerefMut};
 
 use crate::hex_with_len;
 
+/// The largest value that can be encoded as a varint.
+pub const MAX_VARINT: u64 = (1 << 62) - 1;
+
 /// Decoder is a view into a byte array that has a read offset.  Use it for parsing.
 pub struct Decoder<'a> {
     buf: &'a [u8],
@@ -79,6 +82,22 @@ impl<'a> Decoder<'a> {
         }
     }
 
+    /// Provides the next `n` bytes without moving the read position.
+    #[must_use]
+    pub fn peek(&self, n: usize) -> Option<&'a [u8]> {
+        if self.remaining() < n {
+            None
+        } else {
+            Some(&self.buf[self.offset..self.offset + n])
+        }
+    }
+
+    /// Provides the next QUIC varint without moving the read position.
+    #[must_use]
+    pub fn peek_varint(&self) -> Option<u64> {
+        Decoder::new(&self.buf[self.offset..]).decode_varint()
+    }
+
     /// Decodes arbitrary data.
     pub fn decode(&mut self, n: usize) -> Option<&'a [u8]> {
         if self.remaining() < n {
@@ -152,6 +171,17 @@ impl<'a> Decoder<'a> {
         let len = self.decode_varint();
         self.decode_checked(len)
     }
+
+    /// Make a decoder for the next `n` bytes, which borrows from the same buffer.
+    pub fn decode_sub(&mut self, n: usize) -> Option<Decoder<'a>> {
+        self.decode(n).map(Decoder::new)
+    }
+
+    /// Make a decoder for a QUIC varint-length-prefixed buffer, which borrows from the same
+    /// buffer.
+    pub fn decode_vvec_sub(&mut self) -> Option<Decoder<'a>> {
+        self.decode_vvec().map(Decoder::new)
+    }
 }
 
 // Implement `Deref` for `Decoder` so that values can be examined without moving the cursor.
@@ -307,16 +337,64 @@ impl Encoder {
     }
 
     /// Encode a vector in TLS style using a closure for the contents.
-    #[allow(clippy::cast_possible_truncation)]
+    /// # Panics
+    /// If the length of what `f` encodes does not fit in `n` bytes.
     pub fn encode_vec_with<F: FnOnce(&mut Self)>(&mut self, n: usize, f: F) -> &mut Self {
-        let start = self.buf.len();
-        self.buf.resize(self.buf.len() + n, 0);
+        let r = self.reserve(n);
         f(self);
-        let len = self.buf.len() - start - n;
+        let len = self.len_since(r);
+        self.fill_uint(r, len as u64);
+        self
+    }
+
+    /// Reserve `n` bytes for a value that is only known once more has been encoded, e.g. the
+    /// length of what follows. The reserved bytes are written with `fill_uint` or
+    /// `fill_varint`.
+    pub fn reserve(&mut self, n: usize) -> Reservation {
+        let offset = self.buf.len();
+        self.buf.resize(offset + n, 0);
+        Reservation { offset, len: n }
+    }
+
+    /// The number of bytes that have been encoded after `r`.
+    #[must_use]
+    pub fn len_since(&self, r: Reservation) -> usize {
+        self.buf.len() - r.offset - r.len
+    }
+
+    /// Write an integer into the reserved bytes.
+    /// # Panics
+    /// If `v` does not fit.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn fill_uint(&mut self, r: Reservation, v: u64) {
+        let n = r.len;
+        assert!(n > 0 && n <= 8);
+        assert!(
+            n == 8 || v >> (8 * n) == 0,
+            "Value too large for the reservation"
+        );
         for i in 0..n {
-            self.buf[start + i] = ((len >> (8 * (n - i - 1))) & 0xff) as u8
+            self.buf[r.offset + i] = ((v >> (8 * (n - i - 1))) & 0xff) as u8;
         }
-        self
+    }
+
+    /// Write a QUIC varint into the reserved bytes, which must be 1, 2, 4 or 8 bytes. The
+    /// varint uses all reserved bytes, even if a shorter encoding would be possible.
+    /// # Panics
+    /// If the reservation has a different size or `v` does not fit.
+    pub fn fill_varint(&mut self, r: Reservation, v: u64) {
+        let prefix = match r.len {
+            1 => 0,
+            2 => 1 << 14,
+            4 => 2 << 30,
+            8 => 3 << 62,
+            _ => panic!("Invalid varint length"),
+        };
+        assert!(
+            v < 1 << (8 * r.len - 2),
+            "Value too large for the reservation"
+        );
+        self.fill_uint(r, v | prefix);
     }
 
     /// Encode a vector with a varint length.
@@ -326,41 +404,35 @@ impl Encoder {
     }
 
     /// Encode a vector with a varint length using a closure.
-    #[allow(clippy::cast_possible_truncation)]
+    /// # Panics
+    /// If the length of what `f` encodes does not fit in a varint.
     pub fn encode_vvec_with<F: FnOnce(&mut Self)>(&mut self, f: F) -> &mut Self {
-        let start = self.buf.len();
         // Optimize for short buffers, reserve a single byte for the length.
-        self.buf.resize(self.buf.len() + 1, 0);
+        let r = self.reserve(1);
         f(self);
-        let len = self.buf.len() - start - 1;
-
-        // Now to insert a varint for `len` before the encoded block.
-        //
-        // We now have one zero byte at `start`, followed by `len` encoded bytes:
-        //   |  0  | ... encoded ... |
-        // We are going to encode a varint by putting the low bytes in that spare byte.
-        // Any additional bytes for the varint are put after the encoded blob:
-        //   | low | ... encoded ... | varint high |
-        // Then we will rotate that entire piece right, by however many bytes we add:
-        //   | varint high | low | ... encoded ... |
+        let len = self.len_since(r);
+        let v = u64::try_from(len).expect("encoded value fits in a u64");
+        let n = Self::varint_len(v);
+        if n == 1 {
+            self.fill_varint(r, v);
+            return self;
+        }
+
+        // The length needs more space. The encoded block is moved along to make room:
+        //   | r | ... encoded ... | n - 1 new bytes |
+        // becomes
+        //   | r | n - 1 new bytes | ... encoded ... |
         // As long as encoding more than 63 bytes is rare, this won't cost much relative
         // to the convenience of being able to use this function.
-
-        let v = u64::try_from(len).expect("encoded value fits in a u64");
-        // The lower order byte fits before the inserted block of bytes.
-        self.buf[start] = (v & 0xff) as u8;
-        let (count, bits) = match () {
-            // Great.  The byte we have is enough.
-            _ if v < (1 << 6) => return self,
-            _ if v < (1 << 14) => (1, 1 << 6),
-            _ if v < (1 << 30) => (3, 2 << 22),
-            _ if v < (1 << 62) => (7, 3 << 54),
-            _ => panic!("Varint value too large"),
-        };
-        // Now, we need to encode the high bits after the main block, ...
-        self.encode_uint(count, (v >> 8) | bits);
-        // ..., then rotate the entire thing right by the same amount.
-        self.buf[start..].rotate_right(count);
+        self.buf.resize(self.buf.len() + n - 1, 0);
+        self.buf[r.offset + 1..].rotate_right(n - 1);
+        self.fill_varint(
+            Reservation {
+                offset: r.offset,
+                len: n,
+            },
+            v,
+        );
         self
     }
 
@@ -377,6 +449,13 @@ impl Encoder {
     }
 }
 
+/// Bytes in an `Encoder` that have been reserved with `Encoder::reserve`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reservation {
+    offset: usize,
+    len: usize,
+}
+
 impl Debug for Encoder {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         f.write_str(&hex_with_len(self))
@@ -428,7 +507,9 @@ impl DerefMut for Encoder {
 
 #[cfg(test)]
 mod tests {
-    use super::{Decoder, Encoder};
+    use super::{Decoder, Encoder, MAX_VARINT};
+    use proptest::collection::vec;
+    use proptest::prelude::*;
 
     #[test]
     fn decode() {
@@ -517,6 +598,39 @@ mod tests {
         assert!(dec.decode_vvec().is_none());
     }
 
+    #[test]
+    fn peek() {
+        let enc = Encoder::from_hex("4001ff");
+        let dec = enc.as_decoder();
+        assert_eq!(dec.peek(2).unwrap(), &[0x40, 0x01]);
+        assert_eq!(dec.peek(3).unwrap(), &[0x40, 0x01, 0xff]);
+        assert!(dec.peek(4).is_none());
+        assert_eq!(dec.peek_varint(), Some(1));
+        assert_eq!(dec.remaining(), 3);
+
+        // A truncated varint.
+        let enc = Encoder::from_hex("ff");
+        assert!(enc.as_decoder().peek_varint().is_none());
+    }
+
+    #[test]
+    fn decode_sub() {
+        let enc = Encoder::from_hex("0212340556");
+        let mut dec = enc.as_decoder();
+        let mut sub = dec.decode_vvec_sub().unwrap();
+        assert_eq!(dec.remaining(), 2);
+        assert_eq!(sub.decode_uint(2), Some(0x1234));
+        assert_eq!(sub.remaining(), 0);
+
+        let mut sub = dec.decode_sub(1).unwrap();
+        assert_eq!(dec.remaining(), 1);
+        assert_eq!(sub.decode_byte(), Some(0x05));
+        assert!(sub.decode_byte().is_none());
+
+        assert!(dec.decode_sub(2).is_none());
+        assert!(dec.decode_vvec_sub().is_none());
+    }
+
     #[test]
     fn skip() {
         let enc = Encoder::from_hex("ffff");
@@ -732,6 +846,15 @@ mod tests {
         assert_eq!(enc, Encoder::from_hex("000102"));
     }
 
+    #[test]
+    #[should_panic]
+    fn encode_vec_with_too_long() {
+        let mut enc = Encoder::default();
+        enc.encode_vec_with(1, |enc_inner| {
+            enc_inner.encode(&[0; 256]);
+        });
+    }
+
     #[test]
     fn encode_vvec() {
         let mut enc = Encoder::default();
@@ -759,6 +882,64 @@ mod tests {
         assert_eq!(v, vec![0x40, 0x41, 0xa5]);
     }
 
+    #[test]
+    fn encode_vvec_with_four_byte_length() {
+        let mut enc = Encoder::from_hex("ff");
+        enc.encode_vvec_with(|enc_inner| {
+            enc_inner.encode(&[0xa5; 1 << 14]);
+        });
+        assert_eq!(enc.len(), 1 + 4 + (1 << 14));
+        assert_eq!(&enc[..6], &[0xff, 0x80, 0x00, 0x40, 0x00, 0xa5]);
+        let mut dec = Decoder::from(&enc[1..]);
+        assert_eq!(dec.decode_vvec(), Some(&[0xa5; 1 << 14][..]));
+    }
+
+    #[test]
+    fn reserve_fill() {
+        let mut enc = Encoder::default();
+        let r1 = enc.reserve(1);
+        let r2 = enc.reserve(2);
+        let r4 = enc.reserve(4);
+        enc.encode_byte(0xff);
+        assert_eq!(enc.len_since(r4), 1);
+        assert_eq!(enc.len_since(r1), 7);
+        enc.fill_uint(r1, 0x12);
+        enc.fill_varint(r2, 5);
+        enc.fill_varint(r4, 0x3fff_ffff);
+        assert_eq!(enc, Encoder::from_hex("124005bfffffffff"));
+
+        let mut enc = Encoder::default();
+        let r8 = enc.reserve(8);
+        enc.fill_varint(r8, 1);
+        assert_eq!(enc, Encoder::from_hex("c000000000000001"));
+        // A varint in 8 bytes decodes to the same value.
+        assert_eq!(Decoder::from(&enc[..]).decode_varint(), Some(1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn fill_uint_too_large() {
+        let mut enc = Encoder::default();
+        let r = enc.reserve(1);
+        enc.fill_uint(r, 0x100);
+    }
+
+    #[test]
+    #[should_panic]
+    fn fill_varint_too_large() {
+        let mut enc = Encoder::default();
+        let r = enc.reserve(1);
+        enc.fill_varint(r, 64);
+    }
+
+    #[test]
+    #[should_panic]
+    fn fill_varint_bad_length() {
+        let mut enc = Encoder::default();
+        let r = enc.reserve(3);
+        enc.fill_varint(r, 0);
+    }
+
     // Test that Deref to &[u8] works for Encoder.
     #[test]
     fn encode_builder() {
@@ -795,4 +976,54 @@ mod tests {
         enc.pad_to(7, 0xc2);
         assert_eq!(enc, Encoder::from_hex("0102340000c2c2"));
     }
+
+    proptest! {
+        #[test]
+        fn varint_round_trip(v in 0..=MAX_VARINT) {
+            let mut enc = Encoder::default();
+            enc.encode_varint(v);
+            prop_assert_eq!(enc.len(), Encoder::varint_len(v));
+            let mut dec = enc.as_decoder();
+            prop_assert_eq!(dec.decode_varint(), Some(v));
+            prop_assert_eq!(dec.remaining(), 0);
+        }
+
+        #[test]
+        fn vvec_round_trip(v in vec(any::<u8>(), 0..300), extra in vec(any::<u8>(), 0..3)) {
+            let mut enc = Encoder::default();
+            enc.encode_vvec(&v).encode(&extra);
+            prop_assert_eq!(enc.len(), Encoder::vvec_len(v.len()) + extra.len());
+            let mut dec = enc.as_decoder();
+            prop_assert_eq!(dec.decode_vvec(), Some(&v[..]));
+            prop_assert_eq!(dec.decode_remainder(), &extra[..]);
+        }
+
+        #[test]
+        fn uint_round_trip(n in 1..=8_usize, v: u64) {
+            let v = if n == 8 { v } else { v & ((1 << (8 * n)) - 1) };
+            let mut enc = Encoder::default();
+            enc.encode_uint(n, v);
+            prop_assert_eq!(enc.len(), n);
+            prop_assert_eq!(enc.as_decoder().decode_uint(n), Some(v));
+        }
+
+        #[test]
+        fn decode_arbitrary(data in vec(any::<u8>(), 0..64), ops in vec(0..6_u8, 0..16)) {
+            // Decoding never reads past the end of the input or panics.
+            let mut dec = Decoder::from(&data[..]);
+            for op in ops {
+                let before = dec.remaining();
+                match op {
+                    0 => { let _ = dec.decode_varint(); }
+                    1 => { let _ = dec.decode_vvec(); }
+                    2 => { let _ = dec.decode_vec(2); }
+                    3 => { let _ = dec.decode_uint(3); }
+                    4 => { let _ = dec.decode_byte(); }
+                    _ => { let _ = dec.decode(5); }
+                }
+                prop_assert!(dec.remaining() <= before);
+                prop_assert_eq!(dec.offset() + dec.remaining(), data.len());
+            }
+        }
+    }
 }
@@ -4,7 +4,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::cmp::min;
+use std::cmp::{max, min};
 use std::mem;
 
 use crate::codec::Decoder;
@@ -95,6 +95,34 @@ impl IncrementalDecoderBuffer {
             None
         }
     }
+
+    /// Like `consume`, but `read` writes directly into the buffer, which avoids copying
+    /// through a temporary buffer. `read` is given room for at most `limit` bytes and returns
+    /// the number of bytes it wrote. The buffer grows as data arrives, so a peer that
+    /// announces a long value but doesn't send it can't make this allocate the whole length.
+    /// # Errors
+    /// Any error that `read` returns; the buffer is unchanged in that case.
+    pub fn read_with<E>(
+        &mut self,
+        limit: usize,
+        read: impl FnOnce(&mut [u8]) -> Result<usize, E>,
+    ) -> Result<Option<Vec<u8>>, E> {
+        let start = self.v.len();
+        let room = min(self.remaining, limit);
+        // Grow geometrically, but never beyond the final length.
+        self.v.reserve_exact(min(self.remaining, max(room, start)));
+        self.v.resize(start + room, 0);
+        let res = read(&mut self.v[start..]);
+        let amount = min(*res.as_ref().unwrap_or(&0), room);
+        self.v.truncate(start + amount);
+        res?;
+        self.remaining -= amount;
+        if self.remaining == 0 {
+            Ok(Some(mem::replace(&mut self.v, Vec::new())))
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -227,6 +255,45 @@ mod tests {
         assert_eq!(dec.remaining(), enc.len());
     }
 
+    #[test]
+    fn buffer_read_with() {
+        let b = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        let mut dec = IncrementalDecoderBuffer::new(b.len());
+        let mut offset = 0;
+        let mut res = None;
+        while res.is_none() {
+            res = dec
+                .read_with(3, |buf| -> Result<usize, ()> {
+                    assert!(buf.len() <= 3);
+                    buf.copy_from_slice(&b[offset..offset + buf.len()]);
+                    offset += buf.len();
+                    Ok(buf.len())
+                })
+                .unwrap();
+        }
+        assert_eq!(offset, b.len());
+        assert_eq!(res.unwrap(), b);
+        assert_eq!(dec.min_remaining(), 0);
+    }
+
+    #[test]
+    fn buffer_read_with_short() {
+        let mut dec = IncrementalDecoderBuffer::new(1 << 30);
+        // Nothing read, then an error; neither leaves data in the buffer.
+        assert_eq!(
+            dec.read_with(100, |_| -> Result<usize, ()> { Ok(0) }),
+            Ok(None)
+        );
+        assert_eq!(dec.read_with(100, |_| Err(())), Err(()));
+        assert_eq!(dec.min_remaining(), 1 << 30);
+        let res = dec.read_with(100, |buf| -> Result<usize, ()> {
+            buf[0] = 7;
+            Ok(1)
+        });
+        assert_eq!(res, Ok(None));
+        assert_eq!(dec.min_remaining(), (1 << 30) - 1);
+    }
+
     #[test]
     fn ignore() {
         let db = Encoder::from_hex("12345678ff");
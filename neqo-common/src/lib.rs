@@ -7,15 +7,17 @@
 #![cfg_attr(feature = "deny-warnings", deny(warnings))]
 #![warn(clippy::pedantic)]
 
+pub mod clock;
 mod codec;
 mod datagram;
 pub mod event;
 mod incrdecoder;
 pub mod log;
+pub mod metrics;
 pub mod qlog;
 pub mod timer;
 
-pub use self::codec::{Decoder, Encoder};
+pub use self::codec::{Decoder, Encoder, Reservation, MAX_VARINT};
 pub use self::datagram::Datagram;
 pub use self::incrdecoder::{
     IncrementalDecoderBuffer, IncrementalDecoderIgnore, IncrementalDecoderUint,
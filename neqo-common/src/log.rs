@@ -4,23 +4,46 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Display;
 use std::io::Write;
-use std::sync::Once;
-use std::time::Instant;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, Once, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use ::log::{Level, Log, Record};
 
 #[macro_export]
 macro_rules! do_log {
-    (target: $target:expr, $lvl:expr, $($arg:tt)+) => ({
+    (target: $target:expr, $lvl:expr, $($arg:tt)+) => (
+        $crate::__do_log!(target: $target, $lvl, false, $($arg)+)
+    );
+    ($lvl:expr, $($arg:tt)+) => ($crate::do_log!(target: ::log::__log_module_path!(), $lvl, $($arg)+))
+}
+
+/// Log a line. If `explicit` is set, the line already names its context and the context of
+/// the current thread is not added.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __do_log {
+    (target: $target:expr, $lvl:expr, $explicit:expr, $($arg:tt)+) => ({
         let lvl = $lvl;
-        if lvl <= ::log::max_level() {
-            ::log::__private_api_log(
-                ::log::__log_format_args!($($arg)+),
-                lvl,
-                &($target, ::log::__log_module_path!(), ::log::__log_file!(), ::log::__log_line!()),
+        if $crate::log::enabled(lvl) {
+            $crate::log::dispatch(
+                &::log::Record::builder()
+                    .args(::log::__log_format_args!($($arg)+))
+                    .level(lvl)
+                    .target($target)
+                    .module_path(Some(::log::__log_module_path!()))
+                    .file(Some(::log::__log_file!()))
+                    .line(Some(::log::__log_line!()))
+                    .build(),
+                $explicit,
             );
         }
     });
-    ($lvl:expr, $($arg:tt)+) => ($crate::do_log!(target: ::log::__log_module_path!(), $lvl, $($arg)+))
 }
 
 #[macro_export]
@@ -38,8 +61,21 @@ use env_logger::Builder;
 
 static INIT_ONCE: Once = Once::new();
 
+/// The number of lines that are logged for each rate limited category in each second.
+pub const DEFAULT_RATE_LIMIT: usize = 100;
+const RATE_LIMIT_PERIOD: Duration = Duration::from_secs(1);
+
+static SINK_INSTALLED: AtomicBool = AtomicBool::new(false);
+static RATE_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_RATE_LIMIT);
+
 lazy_static! {
     static ref START_TIME: Instant = Instant::now();
+    static ref SINK: RwLock<Option<Box<dyn Log>>> = RwLock::new(None);
+    static ref RATE_STATE: Mutex<HashMap<&'static str, RateState>> = Mutex::new(HashMap::new());
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Option<Rc<dyn Display>>> = RefCell::new(None);
 }
 
 pub fn init() {
@@ -64,35 +100,190 @@ pub fn init() {
     });
 }
 
+/// Send log lines to `sink` instead of the global logger. The sink decides which lines it
+/// takes with `Log::enabled`.
+pub fn set_sink(sink: Box<dyn Log>) {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) = Some(sink);
+    SINK_INSTALLED.store(true, Ordering::Release);
+}
+
+/// Go back to logging with the global logger.
+pub fn clear_sink() {
+    SINK_INSTALLED.store(false, Ordering::Release);
+    if let Some(sink) = SINK.write().unwrap_or_else(PoisonError::into_inner).take() {
+        sink.flush();
+    }
+}
+
+/// Whether a line at `lvl` might be logged.
+#[must_use]
+pub fn enabled(lvl: Level) -> bool {
+    lvl <= ::log::max_level() || SINK_INSTALLED.load(Ordering::Acquire)
+}
+
+/// Log `record`, tagged with the context of the current thread unless `explicit` is set.
+/// This is used by the logging macros.
+#[doc(hidden)]
+pub fn dispatch(record: &Record, explicit: bool) {
+    let context = if explicit {
+        None
+    } else {
+        CONTEXT.with(|c| c.borrow().clone())
+    };
+    if let Some(context) = context {
+        emit(
+            &Record::builder()
+                .args(format_args!("[{}] {}", context, record.args()))
+                .level(record.level())
+                .target(record.target())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    } else {
+        emit(record);
+    }
+}
+
+fn emit(record: &Record) {
+    if SINK_INSTALLED.load(Ordering::Acquire) {
+        if let Some(sink) = SINK.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+            if sink.enabled(record.metadata()) {
+                sink.log(record);
+            }
+            return;
+        }
+    }
+    if record.level() <= ::log::max_level() {
+        ::log::logger().log(record);
+    }
+}
+
+/// Restores the previous logging context when dropped, see `enter_context`.
+#[must_use]
+pub struct ContextGuard {
+    previous: Option<Rc<dyn Display>>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CONTEXT.with(|c| *c.borrow_mut() = previous);
+    }
+}
+
+/// Tag the log lines of the current thread with `context`, e.g. the role and the connection
+/// ID of a connection, until the returned guard is dropped. Lines that name a context
+/// explicitly, like `qinfo!([self], ...)`, are not tagged. `context` is only formatted when a
+/// line is logged, so this is cheap enough to call for every packet.
+pub fn enter_context(context: Rc<dyn Display>) -> ContextGuard {
+    init();
+    ContextGuard {
+        previous: CONTEXT.with(|c| c.replace(Some(context))),
+    }
+}
+
+#[derive(Debug)]
+struct RateState {
+    start: Instant,
+    count: usize,
+    suppressed: usize,
+}
+
+/// Set how many lines are logged for each rate limited category in each second. Zero turns
+/// rate limiting off.
+pub fn set_rate_limit(lines_per_second: usize) {
+    RATE_LIMIT.store(lines_per_second, Ordering::Relaxed);
+}
+
+/// Whether another line in `category` can be logged. This is used by the logging macros for
+/// lines that are rate limited, e.g. `qinfo!(limit: "garbage", ...)`. Once a category has
+/// exceeded the limit, its lines are dropped until the end of the second, after which the
+/// number of dropped lines is logged.
+pub fn rate_limit_allows(category: &'static str) -> bool {
+    let limit = RATE_LIMIT.load(Ordering::Relaxed);
+    if limit == 0 {
+        return true;
+    }
+    let now = Instant::now();
+    let suppressed = {
+        let mut states = RATE_STATE.lock().unwrap_or_else(PoisonError::into_inner);
+        let state = states.entry(category).or_insert(RateState {
+            start: now,
+            count: 0,
+            suppressed: 0,
+        });
+        let mut suppressed = 0;
+        if now.duration_since(state.start) >= RATE_LIMIT_PERIOD {
+            suppressed = state.suppressed;
+            *state = RateState {
+                start: now,
+                count: 0,
+                suppressed: 0,
+            };
+        }
+        if state.count >= limit {
+            state.suppressed += 1;
+            return false;
+        }
+        state.count += 1;
+        suppressed
+    };
+    if suppressed > 0 {
+        crate::do_log!(
+            Level::Warn,
+            "Dropped {} log lines in category {}",
+            suppressed,
+            category
+        );
+    }
+    true
+}
+
+#[macro_export]
+macro_rules! log_limited {
+    ($lvl:expr, $category:expr, $($log:tt)+) => {{
+        ::neqo_common::log::init();
+        if ::neqo_common::log::enabled($lvl) && ::neqo_common::log::rate_limit_allows($category) {
+            $($log)+;
+        }
+    }};
+}
 #[macro_export]
 macro_rules! log_invoke {
     ($lvl:expr, $ctx:expr, $($arg:tt)*) => ( {
         ::neqo_common::log::init();
-        ::neqo_common::do_log!($lvl, "[{}] {}", $ctx, format!($($arg)*));
+        ::neqo_common::__do_log!(target: ::log::__log_module_path!(), $lvl, true, "[{}] {}", $ctx, format!($($arg)*));
     } )
 }
 #[macro_export]
 macro_rules! qerror {
+    (limit: $category:expr, $($arg:tt)*) => (::neqo_common::log_limited!(::log::Level::Error, $category, ::neqo_common::qerror!($($arg)*)););
     ([$ctx:expr], $($arg:tt)*) => (::neqo_common::log_invoke!(::log::Level::Error, $ctx, $($arg)*););
     ($($arg:tt)*) => ( { ::neqo_common::log::init(); ::neqo_common::do_log!(::log::Level::Error, $($arg)*); } );
 }
 #[macro_export]
 macro_rules! qwarn {
+    (limit: $category:expr, $($arg:tt)*) => (::neqo_common::log_limited!(::log::Level::Warn, $category, ::neqo_common::qwarn!($($arg)*)););
     ([$ctx:expr], $($arg:tt)*) => (::neqo_common::log_invoke!(::log::Level::Warn, $ctx, $($arg)*););
     ($($arg:tt)*) => ( { ::neqo_common::log::init(); ::neqo_common::do_log!(::log::Level::Warn, $($arg)*); } );
 }
 #[macro_export]
 macro_rules! qinfo {
+    (limit: $category:expr, $($arg:tt)*) => (::neqo_common::log_limited!(::log::Level::Info, $category, ::neqo_common::qinfo!($($arg)*)););
     ([$ctx:expr], $($arg:tt)*) => (::neqo_common::log_invoke!(::log::Level::Info, $ctx, $($arg)*););
     ($($arg:tt)*) => ( { ::neqo_common::log::init(); ::neqo_common::do_log!(::log::Level::Info, $($arg)*); } );
 }
 #[macro_export]
 macro_rules! qdebug {
+    (limit: $category:expr, $($arg:tt)*) => (::neqo_common::log_limited!(::log::Level::Debug, $category, ::neqo_common::qdebug!($($arg)*)););
     ([$ctx:expr], $($arg:tt)*) => (::neqo_common::log_invoke!(::log::Level::Debug, $ctx, $($arg)*););
     ($($arg:tt)*) => ( { ::neqo_common::log::init(); ::neqo_common::do_log!(::log::Level::Debug, $($arg)*); } );
 }
 #[macro_export]
 macro_rules! qtrace {
+    (limit: $category:expr, $($arg:tt)*) => (::neqo_common::log_limited!(::log::Level::Trace, $category, ::neqo_common::qtrace!($($arg)*)););
     ([$ctx:expr], $($arg:tt)*) => (::neqo_common::log_invoke!(::log::Level::Trace, $ctx, $($arg)*););
     ($($arg:tt)*) => ( { ::neqo_common::log::init(); ::neqo_common::do_log!(::log::Level::Trace, $($arg)*); } );
 }
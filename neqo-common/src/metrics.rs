@@ -0,0 +1,86 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Hooks for collecting metrics in an embedder, e.g. for Prometheus.
+
+use std::fmt::Debug;
+use std::rc::Rc;
+
+/// The number of packets that have been sent.
+pub const PACKETS_SENT: &str = "quic.packets_sent";
+/// The number of packets that have been declared lost.
+pub const PACKETS_LOST: &str = "quic.packets_lost";
+/// The smoothed round trip time in seconds, updated when an ACK is received.
+pub const RTT: &str = "quic.rtt";
+/// The congestion window in bytes, updated when an ACK is received.
+pub const CWND: &str = "quic.cwnd";
+/// The time in seconds from sending or receiving the first Initial until the handshake is
+/// complete.
+pub const HANDSHAKE_DURATION: &str = "quic.handshake_duration";
+/// The number of header fields that are encoded as a reference to the QPACK static table.
+pub const QPACK_STATIC_TABLE_HITS: &str = "qpack.static_table_hits";
+/// The number of header fields that are encoded as a reference to an existing entry in the
+/// QPACK dynamic table.
+pub const QPACK_DYNAMIC_TABLE_HITS: &str = "qpack.dynamic_table_hits";
+/// The number of entries that have been inserted into the QPACK dynamic table.
+pub const QPACK_DYNAMIC_TABLE_INSERTS: &str = "qpack.dynamic_table_inserts";
+
+/// Receives measurements. The names are the constants in this module. All methods do
+/// nothing by default, so an implementation only needs to handle what it collects.
+pub trait Metrics: Debug {
+    /// Add `value` to the counter `name`.
+    fn counter_inc(&self, _name: &'static str, _value: u64) {}
+    /// Set the gauge `name` to `value`.
+    fn gauge_set(&self, _name: &'static str, _value: f64) {}
+    /// Add an observation of `value` to the histogram `name`.
+    fn histogram_observe(&self, _name: &'static str, _value: f64) {}
+}
+
+/// A `Metrics` that drops all measurements. This is what is used unless metrics are set.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoMetrics;
+
+impl Metrics for NoMetrics {}
+
+pub type MetricsRef = Rc<dyn Metrics>;
+
+#[must_use]
+pub fn no_metrics() -> MetricsRef {
+    Rc::new(NoMetrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{no_metrics, Metrics, MetricsRef, PACKETS_SENT};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[derive(Debug, Default)]
+    struct Counter {
+        packets_sent: Cell<u64>,
+    }
+
+    impl Metrics for Counter {
+        fn counter_inc(&self, name: &'static str, value: u64) {
+            if name == PACKETS_SENT {
+                self.packets_sent.set(self.packets_sent.get() + value);
+            }
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let counter = Rc::new(Counter::default());
+        let metrics: MetricsRef = Rc::clone(&counter) as _;
+        metrics.counter_inc(PACKETS_SENT, 2);
+        metrics.counter_inc("other", 1);
+        metrics.gauge_set(PACKETS_SENT, 1.0);
+        metrics.histogram_observe(PACKETS_SENT, 1.0);
+        assert_eq!(counter.packets_sent.get(), 2);
+
+        no_metrics().counter_inc(PACKETS_SENT, 1);
+    }
+}
@@ -4,17 +4,28 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+// The qlog backend of neqo: the trace that every log starts with and the sinks that a log
+// can be written to. Events are serialized as they are added by the `QlogStreamer` of the
+// qlog crate, and its event definitions are the ones that neqo-transport and neqo-http3
+// share; both are re-exported here so that they come from one place.
+//
+// TODO: a serializer of neqo's own, so that events do not have to be built as `qlog::Event`
+// values first, and event and field definitions for what the qlog crate does not cover.
+// Both are left for a follow-up request.
+
 use std::cell::RefCell;
 use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::time::SystemTime;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Instant, SystemTime};
 
 use chrono::{DateTime, Utc};
-use qlog::{
-    self, CommonFields, Configuration, QlogStreamer, TimeUnits, Trace, VantagePoint,
-    VantagePointType,
-};
+use qlog::{self, CommonFields, Configuration, TimeUnits, Trace, VantagePoint, VantagePointType};
+
+pub use qlog::{event::Event, QlogStreamer};
 
 use crate::Role;
 
@@ -25,7 +36,8 @@ pub struct NeqoQlog {
 }
 
 pub struct NeqoQlogShared {
-    qlog_path: PathBuf,
+    // The file the log is written to, if it is not written to memory.
+    qlog_path: Option<PathBuf>,
     streamer: QlogStreamer,
 }
 
@@ -43,11 +55,46 @@ impl NeqoQlog {
         Ok(Self {
             inner: Rc::new(RefCell::new(Some(NeqoQlogShared {
                 streamer,
-                qlog_path: qlog_path.as_ref().to_owned(),
+                qlog_path: Some(qlog_path.as_ref().to_owned()),
             }))),
         })
     }
 
+    /// Create an enabled `NeqoQlog` that writes the trace of `role` to `file`, which has been
+    /// opened at `qlog_path`. The output is buffered.
+    /// # Errors
+    ///
+    /// Will return `qlog::Error` if cannot write to the new log.
+    pub fn enabled_with_file(
+        file: File,
+        qlog_path: impl AsRef<Path>,
+        role: Role,
+    ) -> Result<Self, qlog::Error> {
+        Self::enabled(
+            new_streamer(role, Box::new(BufWriter::new(file))),
+            qlog_path,
+        )
+    }
+
+    /// Create an enabled `NeqoQlog` that writes the trace of `role` to memory, e.g. for tests.
+    /// The log can be read from the returned `QlogBuffer`; it is complete once the `NeqoQlog`
+    /// and all its clones have been dropped.
+    /// # Errors
+    ///
+    /// Will return `qlog::Error` if cannot write to the new log.
+    pub fn enabled_in_memory(role: Role) -> Result<(Self, QlogBuffer), qlog::Error> {
+        let buffer = QlogBuffer::default();
+        let mut streamer = new_streamer(role, Box::new(buffer.clone()));
+        streamer.start_log()?;
+        let qlog = Self {
+            inner: Rc::new(RefCell::new(Some(NeqoQlogShared {
+                streamer,
+                qlog_path: None,
+            }))),
+        };
+        Ok((qlog, buffer))
+    }
+
     /// Create a disabled `NeqoQlog` configuration.
     #[must_use]
     pub fn disabled() -> Self {
@@ -96,7 +143,11 @@ impl Default for NeqoQlog {
 
 impl fmt::Debug for NeqoQlogShared {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "NeqoQlog writing to {}", self.qlog_path.display())
+        if let Some(qlog_path) = &self.qlog_path {
+            write!(f, "NeqoQlog writing to {}", qlog_path.display())
+        } else {
+            write!(f, "NeqoQlog writing to memory")
+        }
     }
 }
 
@@ -108,6 +159,50 @@ impl Drop for NeqoQlogShared {
     }
 }
 
+/// An in-memory sink for a qlog. Clones share the same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct QlogBuffer {
+    buf: Arc<Mutex<Vec<u8>>>,
+}
+
+impl QlogBuffer {
+    /// The log that has been written so far.
+    #[must_use]
+    pub fn contents(&self) -> String {
+        let buf = self.buf.lock().unwrap_or_else(PoisonError::into_inner);
+        String::from_utf8_lossy(&buf).into_owned()
+    }
+}
+
+impl Write for QlogBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Create a `QlogStreamer` that writes the trace of `role` to `writer`. Event times are
+/// relative to the time this is called.
+#[must_use]
+pub fn new_streamer(role: Role, writer: Box<dyn Write + Send>) -> QlogStreamer {
+    QlogStreamer::new(
+        qlog::QLOG_VERSION.to_string(),
+        Some(format!("neqo-{} qlog", role)),
+        None,
+        None,
+        Instant::now(),
+        new_trace(role),
+        writer,
+    )
+}
+
 #[must_use]
 pub fn new_trace(role: Role) -> qlog::Trace {
     Trace {
@@ -144,3 +239,34 @@ pub fn new_trace(role: Role) -> qlog::Trace {
         events: Vec::new(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, NeqoQlog};
+    use crate::Role;
+    use qlog::H3StreamType;
+
+    #[test]
+    fn in_memory() {
+        let (mut qlog, buffer) = NeqoQlog::enabled_in_memory(Role::Client).unwrap();
+        assert!(buffer.contents().contains("\"title\":\"neqo-Client qlog\""));
+        qlog.add_event(|| {
+            Some(Event::h3_stream_type_set_min(
+                "0".to_string(),
+                H3StreamType::Control,
+            ))
+        });
+        assert!(buffer.contents().contains("\"stream_id\":\"0\""));
+        assert!(!buffer.contents().ends_with("]}]}"));
+
+        // The log is complete once it has been dropped.
+        drop(qlog);
+        assert!(buffer.contents().ends_with("]}]}"));
+    }
+
+    #[test]
+    fn disabled() {
+        let mut qlog = NeqoQlog::disabled();
+        qlog.add_event(|| unreachable!());
+    }
+}
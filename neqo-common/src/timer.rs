@@ -236,9 +236,100 @@ impl<T> Timer<T> {
     }
 }
 
+/// An ordered queue of a few deadlines that are identified by a key, e.g. the timers of a
+/// connection. Setting a deadline for a key replaces the previous one. Unlike `Timer`, this has
+/// no limit on how far apart the deadlines are, but each operation is O(N).
+#[derive(Debug, Clone)]
+pub struct TimerQueue<K> {
+    // Sorted by time; deadlines with the same time keep the order in which they were set.
+    items: Vec<(Instant, K)>,
+}
+
+impl<K: PartialEq> TimerQueue<K> {
+    /// Set the deadline for `key`, replacing any earlier deadline for it.
+    pub fn insert(&mut self, key: K, time: Instant) {
+        self.cancel(&key);
+        let ins = self
+            .items
+            .iter()
+            .position(|(t, _)| *t > time)
+            .unwrap_or(self.items.len());
+        self.items.insert(ins, (time, key));
+    }
+
+    /// Set the deadline for `key` to `time`, or remove it if `time` is `None`. The queue is
+    /// only changed if the deadline is different.
+    pub fn set(&mut self, key: K, time: Option<Instant>) {
+        if self.get(&key) == time {
+            return;
+        }
+        match time {
+            Some(t) => self.insert(key, t),
+            None => {
+                self.cancel(&key);
+            }
+        }
+    }
+
+    /// Remove the deadline for `key`. Returns the time it was set to.
+    pub fn cancel(&mut self, key: &K) -> Option<Instant> {
+        let i = self.items.iter().position(|(_, k)| k == key)?;
+        Some(self.items.remove(i).0)
+    }
+
+    /// The deadline that is set for `key`.
+    #[must_use]
+    pub fn get(&self, key: &K) -> Option<Instant> {
+        self.items.iter().find(|(_, k)| k == key).map(|(t, _)| *t)
+    }
+
+    /// The time of the earliest deadline.
+    #[must_use]
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.items.first().map(|(t, _)| *t)
+    }
+
+    /// The key of the earliest deadline.
+    #[must_use]
+    pub fn next_key(&self) -> Option<&K> {
+        self.items.first().map(|(_, k)| k)
+    }
+
+    /// Remove and return the earliest deadline, unless it is after `now`.
+    pub fn pop_expired(&mut self, now: Instant) -> Option<(K, Instant)> {
+        if self.next_expiry()? > now {
+            return None;
+        }
+        let (time, key) = self.items.remove(0);
+        Some((key, time))
+    }
+
+    /// Remove all deadlines. This keeps the allocated memory, so that a queue can be refilled
+    /// cheaply.
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+}
+
+impl<K> Default for TimerQueue<K> {
+    fn default() -> Self {
+        Self { items: Vec::new() }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{Duration, Instant, Timer};
+    use super::{Duration, Instant, Timer, TimerQueue};
     use lazy_static::lazy_static;
 
     lazy_static! {
@@ -383,4 +474,66 @@ mod test {
 
         assert_eq!(None, t.remove(too_far_future, |candidate| *candidate == v));
     }
+
+    #[test]
+    fn queue_order() {
+        let mut q = TimerQueue::default();
+        assert_eq!(q.next_expiry(), None);
+        for (i, time) in TIMES.iter().enumerate() {
+            q.insert(i, *NOW + *time);
+        }
+        assert_eq!(q.len(), TIMES.len());
+        assert_eq!(q.next_expiry(), Some(*NOW + Duration::from_millis(3)));
+        assert_eq!(q.next_key(), Some(&3));
+
+        // Deadlines with the same time are returned in the order in which they were set.
+        let until = *NOW + Duration::from_millis(40);
+        let mut expired = Vec::new();
+        while let Some((k, _)) = q.pop_expired(until) {
+            expired.push(k);
+        }
+        assert_eq!(expired, vec![3, 2, 4, 0, 5]);
+        assert_eq!(q.pop_expired(until), None);
+        assert_eq!(q.next_key(), Some(&1));
+    }
+
+    #[test]
+    fn queue_replace_and_cancel() {
+        let mut q = TimerQueue::default();
+        q.insert("idle", *NOW + Duration::from_secs(30));
+        q.insert("ack", *NOW + Duration::from_millis(25));
+        assert_eq!(q.next_key(), Some(&"ack"));
+
+        // A new deadline replaces the old one.
+        q.insert("ack", *NOW + Duration::from_secs(60));
+        assert_eq!(q.len(), 2);
+        assert_eq!(q.next_key(), Some(&"idle"));
+        assert_eq!(q.get(&"ack"), Some(*NOW + Duration::from_secs(60)));
+
+        assert_eq!(q.cancel(&"idle"), Some(*NOW + Duration::from_secs(30)));
+        assert_eq!(q.cancel(&"idle"), None);
+        assert_eq!(q.next_expiry(), Some(*NOW + Duration::from_secs(60)));
+        q.clear();
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn queue_set() {
+        let mut q = TimerQueue::default();
+        q.set("idle", Some(*NOW + Duration::from_secs(30)));
+        q.set("ack", Some(*NOW + Duration::from_secs(30)));
+        assert_eq!(q.next_key(), Some(&"idle"));
+
+        // Setting the same deadline again does not move it behind others with the same time.
+        q.set("idle", Some(*NOW + Duration::from_secs(30)));
+        assert_eq!(q.next_key(), Some(&"idle"));
+
+        q.set("ack", Some(*NOW + Duration::from_millis(25)));
+        assert_eq!(q.next_key(), Some(&"ack"));
+        q.set("ack", None);
+        assert_eq!(q.len(), 1);
+        assert_eq!(q.get(&"ack"), None);
+        q.set("ack", None);
+        assert_eq!(q.len(), 1);
+    }
 }
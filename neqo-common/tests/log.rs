@@ -7,7 +7,10 @@
 #![cfg_attr(feature = "deny-warnings", deny(warnings))]
 #![warn(clippy::use_self)]
 
-use neqo_common::{qdebug, qerror, qinfo, qtrace, qwarn};
+use ::log::{Level, Log, Metadata, Record};
+use neqo_common::{log, qdebug, qerror, qinfo, qtrace, qwarn};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
 #[test]
 fn basic() {
@@ -59,3 +62,83 @@ fn context_expr() {
     qdebug!([format!("{:x?}", context)], "debug");
     qtrace!([format!("{:x?}", context)], "trace");
 }
+
+#[test]
+fn limit() {
+    for i in 0..3 {
+        qerror!(limit: "test-limit", "error {}", i);
+        qwarn!(limit: "test-limit", "warn {}", i);
+        qinfo!(limit: "test-limit", ["context"], "info {}", i);
+        qdebug!(limit: "test-limit", "debug");
+        qtrace!(limit: "test-limit", ["context"], "trace");
+    }
+}
+
+#[derive(Default)]
+struct Sink {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl Log for Sink {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info
+    }
+
+    fn log(&self, record: &Record) {
+        self.lines.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+// The sink is global, so everything that uses it is in one test. Other tests might log to the
+// sink at the same time, so only lines that are logged here are checked.
+#[test]
+fn sink() {
+    let sink = Sink::default();
+    let lines = Arc::clone(&sink.lines);
+    log::set_sink(Box::new(sink));
+    let count = |prefix: &str| {
+        lines
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|l| l.starts_with(prefix))
+            .count()
+    };
+
+    qinfo!("sink info");
+    qdebug!("sink debug");
+    assert_eq!(count("sink info"), 1);
+    assert_eq!(count("sink debug"), 0);
+
+    {
+        let _context = log::enter_context(Rc::new("conn"));
+        qinfo!("sink with context");
+        qinfo!(["explicit"], "sink with explicit context");
+        {
+            let _inner = log::enter_context(Rc::new("inner"));
+            qinfo!("sink with inner context");
+        }
+        qinfo!("sink with outer context");
+    }
+    qinfo!("sink without context");
+    assert_eq!(count("[conn] sink with context"), 1);
+    assert_eq!(count("[explicit] sink with explicit context"), 1);
+    assert_eq!(count("[inner] sink with inner context"), 1);
+    assert_eq!(count("[conn] sink with outer context"), 1);
+    assert_eq!(count("sink without context"), 1);
+
+    for i in 0..log::DEFAULT_RATE_LIMIT * 2 {
+        qwarn!(limit: "test-sink", "sink limited {}", i);
+    }
+    // Lines are dropped once the limit is reached, unless a new one second period started
+    // while the loop ran, in which case some more lines get through.
+    let limited = count("sink limited");
+    assert!(limited >= log::DEFAULT_RATE_LIMIT);
+    assert!(limited <= log::DEFAULT_RATE_LIMIT * 2);
+
+    log::clear_sink();
+    qinfo!("sink cleared");
+    assert_eq!(count("sink cleared"), 0);
+}
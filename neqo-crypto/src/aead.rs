@@ -49,6 +49,8 @@ experimental_api!(SSL_AeadDecrypt(
 experimental_api!(SSL_DestroyAead(ctx: *mut SSLAeadContext));
 scoped_ptr!(AeadContext, SSLAeadContext, SSL_DestroyAead);
 
+/// An AEAD instance, keyed using the QUIC/TLS `key` and `iv` labels that
+/// are derived from a traffic secret.
 pub struct Aead {
     ctx: AeadContext,
 }
@@ -63,6 +65,7 @@ impl Aead {
         unsafe { Self::from_raw(version, cipher, s, prefix) }
     }
 
+    /// The number of octets that encryption adds to a plaintext.
     #[must_use]
     #[allow(clippy::unused_self)]
     pub fn expansion(&self) -> usize {
@@ -93,7 +96,7 @@ impl Aead {
         }
     }
 
-    /// Decrypt a plaintext.
+    /// Encrypt a plaintext.
     ///
     /// The space provided in `output` needs to be larger than `input` by
     /// the value provided in `Aead::expansion`.
@@ -44,7 +44,8 @@ impl HpKey {
     /// QUIC-specific API for extracting a header-protection key.
     ///
     /// # Errors
-    /// Errors if HKDF fails or if the label is too long to fit in a `c_uint`.
+    /// Errors if HKDF fails, if the cipher suite isn't supported, or if the
+    /// label is too long to fit in a `c_uint`.
     pub fn extract(version: Version, cipher: Cipher, prk: &SymKey, label: &str) -> Res<Self> {
         let l = label.as_bytes();
         let mut secret: *mut PK11SymKey = null_mut();
@@ -53,7 +54,7 @@ impl HpKey {
             TLS_AES_128_GCM_SHA256 => (CK_MECHANISM_TYPE::from(CKM_AES_ECB), 16),
             TLS_AES_256_GCM_SHA384 => (CK_MECHANISM_TYPE::from(CKM_AES_ECB), 32),
             TLS_CHACHA20_POLY1305_SHA256 => (CK_MECHANISM_TYPE::from(CKM_NSS_CHACHA20_CTR), 32),
-            _ => unreachable!(),
+            _ => return Err(Error::UnsupportedCipher),
         };
 
         // Note that this doesn't allow for passing null() for the handshake hash.
@@ -35,6 +35,7 @@ pub mod selfencrypt;
 mod ssl;
 mod time;
 
+pub use self::aead::Aead;
 pub use self::agent::{
     Agent, AllowZeroRtt, Client, HandshakeState, Record, RecordList, ResumptionToken, SecretAgent,
     SecretAgentInfo, SecretAgentPreInfo, Server, ZeroRttCheckResult, ZeroRttChecker,
@@ -43,6 +44,7 @@ pub use self::auth::AuthenticationStatus;
 pub use self::constants::*;
 pub use self::err::{Error, PRErrorCode, Res};
 pub use self::ext::{ExtensionHandler, ExtensionHandlerResult, ExtensionWriterResult};
+pub use self::hp::HpKey;
 pub use self::p11::{random, SymKey};
 pub use self::replay::AntiReplay;
 pub use self::secrets::SecretDirection;
@@ -50,6 +52,7 @@ pub use self::ssl::Opt;
 
 use self::once::OnceResult;
 
+use std::env;
 use std::ffi::CString;
 use std::os::raw::c_char;
 use std::path::{Path, PathBuf};
@@ -157,6 +160,13 @@ pub fn init_db<P: Into<PathBuf>>(dir: P) {
     }
 }
 
+/// Have NSS write TLS secrets to `path` in the key log format, which Wireshark can use to
+/// decrypt captured packets. NSS reads `SSLKEYLOGFILE` when the first TLS connection is made,
+/// so this has to be called before then. It only works with an NSS that is built to allow it.
+pub fn set_key_log_file<P: AsRef<Path>>(path: P) {
+    env::set_var("SSLKEYLOGFILE", path.as_ref());
+}
+
 /// Panic if NSS isn't initialized.
 pub fn assert_initialized() {
     unsafe {
@@ -8,11 +8,13 @@ use crate::err::{Error, Res};
 use crate::ssl::PRFileDesc;
 use crate::time::{Interval, PRTime, Time};
 
+use neqo_common::clock::Clock;
+
 use std::convert::{TryFrom, TryInto};
 use std::ops::{Deref, DerefMut};
 use std::os::raw::c_uint;
 use std::ptr::{null_mut, NonNull};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 // This is an opaque struct in NSS.
 #[allow(clippy::empty_enum)]
@@ -50,14 +52,17 @@ impl AntiReplay {
     /// Make a new anti-replay context.
     /// See the documentation in NSS for advice on how to set these values.
     ///
+    /// The context starts at the current time of `clock`, which is `SystemClock` outside
+    /// of tests.
+    ///
     /// # Errors
-    /// Returns an error if `now` is in the past relative to our baseline or
+    /// Returns an error if the current time is in the past relative to our baseline or
     /// NSS is unable to generate an anti-replay context.
-    pub fn new(now: Instant, window: Duration, k: usize, bits: usize) -> Res<Self> {
+    pub fn new(clock: &dyn Clock, window: Duration, k: usize, bits: usize) -> Res<Self> {
         let mut ctx: *mut SSLAntiReplayContext = null_mut();
         unsafe {
             SSL_CreateAntiReplayContext(
-                Time::from(now).try_into()?,
+                Time::from(clock.now()).try_into()?,
                 Interval::from(window).try_into()?,
                 c_uint::try_from(k)?,
                 c_uint::try_from(bits)?,
@@ -40,6 +40,26 @@ impl SelfEncrypt {
         })
     }
 
+    /// Like `new`, but with a key that is supplied rather than generated, so that several
+    /// instances can open what the others seal. The key is the size of the hash function
+    /// of `cipher`: 32 bytes for `TLS_AES_128_GCM_SHA256`.
+    ///
+    /// # Errors
+    /// If the key is the wrong size, or if NSS fails to import it.
+    pub fn with_key(version: Version, cipher: Cipher, key: &[u8]) -> Res<Self> {
+        if key.len() != hkdf::key_size(version, cipher)? {
+            return Err(Error::HkdfError);
+        }
+        let key = hkdf::import_key(version, cipher, key)?;
+        Ok(Self {
+            version,
+            cipher,
+            key_id: 0,
+            key,
+            old_key: None,
+        })
+    }
+
     fn make_aead(&self, k: &SymKey, salt: &[u8]) -> Res<Aead> {
         debug_assert_eq!(salt.len(), Self::SALT_LENGTH);
         let salt = hkdf::import_key(self.version, self.cipher, salt)?;
@@ -38,6 +38,8 @@ impl TimeZero {
     /// `BASE_TIME` are used with these conversion functions, they will fail.
     /// To avoid that, we make sure that this sets the base time using the first value
     /// it sees if it is in the past.  If it is not, then use `Instant::now()` instead.
+    /// This is paired with `PR_Now()`, which NSS reads itself, so it can't come from a
+    /// `Clock`.  Tests control time through the `Instant` values they pass in.
     pub fn baseline(t: Instant) -> Self {
         let now = Instant::now();
         let prnow = unsafe { PR_Now() };
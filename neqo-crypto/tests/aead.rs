@@ -114,3 +114,59 @@ fn aead_encrypt_decrypt() {
     let res = aead.decrypt(1, &scratch[..], ciphertext, plaintext_buf);
     assert!(res.is_err());
 }
+
+/// The server Initial packet from Appendix A.3 of RFC 9001.
+#[test]
+fn rfc9001_server_initial() {
+    const SERVER_INITIAL_SECRET: &[u8] = &[
+        0x3c, 0x19, 0x98, 0x28, 0xfd, 0x13, 0x9e, 0xfd, 0x21, 0x6c, 0x15, 0x5a, 0xd8, 0x44, 0xcc,
+        0x81, 0xfb, 0x82, 0xfa, 0x8d, 0x74, 0x46, 0xfa, 0x7d, 0x78, 0xbe, 0x80, 0x3a, 0xcd, 0xda,
+        0x95, 0x1b,
+    ];
+    const HEADER: &[u8] = &[
+        0xc1, 0x00, 0x00, 0x00, 0x01, 0x00, 0x08, 0xf0, 0x67, 0xa5, 0x50, 0x2a, 0x42, 0x62, 0xb5,
+        0x00, 0x40, 0x75, 0x00, 0x01,
+    ];
+    const PAYLOAD: &[u8] = &[
+        0x02, 0x00, 0x00, 0x00, 0x00, 0x06, 0x00, 0x40, 0x5a, 0x02, 0x00, 0x00, 0x56, 0x03, 0x03,
+        0xee, 0xfc, 0xe7, 0xf7, 0xb3, 0x7b, 0xa1, 0xd1, 0x63, 0x2e, 0x96, 0x67, 0x78, 0x25, 0xdd,
+        0xf7, 0x39, 0x88, 0xcf, 0xc7, 0x98, 0x25, 0xdf, 0x56, 0x6d, 0xc5, 0x43, 0x0b, 0x9a, 0x04,
+        0x5a, 0x12, 0x00, 0x13, 0x01, 0x00, 0x00, 0x2e, 0x00, 0x33, 0x00, 0x24, 0x00, 0x1d, 0x00,
+        0x20, 0x9d, 0x3c, 0x94, 0x0d, 0x89, 0x69, 0x0b, 0x84, 0xd0, 0x8a, 0x60, 0x99, 0x3c, 0x14,
+        0x4e, 0xca, 0x68, 0x4d, 0x10, 0x81, 0x28, 0x7c, 0x83, 0x4d, 0x53, 0x11, 0xbc, 0xf3, 0x2b,
+        0xb9, 0xda, 0x1a, 0x00, 0x2b, 0x00, 0x02, 0x03, 0x04,
+    ];
+    const EXPECTED_CIPHERTEXT: &[u8] = &[
+        0x5a, 0x48, 0x2c, 0xd0, 0x99, 0x1c, 0xd2, 0x5b, 0x0a, 0xac, 0x40, 0x6a, 0x58, 0x16, 0xb6,
+        0x39, 0x41, 0x00, 0xf3, 0x7a, 0x1c, 0x69, 0x79, 0x75, 0x54, 0x78, 0x0b, 0xb3, 0x8c, 0xc5,
+        0xa9, 0x9f, 0x5e, 0xde, 0x4c, 0xf7, 0x3c, 0x3e, 0xc2, 0x49, 0x3a, 0x18, 0x39, 0xb3, 0xdb,
+        0xcb, 0xa3, 0xf6, 0xea, 0x46, 0xc5, 0xb7, 0x68, 0x4d, 0xf3, 0x54, 0x8e, 0x7d, 0xde, 0xb9,
+        0xc3, 0xbf, 0x9c, 0x73, 0xcc, 0x3f, 0x3b, 0xde, 0xd7, 0x4b, 0x56, 0x2b, 0xfb, 0x19, 0xfb,
+        0x84, 0x02, 0x2f, 0x8e, 0xf4, 0xcd, 0xd9, 0x37, 0x95, 0xd7, 0x7d, 0x06, 0xed, 0xbb, 0x7a,
+        0xaf, 0x2f, 0x58, 0x89, 0x18, 0x50, 0xab, 0xbd, 0xca, 0x3d, 0x20, 0x39, 0x8c, 0x27, 0x64,
+        0x56, 0xcb, 0xc4, 0x21, 0x58, 0x40, 0x7d, 0xd0, 0x74, 0xee,
+    ];
+
+    fixture_init();
+    let secret = hkdf::import_key(
+        TLS_VERSION_1_3,
+        TLS_AES_128_GCM_SHA256,
+        SERVER_INITIAL_SECRET,
+    )
+    .expect("make a secret");
+    let aead = Aead::new(TLS_VERSION_1_3, TLS_AES_128_GCM_SHA256, &secret, "quic ")
+        .expect("can make an AEAD");
+
+    let ciphertext_buf = &mut [0; 1024];
+    let ciphertext = aead
+        .encrypt(1, HEADER, PAYLOAD, ciphertext_buf)
+        .expect("encrypt should work");
+    assert_eq!(ciphertext, EXPECTED_CIPHERTEXT);
+    assert_eq!(ciphertext.len(), PAYLOAD.len() + aead.expansion());
+
+    let plaintext_buf = &mut [0; 1024];
+    let plaintext = aead
+        .decrypt(1, HEADER, ciphertext, plaintext_buf)
+        .expect("decrypt should also work");
+    assert_eq!(plaintext, PAYLOAD);
+}
@@ -151,3 +151,48 @@ fn expand_label_sha384() {
         ],
     );
 }
+
+/// The Initial secrets from Appendix A.1 of RFC 9001.
+#[test]
+fn quic_initial_secrets() {
+    const INITIAL_SALT: &[u8] = &[
+        0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c,
+        0xad, 0xcc, 0xbb, 0x7f, 0x0a,
+    ];
+    const DCID: &[u8] = &[0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
+    const INITIAL_SECRET: &[u8] = &[
+        0x7d, 0xb5, 0xdf, 0x06, 0xe7, 0xa6, 0x9e, 0x43, 0x24, 0x96, 0xad, 0xed, 0xb0, 0x08, 0x51,
+        0x92, 0x35, 0x95, 0x22, 0x15, 0x96, 0xae, 0x2a, 0xe9, 0xfb, 0x81, 0x15, 0xc1, 0xe9, 0xed,
+        0x0a, 0x44,
+    ];
+    const CLIENT_INITIAL_SECRET: &[u8] = &[
+        0xc0, 0x0c, 0xf1, 0x51, 0xca, 0x5b, 0xe0, 0x75, 0xed, 0x0e, 0xbf, 0xb5, 0xc8, 0x03, 0x23,
+        0xc4, 0x2d, 0x6b, 0x7d, 0xb6, 0x78, 0x81, 0x28, 0x9a, 0xf4, 0x00, 0x8f, 0x1f, 0x6c, 0x35,
+        0x7a, 0xea,
+    ];
+    const SERVER_INITIAL_SECRET: &[u8] = &[
+        0x3c, 0x19, 0x98, 0x28, 0xfd, 0x13, 0x9e, 0xfd, 0x21, 0x6c, 0x15, 0x5a, 0xd8, 0x44, 0xcc,
+        0x81, 0xfb, 0x82, 0xfa, 0x8d, 0x74, 0x46, 0xfa, 0x7d, 0x78, 0xbe, 0x80, 0x3a, 0xcd, 0xda,
+        0x95, 0x1b,
+    ];
+
+    fixture_init();
+    let cipher = TLS_AES_128_GCM_SHA256;
+    let salt = hkdf::import_key(TLS_VERSION_1_3, cipher, INITIAL_SALT).expect("import salt");
+    let ikm = hkdf::import_key(TLS_VERSION_1_3, cipher, DCID).expect("import DCID");
+    let initial = hkdf::extract(TLS_VERSION_1_3, cipher, Some(&salt), &ikm)
+        .expect("HKDF Extract should work");
+    assert_eq!(
+        initial.as_bytes().expect("key should have bytes"),
+        INITIAL_SECRET
+    );
+
+    for (label, expected) in &[
+        ("client in", CLIENT_INITIAL_SECRET),
+        ("server in", SERVER_INITIAL_SECRET),
+    ] {
+        let secret = hkdf::expand_label(TLS_VERSION_1_3, cipher, &initial, &[], label)
+            .expect("HKDF-Expand-Label should work");
+        assert_eq!(secret.as_bytes().expect("key should have bytes"), *expected);
+    }
+}
@@ -42,6 +42,56 @@ fn aes256() {
     assert_eq!(mask, EXPECTED);
 }
 
+/// Header protection for the Initial packets in Appendix A of RFC 9001.
+#[test]
+fn rfc9001_initial() {
+    const CLIENT_INITIAL_SECRET: &[u8] = &[
+        0xc0, 0x0c, 0xf1, 0x51, 0xca, 0x5b, 0xe0, 0x75, 0xed, 0x0e, 0xbf, 0xb5, 0xc8, 0x03, 0x23,
+        0xc4, 0x2d, 0x6b, 0x7d, 0xb6, 0x78, 0x81, 0x28, 0x9a, 0xf4, 0x00, 0x8f, 0x1f, 0x6c, 0x35,
+        0x7a, 0xea,
+    ];
+    const CLIENT_SAMPLE: &[u8] = &[
+        0xd1, 0xb1, 0xc9, 0x8d, 0xd7, 0x68, 0x9f, 0xb8, 0xec, 0x11, 0xd2, 0x42, 0xb1, 0x23, 0xdc,
+        0x9b,
+    ];
+    const CLIENT_MASK: &[u8] = &[0x43, 0x7b, 0x9a, 0xec, 0x36];
+    const SERVER_INITIAL_SECRET: &[u8] = &[
+        0x3c, 0x19, 0x98, 0x28, 0xfd, 0x13, 0x9e, 0xfd, 0x21, 0x6c, 0x15, 0x5a, 0xd8, 0x44, 0xcc,
+        0x81, 0xfb, 0x82, 0xfa, 0x8d, 0x74, 0x46, 0xfa, 0x7d, 0x78, 0xbe, 0x80, 0x3a, 0xcd, 0xda,
+        0x95, 0x1b,
+    ];
+    const SERVER_SAMPLE: &[u8] = &[
+        0x2c, 0xd0, 0x99, 0x1c, 0xd2, 0x5b, 0x0a, 0xac, 0x40, 0x6a, 0x58, 0x16, 0xb6, 0x39, 0x41,
+        0x00,
+    ];
+    const SERVER_MASK: &[u8] = &[0x2e, 0xc0, 0xd8, 0x35, 0x6a];
+
+    fixture_init();
+    for (secret, sample, expected) in &[
+        (CLIENT_INITIAL_SECRET, CLIENT_SAMPLE, CLIENT_MASK),
+        (SERVER_INITIAL_SECRET, SERVER_SAMPLE, SERVER_MASK),
+    ] {
+        let secret = hkdf::import_key(TLS_VERSION_1_3, TLS_AES_128_GCM_SHA256, secret)
+            .expect("import secret");
+        let hp = HpKey::extract(TLS_VERSION_1_3, TLS_AES_128_GCM_SHA256, &secret, "quic hp")
+            .expect("extract label works");
+        assert_eq!(hp.sample_size(), sample.len());
+        let mask = hp.mask(sample).expect("should produce a mask");
+        // Only the first five octets of the mask are used.
+        assert_eq!(&mask[..expected.len()], *expected);
+    }
+}
+
+#[test]
+fn unsupported_cipher() {
+    fixture_init();
+    let ikm =
+        hkdf::import_key(TLS_VERSION_1_3, TLS_AES_128_GCM_SHA256, &[0; 16]).expect("import IKM");
+    let prk =
+        hkdf::extract(TLS_VERSION_1_3, TLS_AES_128_GCM_SHA256, None, &ikm).expect("extract works");
+    assert!(HpKey::extract(TLS_VERSION_1_3, 0x1305, &prk, "hp").is_err());
+}
+
 #[cfg(feature = "chacha")]
 #[test]
 fn chacha20_ctr() {
@@ -27,6 +27,24 @@ fn seal_open() {
     assert_eq!(&opened[..], PLAINTEXT);
 }
 
+#[test]
+fn seal_open_shared_key() {
+    init();
+    let key = [7; 32];
+    let se1 = SelfEncrypt::with_key(TLS_VERSION_1_3, TLS_AES_128_GCM_SHA256, &key).unwrap();
+    let se2 = SelfEncrypt::with_key(TLS_VERSION_1_3, TLS_AES_128_GCM_SHA256, &key).unwrap();
+    let sealed = se1.seal(AAD, PLAINTEXT).expect("sealing works");
+    let opened = se2.open(AAD, &sealed).expect("opening works");
+    assert_eq!(&opened[..], PLAINTEXT);
+}
+
+#[test]
+fn shared_key_wrong_size() {
+    init();
+    let res = SelfEncrypt::with_key(TLS_VERSION_1_3, TLS_AES_128_GCM_SHA256, &[7; 16]);
+    assert_eq!(res.unwrap_err(), Error::HkdfError);
+}
+
 #[test]
 fn seal_rotate_open() {
     let (mut se, sealed) = sealed();
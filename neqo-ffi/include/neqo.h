@@ -0,0 +1,637 @@
+#ifndef NEQO_H
+#define NEQO_H
+
+/* This file is generated by cbindgen from neqo-ffi; do not edit it. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum NeqoHttp3State {
+  NeqoHttp3State_Initializing,
+  NeqoHttp3State_ZeroRtt,
+  NeqoHttp3State_Connected,
+  NeqoHttp3State_GoingAway,
+  NeqoHttp3State_Closing,
+  NeqoHttp3State_Closed,
+} NeqoHttp3State;
+
+/**
+ * What to do after processing.
+ */
+typedef enum NeqoOutputKind {
+  /**
+   * Nothing, until a datagram arrives or something is sent.
+   */
+  NeqoOutputKind_None,
+  /**
+   * Send the datagram in `data` and `len` to the peer, then process again.
+   */
+  NeqoOutputKind_Datagram,
+  /**
+   * Process again after `timeout_ms` milliseconds, unless a datagram arrives first.
+   */
+  NeqoOutputKind_Timer,
+} NeqoOutputKind;
+
+typedef enum NeqoState {
+  NeqoState_Init,
+  NeqoState_WaitInitial,
+  NeqoState_Handshaking,
+  NeqoState_Connected,
+  NeqoState_Confirmed,
+  NeqoState_Closing,
+  NeqoState_Draining,
+  NeqoState_Closed,
+} NeqoState;
+
+/**
+ * The outcome of a call.
+ */
+typedef enum NeqoStatus {
+  NeqoStatus_Ok,
+  /**
+   * An argument was null, not UTF-8, or otherwise unusable.
+   */
+  NeqoStatus_InvalidArgument,
+  /**
+   * The stream doesn't exist, or not in the direction that was asked for.
+   */
+  NeqoStatus_InvalidStream,
+  /**
+   * This can't be done yet, for instance because the peer's stream limit was reached.
+   */
+  NeqoStatus_WouldBlock,
+  /**
+   * The connection is closing or closed.
+   */
+  NeqoStatus_Closed,
+  /**
+   * Any other failure.
+   */
+  NeqoStatus_Error,
+} NeqoStatus;
+
+typedef struct NeqoConnection NeqoConnection;
+
+typedef struct NeqoHttp3Client NeqoHttp3Client;
+
+/**
+ * Settings for a new client. Pass null to `neqo_http3_client_new` for the defaults.
+ */
+typedef struct NeqoHttp3Parameters {
+  /**
+   * The size of the QPACK dynamic table that the server can use; 16384 by default.
+   */
+  uint64_t max_table_size_decoder;
+  /**
+   * The largest QPACK dynamic table that the client uses; 16384 by default.
+   */
+  uint64_t max_table_size_encoder;
+  /**
+   * How many streams can wait for QPACK updates; 10 by default.
+   */
+  uint16_t max_blocked_streams;
+} NeqoHttp3Parameters;
+
+typedef struct NeqoOutput {
+  NeqoOutputKind kind;
+  const uint8_t *data;
+  uintptr_t len;
+  uint64_t timeout_ms;
+} NeqoOutput;
+
+/**
+ * A header field. The name and value are not NUL-terminated.
+ */
+typedef struct NeqoHeader {
+  const uint8_t *name;
+  uintptr_t name_len;
+  const uint8_t *value;
+  uintptr_t value_len;
+} NeqoHeader;
+
+/**
+ * Why a connection closed. `application` says whether `code` is an application error
+ * code or a QUIC transport error code.
+ */
+typedef struct NeqoCloseError {
+  bool application;
+  uint64_t code;
+} NeqoCloseError;
+
+/**
+ * An event on an HTTP/3 client. Header fields and the data of `ResumptionToken` stay
+ * valid until the next call on the client.
+ */
+typedef enum NeqoHttp3Event_Tag {
+  /**
+   * There are no more events.
+   */
+  NeqoHttp3Event_None,
+  /**
+   * The response header fields have arrived. `interim` is set for 1xx responses.
+   */
+  NeqoHttp3Event_HeaderReady,
+  /**
+   * More of the request body can be sent.
+   */
+  NeqoHttp3Event_DataWritable,
+  /**
+   * Some of the response body can be read with `neqo_http3_client_read_response_data`.
+   */
+  NeqoHttp3Event_DataReadable,
+  /**
+   * Trailers have arrived, which ends the response.
+   */
+  NeqoHttp3Event_TrailersReady,
+  /**
+   * The response was abandoned; `local` is set when that was this end's doing.
+   */
+  NeqoHttp3Event_Reset,
+  /**
+   * The server doesn't want the rest of the request.
+   */
+  NeqoHttp3Event_StopSending,
+  /**
+   * More requests can be made.
+   */
+  NeqoHttp3Event_RequestsCreatable,
+  /**
+   * Check the server certificate, then call `neqo_http3_client_authenticated`.
+   */
+  NeqoHttp3Event_AuthenticationNeeded,
+  /**
+   * A token that can be passed to `neqo_http3_client_enable_resumption` later.
+   */
+  NeqoHttp3Event_ResumptionToken,
+  /**
+   * The server didn't take the requests that were sent in 0-RTT.
+   */
+  NeqoHttp3Event_ZeroRttRejected,
+  /**
+   * The server won't take new requests on this connection.
+   */
+  NeqoHttp3Event_GoawayReceived,
+  /**
+   * `error` is only set when the state is closing or closed.
+   */
+  NeqoHttp3Event_StateChange,
+} NeqoHttp3Event_Tag;
+
+typedef struct NeqoHttp3Event_HeaderReady_Body {
+  uint64_t stream_id;
+  const NeqoHeader *headers;
+  uintptr_t headers_len;
+  bool interim;
+  bool fin;
+} NeqoHttp3Event_HeaderReady_Body;
+
+typedef struct NeqoHttp3Event_DataWritable_Body {
+  uint64_t stream_id;
+} NeqoHttp3Event_DataWritable_Body;
+
+typedef struct NeqoHttp3Event_DataReadable_Body {
+  uint64_t stream_id;
+} NeqoHttp3Event_DataReadable_Body;
+
+typedef struct NeqoHttp3Event_TrailersReady_Body {
+  uint64_t stream_id;
+  const NeqoHeader *headers;
+  uintptr_t headers_len;
+} NeqoHttp3Event_TrailersReady_Body;
+
+typedef struct NeqoHttp3Event_Reset_Body {
+  uint64_t stream_id;
+  uint64_t error;
+  bool local;
+} NeqoHttp3Event_Reset_Body;
+
+typedef struct NeqoHttp3Event_StopSending_Body {
+  uint64_t stream_id;
+  uint64_t error;
+} NeqoHttp3Event_StopSending_Body;
+
+typedef struct NeqoHttp3Event_ResumptionToken_Body {
+  const uint8_t *data;
+  uintptr_t len;
+} NeqoHttp3Event_ResumptionToken_Body;
+
+typedef struct NeqoHttp3Event_StateChange_Body {
+  NeqoHttp3State state;
+  NeqoCloseError error;
+} NeqoHttp3Event_StateChange_Body;
+
+typedef struct NeqoHttp3Event {
+  NeqoHttp3Event_Tag tag;
+  union {
+    NeqoHttp3Event_HeaderReady_Body header_ready;
+    NeqoHttp3Event_DataWritable_Body data_writable;
+    NeqoHttp3Event_DataReadable_Body data_readable;
+    NeqoHttp3Event_TrailersReady_Body trailers_ready;
+    NeqoHttp3Event_Reset_Body reset;
+    NeqoHttp3Event_StopSending_Body stop_sending;
+    NeqoHttp3Event_ResumptionToken_Body resumption_token;
+    NeqoHttp3Event_StateChange_Body state_change;
+  };
+} NeqoHttp3Event;
+
+/**
+ * An event on a connection. The data of `ResumptionToken` and `Datagram` stays valid
+ * until the next call on the connection.
+ */
+typedef enum NeqoConnectionEvent_Tag {
+  /**
+   * There are no more events.
+   */
+  NeqoConnectionEvent_None,
+  /**
+   * Check the server certificate, then call `neqo_connection_authenticated`.
+   */
+  NeqoConnectionEvent_AuthenticationNeeded,
+  NeqoConnectionEvent_NewStream,
+  NeqoConnectionEvent_SendStreamWritable,
+  NeqoConnectionEvent_RecvStreamReadable,
+  NeqoConnectionEvent_RecvStreamReset,
+  NeqoConnectionEvent_SendStreamStopSending,
+  NeqoConnectionEvent_SendStreamComplete,
+  NeqoConnectionEvent_SendStreamCreatable,
+  /**
+   * `error` is only set when the state is closing, draining or closed.
+   */
+  NeqoConnectionEvent_StateChange,
+  NeqoConnectionEvent_ZeroRttRejected,
+  /**
+   * A token that can be passed to `neqo_connection_enable_resumption` later.
+   */
+  NeqoConnectionEvent_ResumptionToken,
+  NeqoConnectionEvent_Datagram,
+} NeqoConnectionEvent_Tag;
+
+typedef struct NeqoConnectionEvent_NewStream_Body {
+  uint64_t stream_id;
+} NeqoConnectionEvent_NewStream_Body;
+
+typedef struct NeqoConnectionEvent_SendStreamWritable_Body {
+  uint64_t stream_id;
+} NeqoConnectionEvent_SendStreamWritable_Body;
+
+typedef struct NeqoConnectionEvent_RecvStreamReadable_Body {
+  uint64_t stream_id;
+} NeqoConnectionEvent_RecvStreamReadable_Body;
+
+typedef struct NeqoConnectionEvent_RecvStreamReset_Body {
+  uint64_t stream_id;
+  uint64_t error;
+} NeqoConnectionEvent_RecvStreamReset_Body;
+
+typedef struct NeqoConnectionEvent_SendStreamStopSending_Body {
+  uint64_t stream_id;
+  uint64_t error;
+} NeqoConnectionEvent_SendStreamStopSending_Body;
+
+typedef struct NeqoConnectionEvent_SendStreamComplete_Body {
+  uint64_t stream_id;
+} NeqoConnectionEvent_SendStreamComplete_Body;
+
+typedef struct NeqoConnectionEvent_SendStreamCreatable_Body {
+  bool bidi;
+} NeqoConnectionEvent_SendStreamCreatable_Body;
+
+typedef struct NeqoConnectionEvent_StateChange_Body {
+  NeqoState state;
+  NeqoCloseError error;
+} NeqoConnectionEvent_StateChange_Body;
+
+typedef struct NeqoConnectionEvent_ResumptionToken_Body {
+  const uint8_t *data;
+  uintptr_t len;
+} NeqoConnectionEvent_ResumptionToken_Body;
+
+typedef struct NeqoConnectionEvent_Datagram_Body {
+  const uint8_t *data;
+  uintptr_t len;
+} NeqoConnectionEvent_Datagram_Body;
+
+typedef struct NeqoConnectionEvent {
+  NeqoConnectionEvent_Tag tag;
+  union {
+    NeqoConnectionEvent_NewStream_Body new_stream;
+    NeqoConnectionEvent_SendStreamWritable_Body send_stream_writable;
+    NeqoConnectionEvent_RecvStreamReadable_Body recv_stream_readable;
+    NeqoConnectionEvent_RecvStreamReset_Body recv_stream_reset;
+    NeqoConnectionEvent_SendStreamStopSending_Body send_stream_stop_sending;
+    NeqoConnectionEvent_SendStreamComplete_Body send_stream_complete;
+    NeqoConnectionEvent_SendStreamCreatable_Body send_stream_creatable;
+    NeqoConnectionEvent_StateChange_Body state_change;
+    NeqoConnectionEvent_ResumptionToken_Body resumption_token;
+    NeqoConnectionEvent_Datagram_Body datagram;
+  };
+} NeqoConnectionEvent;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Initialize NSS. With a null `db_dir`, NSS runs without a database. Call this once,
+ * before anything else.
+ *
+ * # Safety
+ * `db_dir` is null or a NUL-terminated string.
+ */
+NeqoStatus neqo_init(const char *db_dir);
+
+/**
+ * Create an HTTP/3 client for `server_name`. The addresses are written like
+ * "192.0.2.1:443" or "[2001:db8::1]:443". `params` can be null.
+ *
+ * # Safety
+ * The strings are NUL-terminated, `params` is null or points to a
+ * `NeqoHttp3Parameters`, and `client` points to where the client is put.
+ */
+NeqoStatus neqo_http3_client_new(const char *server_name,
+                                 const char *local_addr,
+                                 const char *remote_addr,
+                                 const NeqoHttp3Parameters *params,
+                                 NeqoHttp3Client **client);
+
+/**
+ * # Safety
+ * `client` is null or was made by `neqo_http3_client_new` and not freed yet.
+ */
+void neqo_http3_client_free(NeqoHttp3Client *client);
+
+/**
+ * Resume a session with a token from a `ResumptionToken` event, so that requests can
+ * be sent in 0-RTT. Do this before the first call to `neqo_http3_client_process_output`.
+ *
+ * # Safety
+ * `client` is a live client and `token` points to `len` bytes.
+ */
+NeqoStatus neqo_http3_client_enable_resumption(NeqoHttp3Client *client,
+                                               const uint8_t *token,
+                                               uintptr_t len);
+
+/**
+ * Report whether the server certificate is good: `error` is 0 if it is, or else the
+ * NSS or mozilla::pkix error code.
+ *
+ * # Safety
+ * `client` is a live client.
+ */
+NeqoStatus neqo_http3_client_authenticated(NeqoHttp3Client *client, int32_t error);
+
+/**
+ * Process a datagram that was received from the server.
+ *
+ * # Safety
+ * `client` is a live client and `data` points to `len` bytes.
+ */
+NeqoStatus neqo_http3_client_process_input(NeqoHttp3Client *client,
+                                           const uint8_t *data,
+                                           uintptr_t len);
+
+/**
+ * Find out what to do next: send a datagram, or wait. Call this until it asks for a
+ * wait, and again whenever the timer fires, a datagram arrives, or a request is made.
+ *
+ * # Safety
+ * `client` is a live client and `output` points to a `NeqoOutput`.
+ */
+NeqoStatus neqo_http3_client_process_output(NeqoHttp3Client *client, NeqoOutput *output);
+
+/**
+ * Close the connection with an HTTP/3 error code. `reason` can be null.
+ *
+ * # Safety
+ * `client` is a live client and `reason` is null or a NUL-terminated string.
+ */
+NeqoStatus neqo_http3_client_close(NeqoHttp3Client *client, uint64_t error, const char *reason);
+
+/**
+ * Take the next event. `event` is set to `None` when there are no more.
+ *
+ * # Safety
+ * `client` is a live client and `event` points to a `NeqoHttp3Event`.
+ */
+NeqoStatus neqo_http3_client_next_event(NeqoHttp3Client *client, NeqoHttp3Event *event);
+
+/**
+ * Start a request. The `headers` are sent after the pseudo-header fields that are made
+ * from `method`, `scheme`, `host` and `path`. Send a body with
+ * `neqo_http3_client_send_request_body`, then end the request with
+ * `neqo_http3_client_stream_close_send`.
+ *
+ * # Safety
+ * `client` is a live client, the strings are NUL-terminated, `headers` points to
+ * `headers_len` header fields, and `stream_id` points to where the stream ID is put.
+ */
+NeqoStatus neqo_http3_client_fetch(NeqoHttp3Client *client,
+                                   const char *method,
+                                   const char *scheme,
+                                   const char *host,
+                                   const char *path,
+                                   const NeqoHeader *headers,
+                                   uintptr_t headers_len,
+                                   uint64_t *stream_id);
+
+/**
+ * Send as much of a request body as flow control allows; `written` is set to how much
+ * that was. Wait for `DataWritable` before sending more.
+ *
+ * # Safety
+ * `client` is a live client, `data` points to `len` bytes, and `written` is
+ * writable.
+ */
+NeqoStatus neqo_http3_client_send_request_body(NeqoHttp3Client *client,
+                                               uint64_t stream_id,
+                                               const uint8_t *data,
+                                               uintptr_t len,
+                                               uintptr_t *written);
+
+/**
+ * Send trailers, which ends the request.
+ *
+ * # Safety
+ * `client` is a live client and `headers` points to `headers_len` header fields.
+ */
+NeqoStatus neqo_http3_client_send_request_trailers(NeqoHttp3Client *client,
+                                                   uint64_t stream_id,
+                                                   const NeqoHeader *headers,
+                                                   uintptr_t headers_len);
+
+/**
+ * End a request.
+ *
+ * # Safety
+ * `client` is a live client.
+ */
+NeqoStatus neqo_http3_client_stream_close_send(NeqoHttp3Client *client, uint64_t stream_id);
+
+/**
+ * Abandon a request and its response.
+ *
+ * # Safety
+ * `client` is a live client.
+ */
+NeqoStatus neqo_http3_client_cancel_request(NeqoHttp3Client *client,
+                                            uint64_t stream_id,
+                                            uint64_t error);
+
+/**
+ * Read the response body into `buf`. `read` is set to how many bytes were read, and
+ * `fin` to whether the response has ended.
+ *
+ * # Safety
+ * `client` is a live client, `buf` points to `len` writable bytes, and `read` and `fin`
+ * are writable.
+ */
+NeqoStatus neqo_http3_client_read_response_data(NeqoHttp3Client *client,
+                                                uint64_t stream_id,
+                                                uint8_t *buf,
+                                                uintptr_t len,
+                                                uintptr_t *read,
+                                                bool *fin);
+
+/**
+ * Create a client connection to `server_name`. `alpn` is a comma-separated list of
+ * application protocols, and the addresses are written like "192.0.2.1:443" or
+ * "[2001:db8::1]:443".
+ *
+ * # Safety
+ * The strings are NUL-terminated and `conn` points to where the connection is put.
+ */
+NeqoStatus neqo_connection_new_client(const char *server_name,
+                                      const char *alpn,
+                                      const char *local_addr,
+                                      const char *remote_addr,
+                                      NeqoConnection **conn);
+
+/**
+ * # Safety
+ * `conn` is null or was made by `neqo_connection_new_client` and not freed yet.
+ */
+void neqo_connection_free(NeqoConnection *conn);
+
+/**
+ * Resume a session with a token from a `ResumptionToken` event. Do this before the
+ * first call to `neqo_connection_process_output`.
+ *
+ * # Safety
+ * `conn` is a live connection and `token` points to `len` bytes.
+ */
+NeqoStatus neqo_connection_enable_resumption(NeqoConnection *conn,
+                                             const uint8_t *token,
+                                             uintptr_t len);
+
+/**
+ * Report whether the server certificate is good: `error` is 0 if it is, or else the
+ * NSS or mozilla::pkix error code.
+ *
+ * # Safety
+ * `conn` is a live connection.
+ */
+NeqoStatus neqo_connection_authenticated(NeqoConnection *conn, int32_t error);
+
+/**
+ * Process a datagram that was received from the peer.
+ *
+ * # Safety
+ * `conn` is a live connection and `data` points to `len` bytes.
+ */
+NeqoStatus neqo_connection_process_input(NeqoConnection *conn, const uint8_t *data, uintptr_t len);
+
+/**
+ * Find out what to do next: send a datagram, or wait. Call this until it asks for a
+ * wait, and again whenever the timer fires, a datagram arrives, or something is sent.
+ *
+ * # Safety
+ * `conn` is a live connection and `output` points to a `NeqoOutput`.
+ */
+NeqoStatus neqo_connection_process_output(NeqoConnection *conn, NeqoOutput *output);
+
+/**
+ * Close the connection with an application error code. `reason` can be null.
+ *
+ * # Safety
+ * `conn` is a live connection and `reason` is null or a NUL-terminated string.
+ */
+NeqoStatus neqo_connection_close(NeqoConnection *conn, uint64_t error, const char *reason);
+
+/**
+ * Take the next event. `event` is set to `None` when there are no more.
+ *
+ * # Safety
+ * `conn` is a live connection and `event` points to a `NeqoConnectionEvent`.
+ */
+NeqoStatus neqo_connection_next_event(NeqoConnection *conn, NeqoConnectionEvent *event);
+
+/**
+ * # Safety
+ * `conn` is a live connection and `stream_id` points to where the new ID is put.
+ */
+NeqoStatus neqo_connection_stream_create(NeqoConnection *conn, bool bidi, uint64_t *stream_id);
+
+/**
+ * Send as much of `data` as flow control allows; `written` is set to how much that was.
+ *
+ * # Safety
+ * `conn` is a live connection, `data` points to `len` bytes, and `written` is
+ * writable.
+ */
+NeqoStatus neqo_connection_stream_send(NeqoConnection *conn,
+                                       uint64_t stream_id,
+                                       const uint8_t *data,
+                                       uintptr_t len,
+                                       uintptr_t *written);
+
+/**
+ * End the sending side of a stream once what has been sent is delivered.
+ *
+ * # Safety
+ * `conn` is a live connection.
+ */
+NeqoStatus neqo_connection_stream_close_send(NeqoConnection *conn, uint64_t stream_id);
+
+/**
+ * Abandon the sending side of a stream.
+ *
+ * # Safety
+ * `conn` is a live connection.
+ */
+NeqoStatus neqo_connection_stream_reset_send(NeqoConnection *conn,
+                                             uint64_t stream_id,
+                                             uint64_t error);
+
+/**
+ * Read from a stream into `buf`. `read` is set to how many bytes were read, and `fin`
+ * to whether the stream has ended.
+ *
+ * # Safety
+ * `conn` is a live connection, `buf` points to `len` writable bytes, and `read` and
+ * `fin` are writable.
+ */
+NeqoStatus neqo_connection_stream_recv(NeqoConnection *conn,
+                                       uint64_t stream_id,
+                                       uint8_t *buf,
+                                       uintptr_t len,
+                                       uintptr_t *read,
+                                       bool *fin);
+
+/**
+ * Ask the peer to stop sending on a stream.
+ *
+ * # Safety
+ * `conn` is a live connection.
+ */
+NeqoStatus neqo_connection_stream_stop_sending(NeqoConnection *conn,
+                                               uint64_t stream_id,
+                                               uint64_t error);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* NEQO_H */
@@ -0,0 +1,531 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Bindings for an HTTP/3 client. Server push, WebTransport and HTTP datagrams are not
+// offered through these bindings, so they are turned off.
+
+use neqo_common::event::Provider;
+use neqo_http3::{Header, Http3Client, Http3ClientEvent, Http3Parameters, Http3State};
+use neqo_qpack::QpackSettings;
+use neqo_transport::{CongestionControlAlgorithm, FixedConnectionIdManager, QuicVersion};
+
+use std::cell::RefCell;
+use std::os::raw::c_char;
+use std::rc::Rc;
+use std::str;
+use std::time::Instant;
+
+use crate::{
+    free_arg, obj_arg, set_out, slice_arg, slice_arg_mut, status, str_arg, Addresses,
+    NeqoCloseError, NeqoOutput, NeqoStatus,
+};
+
+pub struct NeqoHttp3Client {
+    client: Http3Client,
+    addrs: Addresses,
+    /// The last datagram that was handed out.
+    out: Vec<u8>,
+    /// The header fields of the last event that was handed out.
+    headers: Vec<Header>,
+    header_refs: Vec<NeqoHeader>,
+    /// The data of the last event that was handed out.
+    event_data: Vec<u8>,
+}
+
+/// Settings for a new client. Pass null to `neqo_http3_client_new` for the defaults.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct NeqoHttp3Parameters {
+    /// The size of the QPACK dynamic table that the server can use; 16384 by default.
+    pub max_table_size_decoder: u64,
+    /// The largest QPACK dynamic table that the client uses; 16384 by default.
+    pub max_table_size_encoder: u64,
+    /// How many streams can wait for QPACK updates; 10 by default.
+    pub max_blocked_streams: u16,
+}
+
+impl Default for NeqoHttp3Parameters {
+    fn default() -> Self {
+        Self {
+            max_table_size_decoder: 16384,
+            max_table_size_encoder: 16384,
+            max_blocked_streams: 10,
+        }
+    }
+}
+
+/// A header field. The name and value are not NUL-terminated.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct NeqoHeader {
+    pub name: *const u8,
+    pub name_len: usize,
+    pub value: *const u8,
+    pub value_len: usize,
+}
+
+unsafe fn headers_arg(headers: *const NeqoHeader, len: usize) -> Result<Vec<Header>, NeqoStatus> {
+    let field = |p: *const u8, len: usize| {
+        str::from_utf8(slice_arg(p, len)?)
+            .map(String::from)
+            .map_err(|_| NeqoStatus::InvalidArgument)
+    };
+    let mut fields = Vec::with_capacity(len);
+    for h in slice_arg(headers, len)? {
+        fields.push((field(h.name, h.name_len)?, field(h.value, h.value_len)?));
+    }
+    Ok(fields)
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NeqoHttp3State {
+    Initializing,
+    ZeroRtt,
+    Connected,
+    GoingAway,
+    Closing,
+    Closed,
+}
+
+impl NeqoHttp3State {
+    fn new(state: &Http3State) -> (Self, NeqoCloseError) {
+        match state {
+            Http3State::Initializing => (Self::Initializing, NeqoCloseError::default()),
+            Http3State::ZeroRtt => (Self::ZeroRtt, NeqoCloseError::default()),
+            Http3State::Connected => (Self::Connected, NeqoCloseError::default()),
+            Http3State::GoingAway(_) => (Self::GoingAway, NeqoCloseError::default()),
+            Http3State::Closing(error) => (Self::Closing, (*error).into()),
+            Http3State::Closed(error) => (Self::Closed, (*error).into()),
+        }
+    }
+}
+
+/// An event on an HTTP/3 client. Header fields and the data of `ResumptionToken` stay
+/// valid until the next call on the client.
+#[repr(C)]
+#[derive(Debug)]
+pub enum NeqoHttp3Event {
+    /// There are no more events.
+    None,
+    /// The response header fields have arrived. `interim` is set for 1xx responses.
+    HeaderReady {
+        stream_id: u64,
+        headers: *const NeqoHeader,
+        headers_len: usize,
+        interim: bool,
+        fin: bool,
+    },
+    /// More of the request body can be sent.
+    DataWritable { stream_id: u64 },
+    /// Some of the response body can be read with `neqo_http3_client_read_response_data`.
+    DataReadable { stream_id: u64 },
+    /// Trailers have arrived, which ends the response.
+    TrailersReady {
+        stream_id: u64,
+        headers: *const NeqoHeader,
+        headers_len: usize,
+    },
+    /// The response was abandoned; `local` is set when that was this end's doing.
+    Reset {
+        stream_id: u64,
+        error: u64,
+        local: bool,
+    },
+    /// The server doesn't want the rest of the request.
+    StopSending { stream_id: u64, error: u64 },
+    /// More requests can be made.
+    RequestsCreatable,
+    /// Check the server certificate, then call `neqo_http3_client_authenticated`.
+    AuthenticationNeeded,
+    /// A token that can be passed to `neqo_http3_client_enable_resumption` later.
+    ResumptionToken { data: *const u8, len: usize },
+    /// The server didn't take the requests that were sent in 0-RTT.
+    ZeroRttRejected,
+    /// The server won't take new requests on this connection.
+    GoawayReceived,
+    /// `error` is only set when the state is closing or closed.
+    StateChange {
+        state: NeqoHttp3State,
+        error: NeqoCloseError,
+    },
+}
+
+impl NeqoHttp3Client {
+    /// Keep `headers` until the next event, returning where they are.
+    fn keep_headers(&mut self, headers: Vec<Header>) -> (*const NeqoHeader, usize) {
+        self.headers = headers;
+        self.header_refs = self
+            .headers
+            .iter()
+            .map(|(n, v)| NeqoHeader {
+                name: n.as_ptr(),
+                name_len: n.len(),
+                value: v.as_ptr(),
+                value_len: v.len(),
+            })
+            .collect();
+        (self.header_refs.as_ptr(), self.header_refs.len())
+    }
+
+    /// Describe an event, or `None` for those that these bindings don't offer.
+    fn event(&mut self, event: Http3ClientEvent) -> Option<NeqoHttp3Event> {
+        Some(match event {
+            Http3ClientEvent::HeaderReady {
+                stream_id,
+                headers,
+                interim,
+                fin,
+            } => {
+                let (headers, headers_len) = self.keep_headers(headers);
+                NeqoHttp3Event::HeaderReady {
+                    stream_id,
+                    headers,
+                    headers_len,
+                    interim,
+                    fin,
+                }
+            }
+            Http3ClientEvent::DataWritable { stream_id } => {
+                NeqoHttp3Event::DataWritable { stream_id }
+            }
+            Http3ClientEvent::DataReadable { stream_id } => {
+                NeqoHttp3Event::DataReadable { stream_id }
+            }
+            Http3ClientEvent::TrailersReady {
+                stream_id,
+                trailers,
+            } => {
+                let (headers, headers_len) = self.keep_headers(trailers);
+                NeqoHttp3Event::TrailersReady {
+                    stream_id,
+                    headers,
+                    headers_len,
+                }
+            }
+            Http3ClientEvent::Reset {
+                stream_id,
+                error,
+                local,
+            } => NeqoHttp3Event::Reset {
+                stream_id,
+                error,
+                local,
+            },
+            Http3ClientEvent::StopSending { stream_id, error } => {
+                NeqoHttp3Event::StopSending { stream_id, error }
+            }
+            Http3ClientEvent::RequestsCreatable => NeqoHttp3Event::RequestsCreatable,
+            Http3ClientEvent::AuthenticationNeeded => NeqoHttp3Event::AuthenticationNeeded,
+            Http3ClientEvent::ResumptionToken(token) => {
+                self.event_data = token.as_ref().to_vec();
+                NeqoHttp3Event::ResumptionToken {
+                    data: self.event_data.as_ptr(),
+                    len: self.event_data.len(),
+                }
+            }
+            Http3ClientEvent::ZeroRttRejected => NeqoHttp3Event::ZeroRttRejected,
+            Http3ClientEvent::GoawayReceived => NeqoHttp3Event::GoawayReceived,
+            Http3ClientEvent::StateChange(state) => {
+                let (state, error) = NeqoHttp3State::new(&state);
+                NeqoHttp3Event::StateChange { state, error }
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// Create an HTTP/3 client for `server_name`. The addresses are written like
+/// "192.0.2.1:443" or "[2001:db8::1]:443". `params` can be null.
+///
+/// # Safety
+/// The strings are NUL-terminated, `params` is null or points to a
+/// `NeqoHttp3Parameters`, and `client` points to where the client is put.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_new(
+    server_name: *const c_char,
+    local_addr: *const c_char,
+    remote_addr: *const c_char,
+    params: *const NeqoHttp3Parameters,
+    client: *mut *mut NeqoHttp3Client,
+) -> NeqoStatus {
+    status(|| {
+        let client = obj_arg(client)?;
+        let addrs = Addresses::new(local_addr, remote_addr)?;
+        let params = params.as_ref().copied().unwrap_or_default();
+        let c = Http3Client::new(
+            str_arg(server_name)?,
+            Rc::new(RefCell::new(FixedConnectionIdManager::new(0))),
+            addrs.local,
+            addrs.remote,
+            &CongestionControlAlgorithm::NewReno,
+            QuicVersion::default(),
+            &Http3Parameters::new(
+                QpackSettings {
+                    max_table_size_encoder: params.max_table_size_encoder,
+                    max_table_size_decoder: params.max_table_size_decoder,
+                    max_blocked_streams: params.max_blocked_streams,
+                },
+                0,
+            ),
+        )?;
+        let c = Box::new(NeqoHttp3Client {
+            client: c,
+            addrs,
+            out: Vec::new(),
+            headers: Vec::new(),
+            header_refs: Vec::new(),
+            event_data: Vec::new(),
+        });
+        *client = Box::into_raw(c);
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `client` is null or was made by `neqo_http3_client_new` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_free(client: *mut NeqoHttp3Client) {
+    free_arg(client);
+}
+
+/// Resume a session with a token from a `ResumptionToken` event, so that requests can
+/// be sent in 0-RTT. Do this before the first call to `neqo_http3_client_process_output`.
+///
+/// # Safety
+/// `client` is a live client and `token` points to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_enable_resumption(
+    client: *mut NeqoHttp3Client,
+    token: *const u8,
+    len: usize,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(client)?;
+        Ok(c.client
+            .enable_resumption(Instant::now(), slice_arg(token, len)?)?)
+    })
+}
+
+/// Report whether the server certificate is good: `error` is 0 if it is, or else the
+/// NSS or mozilla::pkix error code.
+///
+/// # Safety
+/// `client` is a live client.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_authenticated(
+    client: *mut NeqoHttp3Client,
+    error: i32,
+) -> NeqoStatus {
+    status(|| obj_arg(client).map(|c| c.client.authenticated(error.into(), Instant::now())))
+}
+
+/// Process a datagram that was received from the server.
+///
+/// # Safety
+/// `client` is a live client and `data` points to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_process_input(
+    client: *mut NeqoHttp3Client,
+    data: *const u8,
+    len: usize,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(client)?;
+        let d = c.addrs.datagram(slice_arg(data, len)?);
+        c.client.process_input(d, Instant::now());
+        Ok(())
+    })
+}
+
+/// Find out what to do next: send a datagram, or wait. Call this until it asks for a
+/// wait, and again whenever the timer fires, a datagram arrives, or a request is made.
+///
+/// # Safety
+/// `client` is a live client and `output` points to a `NeqoOutput`.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_process_output(
+    client: *mut NeqoHttp3Client,
+    output: *mut NeqoOutput,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(client)?;
+        let out = c.client.process_output(Instant::now());
+        set_out(output, NeqoOutput::new(out, &mut c.out))
+    })
+}
+
+/// Close the connection with an HTTP/3 error code. `reason` can be null.
+///
+/// # Safety
+/// `client` is a live client and `reason` is null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_close(
+    client: *mut NeqoHttp3Client,
+    error: u64,
+    reason: *const c_char,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(client)?;
+        let reason = if reason.is_null() {
+            ""
+        } else {
+            str_arg(reason)?
+        };
+        c.client.close(Instant::now(), error, reason);
+        Ok(())
+    })
+}
+
+/// Take the next event. `event` is set to `None` when there are no more.
+///
+/// # Safety
+/// `client` is a live client and `event` points to a `NeqoHttp3Event`.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_next_event(
+    client: *mut NeqoHttp3Client,
+    event: *mut NeqoHttp3Event,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(client)?;
+        let e = loop {
+            match c.client.next_event() {
+                Some(e) => {
+                    if let Some(e) = c.event(e) {
+                        break e;
+                    }
+                }
+                None => break NeqoHttp3Event::None,
+            }
+        };
+        set_out(event, e)
+    })
+}
+
+/// Start a request. The `headers` are sent after the pseudo-header fields that are made
+/// from `method`, `scheme`, `host` and `path`. Send a body with
+/// `neqo_http3_client_send_request_body`, then end the request with
+/// `neqo_http3_client_stream_close_send`.
+///
+/// # Safety
+/// `client` is a live client, the strings are NUL-terminated, `headers` points to
+/// `headers_len` header fields, and `stream_id` points to where the stream ID is put.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn neqo_http3_client_fetch(
+    client: *mut NeqoHttp3Client,
+    method: *const c_char,
+    scheme: *const c_char,
+    host: *const c_char,
+    path: *const c_char,
+    headers: *const NeqoHeader,
+    headers_len: usize,
+    stream_id: *mut u64,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(client)?;
+        let id = c.client.fetch(
+            Instant::now(),
+            str_arg(method)?,
+            str_arg(scheme)?,
+            str_arg(host)?,
+            str_arg(path)?,
+            &headers_arg(headers, headers_len)?,
+        )?;
+        set_out(stream_id, id)
+    })
+}
+
+/// Send as much of a request body as flow control allows; `written` is set to how much
+/// that was. Wait for `DataWritable` before sending more.
+///
+/// # Safety
+/// `client` is a live client, `data` points to `len` bytes, and `written` is
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_send_request_body(
+    client: *mut NeqoHttp3Client,
+    stream_id: u64,
+    data: *const u8,
+    len: usize,
+    written: *mut usize,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(client)?;
+        let n = c
+            .client
+            .send_request_body(stream_id, slice_arg(data, len)?)?;
+        set_out(written, n)
+    })
+}
+
+/// Send trailers, which ends the request.
+///
+/// # Safety
+/// `client` is a live client and `headers` points to `headers_len` header fields.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_send_request_trailers(
+    client: *mut NeqoHttp3Client,
+    stream_id: u64,
+    headers: *const NeqoHeader,
+    headers_len: usize,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(client)?;
+        let trailers = headers_arg(headers, headers_len)?;
+        Ok(c.client.send_request_trailers(stream_id, &trailers)?)
+    })
+}
+
+/// End a request.
+///
+/// # Safety
+/// `client` is a live client.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_stream_close_send(
+    client: *mut NeqoHttp3Client,
+    stream_id: u64,
+) -> NeqoStatus {
+    status(|| Ok(obj_arg(client)?.client.stream_close_send(stream_id)?))
+}
+
+/// Abandon a request and its response.
+///
+/// # Safety
+/// `client` is a live client.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_cancel_request(
+    client: *mut NeqoHttp3Client,
+    stream_id: u64,
+    error: u64,
+) -> NeqoStatus {
+    status(|| Ok(obj_arg(client)?.client.cancel_request(stream_id, error)?))
+}
+
+/// Read the response body into `buf`. `read` is set to how many bytes were read, and
+/// `fin` to whether the response has ended.
+///
+/// # Safety
+/// `client` is a live client, `buf` points to `len` writable bytes, and `read` and `fin`
+/// are writable.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_http3_client_read_response_data(
+    client: *mut NeqoHttp3Client,
+    stream_id: u64,
+    buf: *mut u8,
+    len: usize,
+    read: *mut usize,
+    fin: *mut bool,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(client)?;
+        let (n, f) =
+            c.client
+                .read_response_data(Instant::now(), stream_id, slice_arg_mut(buf, len)?)?;
+        set_out(read, n)?;
+        set_out(fin, f)
+    })
+}
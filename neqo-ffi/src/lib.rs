@@ -0,0 +1,310 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// A C API for QUIC connections and HTTP/3 clients. The header is include/neqo.h, which is
+// generated by cbindgen.
+//
+// Objects are created with a `_new` function and released with the matching `_free`.
+// Functions that can fail return a `NeqoStatus`. Data that a function hands back, such
+// as a datagram or the header fields of an event, belongs to the object and stays valid
+// until the next call on that object. A panic does not unwind into C: the call returns
+// `NeqoStatus::Error` instead, and the object should only be freed after that.
+
+#![cfg_attr(feature = "deny-warnings", deny(warnings))]
+#![warn(clippy::pedantic)]
+
+mod http3;
+mod transport;
+
+pub use http3::*;
+pub use transport::*;
+
+use neqo_common::Datagram;
+use neqo_transport::{CloseError, ConnectionError, Output};
+
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::net::SocketAddr;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
+use std::time::Duration;
+
+/// The outcome of a call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NeqoStatus {
+    Ok,
+    /// An argument was null, not UTF-8, or otherwise unusable.
+    InvalidArgument,
+    /// The stream doesn't exist, or not in the direction that was asked for.
+    InvalidStream,
+    /// This can't be done yet, for instance because the peer's stream limit was reached.
+    WouldBlock,
+    /// The connection is closing or closed.
+    Closed,
+    /// Any other failure.
+    Error,
+}
+
+impl From<neqo_transport::Error> for NeqoStatus {
+    fn from(err: neqo_transport::Error) -> Self {
+        match err {
+            neqo_transport::Error::InvalidStreamId => Self::InvalidStream,
+            neqo_transport::Error::InvalidInput => Self::InvalidArgument,
+            neqo_transport::Error::StreamLimitError => Self::WouldBlock,
+            neqo_transport::Error::ConnectionState => Self::Closed,
+            _ => Self::Error,
+        }
+    }
+}
+
+impl From<neqo_http3::Error> for NeqoStatus {
+    fn from(err: neqo_http3::Error) -> Self {
+        match err {
+            neqo_http3::Error::InvalidStreamId | neqo_http3::Error::TransportStreamDoesNotExist => {
+                Self::InvalidStream
+            }
+            neqo_http3::Error::InvalidInput | neqo_http3::Error::FieldSectionTooLarge => {
+                Self::InvalidArgument
+            }
+            neqo_http3::Error::StreamLimitError => Self::WouldBlock,
+            neqo_http3::Error::AlreadyClosed => Self::Closed,
+            neqo_http3::Error::TransportError(e) => Self::from(e),
+            _ => Self::Error,
+        }
+    }
+}
+
+/// Run the body of a call and report how it went. A panic is reported as an error.
+fn status(f: impl FnOnce() -> Result<(), NeqoStatus>) -> NeqoStatus {
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or(Err(NeqoStatus::Error))
+        .err()
+        .unwrap_or(NeqoStatus::Ok)
+}
+
+/// Free an object that a `_new` function made, if `obj` isn't null. A panic while it is
+/// dropped is caught, as there is nobody to report it to.
+unsafe fn free_arg<T>(obj: *mut T) {
+    if !obj.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(obj))));
+    }
+}
+
+/// Why a connection closed. `application` says whether `code` is an application error
+/// code or a QUIC transport error code.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NeqoCloseError {
+    pub application: bool,
+    pub code: u64,
+}
+
+impl From<CloseError> for NeqoCloseError {
+    fn from(err: CloseError) -> Self {
+        match err {
+            CloseError::Transport(code) => Self {
+                application: false,
+                code,
+            },
+            CloseError::Application(code) => Self {
+                application: true,
+                code,
+            },
+        }
+    }
+}
+
+impl From<&ConnectionError> for NeqoCloseError {
+    fn from(err: &ConnectionError) -> Self {
+        match err {
+            ConnectionError::Transport(e) => Self {
+                application: false,
+                code: e.code(),
+            },
+            ConnectionError::Application(code) => Self {
+                application: true,
+                code: *code,
+            },
+        }
+    }
+}
+
+/// What to do after processing.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NeqoOutputKind {
+    /// Nothing, until a datagram arrives or something is sent.
+    None,
+    /// Send the datagram in `data` and `len` to the peer, then process again.
+    Datagram,
+    /// Process again after `timeout_ms` milliseconds, unless a datagram arrives first.
+    Timer,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct NeqoOutput {
+    pub kind: NeqoOutputKind,
+    pub data: *const u8,
+    pub len: usize,
+    pub timeout_ms: u64,
+}
+
+impl Default for NeqoOutput {
+    fn default() -> Self {
+        Self {
+            kind: NeqoOutputKind::None,
+            data: ptr::null(),
+            len: 0,
+            timeout_ms: 0,
+        }
+    }
+}
+
+impl NeqoOutput {
+    /// Describe `output`, keeping any datagram in `buf`.
+    fn new(output: Output, buf: &mut Vec<u8>) -> Self {
+        match output {
+            Output::None => Self::default(),
+            Output::Datagram(d) => {
+                *buf = d.to_vec();
+                Self {
+                    kind: NeqoOutputKind::Datagram,
+                    data: buf.as_ptr(),
+                    len: buf.len(),
+                    ..Self::default()
+                }
+            }
+            Output::Callback(t) => Self {
+                kind: NeqoOutputKind::Timer,
+                timeout_ms: timeout_ms(t),
+                ..Self::default()
+            },
+        }
+    }
+}
+
+/// Round up, so that the timer doesn't fire before anything is due.
+fn timeout_ms(t: Duration) -> u64 {
+    let ms = t.as_millis() + u128::from(t.subsec_nanos() % 1_000_000 != 0);
+    u64::try_from(ms).unwrap_or(u64::MAX)
+}
+
+/// The local and remote addresses of a connection, which the datagrams it receives are
+/// addressed with.
+#[derive(Debug)]
+struct Addresses {
+    local: SocketAddr,
+    remote: SocketAddr,
+}
+
+impl Addresses {
+    unsafe fn new(local: *const c_char, remote: *const c_char) -> Result<Self, NeqoStatus> {
+        let addr = |s: *const c_char| {
+            str_arg(s)?
+                .parse::<SocketAddr>()
+                .map_err(|_| NeqoStatus::InvalidArgument)
+        };
+        Ok(Self {
+            local: addr(local)?,
+            remote: addr(remote)?,
+        })
+    }
+
+    fn datagram(&self, data: &[u8]) -> Datagram {
+        Datagram::new(self.remote, self.local, data)
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Result<&'a str, NeqoStatus> {
+    if s.is_null() {
+        return Err(NeqoStatus::InvalidArgument);
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| NeqoStatus::InvalidArgument)
+}
+
+/// A slice from a pointer and length. The pointer can be null if the length is zero.
+unsafe fn slice_arg<'a, T>(p: *const T, len: usize) -> Result<&'a [T], NeqoStatus> {
+    if len == 0 {
+        Ok(&[])
+    } else if p.is_null() {
+        Err(NeqoStatus::InvalidArgument)
+    } else {
+        Ok(slice::from_raw_parts(p, len))
+    }
+}
+
+unsafe fn slice_arg_mut<'a>(p: *mut u8, len: usize) -> Result<&'a mut [u8], NeqoStatus> {
+    if len == 0 {
+        Ok(&mut [])
+    } else if p.is_null() {
+        Err(NeqoStatus::InvalidArgument)
+    } else {
+        Ok(slice::from_raw_parts_mut(p, len))
+    }
+}
+
+unsafe fn obj_arg<'a, T>(p: *mut T) -> Result<&'a mut T, NeqoStatus> {
+    p.as_mut().ok_or(NeqoStatus::InvalidArgument)
+}
+
+/// Set `*out` to `v`.
+unsafe fn set_out<T>(out: *mut T, v: T) -> Result<(), NeqoStatus> {
+    obj_arg(out).map(|o| *o = v)
+}
+
+/// Initialize NSS. With a null `db_dir`, NSS runs without a database. Call this once,
+/// before anything else.
+///
+/// # Safety
+/// `db_dir` is null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_init(db_dir: *const c_char) -> NeqoStatus {
+    status(|| {
+        if db_dir.is_null() {
+            neqo_crypto::init();
+            return Ok(());
+        }
+        let d = str_arg(db_dir)?;
+        let dir = PathBuf::from(d);
+        if !dir.is_dir() {
+            return Err(NeqoStatus::InvalidArgument);
+        }
+        neqo_crypto::init_db(dir);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{free_arg, status, NeqoStatus};
+
+    struct PanicOnDrop;
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            panic!("dropped");
+        }
+    }
+
+    #[test]
+    fn status_catches_panic() {
+        assert_eq!(status(|| Ok(())), NeqoStatus::Ok);
+        assert_eq!(status(|| Err(NeqoStatus::Closed)), NeqoStatus::Closed);
+        assert_eq!(status(|| panic!("oops")), NeqoStatus::Error);
+    }
+
+    #[test]
+    fn free_catches_panic() {
+        unsafe { free_arg(Box::into_raw(Box::new(PanicOnDrop))) };
+    }
+}
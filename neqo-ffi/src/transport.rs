@@ -0,0 +1,418 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Bindings for a QUIC client connection, for protocols other than HTTP/3.
+
+use neqo_common::event::Provider;
+use neqo_transport::{
+    CongestionControlAlgorithm, Connection, ConnectionEvent, FixedConnectionIdManager, QuicVersion,
+    State, StreamType,
+};
+
+use std::cell::RefCell;
+use std::os::raw::c_char;
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::{
+    free_arg, obj_arg, set_out, slice_arg, slice_arg_mut, status, str_arg, Addresses,
+    NeqoCloseError, NeqoOutput, NeqoStatus,
+};
+
+pub struct NeqoConnection {
+    conn: Connection,
+    addrs: Addresses,
+    /// The last datagram that was handed out.
+    out: Vec<u8>,
+    /// The data of the last event that was handed out.
+    event_data: Vec<u8>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NeqoState {
+    Init,
+    WaitInitial,
+    Handshaking,
+    Connected,
+    Confirmed,
+    Closing,
+    Draining,
+    Closed,
+}
+
+impl NeqoState {
+    fn new(state: &State) -> (Self, NeqoCloseError) {
+        match state {
+            State::Init => (Self::Init, NeqoCloseError::default()),
+            State::WaitInitial => (Self::WaitInitial, NeqoCloseError::default()),
+            State::Handshaking => (Self::Handshaking, NeqoCloseError::default()),
+            State::Connected => (Self::Connected, NeqoCloseError::default()),
+            State::Confirmed => (Self::Confirmed, NeqoCloseError::default()),
+            State::Closing { error, .. } => (Self::Closing, error.into()),
+            State::Draining { error, .. } => (Self::Draining, error.into()),
+            State::Closed(error) => (Self::Closed, error.into()),
+        }
+    }
+}
+
+/// An event on a connection. The data of `ResumptionToken` and `Datagram` stays valid
+/// until the next call on the connection.
+#[repr(C)]
+#[derive(Debug)]
+pub enum NeqoConnectionEvent {
+    /// There are no more events.
+    None,
+    /// Check the server certificate, then call `neqo_connection_authenticated`.
+    AuthenticationNeeded,
+    NewStream {
+        stream_id: u64,
+    },
+    SendStreamWritable {
+        stream_id: u64,
+    },
+    RecvStreamReadable {
+        stream_id: u64,
+    },
+    RecvStreamReset {
+        stream_id: u64,
+        error: u64,
+    },
+    SendStreamStopSending {
+        stream_id: u64,
+        error: u64,
+    },
+    SendStreamComplete {
+        stream_id: u64,
+    },
+    SendStreamCreatable {
+        bidi: bool,
+    },
+    /// `error` is only set when the state is closing, draining or closed.
+    StateChange {
+        state: NeqoState,
+        error: NeqoCloseError,
+    },
+    ZeroRttRejected,
+    /// A token that can be passed to `neqo_connection_enable_resumption` later.
+    ResumptionToken {
+        data: *const u8,
+        len: usize,
+    },
+    Datagram {
+        data: *const u8,
+        len: usize,
+    },
+}
+
+impl NeqoConnectionEvent {
+    fn new(event: ConnectionEvent, data: &mut Vec<u8>) -> Self {
+        match event {
+            ConnectionEvent::AuthenticationNeeded => Self::AuthenticationNeeded,
+            ConnectionEvent::NewStream { stream_id } => Self::NewStream {
+                stream_id: stream_id.as_u64(),
+            },
+            ConnectionEvent::SendStreamWritable { stream_id } => Self::SendStreamWritable {
+                stream_id: stream_id.as_u64(),
+            },
+            ConnectionEvent::RecvStreamReadable { stream_id } => {
+                Self::RecvStreamReadable { stream_id }
+            }
+            ConnectionEvent::RecvStreamReset {
+                stream_id,
+                app_error,
+            } => Self::RecvStreamReset {
+                stream_id,
+                error: app_error,
+            },
+            ConnectionEvent::SendStreamStopSending {
+                stream_id,
+                app_error,
+            } => Self::SendStreamStopSending {
+                stream_id,
+                error: app_error,
+            },
+            ConnectionEvent::SendStreamComplete { stream_id } => {
+                Self::SendStreamComplete { stream_id }
+            }
+            ConnectionEvent::SendStreamCreatable { stream_type } => Self::SendStreamCreatable {
+                bidi: stream_type == StreamType::BiDi,
+            },
+            ConnectionEvent::StateChange(state) => {
+                let (state, error) = NeqoState::new(&state);
+                Self::StateChange { state, error }
+            }
+            ConnectionEvent::ZeroRttRejected => Self::ZeroRttRejected,
+            ConnectionEvent::ResumptionToken(token) => {
+                *data = token.as_ref().to_vec();
+                Self::ResumptionToken {
+                    data: data.as_ptr(),
+                    len: data.len(),
+                }
+            }
+            ConnectionEvent::Datagram(d) => {
+                *data = d;
+                Self::Datagram {
+                    data: data.as_ptr(),
+                    len: data.len(),
+                }
+            }
+        }
+    }
+}
+
+/// Create a client connection to `server_name`. `alpn` is a comma-separated list of
+/// application protocols, and the addresses are written like "192.0.2.1:443" or
+/// "[2001:db8::1]:443".
+///
+/// # Safety
+/// The strings are NUL-terminated and `conn` points to where the connection is put.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_new_client(
+    server_name: *const c_char,
+    alpn: *const c_char,
+    local_addr: *const c_char,
+    remote_addr: *const c_char,
+    conn: *mut *mut NeqoConnection,
+) -> NeqoStatus {
+    status(|| {
+        let conn = obj_arg(conn)?;
+        let addrs = Addresses::new(local_addr, remote_addr)?;
+        let protocols = str_arg(alpn)?.split(',').collect::<Vec<_>>();
+        let c = Connection::new_client(
+            str_arg(server_name)?,
+            &protocols,
+            Rc::new(RefCell::new(FixedConnectionIdManager::new(0))),
+            addrs.local,
+            addrs.remote,
+            &CongestionControlAlgorithm::NewReno,
+            QuicVersion::default(),
+        )?;
+        let c = Box::new(NeqoConnection {
+            conn: c,
+            addrs,
+            out: Vec::new(),
+            event_data: Vec::new(),
+        });
+        *conn = Box::into_raw(c);
+        Ok(())
+    })
+}
+
+/// # Safety
+/// `conn` is null or was made by `neqo_connection_new_client` and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_free(conn: *mut NeqoConnection) {
+    free_arg(conn);
+}
+
+/// Resume a session with a token from a `ResumptionToken` event. Do this before the
+/// first call to `neqo_connection_process_output`.
+///
+/// # Safety
+/// `conn` is a live connection and `token` points to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_enable_resumption(
+    conn: *mut NeqoConnection,
+    token: *const u8,
+    len: usize,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(conn)?;
+        Ok(c.conn
+            .enable_resumption(Instant::now(), slice_arg(token, len)?)?)
+    })
+}
+
+/// Report whether the server certificate is good: `error` is 0 if it is, or else the
+/// NSS or mozilla::pkix error code.
+///
+/// # Safety
+/// `conn` is a live connection.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_authenticated(
+    conn: *mut NeqoConnection,
+    error: i32,
+) -> NeqoStatus {
+    status(|| obj_arg(conn).map(|c| c.conn.authenticated(error.into(), Instant::now())))
+}
+
+/// Process a datagram that was received from the peer.
+///
+/// # Safety
+/// `conn` is a live connection and `data` points to `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_process_input(
+    conn: *mut NeqoConnection,
+    data: *const u8,
+    len: usize,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(conn)?;
+        let d = c.addrs.datagram(slice_arg(data, len)?);
+        c.conn.process_input(d, Instant::now());
+        Ok(())
+    })
+}
+
+/// Find out what to do next: send a datagram, or wait. Call this until it asks for a
+/// wait, and again whenever the timer fires, a datagram arrives, or something is sent.
+///
+/// # Safety
+/// `conn` is a live connection and `output` points to a `NeqoOutput`.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_process_output(
+    conn: *mut NeqoConnection,
+    output: *mut NeqoOutput,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(conn)?;
+        let out = c.conn.process_output(Instant::now());
+        set_out(output, NeqoOutput::new(out, &mut c.out))
+    })
+}
+
+/// Close the connection with an application error code. `reason` can be null.
+///
+/// # Safety
+/// `conn` is a live connection and `reason` is null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_close(
+    conn: *mut NeqoConnection,
+    error: u64,
+    reason: *const c_char,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(conn)?;
+        let reason = if reason.is_null() {
+            ""
+        } else {
+            str_arg(reason)?
+        };
+        c.conn.close(Instant::now(), error, reason);
+        Ok(())
+    })
+}
+
+/// Take the next event. `event` is set to `None` when there are no more.
+///
+/// # Safety
+/// `conn` is a live connection and `event` points to a `NeqoConnectionEvent`.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_next_event(
+    conn: *mut NeqoConnection,
+    event: *mut NeqoConnectionEvent,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(conn)?;
+        let e = c.conn.next_event().map_or(NeqoConnectionEvent::None, |e| {
+            NeqoConnectionEvent::new(e, &mut c.event_data)
+        });
+        set_out(event, e)
+    })
+}
+
+/// # Safety
+/// `conn` is a live connection and `stream_id` points to where the new ID is put.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_stream_create(
+    conn: *mut NeqoConnection,
+    bidi: bool,
+    stream_id: *mut u64,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(conn)?;
+        let st = if bidi {
+            StreamType::BiDi
+        } else {
+            StreamType::UniDi
+        };
+        set_out(stream_id, c.conn.stream_create(st)?)
+    })
+}
+
+/// Send as much of `data` as flow control allows; `written` is set to how much that was.
+///
+/// # Safety
+/// `conn` is a live connection, `data` points to `len` bytes, and `written` is
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_stream_send(
+    conn: *mut NeqoConnection,
+    stream_id: u64,
+    data: *const u8,
+    len: usize,
+    written: *mut usize,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(conn)?;
+        set_out(
+            written,
+            c.conn.stream_send(stream_id, slice_arg(data, len)?)?,
+        )
+    })
+}
+
+/// End the sending side of a stream once what has been sent is delivered.
+///
+/// # Safety
+/// `conn` is a live connection.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_stream_close_send(
+    conn: *mut NeqoConnection,
+    stream_id: u64,
+) -> NeqoStatus {
+    status(|| Ok(obj_arg(conn)?.conn.stream_close_send(stream_id)?))
+}
+
+/// Abandon the sending side of a stream.
+///
+/// # Safety
+/// `conn` is a live connection.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_stream_reset_send(
+    conn: *mut NeqoConnection,
+    stream_id: u64,
+    error: u64,
+) -> NeqoStatus {
+    status(|| Ok(obj_arg(conn)?.conn.stream_reset_send(stream_id, error)?))
+}
+
+/// Read from a stream into `buf`. `read` is set to how many bytes were read, and `fin`
+/// to whether the stream has ended.
+///
+/// # Safety
+/// `conn` is a live connection, `buf` points to `len` writable bytes, and `read` and
+/// `fin` are writable.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_stream_recv(
+    conn: *mut NeqoConnection,
+    stream_id: u64,
+    buf: *mut u8,
+    len: usize,
+    read: *mut usize,
+    fin: *mut bool,
+) -> NeqoStatus {
+    status(|| {
+        let c = obj_arg(conn)?;
+        let (n, f) = c.conn.stream_recv(stream_id, slice_arg_mut(buf, len)?)?;
+        set_out(read, n)?;
+        set_out(fin, f)
+    })
+}
+
+/// Ask the peer to stop sending on a stream.
+///
+/// # Safety
+/// `conn` is a live connection.
+#[no_mangle]
+pub unsafe extern "C" fn neqo_connection_stream_stop_sending(
+    conn: *mut NeqoConnection,
+    stream_id: u64,
+    error: u64,
+) -> NeqoStatus {
+    status(|| Ok(obj_arg(conn)?.conn.stream_stop_sending(stream_id, error)?))
+}
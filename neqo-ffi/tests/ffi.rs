@@ -0,0 +1,194 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// These drive the C API from Rust, against a server made with the Rust API.
+
+#![cfg_attr(feature = "deny-warnings", deny(warnings))]
+#![warn(clippy::pedantic)]
+
+use neqo_common::{event::Provider, Datagram};
+use neqo_ffi::{
+    neqo_connection_authenticated, neqo_connection_free, neqo_connection_new_client,
+    neqo_connection_next_event, neqo_connection_process_input, neqo_connection_process_output,
+    neqo_connection_stream_close_send, neqo_connection_stream_create, neqo_connection_stream_send,
+    neqo_http3_client_new, NeqoConnection, NeqoConnectionEvent, NeqoOutput, NeqoOutputKind,
+    NeqoState, NeqoStatus,
+};
+use neqo_transport::{Connection, ConnectionEvent};
+use test_fixture::{default_server, fixture_init, loopback, now};
+
+use std::ffi::CString;
+use std::ptr;
+use std::slice;
+
+const ADDR: &str = "[::1]:443";
+
+fn cstr(s: &str) -> CString {
+    CString::new(s).unwrap()
+}
+
+fn new_client() -> *mut NeqoConnection {
+    fixture_init();
+    let mut client = ptr::null_mut();
+    let status = unsafe {
+        neqo_connection_new_client(
+            cstr("example.com").as_ptr(),
+            cstr("alpn").as_ptr(),
+            cstr(ADDR).as_ptr(),
+            cstr(ADDR).as_ptr(),
+            &mut client,
+        )
+    };
+    assert_eq!(status, NeqoStatus::Ok);
+    assert!(!client.is_null());
+    client
+}
+
+/// Take the client's events, authenticating the server when asked.
+/// Returns true if the client reached the connected state.
+fn handle_events(client: *mut NeqoConnection) -> bool {
+    let mut connected = false;
+    loop {
+        let mut event = NeqoConnectionEvent::None;
+        assert_eq!(
+            unsafe { neqo_connection_next_event(client, &mut event) },
+            NeqoStatus::Ok
+        );
+        match event {
+            NeqoConnectionEvent::None => return connected,
+            NeqoConnectionEvent::AuthenticationNeeded => {
+                assert_eq!(
+                    unsafe { neqo_connection_authenticated(client, 0) },
+                    NeqoStatus::Ok
+                );
+            }
+            NeqoConnectionEvent::StateChange {
+                state: NeqoState::Connected,
+                ..
+            } => connected = true,
+            _ => {}
+        }
+    }
+}
+
+/// Pass datagrams between the client and server until neither has any to send.
+/// Returns true if the client reached the connected state.
+fn exchange(client: *mut NeqoConnection, server: &mut Connection) -> bool {
+    let mut connected = false;
+    for _ in 0..20 {
+        let mut sent = false;
+        loop {
+            let mut out = NeqoOutput::default();
+            assert_eq!(
+                unsafe { neqo_connection_process_output(client, &mut out) },
+                NeqoStatus::Ok
+            );
+            if out.kind != NeqoOutputKind::Datagram {
+                break;
+            }
+            let d = unsafe { slice::from_raw_parts(out.data, out.len) };
+            server.process_input(Datagram::new(loopback(), loopback(), d), now());
+            sent = true;
+        }
+        while let Some(d) = server.process_output(now()).dgram() {
+            assert_eq!(
+                unsafe { neqo_connection_process_input(client, d.as_ptr(), d.len()) },
+                NeqoStatus::Ok
+            );
+            sent = true;
+        }
+        connected |= handle_events(client);
+        if !sent {
+            break;
+        }
+    }
+    connected
+}
+
+#[test]
+fn connect_and_send() {
+    let client = new_client();
+    let mut server = default_server();
+    assert!(exchange(client, &mut server));
+
+    let mut stream_id = u64::MAX;
+    let mut written = 0;
+    unsafe {
+        assert_eq!(
+            neqo_connection_stream_create(client, true, &mut stream_id),
+            NeqoStatus::Ok
+        );
+        assert_eq!(
+            neqo_connection_stream_send(client, stream_id, b"hello".as_ptr(), 5, &mut written),
+            NeqoStatus::Ok
+        );
+        assert_eq!(
+            neqo_connection_stream_close_send(client, stream_id),
+            NeqoStatus::Ok
+        );
+    }
+    assert_eq!(written, 5);
+    exchange(client, &mut server);
+
+    assert!(server.events().any(
+        |e| matches!(e, ConnectionEvent::RecvStreamReadable { stream_id: id } if id == stream_id)
+    ));
+    let mut buf = [0; 16];
+    let (n, fin) = server.stream_recv(stream_id, &mut buf).unwrap();
+    assert_eq!(&buf[..n], b"hello");
+    assert!(fin);
+
+    unsafe { neqo_connection_free(client) };
+}
+
+#[test]
+fn bad_arguments() {
+    let client = new_client();
+    let mut stream_id = 0;
+    unsafe {
+        assert_eq!(
+            neqo_connection_stream_create(ptr::null_mut(), true, &mut stream_id),
+            NeqoStatus::InvalidArgument
+        );
+        assert_eq!(
+            neqo_connection_stream_create(client, true, ptr::null_mut()),
+            NeqoStatus::InvalidArgument
+        );
+        assert_eq!(
+            neqo_connection_stream_send(client, 2, ptr::null(), 1, &mut 0),
+            NeqoStatus::InvalidArgument
+        );
+        neqo_connection_free(client);
+        neqo_connection_free(ptr::null_mut());
+    }
+}
+
+#[test]
+fn bad_address() {
+    fixture_init();
+    let mut client = ptr::null_mut();
+    let status = unsafe {
+        neqo_http3_client_new(
+            cstr("example.com").as_ptr(),
+            cstr("localhost").as_ptr(),
+            cstr(ADDR).as_ptr(),
+            ptr::null(),
+            &mut client,
+        )
+    };
+    assert_eq!(status, NeqoStatus::InvalidArgument);
+    assert!(client.is_null());
+}
+
+#[test]
+fn header_is_current() {
+    let generated = include_str!(concat!(env!("OUT_DIR"), "/neqo.h"));
+    let shipped = include_str!("../include/neqo.h");
+    assert!(
+        generated == shipped,
+        "include/neqo.h does not match the sources, regenerate it with cbindgen"
+    );
+}
@@ -0,0 +1,108 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// The Capsule Protocol (RFC 9297, Section 3.2). Capsules are carried in the data of a CONNECT
+// stream.
+
+use crate::{Error, Res};
+use neqo_common::{Decoder, Encoder};
+use std::convert::TryFrom;
+
+pub(crate) const CAPSULE_TYPE_DATAGRAM: u64 = 0x00;
+
+/// The longest capsule value that is accepted. This fits any UDP payload.
+const MAX_CAPSULE_LEN: u64 = 1 << 16;
+/// The most data that is kept while waiting for capsules to be taken.
+const MAX_BUFFERED: usize = 1 << 18;
+
+/// Encode a capsule.
+pub(crate) fn encode_capsule<F: FnOnce(&mut Encoder)>(capsule_type: u64, f: F) -> Vec<u8> {
+    let mut enc = Encoder::default();
+    enc.encode_varint(capsule_type);
+    enc.encode_vvec_with(f);
+    enc.into()
+}
+
+/// Collects the data of a stream and splits it into capsules.
+#[derive(Debug, Default)]
+pub(crate) struct CapsuleReader {
+    buf: Vec<u8>,
+}
+
+impl CapsuleReader {
+    /// # Errors
+    /// `HttpExcessiveLoad` if more data is buffered than `MAX_BUFFERED`, i.e. capsules are
+    /// not taken as fast as data arrives.
+    pub fn receive(&mut self, data: &[u8]) -> Res<()> {
+        if self.buf.len() + data.len() > MAX_BUFFERED {
+            return Err(Error::HttpExcessiveLoad);
+        }
+        self.buf.extend_from_slice(data);
+        Ok(())
+    }
+
+    /// Take the next complete capsule, as the capsule type and the capsule value.
+    /// # Errors
+    /// `HttpDatagramStream` if a capsule is longer than `MAX_CAPSULE_LEN`. This is found as
+    /// soon as the length has been received.
+    pub fn next_capsule(&mut self) -> Res<Option<(u64, Vec<u8>)>> {
+        let mut dec = Decoder::new(&self.buf);
+        let (capsule_type, len) = match (dec.decode_varint(), dec.decode_varint()) {
+            (Some(t), Some(len)) => (t, len),
+            _ => return Ok(None),
+        };
+        if len > MAX_CAPSULE_LEN {
+            return Err(Error::HttpDatagramStream);
+        }
+        // The length is small enough for this to fit.
+        let value = match dec.decode(usize::try_from(len).unwrap()) {
+            Some(v) => v.to_vec(),
+            None => return Ok(None),
+        };
+        let consumed = dec.offset();
+        self.buf.drain(..consumed);
+        Ok(Some((capsule_type, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_capsule, CapsuleReader, MAX_BUFFERED, MAX_CAPSULE_LEN};
+    use crate::Error;
+    use neqo_common::Encoder;
+
+    #[test]
+    fn capsules_in_pieces() {
+        let mut data = encode_capsule(0x00, |enc| enc.encode(&[1, 2, 3]));
+        data.append(&mut encode_capsule(0x2843, |_| {}));
+        let mut reader = CapsuleReader::default();
+        for b in &data {
+            assert_eq!(reader.next_capsule(), Ok(None));
+            reader.receive(&[*b]).unwrap();
+        }
+        assert_eq!(reader.next_capsule(), Ok(Some((0x00, vec![1, 2, 3]))));
+        assert_eq!(reader.next_capsule(), Ok(Some((0x2843, Vec::new()))));
+        assert_eq!(reader.next_capsule(), Ok(None));
+    }
+
+    #[test]
+    fn capsule_too_long() {
+        // Only the type and the length are needed to reject the capsule.
+        let mut enc = Encoder::default();
+        enc.encode_varint(0_u64);
+        enc.encode_varint(MAX_CAPSULE_LEN + 1);
+        let mut reader = CapsuleReader::default();
+        reader.receive(&enc).unwrap();
+        assert_eq!(reader.next_capsule(), Err(Error::HttpDatagramStream));
+    }
+
+    #[test]
+    fn too_much_buffered() {
+        let mut reader = CapsuleReader::default();
+        reader.receive(&vec![0; MAX_BUFFERED]).unwrap();
+        assert_eq!(reader.receive(&[0]), Err(Error::HttpExcessiveLoad));
+    }
+}
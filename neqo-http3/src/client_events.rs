@@ -7,7 +7,9 @@
 #![allow(clippy::module_name_repetitions)]
 
 use crate::connection::Http3State;
+use crate::request_metrics::RequestMetrics;
 use crate::send_message::SendMessageEvents;
+use crate::webtransport::{WebTransportEvent, WebTransportEvents};
 use crate::Header;
 use crate::RecvMessageEvents;
 
@@ -28,10 +30,18 @@ pub enum Http3ClientEvent {
         interim: bool,
         fin: bool,
     },
-    /// A stream can accept new data.
+    /// A stream can accept new data, e.g. more of a request body after `send_request_body`
+    /// has returned less than the supplied amount.
     DataWritable { stream_id: u64 },
     /// New bytes available for reading.
     DataReadable { stream_id: u64 },
+    /// Response trailers are received.
+    TrailersReady {
+        stream_id: u64,
+        trailers: Vec<Header>,
+    },
+    /// An HTTP Datagram has been received for a request.
+    Datagram { stream_id: u64, datagram: Vec<u8> },
     /// Peer reset the stream or there was an parsing error.
     Reset {
         stream_id: u64,
@@ -55,6 +65,8 @@ pub enum Http3ClientEvent {
     },
     /// New bytes are available on a push stream for reading.
     PushDataReadable { push_id: u64 },
+    /// A push response trailers are ready.
+    PushTrailersReady { push_id: u64, trailers: Vec<Header> },
     /// A push has been canceled.
     PushCanceled { push_id: u64 },
     /// A push stream was been reset due to a HttpGeneralProtocol error.
@@ -66,12 +78,22 @@ pub enum Http3ClientEvent {
     AuthenticationNeeded,
     /// A new resumption token.
     ResumptionToken(ResumptionToken),
-    /// Zero Rtt has been rejected.
+    /// Zero Rtt has been rejected. Requests sent in 0-RTT are sent again on the same streams
+    /// once the handshake is done; a request that cannot be sent again (e.g. one with trailers
+    /// or a WebTransport session) gets a `Reset` event with `HttpRequestRejected`.
     ZeroRttRejected,
-    /// Client has received a GOAWAY frame
+    /// Client has received a GOAWAY frame. No new requests can be made on this connection.
+    /// Requests that the server will not process are reset with `HttpRequestRejected`
+    /// before this event; they have not been started and can be retried on a new connection.
     GoawayReceived,
+    /// The server has advertised origins in an ORIGIN frame, see `Http3Client::origins`.
+    OriginReceived,
+    /// A request has ended and these are its metrics, see `Http3Client::set_request_metrics`.
+    RequestMetrics(RequestMetrics),
     /// Connection state change.
     StateChange(Http3State),
+    /// An event of a WebTransport session.
+    WebTransport(WebTransportEvent),
 }
 
 #[derive(Debug, Default, Clone)]
@@ -95,12 +117,21 @@ impl RecvMessageEvents for Http3ClientEvents {
         self.insert(Http3ClientEvent::DataReadable { stream_id });
     }
 
+    /// Add a new `TrailersReady` event.
+    fn trailers_ready(&self, stream_id: u64, trailers: Vec<Header>) {
+        self.insert(Http3ClientEvent::TrailersReady {
+            stream_id,
+            trailers,
+        });
+    }
+
     /// Add a new `Reset` event.
     fn reset(&self, stream_id: u64, error: AppError, local: bool) {
         self.remove(|evt| {
             matches!(evt,
                 Http3ClientEvent::HeaderReady { stream_id: x, .. }
                 | Http3ClientEvent::DataReadable { stream_id: x }
+                | Http3ClientEvent::TrailersReady { stream_id: x, .. }
                 | Http3ClientEvent::PushPromise { request_stream_id: x, .. }
                 | Http3ClientEvent::Reset { stream_id: x, .. } if *x == stream_id)
         });
@@ -134,6 +165,13 @@ impl SendMessageEvents for Http3ClientEvents {
     }
 }
 
+impl WebTransportEvents for Http3ClientEvents {
+    /// Add a new `WebTransport` event.
+    fn webtransport_event(&self, event: WebTransportEvent) {
+        self.insert(Http3ClientEvent::WebTransport(event));
+    }
+}
+
 impl Http3ClientEvents {
     pub fn push_promise(&self, push_id: u64, request_stream_id: u64, headers: Vec<Header>) {
         self.insert(Http3ClientEvent::PushPromise {
@@ -175,12 +213,30 @@ impl Http3ClientEvents {
         self.insert(Http3ClientEvent::ZeroRttRejected);
     }
 
+    /// Add a new `Datagram` event.
+    pub(crate) fn datagram(&self, stream_id: u64, datagram: Vec<u8>) {
+        self.insert(Http3ClientEvent::Datagram {
+            stream_id,
+            datagram,
+        });
+    }
+
     /// Add a new `GoawayReceived` event.
     pub(crate) fn goaway_received(&self) {
         self.remove(|evt| matches!(evt, Http3ClientEvent::RequestsCreatable));
         self.insert(Http3ClientEvent::GoawayReceived);
     }
 
+    /// Add a new `RequestMetrics` event.
+    pub(crate) fn request_metrics(&self, metrics: RequestMetrics) {
+        self.insert(Http3ClientEvent::RequestMetrics(metrics));
+    }
+
+    /// Add a new `OriginReceived` event.
+    pub(crate) fn origin_received(&self) {
+        self.insert(Http3ClientEvent::OriginReceived);
+    }
+
     pub fn insert(&self, event: Http3ClientEvent) {
         self.events.borrow_mut().push_back(event);
     }
@@ -214,6 +270,8 @@ impl Http3ClientEvents {
                 Http3ClientEvent::HeaderReady { stream_id: x, .. }
                 | Http3ClientEvent::DataWritable { stream_id: x }
                 | Http3ClientEvent::DataReadable { stream_id: x }
+                | Http3ClientEvent::TrailersReady { stream_id: x, .. }
+                | Http3ClientEvent::Datagram { stream_id: x, .. }
                 | Http3ClientEvent::PushPromise { request_stream_id: x, .. }
                 | Http3ClientEvent::Reset { stream_id: x, .. }
                 | Http3ClientEvent::StopSending { stream_id: x, .. } if *x == stream_id)
@@ -235,6 +293,7 @@ impl Http3ClientEvents {
                 Http3ClientEvent::PushPromise{ push_id: x, .. }
                 | Http3ClientEvent::PushHeaderReady{ push_id: x, .. }
                 | Http3ClientEvent::PushDataReadable{ push_id: x, .. }
+                | Http3ClientEvent::PushTrailersReady{ push_id: x, .. }
                 | Http3ClientEvent::PushCanceled{ push_id: x, .. } if *x == push_id)
         });
     }
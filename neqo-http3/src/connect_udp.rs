@@ -0,0 +1,278 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Proxying UDP in HTTP (connect-udp, RFC 9298).
+//
+// A UDP proxy session is an extended CONNECT request with the `connect-udp` protocol. UDP
+// payloads are carried in HTTP Datagrams with context ID 0. Once SETTINGS_H3_DATAGRAM has been
+// negotiated they are sent in QUIC DATAGRAM frames (RFC 9297, Section 2.1). Otherwise they are
+// sent as DATAGRAM capsules in the data of the request stream (RFC 9297, Section 3.5), so a
+// proxy session works without HTTP Datagram support as well.
+
+use crate::capsule::{encode_capsule, CapsuleReader, CAPSULE_TYPE_DATAGRAM};
+use crate::{Error, Header, Res};
+use neqo_common::{Decoder, Encoder};
+
+pub const CONNECT_UDP_PROTOCOL: &str = "connect-udp";
+const CONNECT_UDP_PATH_PREFIX: &str = "/.well-known/masque/udp/";
+const UDP_CONTEXT_ID: u64 = 0;
+
+/// The target of a UDP proxy session, i.e. the host and port that UDP payloads are sent to by
+/// the proxy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectUdpTarget {
+    host: String,
+    port: u16,
+}
+
+impl ConnectUdpTarget {
+    #[must_use]
+    pub fn new(host: &str, port: u16) -> Self {
+        Self {
+            host: host.to_owned(),
+            port,
+        }
+    }
+
+    #[must_use]
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    #[must_use]
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The `:path` of a request for this target, using the default URI template
+    /// `/.well-known/masque/udp/{target_host}/{target_port}/`.
+    #[must_use]
+    pub fn path(&self) -> String {
+        format!(
+            "{}{}/{}/",
+            CONNECT_UDP_PATH_PREFIX,
+            self.host.replace(':', "%3A"),
+            self.port
+        )
+    }
+
+    /// Get the target of a connect-udp request from its headers. A server uses this to find out
+    /// whether a request opens a UDP proxy session; it accepts the session by sending a 2xx
+    /// response with `set_response_headers`.
+    /// Returns `None` if this is not a connect-udp request or if its path does not follow the
+    /// default URI template.
+    #[must_use]
+    pub fn from_headers(headers: &[Header]) -> Option<Self> {
+        let get = |n: &str| {
+            headers
+                .iter()
+                .find(|(name, _)| name == n)
+                .map(|(_, value)| value.as_str())
+        };
+        if get(":method") != Some("CONNECT") || get(":protocol") != Some(CONNECT_UDP_PROTOCOL) {
+            return None;
+        }
+        let path = get(":path")?;
+        if !path.starts_with(CONNECT_UDP_PATH_PREFIX) {
+            return None;
+        }
+        let mut parts = path[CONNECT_UDP_PATH_PREFIX.len()..].split('/');
+        let host = parts.next()?.replace("%3A", ":").replace("%3a", ":");
+        let port = parts.next()?.parse().ok()?;
+        if host.is_empty() || parts.next() != Some("") || parts.next().is_some() {
+            return None;
+        }
+        Some(Self { host, port })
+    }
+}
+
+/// Encode a UDP payload as a DATAGRAM capsule.
+pub(crate) fn encode_udp_payload(payload: &[u8]) -> Vec<u8> {
+    encode_capsule(CAPSULE_TYPE_DATAGRAM, |enc| {
+        enc.encode_varint(UDP_CONTEXT_ID);
+        enc.encode(payload);
+    })
+}
+
+/// Encode a UDP payload as the payload of an HTTP Datagram.
+pub(crate) fn encode_udp_datagram(payload: &[u8]) -> Vec<u8> {
+    let mut enc = Encoder::with_capacity(payload.len() + 1);
+    enc.encode_varint(UDP_CONTEXT_ID);
+    enc.encode(payload);
+    enc.into()
+}
+
+/// Take the UDP payload from an HTTP Datagram, or `None` if it has another context ID.
+fn decode_udp_payload(datagram: &[u8], err: Error) -> Res<Option<Vec<u8>>> {
+    let mut dec = Decoder::new(datagram);
+    match dec.decode_varint() {
+        Some(UDP_CONTEXT_ID) => Ok(Some(dec.decode_remainder().to_vec())),
+        Some(_) => Ok(None),
+        None => Err(err),
+    }
+}
+
+/// Extracts UDP payloads from the data of a UDP proxy session. The data read from the stream,
+/// i.e. `Http3Client::read_response_data` on the client and `Http3ServerEvent::Data` on the
+/// server, is passed to `receive` and complete payloads are then taken with `next_payload`.
+/// Payloads that arrive in HTTP Datagrams are taken with `payload_from_datagram`.
+#[derive(Debug, Default)]
+pub struct ConnectUdpReader {
+    capsules: CapsuleReader,
+}
+
+impl ConnectUdpReader {
+    /// # Errors
+    /// `HttpExcessiveLoad` if too much data is waiting to be taken with `next_payload`.
+    pub fn receive(&mut self, data: &[u8]) -> Res<()> {
+        self.capsules.receive(data)
+    }
+
+    /// Take the next complete UDP payload. Capsules of unknown types and datagrams with an
+    /// unknown context ID are skipped.
+    /// # Errors
+    /// `HttpGeneralProtocolStream` if a DATAGRAM capsule does not contain a context ID and
+    /// `HttpDatagramStream` if a capsule is too long.
+    pub fn next_payload(&mut self) -> Res<Option<Vec<u8>>> {
+        while let Some((capsule_type, capsule)) = self.capsules.next_capsule()? {
+            if capsule_type != CAPSULE_TYPE_DATAGRAM {
+                continue;
+            }
+            if let Some(payload) = decode_udp_payload(&capsule, Error::HttpGeneralProtocolStream)? {
+                return Ok(Some(payload));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Take the UDP payload from an HTTP Datagram that was received on a UDP proxy session,
+    /// i.e. from `Http3ClientEvent::Datagram` or `Http3ServerEvent::Datagram`. Returns `None`
+    /// for a datagram with an unknown context ID.
+    /// # Errors
+    /// `HttpDatagram` if the datagram does not contain a context ID.
+    pub fn payload_from_datagram(datagram: &[u8]) -> Res<Option<Vec<u8>>> {
+        decode_udp_payload(datagram, Error::HttpDatagram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_udp_datagram, encode_udp_payload, ConnectUdpReader, ConnectUdpTarget};
+    use crate::Error;
+
+    fn headers(method: &str, protocol: &str, path: &str) -> Vec<(String, String)> {
+        vec![
+            (String::from(":method"), String::from(method)),
+            (String::from(":protocol"), String::from(protocol)),
+            (String::from(":scheme"), String::from("https")),
+            (String::from(":authority"), String::from("proxy.example")),
+            (String::from(":path"), String::from(path)),
+        ]
+    }
+
+    #[test]
+    fn target_path() {
+        let target = ConnectUdpTarget::new("192.0.2.6", 443);
+        assert_eq!(target.path(), "/.well-known/masque/udp/192.0.2.6/443/");
+        let target = ConnectUdpTarget::new("2001:db8::42", 53);
+        assert_eq!(
+            target.path(),
+            "/.well-known/masque/udp/2001%3Adb8%3A%3A42/53/"
+        );
+    }
+
+    #[test]
+    fn target_from_headers() {
+        for target in &[
+            ConnectUdpTarget::new("example.com", 443),
+            ConnectUdpTarget::new("2001:db8::42", 53),
+        ] {
+            let h = headers("CONNECT", "connect-udp", &target.path());
+            assert_eq!(ConnectUdpTarget::from_headers(&h).as_ref(), Some(target));
+        }
+    }
+
+    #[test]
+    fn target_from_wrong_headers() {
+        let path = "/.well-known/masque/udp/example.com/443/";
+        assert!(ConnectUdpTarget::from_headers(&headers("GET", "connect-udp", path)).is_none());
+        assert!(ConnectUdpTarget::from_headers(&headers("CONNECT", "websocket", path)).is_none());
+        for path in &[
+            "/",
+            "/.well-known/masque/udp/example.com/",
+            "/.well-known/masque/udp/example.com/https/",
+            "/.well-known/masque/udp//443/",
+            "/.well-known/masque/udp/example.com/443/x",
+        ] {
+            let h = headers("CONNECT", "connect-udp", path);
+            assert!(ConnectUdpTarget::from_headers(&h).is_none());
+        }
+    }
+
+    #[test]
+    fn capsule() {
+        assert_eq!(
+            encode_udp_payload(&[1, 2, 3]),
+            vec![0x00, 0x04, 0x00, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn read_payloads() {
+        let mut data = encode_udp_payload(&[1, 2, 3]);
+        data.extend_from_slice(&encode_udp_payload(&[]));
+        data.extend_from_slice(&encode_udp_payload(&[4; 100]));
+
+        // Deliver the data one byte at a time.
+        let mut reader = ConnectUdpReader::default();
+        let mut payloads = Vec::new();
+        for b in data {
+            reader.receive(&[b]).unwrap();
+            while let Some(p) = reader.next_payload().unwrap() {
+                payloads.push(p);
+            }
+        }
+        assert_eq!(payloads, vec![vec![1, 2, 3], vec![], vec![4; 100]]);
+    }
+
+    #[test]
+    fn read_skips_unknown() {
+        let mut reader = ConnectUdpReader::default();
+        // An unknown capsule type, a datagram with context ID 2 and then a UDP payload.
+        reader
+            .receive(&[
+                0x17, 0x02, 0xaa, 0xbb, 0x00, 0x02, 0x02, 0xcc, 0x00, 0x02, 0x00, 0xdd,
+            ])
+            .unwrap();
+        assert_eq!(reader.next_payload(), Ok(Some(vec![0xdd])));
+        assert_eq!(reader.next_payload(), Ok(None));
+    }
+
+    #[test]
+    fn read_without_context_id() {
+        let mut reader = ConnectUdpReader::default();
+        reader.receive(&[0x00, 0x00]).unwrap();
+        assert_eq!(reader.next_payload(), Err(Error::HttpGeneralProtocolStream));
+    }
+
+    #[test]
+    fn datagram() {
+        let datagram = encode_udp_datagram(&[1, 2, 3]);
+        assert_eq!(datagram, vec![0x00, 1, 2, 3]);
+        assert_eq!(
+            ConnectUdpReader::payload_from_datagram(&datagram),
+            Ok(Some(vec![1, 2, 3]))
+        );
+        assert_eq!(
+            ConnectUdpReader::payload_from_datagram(&[0x02, 1]),
+            Ok(None)
+        );
+        assert_eq!(
+            ConnectUdpReader::payload_from_datagram(&[]),
+            Err(Error::HttpDatagram)
+        );
+    }
+}
@@ -6,23 +6,28 @@
 
 #![allow(clippy::module_name_repetitions)]
 
+use crate::connect_udp::{encode_udp_datagram, encode_udp_payload};
 use crate::control_stream_local::{ControlStreamLocal, HTTP3_UNI_STREAM_TYPE_CONTROL};
 use crate::control_stream_remote::ControlStreamRemote;
-use crate::hframe::HFrame;
+use crate::headers_checks::field_section_size;
+use crate::hframe::{grease_value, HFrame};
 use crate::send_message::SendMessage;
 use crate::settings::{HSetting, HSettingType, HSettings, HttpZeroRttChecker};
 use crate::stream_type_reader::NewStreamTypeReader;
+use crate::webtransport::{WebTransportStreamReader, WEBTRANSPORT_UNI_STREAM_TYPE};
 use crate::{RecvStream, ResetType};
-use neqo_common::{qdebug, qerror, qinfo, qtrace, qwarn};
+use neqo_common::{qdebug, qerror, qinfo, qtrace, qwarn, Decoder, Encoder};
+use neqo_crypto::random;
 use neqo_qpack::decoder::{QPackDecoder, QPACK_UNI_STREAM_TYPE_DECODER};
 use neqo_qpack::encoder::{QPackEncoder, QPACK_UNI_STREAM_TYPE_ENCODER};
 use neqo_qpack::QpackSettings;
 use neqo_transport::{AppError, CloseError, Connection, State, StreamType};
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::mem;
 
-use crate::{Error, Res};
+use crate::{Error, Header, Res};
 
 const HTTP3_UNI_STREAM_TYPE_PUSH: u64 = 0x1;
 const QPACK_TABLE_SIZE_LIMIT: u64 = 1 << 30;
@@ -58,19 +63,68 @@ impl Http3State {
     }
 }
 
+/// Where an HTTP/3 or QPACK error that closed the connection was found. Errors in QUIC frames
+/// have a `neqo_transport::ErrorContext` instead.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ErrorContext {
+    pub stream_id: u64,
+    /// The type of the stream if it is the control stream or a QPACK stream of the peer.
+    pub stream_type: Option<u64>,
+}
+
+impl ::std::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "stream {}", self.stream_id)?;
+        match self.stream_type {
+            Some(HTTP3_UNI_STREAM_TYPE_CONTROL) => write!(f, " (control)"),
+            Some(QPACK_UNI_STREAM_TYPE_ENCODER) => write!(f, " (QPACK encoder)"),
+            Some(QPACK_UNI_STREAM_TYPE_DECODER) => write!(f, " (QPACK decoder)"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The `max_datagram_frame_size` transport parameter that is sent when HTTP Datagrams are
+/// enabled. QUIC datagrams are still limited by the path MTU.
+pub(crate) const LOCAL_MAX_DATAGRAM_FRAME_SIZE: u64 = 65535;
+
 #[derive(Debug)]
 pub(crate) struct Http3Connection {
     pub state: Http3State,
     local_qpack_settings: QpackSettings,
+    // Whether SETTINGS_ENABLE_CONNECT_PROTOCOL is sent, i.e. extended CONNECT is accepted.
+    local_enable_connect_protocol: bool,
+    // Whether SETTINGS_ENABLE_WEBTRANSPORT is sent, i.e. WebTransport streams are accepted.
+    local_enable_webtransport: bool,
+    // Whether SETTINGS_H3_DATAGRAM is sent, i.e. HTTP Datagrams (RFC 9297) are accepted.
+    local_enable_h3_datagram: bool,
+    // Settings that the application sends in addition to the ones above.
+    local_extension_settings: Vec<HSetting>,
+    // Whether a stream of a reserved type is opened and reserved frames are sent on requests.
+    grease: bool,
+    // The SETTINGS_MAX_FIELD_SECTION_SIZE that is sent; received header sections that are
+    // larger are discarded. There is no limit if this is not set.
+    local_max_field_section_size: Option<u64>,
+    // The origins that are sent in an ORIGIN frame; only a server sends them.
+    local_origins: Vec<String>,
     control_stream_local: ControlStreamLocal,
     control_stream_remote: ControlStreamRemote,
     new_streams: HashMap<u64, NewStreamTypeReader>,
+    // WebTransport streams whose session ID has not been read yet.
+    webtransport_pending: HashMap<u64, WebTransportStreamReader>,
+    // WebTransport streams whose session ID has been read, as (stream ID, session ID). They are
+    // taken by the client or the server, which keep track of the sessions.
+    new_webtransport_streams: Vec<(u64, u64)>,
     pub qpack_encoder: QPackEncoder,
     pub qpack_decoder: QPackDecoder,
     settings_state: Http3RemoteSettingsState,
     streams_have_data_to_send: BTreeSet<u64>,
     pub send_streams: HashMap<u64, SendMessage>,
     pub recv_streams: HashMap<u64, Box<dyn RecvStream>>,
+    // Streams that carry a CONNECT tunnel. Closed streams are removed when a tunnel is added.
+    connect_tunnels: HashSet<u64>,
+    // Where the error that closed the connection was found.
+    error_context: Option<ErrorContext>,
 }
 
 impl ::std::fmt::Display for Http3Connection {
@@ -81,7 +135,12 @@ impl ::std::fmt::Display for Http3Connection {
 
 impl Http3Connection {
     /// Create a new connection.
-    pub fn new(local_qpack_settings: QpackSettings) -> Self {
+    pub fn new(
+        local_qpack_settings: QpackSettings,
+        local_enable_connect_protocol: bool,
+        local_enable_webtransport: bool,
+        local_enable_h3_datagram: bool,
+    ) -> Self {
         if (local_qpack_settings.max_table_size_encoder >= QPACK_TABLE_SIZE_LIMIT)
             || (local_qpack_settings.max_table_size_decoder >= QPACK_TABLE_SIZE_LIMIT)
         {
@@ -90,15 +149,26 @@ impl Http3Connection {
         Self {
             state: Http3State::Initializing,
             local_qpack_settings,
+            local_enable_connect_protocol,
+            local_enable_webtransport,
+            local_enable_h3_datagram,
+            local_extension_settings: Vec::new(),
+            grease: false,
+            local_max_field_section_size: None,
+            local_origins: Vec::new(),
             control_stream_local: ControlStreamLocal::default(),
             control_stream_remote: ControlStreamRemote::new(),
             new_streams: HashMap::new(),
+            webtransport_pending: HashMap::new(),
+            new_webtransport_streams: Vec::new(),
             qpack_encoder: QPackEncoder::new(local_qpack_settings, true),
             qpack_decoder: QPackDecoder::new(local_qpack_settings),
             settings_state: Http3RemoteSettingsState::NotReceived,
             streams_have_data_to_send: BTreeSet::new(),
             send_streams: HashMap::new(),
             recv_streams: HashMap::new(),
+            connect_tunnels: HashSet::new(),
+            error_context: None,
         }
     }
 
@@ -108,26 +178,119 @@ impl Http3Connection {
 
         self.send_settings();
         self.create_qpack_streams(conn)?;
+        if self.grease {
+            Self::send_grease_stream(conn);
+        }
         Ok(())
     }
 
+    // Open a unidirectional stream of a reserved type with some random data. The peer must
+    // ignore it, so it does not matter if this fails.
+    fn send_grease_stream(conn: &mut Connection) {
+        if let Ok(stream_id) = conn.stream_create(StreamType::UniDi) {
+            qdebug!("Send a grease stream {}.", stream_id);
+            let r = random(8);
+            let mut enc = Encoder::default();
+            enc.encode_varint(grease_value());
+            enc.encode(&r[1..usize::from(1 + (r[0] & 0x7))]);
+            let _ = conn.stream_send(stream_id, &enc);
+            let _ = conn.stream_close_send(stream_id);
+        }
+    }
+
     fn send_settings(&mut self) {
         qdebug!([self], "Send settings.");
+        let mut settings = vec![
+            HSetting {
+                setting_type: HSettingType::MaxTableCapacity,
+                value: self.qpack_decoder.get_max_table_size(),
+            },
+            HSetting {
+                setting_type: HSettingType::BlockedStreams,
+                value: self.qpack_decoder.get_blocked_streams().into(),
+            },
+        ];
+        if let Some(max) = self.local_max_field_section_size {
+            settings.push(HSetting::new(HSettingType::MaxHeaderListSize, max));
+        }
+        if self.local_enable_connect_protocol {
+            settings.push(HSetting::new(HSettingType::EnableConnectProtocol, 1));
+        }
+        if self.local_enable_webtransport {
+            settings.push(HSetting::new(HSettingType::EnableWebTransport, 1));
+        }
+        if self.local_enable_h3_datagram {
+            settings.push(HSetting::new(HSettingType::EnableH3Datagram, 1));
+        }
+        settings.extend_from_slice(&self.local_extension_settings);
         self.control_stream_local.queue_frame(&HFrame::Settings {
-            settings: HSettings::new(&[
-                HSetting {
-                    setting_type: HSettingType::MaxTableCapacity,
-                    value: self.qpack_decoder.get_max_table_size(),
-                },
-                HSetting {
-                    setting_type: HSettingType::BlockedStreams,
-                    value: self.qpack_decoder.get_blocked_streams().into(),
-                },
-            ]),
+            settings: HSettings::new(&settings),
         });
+        if !self.local_origins.is_empty() {
+            self.control_stream_local.queue_frame(&HFrame::Origin {
+                origins: self.local_origins.clone(),
+            });
+        }
         self.control_stream_local.queue_frame(&HFrame::Grease);
     }
 
+    /// Set the settings that are sent in addition to the ones managed by neqo-http3. This must
+    /// be called before the SETTINGS frame is sent.
+    pub(crate) fn set_extension_settings(&mut self, settings: Vec<HSetting>) {
+        self.local_extension_settings = settings;
+    }
+
+    /// Set the origins that are sent in an ORIGIN frame after the SETTINGS frame.
+    pub(crate) fn set_origins(&mut self, origins: Vec<String>) {
+        self.local_origins = origins;
+    }
+
+    /// Open a stream of a reserved type when the connection is established, and send a frame
+    /// of a reserved type before the headers of each request or response.
+    pub(crate) fn set_grease(&mut self, grease: bool) {
+        self.grease = grease;
+    }
+
+    /// Set the SETTINGS_MAX_FIELD_SECTION_SIZE that is sent. This must be called before the
+    /// SETTINGS frame is sent.
+    pub(crate) fn set_max_field_section_size(&mut self, max: u64) {
+        self.local_max_field_section_size = Some(max);
+    }
+
+    /// The limit for received header sections, if there is one.
+    pub(crate) fn local_max_field_section_size(&self) -> Option<u64> {
+        self.local_max_field_section_size
+    }
+
+    /// Check that the peer accepts `headers`, i.e. that they are not larger than the
+    /// SETTINGS_MAX_FIELD_SECTION_SIZE it has sent. Settings remembered for 0-RTT count as well.
+    /// # Errors
+    /// `FieldSectionTooLarge` if the peer would not accept the headers.
+    pub(crate) fn check_peer_field_section_size(&self, headers: &[Header]) -> Res<()> {
+        let max = match &self.settings_state {
+            Http3RemoteSettingsState::Received(settings)
+            | Http3RemoteSettingsState::ZeroRtt(settings) => {
+                settings.get(HSettingType::MaxHeaderListSize)
+            }
+            Http3RemoteSettingsState::NotReceived => return Ok(()),
+        };
+        if field_section_size(headers) > max {
+            qinfo!([self], "The field section exceeds the limit of the peer.");
+            return Err(Error::FieldSectionTooLarge);
+        }
+        Ok(())
+    }
+
+    /// The settings received from the peer that are not managed by neqo-http3. Settings
+    /// remembered for 0-RTT count as well.
+    pub(crate) fn peer_extension_settings(&self) -> Vec<(u64, u64)> {
+        match &self.settings_state {
+            Http3RemoteSettingsState::Received(settings)
+            | Http3RemoteSettingsState::ZeroRtt(settings) => settings.extensions(),
+            Http3RemoteSettingsState::NotReceived => Vec::new(),
+        }
+    }
+
     /// Save settings for adding to the session ticket.
     pub(crate) fn save_settings(&self) -> Vec<u8> {
         HttpZeroRttChecker::save(self.local_qpack_settings)
@@ -190,6 +353,196 @@ impl Http3Connection {
         Ok(())
     }
 
+    /// Whether the peer accepts extended CONNECT requests, i.e. requests with a `:protocol`
+    /// pseudo-header. Settings remembered for 0-RTT count as well.
+    pub fn peer_enables_connect_protocol(&self) -> bool {
+        match &self.settings_state {
+            Http3RemoteSettingsState::Received(settings)
+            | Http3RemoteSettingsState::ZeroRtt(settings) => {
+                settings.get(HSettingType::EnableConnectProtocol) == 1
+            }
+            Http3RemoteSettingsState::NotReceived => false,
+        }
+    }
+
+    /// Whether the peer accepts WebTransport sessions. Settings remembered for 0-RTT count as
+    /// well.
+    pub fn peer_enables_webtransport(&self) -> bool {
+        match &self.settings_state {
+            Http3RemoteSettingsState::Received(settings)
+            | Http3RemoteSettingsState::ZeroRtt(settings) => {
+                settings.get(HSettingType::EnableWebTransport) == 1
+            }
+            Http3RemoteSettingsState::NotReceived => false,
+        }
+    }
+
+    /// Whether WebTransport has been enabled locally.
+    pub fn enables_webtransport(&self) -> bool {
+        self.local_enable_webtransport
+    }
+
+    /// Whether the peer accepts HTTP Datagrams. Settings remembered for 0-RTT count as well.
+    pub fn peer_enables_h3_datagram(&self) -> bool {
+        match &self.settings_state {
+            Http3RemoteSettingsState::Received(settings)
+            | Http3RemoteSettingsState::ZeroRtt(settings) => {
+                settings.get(HSettingType::EnableH3Datagram) == 1
+            }
+            Http3RemoteSettingsState::NotReceived => false,
+        }
+    }
+
+    /// Whether HTTP Datagrams can be sent, i.e. both endpoints sent SETTINGS_H3_DATAGRAM.
+    pub fn h3_datagram_negotiated(&self) -> bool {
+        self.local_enable_h3_datagram && self.peer_enables_h3_datagram()
+    }
+
+    /// The largest HTTP Datagram payload that can be sent on a request. This is limited by
+    /// the size of the QUIC datagrams that the peer accepts.
+    /// # Errors
+    /// `Unavailable` if HTTP Datagrams have not been negotiated,
+    /// `InvalidStreamId` if `stream_id` is not an open request stream.
+    pub fn max_datagram_size(&self, conn: &Connection, stream_id: u64) -> Res<u64> {
+        if !self.h3_datagram_negotiated() {
+            return Err(Error::Unavailable);
+        }
+        if stream_id % 4 != 0
+            || !(self.send_streams.contains_key(&stream_id)
+                || self.recv_streams.contains_key(&stream_id))
+        {
+            return Err(Error::InvalidStreamId);
+        }
+        let max = conn.max_datagram_size().map_err(|_| Error::Unavailable)?;
+        let quarter_stream_id_len = u64::try_from(Encoder::varint_len(stream_id / 4)).unwrap();
+        Ok(max.saturating_sub(quarter_stream_id_len))
+    }
+
+    /// Send an HTTP Datagram that belongs to the request on `stream_id`. It is carried in a
+    /// QUIC DATAGRAM frame, prefixed with the quarter stream ID of the request.
+    /// # Errors
+    /// `Unavailable` if HTTP Datagrams have not been negotiated,
+    /// `InvalidStreamId` if `stream_id` is not an open request stream,
+    /// `InvalidInput` if `buf` is larger than `max_datagram_size`.
+    pub fn send_datagram(&self, conn: &mut Connection, stream_id: u64, buf: &[u8]) -> Res<()> {
+        if u64::try_from(buf.len()).unwrap() > self.max_datagram_size(conn, stream_id)? {
+            return Err(Error::InvalidInput);
+        }
+        let mut enc = Encoder::with_capacity(buf.len() + 8);
+        enc.encode_varint(stream_id / 4);
+        enc.encode(buf);
+        conn.send_datagram(&enc).map_err(|_| Error::Unavailable)
+    }
+
+    /// Send an HTTP Datagram like `send_datagram`, but drop it if it cannot be sent, e.g. because
+    /// it does not fit into a QUIC datagram. Returns whether it has been sent.
+    /// # Errors
+    /// `InvalidStreamId` if `stream_id` is not an open request stream.
+    pub fn send_unreliable_datagram(
+        &self,
+        conn: &mut Connection,
+        stream_id: u64,
+        buf: &[u8],
+    ) -> Res<bool> {
+        match self.send_datagram(conn, stream_id, buf) {
+            Ok(()) => Ok(true),
+            Err(Error::InvalidInput) | Err(Error::Unavailable) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Send a UDP payload on a connect-udp session. It is sent in an HTTP Datagram once they
+    /// have been negotiated and as a DATAGRAM capsule on the request stream otherwise. Like a
+    /// UDP datagram, the payload is dropped if it cannot be sent at once. Returns whether it
+    /// has been sent.
+    /// # Errors
+    /// `InvalidStreamId` if the stream does not exist, or a transport error.
+    pub fn send_udp_payload(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        payload: &[u8],
+    ) -> Res<bool> {
+        if self.h3_datagram_negotiated() {
+            return self.send_unreliable_datagram(conn, stream_id, &encode_udp_datagram(payload));
+        }
+        self.send_streams
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?
+            .send_body_atomic(conn, &encode_udp_payload(payload))
+    }
+
+    /// Handle a received QUIC datagram. Returns the request stream that the HTTP Datagram
+    /// belongs to and its payload, or `None` if the datagram is dropped because HTTP Datagrams
+    /// are not enabled or the request is not open (anymore).
+    /// # Errors
+    /// `HttpDatagram` if the datagram does not start with a valid quarter stream ID.
+    pub fn handle_datagram(&self, datagram: &[u8]) -> Res<Option<(u64, Vec<u8>)>> {
+        if !self.local_enable_h3_datagram {
+            qdebug!([self], "Drop a datagram, HTTP Datagrams are not enabled.");
+            return Ok(None);
+        }
+        let mut dec = Decoder::from(datagram);
+        let quarter_stream_id = dec.decode_varint().ok_or(Error::HttpDatagram)?;
+        if quarter_stream_id >= (1 << 60) {
+            return Err(Error::HttpDatagram);
+        }
+        let stream_id = quarter_stream_id * 4;
+        if !self.send_streams.contains_key(&stream_id)
+            && !self.recv_streams.contains_key(&stream_id)
+        {
+            qdebug!([self], "Drop a datagram for stream {}.", stream_id);
+            return Ok(None);
+        }
+        Ok(Some((stream_id, dec.decode_remainder().to_vec())))
+    }
+
+    /// Take the WebTransport streams whose session ID has been read.
+    pub fn take_new_webtransport_streams(&mut self) -> Vec<(u64, u64)> {
+        mem::replace(&mut self.new_webtransport_streams, Vec::new())
+    }
+
+    /// A bidirectional stream opened by the server can only be a WebTransport stream. Its
+    /// session ID is read like the type of a unidirectional stream.
+    pub fn handle_new_webtransport_bidi_stream(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+    ) -> Res<()> {
+        if !self.local_enable_webtransport {
+            return Err(Error::HttpStreamCreation);
+        }
+        qinfo!([self], "A new WebTransport bidi stream {}.", stream_id);
+        self.webtransport_pending
+            .insert(stream_id, WebTransportStreamReader::new(StreamType::BiDi));
+        self.read_webtransport_session_id(conn, stream_id);
+        Ok(())
+    }
+
+    fn read_webtransport_session_id(&mut self, conn: &mut Connection, stream_id: u64) {
+        let (session_id, fin) = match self.webtransport_pending.get_mut(&stream_id) {
+            Some(r) => (r.get_session_id(conn, stream_id), r.fin()),
+            None => return,
+        };
+        match session_id {
+            Ok(None) => {
+                if fin {
+                    self.webtransport_pending.remove(&stream_id);
+                }
+            }
+            Ok(Some(session_id)) => {
+                self.webtransport_pending.remove(&stream_id);
+                self.new_webtransport_streams.push((stream_id, session_id));
+            }
+            Err(e) => {
+                // Only this stream is closed.
+                self.webtransport_pending.remove(&stream_id);
+                let _ = conn.stream_stop_sending(stream_id, e.code());
+                let _ = conn.stream_reset_send(stream_id, e.code());
+            }
+        }
+    }
+
     /// Returns the settings for a connection. This is used for creating a resumption token.
     pub fn get_settings(&self) -> Option<HSettings> {
         if let Http3RemoteSettingsState::Received(settings) = &self.settings_state {
@@ -236,7 +589,16 @@ impl Http3Connection {
             let unblocked_streams = self.qpack_decoder.receive(conn, stream_id)?;
             for stream_id in unblocked_streams {
                 qdebug!([self], "Stream {} is unblocked", stream_id);
-                self.handle_read_stream(conn, stream_id, true)?;
+                if let Err(e) = self.handle_read_stream(conn, stream_id, true) {
+                    if !e.stream_reset_error() {
+                        return Err(e);
+                    }
+                    // Only the unblocked stream is reset, not the QPACK encoder stream.
+                    let _ = conn.stream_stop_sending(stream_id, e.code());
+                    if let Some(s) = self.recv_streams.remove(&stream_id) {
+                        s.stream_reset(e.code(), &mut self.qpack_decoder, ResetType::Local);
+                    }
+                }
             }
             Ok(true)
         } else {
@@ -250,6 +612,14 @@ impl Http3Connection {
 
         loop {
             if let Some(f) = self.control_stream_remote.receive(conn)? {
+                if let HFrame::Settings { settings } = &f {
+                    // HTTP Datagrams need QUIC datagrams.
+                    if settings.get(HSettingType::EnableH3Datagram) == 1
+                        && conn.max_datagram_size().is_err()
+                    {
+                        return Err(Error::HttpSettings);
+                    }
+                }
                 if let Some(f) = self.handle_control_frame(f)? {
                     control_frames.push(f);
                 }
@@ -261,6 +631,28 @@ impl Http3Connection {
         }
     }
 
+    /// Note that the connection is closed because of an error on `stream_id`.
+    pub fn set_error_context(&mut self, stream_id: u64) {
+        let stream_type = if self.control_stream_remote.is_recv_stream(stream_id) {
+            Some(HTTP3_UNI_STREAM_TYPE_CONTROL)
+        } else if self.qpack_encoder.remote_stream_id() == Some(stream_id) {
+            Some(QPACK_UNI_STREAM_TYPE_DECODER)
+        } else if self.qpack_decoder.remote_stream_id() == Some(stream_id) {
+            Some(QPACK_UNI_STREAM_TYPE_ENCODER)
+        } else {
+            None
+        };
+        self.error_context = Some(ErrorContext {
+            stream_id,
+            stream_type,
+        });
+    }
+
+    /// Where the error that closed the connection was found, if it was found on a stream.
+    pub fn error_context(&self) -> Option<&ErrorContext> {
+        self.error_context.as_ref()
+    }
+
     /// This function handles reading from all streams, i.e. control, qpack, request/response
     /// stream and unidi stream that are still do not have a type.
     /// The function cannot handle:
@@ -319,6 +711,9 @@ impl Http3Connection {
                 };
             }
 
+            Ok(HandleReadableOutput::NoOutput)
+        } else if self.webtransport_pending.contains_key(&stream_id) {
+            self.read_webtransport_session_id(conn, stream_id);
             Ok(HandleReadableOutput::NoOutput)
         } else {
             // For a new stream we receive NewStream event and a
@@ -344,8 +739,38 @@ impl Http3Connection {
             .any(|id| stream_id == *id)
     }
 
+    /// Mark `stream_id` as a CONNECT tunnel, see `handle_stream_reset`.
+    pub fn add_connect_tunnel(&mut self, stream_id: u64) {
+        let send_streams = &self.send_streams;
+        let recv_streams = &self.recv_streams;
+        self.connect_tunnels
+            .retain(|id| send_streams.contains_key(id) || recv_streams.contains_key(id));
+        self.connect_tunnels.insert(stream_id);
+    }
+
+    /// A CONNECT tunnel fails as a whole when either direction is reset (RFC 9114, Section
+    /// 4.4), so the direction that is still open is closed with `H3_CONNECT_ERROR`.
+    fn connect_tunnel_failed(&mut self, conn: &mut Connection, stream_id: u64) {
+        if !self.connect_tunnels.remove(&stream_id) {
+            return;
+        }
+        let error = Error::HttpConnect.code();
+        if self.send_streams.remove(&stream_id).is_some() {
+            let _ = conn.stream_reset_send(stream_id, error);
+        }
+        if let Some(s) = self.recv_streams.remove(&stream_id) {
+            s.stream_reset(error, &mut self.qpack_decoder, ResetType::Local);
+            let _ = conn.stream_stop_sending(stream_id, error);
+        }
+    }
+
     /// This is called when a RESET frame has been received.
-    pub fn handle_stream_reset(&mut self, stream_id: u64, app_error: AppError) -> Res<()> {
+    pub fn handle_stream_reset(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        app_error: AppError,
+    ) -> Res<()> {
         qinfo!(
             [self],
             "Handle a stream reset stream_id={} app_err={}",
@@ -355,6 +780,7 @@ impl Http3Connection {
 
         if let Some(s) = self.recv_streams.remove(&stream_id) {
             s.stream_reset(app_error, &mut self.qpack_decoder, ResetType::Remote);
+            self.connect_tunnel_failed(conn, stream_id);
             Ok(())
         } else if self.is_critical_stream(stream_id) {
             Err(Error::HttpClosedCriticalStream)
@@ -363,7 +789,12 @@ impl Http3Connection {
         }
     }
 
-    pub fn handle_stream_stop_sending(&mut self, stream_id: u64, app_error: AppError) -> Res<()> {
+    pub fn handle_stream_stop_sending(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        app_error: AppError,
+    ) -> Res<()> {
         qinfo!(
             [self],
             "Handle stream_stop_sending stream_id={} app_err={}",
@@ -373,6 +804,7 @@ impl Http3Connection {
 
         if let Some(mut s) = self.send_streams.remove(&stream_id) {
             s.stop_sending(app_error);
+            self.connect_tunnel_failed(conn, stream_id);
             Ok(())
         } else if self.is_critical_stream(stream_id) {
             Err(Error::HttpClosedCriticalStream)
@@ -424,11 +856,13 @@ impl Http3Connection {
             self.control_stream_local = ControlStreamLocal::default();
             self.control_stream_remote = ControlStreamRemote::new();
             self.new_streams.clear();
+            self.webtransport_pending.clear();
+            self.new_webtransport_streams.clear();
             self.qpack_encoder = QPackEncoder::new(self.local_qpack_settings, true);
             self.qpack_decoder = QPackDecoder::new(self.local_qpack_settings);
             self.settings_state = Http3RemoteSettingsState::NotReceived;
             self.streams_have_data_to_send.clear();
-            // TODO: investigate whether this code can automatically retry failed transactions.
+            // The client sends the requests again once the handshake is done.
             self.send_streams.clear();
             self.recv_streams.clear();
             Ok(())
@@ -464,7 +898,16 @@ impl Http3Connection {
             recv_stream.receive(conn, &mut self.qpack_decoder)?;
         }
         if recv_stream.done() {
+            let webtransport_session = recv_stream.webtransport_session();
             self.recv_streams.remove(&stream_id);
+            if let Some(session_id) = webtransport_session {
+                if !self.local_enable_webtransport {
+                    return Err(Error::HttpFrameUnexpected);
+                }
+                // The stream is not a request, the rest of it is WebTransport data.
+                self.send_streams.remove(&stream_id);
+                self.new_webtransport_streams.push((stream_id, session_id));
+            }
         }
         Ok(true)
     }
@@ -500,8 +943,23 @@ impl Http3Connection {
                     .map_err(|_| Error::HttpStreamCreation)?;
                 Ok(false)
             }
+            WEBTRANSPORT_UNI_STREAM_TYPE if self.local_enable_webtransport => {
+                qinfo!([self], "A new WebTransport stream {}", stream_id);
+                self.webtransport_pending
+                    .insert(stream_id, WebTransportStreamReader::new(StreamType::UniDi));
+                self.read_webtransport_session_id(conn, stream_id);
+                Ok(false)
+            }
             _ => {
-                conn.stream_stop_sending(stream_id, Error::HttpStreamCreation.code())?;
+                // Unknown stream types, e.g. grease, are ignored. The stream may already be
+                // closed, then there is nothing to stop.
+                qdebug!(
+                    [self],
+                    "Ignore stream {} of type {}.",
+                    stream_id,
+                    stream_type
+                );
+                let _ = conn.stream_stop_sending(stream_id, Error::HttpStreamCreation.code());
                 Ok(false)
             }
         }
@@ -576,9 +1034,12 @@ impl Http3Connection {
                 self.handle_settings(settings)?;
                 Ok(None)
             }
-            HFrame::Goaway { .. } | HFrame::MaxPushId { .. } | HFrame::CancelPush { .. } => {
-                Ok(Some(f))
-            }
+            HFrame::Goaway { .. }
+            | HFrame::MaxPushId { .. }
+            | HFrame::CancelPush { .. }
+            | HFrame::Origin { .. }
+            | HFrame::PriorityUpdateRequest { .. }
+            | HFrame::PriorityUpdatePush { .. } => Ok(Some(f)),
             _ => Err(Error::HttpFrameUnexpected),
         }
     }
@@ -591,7 +1052,11 @@ impl Http3Connection {
                 HSettingType::BlockedStreams => {
                     self.qpack_encoder.set_max_blocked_streams(s.value)?
                 }
-                HSettingType::MaxHeaderListSize => (),
+                HSettingType::MaxHeaderListSize
+                | HSettingType::EnableConnectProtocol
+                | HSettingType::EnableWebTransport
+                | HSettingType::EnableH3Datagram
+                | HSettingType::Extension(_) => (),
             }
         }
         Ok(())
@@ -599,6 +1064,12 @@ impl Http3Connection {
 
     fn handle_settings(&mut self, new_settings: HSettings) -> Res<()> {
         qinfo!([self], "Handle SETTINGS frame.");
+        if new_settings.get(HSettingType::EnableConnectProtocol) > 1
+            || new_settings.get(HSettingType::EnableWebTransport) > 1
+            || new_settings.get(HSettingType::EnableH3Datagram) > 1
+        {
+            return Err(Error::HttpSettings);
+        }
         match &self.settings_state {
             Http3RemoteSettingsState::NotReceived => {
                 self.set_qpack_settings(&new_settings)?;
@@ -611,6 +1082,9 @@ impl Http3Connection {
                     HSettingType::MaxHeaderListSize,
                     HSettingType::MaxTableCapacity,
                     HSettingType::BlockedStreams,
+                    HSettingType::EnableConnectProtocol,
+                    HSettingType::EnableWebTransport,
+                    HSettingType::EnableH3Datagram,
                 ] {
                     let zero_rtt_value = settings.get(*st);
                     let new_value = new_settings.get(*st);
@@ -659,9 +1133,12 @@ impl Http3Connection {
     pub fn add_streams(
         &mut self,
         stream_id: u64,
-        send_stream: SendMessage,
+        mut send_stream: SendMessage,
         recv_stream: Box<dyn RecvStream>,
     ) {
+        if self.grease {
+            send_stream.set_grease();
+        }
         if send_stream.has_data_to_send() {
             self.streams_have_data_to_send.insert(stream_id);
         }
@@ -5,26 +5,39 @@
 // except according to those terms.
 
 use crate::client_events::{Http3ClientEvent, Http3ClientEvents};
-use crate::connection::{HandleReadableOutput, Http3Connection, Http3State};
+use crate::connect_udp::{ConnectUdpTarget, CONNECT_UDP_PROTOCOL};
+use crate::connection::{
+    ErrorContext, HandleReadableOutput, Http3Connection, Http3State, LOCAL_MAX_DATAGRAM_FRAME_SIZE,
+};
 use crate::hframe::HFrame;
+use crate::origin;
+use crate::priority::{set_stream_priority, Priority};
 use crate::push_controller::PushController;
 use crate::push_stream::PushStream;
 use crate::recv_message::{MessageType, RecvMessage};
+use crate::request_metrics::RequestMetrics;
 use crate::send_message::{SendMessage, SendMessageEvents};
-use crate::settings::HSettings;
-use crate::{Header, RecvMessageEvents, ResetType};
+use crate::settings::{extension_settings, HSettings};
+use crate::webtransport::{
+    WebTransportEvent, WebTransportEvents, WebTransportSessionListener, WebTransportSessions,
+    WEBTRANSPORT_PROTOCOL,
+};
+use crate::{Header, RecvMessageEvents, Request, ResetType};
 use neqo_common::{
-    event::Provider as EventProvider, hex, hex_with_len, qdebug, qinfo, qlog::NeqoQlog, qtrace,
-    Datagram, Decoder, Encoder, Role,
+    event::Provider as EventProvider, hex, hex_with_len, metrics::MetricsRef, qdebug, qinfo,
+    qlog::NeqoQlog, qtrace, qwarn, Datagram, Decoder, Encoder, Role,
 };
 use neqo_crypto::{agent::CertificateInfo, AuthenticationStatus, ResumptionToken, SecretAgentInfo};
 use neqo_qpack::{stats::Stats, QpackSettings};
 use neqo_transport::{
+    tparams::{self, TransportParameter},
     AppError, CongestionControlAlgorithm, Connection, ConnectionEvent, ConnectionId,
     ConnectionIdManager, Output, QuicVersion, StreamId, StreamType, ZeroRttState,
 };
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt::Display;
+use std::mem;
 use std::net::SocketAddr;
 use std::rc::Rc;
 use std::time::Instant;
@@ -67,9 +80,65 @@ fn alpn_from_quic_version(version: QuicVersion) -> &'static str {
     }
 }
 
+/// The parameters of an `Http3Client`. Extensions such as WebTransport are disabled unless
+/// they are enabled with the methods of the same name.
+#[derive(Debug, Clone, Copy)]
 pub struct Http3Parameters {
     pub qpack_settings: QpackSettings,
     pub max_concurrent_push_streams: u64,
+    webtransport: bool,
+    http3_datagram: bool,
+}
+
+impl Http3Parameters {
+    #[must_use]
+    pub fn new(qpack_settings: QpackSettings, max_concurrent_push_streams: u64) -> Self {
+        Self {
+            qpack_settings,
+            max_concurrent_push_streams,
+            webtransport: false,
+            http3_datagram: false,
+        }
+    }
+
+    /// Enable WebTransport.
+    #[must_use]
+    pub fn webtransport(mut self, enable: bool) -> Self {
+        self.webtransport = enable;
+        self
+    }
+
+    /// Accept HTTP Datagrams (RFC 9297). This sends `SETTINGS_H3_DATAGRAM` and the
+    /// max_datagram_frame_size transport parameter.
+    #[must_use]
+    pub fn http3_datagram(mut self, enable: bool) -> Self {
+        self.http3_datagram = enable;
+        self
+    }
+}
+
+/// What happens to a request that has been sent in 0-RTT if the server rejects 0-RTT.
+#[derive(Debug)]
+enum ZeroRttRetry {
+    /// The request is sent again with the same headers and the body that has been sent so far.
+    Resend {
+        headers: Vec<Header>,
+        body: Vec<u8>,
+        fin: bool,
+    },
+    /// The request cannot be sent again, e.g. a WebTransport session or a request with
+    /// trailers. The application gets a `Reset` event with `HttpRequestRejected`.
+    Reject,
+    /// The application has reset the request.
+    Cancelled,
+}
+
+/// A request that has been sent in 0-RTT. The requests are kept until the handshake is done
+/// so that they can be sent again on the same stream IDs if the server rejects 0-RTT.
+#[derive(Debug)]
+struct ZeroRttRequest {
+    stream_id: u64,
+    retry: ZeroRttRetry,
 }
 
 pub struct Http3Client {
@@ -77,6 +146,12 @@ pub struct Http3Client {
     base_handler: Http3Connection,
     events: Http3ClientEvents,
     push_handler: Rc<RefCell<PushController>>,
+    webtransport: WebTransportSessions,
+    zero_rtt_requests: Vec<ZeroRttRequest>,
+    // The origins the server has advertised in ORIGIN frames.
+    origins: Vec<String>,
+    // The metrics of the requests, if they are collected.
+    request_metrics: Option<HashMap<u64, Rc<RefCell<RequestMetrics>>>>,
 }
 
 impl Display for Http3Client {
@@ -114,15 +189,33 @@ impl Http3Client {
 
     #[must_use]
     pub fn new_with_conn(c: Connection, http3_parameters: &Http3Parameters) -> Self {
+        if http3_parameters.http3_datagram
+            && c.set_local_tparam(
+                tparams::MAX_DATAGRAM_FRAME_SIZE,
+                TransportParameter::Integer(LOCAL_MAX_DATAGRAM_FRAME_SIZE),
+            )
+            .is_err()
+        {
+            qwarn!("Unable to enable QUIC datagrams, the connection has already started.");
+        }
         let events = Http3ClientEvents::default();
         Self {
             conn: c,
-            base_handler: Http3Connection::new(http3_parameters.qpack_settings),
+            base_handler: Http3Connection::new(
+                http3_parameters.qpack_settings,
+                false,
+                http3_parameters.webtransport,
+                http3_parameters.http3_datagram,
+            ),
             events: events.clone(),
             push_handler: Rc::new(RefCell::new(PushController::new(
                 http3_parameters.max_concurrent_push_streams,
                 events,
             ))),
+            webtransport: WebTransportSessions::default(),
+            zero_rtt_requests: Vec::new(),
+            origins: Vec::new(),
+            request_metrics: None,
         }
     }
 
@@ -136,6 +229,13 @@ impl Http3Client {
         self.base_handler.state()
     }
 
+    /// Where the HTTP/3 or QPACK error that closed the connection was found, if it was found
+    /// on a stream. `conn().error_context()` tells where a transport error was found.
+    #[must_use]
+    pub fn error_context(&self) -> Option<&ErrorContext> {
+        self.base_handler.error_context()
+    }
+
     #[must_use]
     pub fn tls_info(&self) -> Option<&SecretAgentInfo> {
         self.conn.tls_info()
@@ -156,6 +256,12 @@ impl Http3Client {
         self.conn.set_qlog(qlog);
     }
 
+    /// Set where the metrics of the connection and of QPACK are reported.
+    pub fn set_metrics(&mut self, metrics: MetricsRef) {
+        self.conn.set_metrics(Rc::clone(&metrics));
+        self.base_handler.qpack_encoder.set_metrics(metrics);
+    }
+
     /// Get the connection id, which is useful for disambiguating connections to
     /// the same origin.
     #[must_use]
@@ -165,15 +271,123 @@ impl Http3Client {
 
     /// A resumption token encodes transport and settings parameter as well.
     fn create_resumption_token(&mut self, token: &ResumptionToken) {
-        if let Some(settings) = self.base_handler.get_settings() {
+        if let Some(token) = self.encode_resumption_token(token) {
+            self.events.resumption_token(token);
+        }
+    }
+
+    fn encode_resumption_token(&self, token: &ResumptionToken) -> Option<ResumptionToken> {
+        self.base_handler.get_settings().map(|settings| {
             let mut enc = Encoder::default();
             settings.encode_frame_contents(&mut enc);
             enc.encode(token.as_ref());
-            self.events
-                .resumption_token(ResumptionToken::new(enc.into(), token.expiration_time()));
+            ResumptionToken::new(enc.into(), token.expiration_time())
+        })
+    }
+
+    /// Get a resumption token without waiting for the `ResumptionToken` event, which is
+    /// useful when the connection is about to close. See `Connection::take_resumption_token`.
+    pub fn take_resumption_token(&mut self, now: Instant) -> Option<ResumptionToken> {
+        let token = self.conn.take_resumption_token(now)?;
+        self.encode_resumption_token(&token)
+    }
+
+    /// Send settings in addition to the ones managed by neqo-http3, e.g. for an extension, as
+    /// identifier and value pairs. This must be called before the connection starts (and
+    /// before `enable_resumption`).
+    /// # Errors
+    /// `InvalidInput` if an identifier is used twice or it is one of the settings managed by
+    /// neqo-http3 or a reserved one, `AlreadyInitialized` if the settings have already been sent.
+    pub fn set_extension_settings(&mut self, settings: &[(u64, u64)]) -> Res<()> {
+        let settings = extension_settings(settings)?;
+        if self.base_handler.state() != Http3State::Initializing {
+            return Err(Error::AlreadyInitialized);
+        }
+        self.base_handler.set_extension_settings(settings);
+        Ok(())
+    }
+
+    /// Send frames and a stream of reserved types (RFC 9114, Section 9), so that servers that
+    /// do not ignore unknown types fail early. A frame of a reserved type is sent before the
+    /// headers of each request. This must be called before the connection starts.
+    /// # Errors
+    /// `AlreadyInitialized` if the connection has already started.
+    pub fn set_grease(&mut self, grease: bool) -> Res<()> {
+        if self.base_handler.state() != Http3State::Initializing {
+            return Err(Error::AlreadyInitialized);
+        }
+        self.base_handler.set_grease(grease);
+        Ok(())
+    }
+
+    /// Limit the size of response headers and trailers (RFC 9114, Section 4.2.2). The limit is
+    /// sent to the server in SETTINGS_MAX_FIELD_SECTION_SIZE. A response with a larger field
+    /// section is discarded and the request is reset with `H3_EXCESSIVE_LOAD`. By default there
+    /// is no limit. This must be called before the connection starts.
+    /// # Errors
+    /// `AlreadyInitialized` if the connection has already started.
+    pub fn set_max_field_section_size(&mut self, max: u64) -> Res<()> {
+        if self.base_handler.state() != Http3State::Initializing {
+            return Err(Error::AlreadyInitialized);
+        }
+        self.base_handler.set_max_field_section_size(max);
+        Ok(())
+    }
+
+    /// Collect timing and transfer metrics of each request. The metrics of a request are
+    /// reported in a `RequestMetrics` event once the request has ended, i.e. its response has
+    /// been read completely or it has been reset or cancelled.
+    pub fn set_request_metrics(&mut self, enable: bool) {
+        if !enable {
+            self.request_metrics = None;
+        } else if self.request_metrics.is_none() {
+            self.request_metrics = Some(HashMap::new());
         }
     }
 
+    fn request_metrics(&self, stream_id: u64) -> Option<Rc<RefCell<RequestMetrics>>> {
+        self.request_metrics.as_ref()?.get(&stream_id).cloned()
+    }
+
+    /// Report the metrics of the requests whose response is not read anymore and forget them.
+    fn report_request_metrics(&mut self) {
+        let recv_streams = &self.base_handler.recv_streams;
+        let events = &self.events;
+        if let Some(request_metrics) = &mut self.request_metrics {
+            request_metrics.retain(|stream_id, metrics| {
+                let active = recv_streams.contains_key(stream_id);
+                if !active {
+                    events.request_metrics(metrics.borrow().clone());
+                }
+                active
+            });
+        }
+    }
+
+    /// The settings received from the server that are not managed by neqo-http3, as
+    /// identifier and value pairs. Before the server SETTINGS frame is received, these are the
+    /// settings remembered from the resumption token, if 0-RTT is used.
+    #[must_use]
+    pub fn peer_extension_settings(&self) -> Vec<(u64, u64)> {
+        self.base_handler.peer_extension_settings()
+    }
+
+    /// The origins that the server has advertised in ORIGIN frames (RFC 9412), in the form
+    /// `scheme://host[:port]`, lowercase and without default ports. This is empty until an
+    /// ORIGIN frame is received; an `OriginReceived` event signals a change.
+    #[must_use]
+    pub fn origins(&self) -> &[String] {
+        &self.origins
+    }
+
+    /// Whether the server has advertised `origin` in an ORIGIN frame. This can be used to
+    /// decide whether a request for another host can be sent on this connection; the request
+    /// should only be sent if the server certificate is valid for that host as well.
+    #[must_use]
+    pub fn is_origin_advertised(&self, origin: &str) -> bool {
+        origin::normalize(origin).map_or(false, |o| self.origins.contains(&o))
+    }
+
     /// This may be call if an application has a resumption token. This must be called before connection starts.
     /// # Errors
     /// An error is return if token cannot be decoded or a connection is is a wrong state.
@@ -182,12 +396,11 @@ impl Http3Client {
             return Err(Error::InvalidState);
         }
         let mut dec = Decoder::from(token.as_ref());
-        let settings_slice = match dec.decode_vvec() {
+        let mut dec_settings = match dec.decode_vvec_sub() {
             Some(v) => v,
             None => return Err(Error::InvalidResumptionToken),
         };
-        qtrace!([self], "  settings {}", hex_with_len(&settings_slice));
-        let mut dec_settings = Decoder::from(settings_slice);
+        qtrace!([self], "  settings {}", hex_with_len(&dec_settings[..]));
         let mut settings = HSettings::default();
         settings
             .decode_frame_contents(&mut dec_settings)
@@ -244,9 +457,11 @@ impl Http3Client {
     // API: Request/response
 
     /// This is call to make a new http request. Each request can have headers and they are added when request
-    /// is created. A response body may be added by calling `send_request_body`.
+    /// is created. A request body may be added by calling `send_request_body` once the stream
+    /// becomes writable (`DataWritable` event).
     /// # Errors
     /// If a new stream cannot be created an error will be return.
+    /// `FieldSectionTooLarge` if the headers exceed the limit the server has advertised.
     pub fn fetch(
         &mut self,
         now: Instant,
@@ -264,6 +479,149 @@ impl Http3Client {
             host,
             path
         );
+        // Transform pseudo-header fields
+        let mut final_headers = Vec::new();
+        final_headers.push((":method".into(), method.to_owned()));
+        final_headers.push((":scheme".into(), scheme.to_owned()));
+        final_headers.push((":authority".into(), host.to_owned()));
+        final_headers.push((":path".into(), path.to_owned()));
+        final_headers.extend_from_slice(headers);
+        self.create_request(now, final_headers, None, Box::new(self.events.clone()))
+    }
+
+    /// Send a complete request: the header fields, the body if the request has one, and the
+    /// end of the request. The response is read as with `fetch`.
+    /// # Errors
+    /// If a new stream cannot be created an error will be return.
+    /// `FieldSectionTooLarge` if the headers exceed the limit the server has advertised.
+    pub fn send_request(&mut self, now: Instant, request: &Request) -> Res<u64> {
+        qinfo!(
+            [self],
+            "Send request method={}, scheme={}, host={}, path={}",
+            request.method(),
+            request.scheme(),
+            request.authority(),
+            request.path()
+        );
+        self.create_request(
+            now,
+            request.header_list(),
+            request.body_data().map(<[u8]>::to_vec),
+            Box::new(self.events.clone()),
+        )
+    }
+
+    /// Open a tunnel to `authority` (a host and a port) with a CONNECT request. Once a 2xx
+    /// response has been received, data written with `send_request_body` and read with
+    /// `read_response_data` are the bytes of the tunnel. The tunnel is closed with
+    /// `stream_close_send`; on a tunnel error the stream should be reset with
+    /// `Error::HttpConnect.code()`. If the server resets either direction of the tunnel, the
+    /// other direction is reset with `H3_CONNECT_ERROR` as well, and the tunnel ends with a
+    /// `Reset` or `StopSending` event.
+    /// # Errors
+    /// If a new stream cannot be created an error will be return.
+    pub fn connect_tunnel(
+        &mut self,
+        now: Instant,
+        authority: &str,
+        headers: &[Header],
+    ) -> Res<u64> {
+        qinfo!([self], "Connect tunnel authority={}", authority);
+        let mut final_headers = Vec::new();
+        final_headers.push((":method".into(), "CONNECT".to_owned()));
+        final_headers.push((":authority".into(), authority.to_owned()));
+        final_headers.extend_from_slice(headers);
+        let id = self.create_request(now, final_headers, None, Box::new(self.events.clone()))?;
+        self.base_handler.add_connect_tunnel(id);
+        Ok(id)
+    }
+
+    /// Open a UDP proxy session (connect-udp) through the proxy at `authority`. The session
+    /// is established when a 2xx response is received. UDP payloads are sent with
+    /// `send_udp_payload`. If HTTP Datagrams have been negotiated, received payloads arrive in
+    /// `Datagram` events and are taken with `ConnectUdpReader::payload_from_datagram`;
+    /// otherwise they are extracted from the data read with `read_response_data` using a
+    /// `ConnectUdpReader`. The session is closed with `stream_close_send`.
+    /// # Errors
+    /// `Unavailable` if the server has not enabled extended CONNECT (or its settings have not
+    /// been received yet). If a new stream cannot be created an error will be return.
+    pub fn connect_udp(
+        &mut self,
+        now: Instant,
+        authority: &str,
+        target: &ConnectUdpTarget,
+    ) -> Res<u64> {
+        qinfo!(
+            [self],
+            "Connect-udp authority={} target={}:{}",
+            authority,
+            target.host(),
+            target.port()
+        );
+        if !self.base_handler.peer_enables_connect_protocol() {
+            return Err(Error::Unavailable);
+        }
+        let final_headers = vec![
+            (":method".into(), "CONNECT".to_owned()),
+            (":protocol".into(), CONNECT_UDP_PROTOCOL.to_owned()),
+            (":scheme".into(), "https".to_owned()),
+            (":authority".into(), authority.to_owned()),
+            (":path".into(), target.path()),
+            ("capsule-protocol".into(), "?1".to_owned()),
+        ];
+        self.create_request(now, final_headers, None, Box::new(self.events.clone()))
+    }
+
+    /// Send a UDP payload on a connect-udp session. It is sent in an HTTP Datagram if they have
+    /// been negotiated and in a DATAGRAM capsule on the request stream otherwise. Like a UDP
+    /// datagram, the payload is dropped if it cannot be sent at once, e.g. if it does not fit
+    /// into a QUIC datagram or flow control does not allow it. Returns whether the payload has
+    /// been sent.
+    /// # Errors
+    /// `InvalidStreamId` if the stream does not exist, or a transport error.
+    pub fn send_udp_payload(&mut self, stream_id: u64, payload: &[u8]) -> Res<bool> {
+        qinfo!(
+            [self],
+            "send_udp_payload on stream {} sending {} bytes.",
+            stream_id,
+            payload.len()
+        );
+        self.base_handler
+            .send_udp_payload(&mut self.conn, stream_id, payload)
+    }
+
+    /// Send an HTTP Datagram (RFC 9297) that belongs to the request on `stream_id`. HTTP
+    /// Datagrams are used if they are enabled with `Http3Parameters` and by the server. Like
+    /// a UDP datagram, an HTTP Datagram is not retransmitted if it is lost.
+    /// # Errors
+    /// `Unavailable` if HTTP Datagrams have not been negotiated,
+    /// `InvalidStreamId` if the request does not exist,
+    /// `InvalidInput` if `buf` is larger than `max_datagram_size`.
+    pub fn send_datagram(&mut self, stream_id: u64, buf: &[u8]) -> Res<()> {
+        qinfo!(
+            [self],
+            "send_datagram on stream {} sending {} bytes.",
+            stream_id,
+            buf.len()
+        );
+        self.base_handler
+            .send_datagram(&mut self.conn, stream_id, buf)
+    }
+
+    /// The largest HTTP Datagram that can be sent on the request `stream_id`.
+    /// # Errors
+    /// The same as `send_datagram`.
+    pub fn max_datagram_size(&self, stream_id: u64) -> Res<u64> {
+        self.base_handler.max_datagram_size(&self.conn, stream_id)
+    }
+
+    fn create_request(
+        &mut self,
+        now: Instant,
+        final_headers: Vec<Header>,
+        body: Option<Vec<u8>>,
+        recv_events: Box<dyn RecvMessageEvents>,
+    ) -> Res<u64> {
         // Requests cannot be created when a connection is in states: Initializing, GoingAway, Closing and Closed.
         match self.base_handler.state() {
             Http3State::GoingAway(..) | Http3State::Closing(..) | Http3State::Closed(..) => {
@@ -272,30 +630,52 @@ impl Http3Client {
             Http3State::Initializing => return Err(Error::Unavailable),
             _ => {}
         }
+        self.base_handler
+            .check_peer_field_section_size(&final_headers)?;
+        let zero_rtt_retry = if self.base_handler.state() == Http3State::ZeroRtt {
+            Some(ZeroRttRetry::Resend {
+                headers: final_headers.clone(),
+                fin: body.is_some(),
+                body: body.clone().unwrap_or_default(),
+            })
+        } else {
+            None
+        };
 
         let id = self
             .conn
             .stream_create(StreamType::BiDi)
             .map_err(|e| Error::map_stream_create_errors(&e))?;
 
-        // Transform pseudo-header fields
-        let mut final_headers = Vec::new();
-        final_headers.push((":method".into(), method.to_owned()));
-        final_headers.push((":scheme".into(), scheme.to_owned()));
-        final_headers.push((":authority".into(), host.to_owned()));
-        final_headers.push((":path".into(), path.to_owned()));
-        final_headers.extend_from_slice(headers);
+        // A request body is sent with the priority the request asks for.
+        if let Some(priority) = Priority::from_headers(&final_headers) {
+            self.conn.stream_priority(id, priority.into())?;
+        }
 
-        self.base_handler.add_streams(
+        let mut send_message = if let Some(body) = body {
+            SendMessage::new_with_body(id, final_headers, body, true, Box::new(self.events.clone()))
+        } else {
+            SendMessage::new_with_headers(id, final_headers, Box::new(self.events.clone()))
+        };
+        let mut recv_message = RecvMessage::new(
+            MessageType::Response,
             id,
-            SendMessage::new_with_headers(id, final_headers, Box::new(self.events.clone())),
-            Box::new(RecvMessage::new(
-                MessageType::Response,
-                id,
-                Box::new(self.events.clone()),
-                Some(self.push_handler.clone()),
-            )),
+            recv_events,
+            Some(self.push_handler.clone()),
+            self.base_handler.local_max_field_section_size(),
         );
+        if let Some(request_metrics) = &mut self.request_metrics {
+            let metrics = Rc::new(RefCell::new(RequestMetrics::new(
+                id,
+                now,
+                zero_rtt_retry.is_some(),
+            )));
+            send_message.set_metrics(metrics.clone());
+            recv_message.set_metrics(metrics.clone());
+            request_metrics.insert(id, metrics);
+        }
+        self.base_handler
+            .add_streams(id, send_message, Box::new(recv_message));
 
         // Call immediately send so that at least headers get sent. This will make Firefox faster, since
         // it can send request body immediatly in most cases and does not need to do a complete process loop.
@@ -312,9 +692,227 @@ impl Http3Client {
             return Err(e);
         }
 
+        if let Some(retry) = zero_rtt_retry {
+            self.zero_rtt_requests.push(ZeroRttRequest {
+                stream_id: id,
+                retry,
+            });
+        }
+        Ok(id)
+    }
+
+    fn zero_rtt_retry(&mut self, stream_id: u64) -> Option<&mut ZeroRttRetry> {
+        self.zero_rtt_requests
+            .iter_mut()
+            .find(|r| r.stream_id == stream_id)
+            .map(|r| &mut r.retry)
+    }
+
+    fn set_zero_rtt_retry(&mut self, stream_id: u64, retry: ZeroRttRetry) {
+        if let Some(r) = self.zero_rtt_retry(stream_id) {
+            *r = retry;
+        }
+    }
+
+    /// Once the handshake is done, the requests that have been sent in 0-RTT are forgotten if
+    /// the server has accepted 0-RTT. Otherwise they are sent again; the streams are created in
+    /// the same order as before, therefore the requests keep their stream IDs.
+    fn handle_zero_rtt_requests(&mut self) -> Res<()> {
+        let requests = mem::take(&mut self.zero_rtt_requests);
+        if *self.conn.zero_rtt_state() != ZeroRttState::Rejected {
+            return Ok(());
+        }
+        for request in requests {
+            qinfo!([self], "Retry 0-RTT request stream={}.", request.stream_id);
+            if let Some(metrics) = self.request_metrics(request.stream_id) {
+                metrics.borrow_mut().zero_rtt = false;
+            }
+            let id = self.conn.stream_create(StreamType::BiDi).ok();
+            match (request.retry, id) {
+                (ZeroRttRetry::Resend { headers, body, fin }, Some(id))
+                    if id == request.stream_id =>
+                {
+                    if let Some(priority) = Priority::from_headers(&headers) {
+                        self.conn.stream_priority(id, priority.into())?;
+                    }
+                    let mut send_message = SendMessage::new_with_body(
+                        id,
+                        headers,
+                        body,
+                        fin,
+                        Box::new(self.events.clone()),
+                    );
+                    let mut recv_message = RecvMessage::new(
+                        MessageType::Response,
+                        id,
+                        Box::new(self.events.clone()),
+                        Some(self.push_handler.clone()),
+                        self.base_handler.local_max_field_section_size(),
+                    );
+                    // The metrics count the request that is sent again from the start.
+                    if let Some(metrics) = self.request_metrics(id) {
+                        let start = metrics.borrow().start;
+                        *metrics.borrow_mut() = RequestMetrics::new(id, start, false);
+                        send_message.set_metrics(metrics.clone());
+                        recv_message.set_metrics(metrics);
+                    }
+                    self.base_handler
+                        .add_streams(id, send_message, Box::new(recv_message));
+                    continue;
+                }
+                (ZeroRttRetry::Cancelled, _) => {}
+                _ => {
+                    self.events
+                        .reset(request.stream_id, Error::HttpRequestRejected.code(), true);
+                }
+            }
+            // The stream is not used, but it is still created so that the following requests
+            // get their old stream IDs.
+            if let Some(id) = id {
+                let _ = self
+                    .conn
+                    .stream_reset_send(id, Error::HttpRequestCancelled.code());
+                let _ = self
+                    .conn
+                    .stream_stop_sending(id, Error::HttpRequestCancelled.code());
+            }
+        }
+        Ok(())
+    }
+
+    /// Open a WebTransport session at `path` on `authority`. The session has been established
+    /// when a `WebTransportEvent::Session` event with a 2xx status is received. The returned
+    /// session ID is used with the other `webtransport_*` functions.
+    /// # Errors
+    /// `Unavailable` if WebTransport has not been enabled with `Http3Parameters` or by the
+    /// server (or its settings have not been received yet). If a new stream cannot be created
+    /// an error will be return.
+    pub fn webtransport_create_session(
+        &mut self,
+        now: Instant,
+        authority: &str,
+        path: &str,
+        headers: &[Header],
+    ) -> Res<u64> {
+        qinfo!(
+            [self],
+            "Create WebTransport session authority={} path={}",
+            authority,
+            path
+        );
+        if !self.base_handler.enables_webtransport()
+            || !self.base_handler.peer_enables_connect_protocol()
+            || !self.base_handler.peer_enables_webtransport()
+        {
+            return Err(Error::Unavailable);
+        }
+        let mut final_headers = vec![
+            (":method".into(), "CONNECT".to_owned()),
+            (":protocol".into(), WEBTRANSPORT_PROTOCOL.to_owned()),
+            (":scheme".into(), "https".to_owned()),
+            (":authority".into(), authority.to_owned()),
+            (":path".into(), path.to_owned()),
+        ];
+        final_headers.extend_from_slice(headers);
+        let id = self.create_request(
+            now,
+            final_headers,
+            None,
+            Box::new(WebTransportSessionListener::new(self.events.clone())),
+        )?;
+        self.set_zero_rtt_retry(id, ZeroRttRetry::Reject);
+        self.webtransport.add_session(id);
         Ok(id)
     }
 
+    /// Close a WebTransport session. `error` and `message` are sent to the server if flow
+    /// control allows it. The streams of the session are reset.
+    /// # Errors
+    /// `InvalidStreamId` if the session does not exist.
+    pub fn webtransport_close_session(
+        &mut self,
+        session_id: u64,
+        error: u32,
+        message: &str,
+    ) -> Res<()> {
+        qinfo!([self], "Close WebTransport session {}.", session_id);
+        self.webtransport.close_session(
+            &mut self.conn,
+            &mut self.base_handler,
+            session_id,
+            error,
+            message,
+        )
+    }
+
+    /// Open a stream of a WebTransport session. The stream is used with the
+    /// `webtransport_stream_*` functions.
+    /// # Errors
+    /// `InvalidStreamId` if the session does not exist. If a new stream cannot be created an
+    /// error will be return.
+    pub fn webtransport_create_stream(
+        &mut self,
+        session_id: u64,
+        stream_type: StreamType,
+    ) -> Res<u64> {
+        qinfo!(
+            [self],
+            "Create WebTransport stream of session {}.",
+            session_id
+        );
+        self.webtransport
+            .create_stream(&mut self.conn, session_id, stream_type)
+    }
+
+    /// Send a datagram on a WebTransport session. The datagram is dropped if it cannot be sent
+    /// at once. Returns whether the datagram has been sent.
+    /// # Errors
+    /// `InvalidStreamId` if the session does not exist, or a transport error.
+    pub fn webtransport_send_datagram(&mut self, session_id: u64, datagram: &[u8]) -> Res<bool> {
+        self.webtransport.send_datagram(
+            &mut self.conn,
+            &mut self.base_handler,
+            session_id,
+            datagram,
+        )
+    }
+
+    /// Send data on a WebTransport stream.
+    /// # Errors
+    /// `InvalidStreamId` if the stream is not a WebTransport stream, or a transport error.
+    pub fn webtransport_stream_send(&mut self, stream_id: u64, buf: &[u8]) -> Res<usize> {
+        self.webtransport
+            .stream_send(&mut self.conn, stream_id, buf)
+    }
+
+    /// Read data from a WebTransport stream.
+    /// # Errors
+    /// `InvalidStreamId` if the stream is not a WebTransport stream, or a transport error.
+    pub fn webtransport_stream_recv(
+        &mut self,
+        stream_id: u64,
+        buf: &mut [u8],
+    ) -> Res<(usize, bool)> {
+        self.webtransport
+            .stream_recv(&mut self.conn, stream_id, buf)
+    }
+
+    /// Close the sending side of a WebTransport stream.
+    /// # Errors
+    /// `InvalidStreamId` if the stream is not a WebTransport stream, or a transport error.
+    pub fn webtransport_stream_close_send(&mut self, stream_id: u64) -> Res<()> {
+        self.webtransport
+            .stream_close_send(&mut self.conn, stream_id)
+    }
+
+    /// Reset both sides of a WebTransport stream.
+    /// # Errors
+    /// `InvalidStreamId` if the stream is not a WebTransport stream.
+    pub fn webtransport_stream_reset(&mut self, stream_id: u64, error: AppError) -> Res<()> {
+        self.webtransport
+            .stream_reset(&mut self.conn, stream_id, error)
+    }
+
     /// An application may reset a stream(request).
     /// Both sides, sending and receiving side, will be closed.
     /// # Errors
@@ -324,19 +922,71 @@ impl Http3Client {
         self.base_handler
             .stream_reset(&mut self.conn, stream_id, error)?;
         self.events.remove_events_for_stream_id(stream_id);
+        self.set_zero_rtt_retry(stream_id, ZeroRttRetry::Cancelled);
+        self.report_request_metrics();
         Ok(())
     }
 
-    /// This is call when application is done sending a request.
+    /// Cancel a request whose response is no longer needed. Both sides of the stream are
+    /// closed: a RESET_STREAM and a STOP_SENDING frame are sent with `error`, usually
+    /// `Error::HttpRequestCancelled.code()`. Header blocks of the response that have not been
+    /// decoded yet are dropped and the server is told so on the QPACK decoder stream.
+    /// Unlike `stream_reset`, this ends the request with a `Reset` event (with `local` set),
+    /// in place of any events of the request that have not been read yet.
+    /// # Errors
+    /// `InvalidStreamId` if the request does not exist or it is a WebTransport session, which
+    /// is closed with `webtransport_close_session`.
+    pub fn cancel_request(&mut self, stream_id: u64, error: AppError) -> Res<()> {
+        qinfo!([self], "Cancel request {} error={}.", stream_id, error);
+        if self.webtransport.is_session(stream_id) {
+            return Err(Error::InvalidStreamId);
+        }
+        self.stream_reset(stream_id, error)?;
+        self.events.reset(stream_id, error, true);
+        Ok(())
+    }
+
+    /// This is call when application is done sending a request. Any request body data that
+    /// has been accepted by `send_request_body` is still delivered before the stream is closed.
     /// # Errors
     /// An error will be return if stream does not exist.
     pub fn stream_close_send(&mut self, stream_id: u64) -> Res<()> {
         qinfo!([self], "Close sending side stream={}.", stream_id);
         self.base_handler
-            .stream_close_send(&mut self.conn, stream_id)
+            .stream_close_send(&mut self.conn, stream_id)?;
+        if let Some(ZeroRttRetry::Resend { fin, .. }) = self.zero_rtt_retry(stream_id) {
+            *fin = true;
+        }
+        Ok(())
+    }
+
+    /// Change the priority of a request. The new priority is sent to the server in a
+    /// PRIORITY_UPDATE frame.
+    /// # Errors
+    /// `InvalidStreamId` if the request does not exist or its response has been read.
+    pub fn priority_update(&mut self, stream_id: u64, priority: Priority) -> Res<()> {
+        qinfo!(
+            [self],
+            "Priority update stream={} priority={:?}.",
+            stream_id,
+            priority
+        );
+        if !self.base_handler.recv_streams.contains_key(&stream_id) {
+            return Err(Error::InvalidStreamId);
+        }
+        set_stream_priority(&mut self.conn, stream_id, priority)?;
+        self.base_handler
+            .queue_control_frame(&HFrame::PriorityUpdateRequest {
+                element_id: stream_id,
+                priority,
+            });
+        Ok(())
     }
 
     /// To supply a request body this function is called (headers are supplied through the `fetch` function.)
+    /// The body may be sent in several calls. The function returns the number of bytes that have been
+    /// accepted, which may be less than `buf.len()` if the stream is flow-control limited. In that case
+    /// the application should wait for a `DataWritable` event before sending the rest.
     /// # Errors
     /// `InvalidStreamId` if thee stream does not exist,
     /// `AlreadyClosed` if the stream has already been closed.
@@ -350,15 +1000,41 @@ impl Http3Client {
             stream_id,
             buf.len()
         );
+        let sent = self
+            .base_handler
+            .send_streams
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?
+            .send_body(&mut self.conn, buf)?;
+        if let Some(ZeroRttRetry::Resend { body, .. }) = self.zero_rtt_retry(stream_id) {
+            body.extend_from_slice(&buf[..sent]);
+        }
+        Ok(sent)
+    }
+
+    /// Send trailers after a request body. This closes the sending side of the request, i.e.
+    /// `stream_close_send` does not need to be called.
+    /// # Errors
+    /// `InvalidStreamId` if the stream does not exist,
+    /// `InvalidState` if the request headers have not been sent yet (wait for `DataWritable`),
+    /// `AlreadyClosed` if the sending side has already been closed.
+    pub fn send_request_trailers(&mut self, stream_id: u64, trailers: &[Header]) -> Res<()> {
+        qinfo!([self], "send_request_trailers on stream {}.", stream_id);
+        self.base_handler.check_peer_field_section_size(trailers)?;
         self.base_handler
             .send_streams
             .get_mut(&stream_id)
             .ok_or(Error::InvalidStreamId)?
-            .send_body(&mut self.conn, buf)
+            .set_trailers(trailers)?;
+        self.base_handler
+            .insert_streams_have_data_to_send(stream_id);
+        self.set_zero_rtt_retry(stream_id, ZeroRttRetry::Reject);
+        Ok(())
     }
 
     /// Response data are read directly into a buffer supplied as a parameter of this function to avoid copying
-    /// data.
+    /// data. The returned flag is true once the whole response body has been read. Data that has not been
+    /// read stays in the transport and limits, through flow control, how much more the server can send.
     /// # Errors
     /// It returns an error if a stream does not exist or an error happen while reading a stream, e.g.
     /// early close, protocol error, etc.
@@ -380,12 +1056,14 @@ impl Http3Client {
             Ok((amount, fin)) => {
                 if recv_stream.done() {
                     self.base_handler.recv_streams.remove(&stream_id);
+                    self.report_request_metrics();
                 }
                 Ok((amount, fin))
             }
             Err(e) => {
                 if e.stream_reset_error() {
                     self.reset_stream_on_error(stream_id, e.code());
+                    self.report_request_metrics();
                     Ok((0, false))
                 } else if e.connection_error() {
                     self.close(now, e.code(), "");
@@ -452,22 +1130,29 @@ impl Http3Client {
         qtrace!([self], "Process http3 internal.");
         match self.base_handler.state() {
             Http3State::ZeroRtt | Http3State::Connected | Http3State::GoingAway(..) => {
-                let res = self.check_connection_events();
-                if self.check_result(now, &res) {
-                    return;
+                let res = self.check_connection_events(now);
+                if !self.check_result(now, &res) {
+                    let res = self.webtransport.process(
+                        &mut self.conn,
+                        &mut self.base_handler,
+                        &self.events,
+                    );
+                    if !self.check_result(now, &res) {
+                        self.push_handler
+                            .borrow_mut()
+                            .maybe_send_max_push_id_frame(&mut self.base_handler);
+                        let res = self.base_handler.process_sending(&mut self.conn);
+                        self.check_result(now, &res);
+                    }
                 }
-                self.push_handler
-                    .borrow_mut()
-                    .maybe_send_max_push_id_frame(&mut self.base_handler);
-                let res = self.base_handler.process_sending(&mut self.conn);
-                self.check_result(now, &res);
             }
             Http3State::Closed { .. } => {}
             _ => {
-                let res = self.check_connection_events();
+                let res = self.check_connection_events(now);
                 let _ = self.check_result(now, &res);
             }
         }
+        self.report_request_metrics();
     }
 
     /// Get packet that need to be written into a UDP socket or a timer value if there is no data to send.
@@ -500,7 +1185,10 @@ impl Http3Client {
                 true
             }
             Err(e) => {
-                qinfo!([self], "Connection error: {}.", e);
+                match self.base_handler.error_context() {
+                    Some(context) => qinfo!([self], "Connection error: {} ({}).", e, context),
+                    None => qinfo!([self], "Connection error: {}.", e),
+                }
                 self.close(now, e.code(), &format!("{}", e));
                 true
             }
@@ -509,13 +1197,15 @@ impl Http3Client {
     }
 
     // If this return an error the connection must be closed.
-    fn check_connection_events(&mut self) -> Res<()> {
+    fn check_connection_events(&mut self, now: Instant) -> Res<()> {
         qtrace!([self], "Check connection events.");
         while let Some(e) = self.conn.next_event() {
             qdebug!([self], "check_connection_events - event {:?}.", e);
             match e {
                 ConnectionEvent::NewStream { stream_id } => match stream_id.stream_type() {
-                    StreamType::BiDi => return Err(Error::HttpStreamCreation),
+                    StreamType::BiDi => self
+                        .base_handler
+                        .handle_new_webtransport_bidi_stream(&mut self.conn, stream_id.as_u64())?,
                     StreamType::UniDi => {
                         if self
                             .base_handler
@@ -533,10 +1223,14 @@ impl Http3Client {
                     }
                 }
                 ConnectionEvent::RecvStreamReadable { stream_id } => {
+                    if let Some(metrics) = self.request_metrics(stream_id) {
+                        metrics.borrow_mut().first_byte_received(now);
+                    }
                     if let Err(e) = self.handle_stream_readable(stream_id) {
                         if e.stream_reset_error() {
                             self.reset_stream_on_error(stream_id, e.code());
                         } else {
+                            self.base_handler.set_error_context(stream_id);
                             return Err(e);
                         }
                     }
@@ -546,13 +1240,15 @@ impl Http3Client {
                     app_error,
                 } => self
                     .base_handler
-                    .handle_stream_reset(stream_id, app_error)?,
+                    .handle_stream_reset(&mut self.conn, stream_id, app_error)?,
                 ConnectionEvent::SendStreamStopSending {
                     stream_id,
                     app_error,
-                } => self
-                    .base_handler
-                    .handle_stream_stop_sending(stream_id, app_error)?,
+                } => self.base_handler.handle_stream_stop_sending(
+                    &mut self.conn,
+                    stream_id,
+                    app_error,
+                )?,
                 ConnectionEvent::SendStreamComplete { .. } => {}
                 ConnectionEvent::SendStreamCreatable { stream_type } => {
                     self.events.new_requests_creatable(stream_type)
@@ -566,15 +1262,30 @@ impl Http3Client {
                         self.events
                             .connection_state_change(self.base_handler.state());
                     }
+                    if self.base_handler.state() == Http3State::Connected {
+                        self.handle_zero_rtt_requests()?;
+                    }
                 }
                 ConnectionEvent::ZeroRttRejected => {
                     self.base_handler.handle_zero_rtt_rejected()?;
+                    self.webtransport = WebTransportSessions::default();
                     self.events.zero_rtt_rejected();
                     self.push_handler.borrow_mut().handle_zero_rtt_rejected();
                 }
                 ConnectionEvent::ResumptionToken(token) => {
                     self.create_resumption_token(&token);
                 }
+                ConnectionEvent::Datagram(datagram) => {
+                    if let Some((stream_id, payload)) =
+                        self.base_handler.handle_datagram(&datagram)?
+                    {
+                        if self.webtransport.is_session(stream_id) {
+                            self.webtransport.datagram(&self.events, stream_id, payload);
+                        } else {
+                            self.events.datagram(stream_id, payload);
+                        }
+                    }
+                }
             }
         }
         Ok(())
@@ -593,8 +1304,14 @@ impl Http3Client {
                             .push_handler
                             .borrow_mut()
                             .handle_cancel_push(push_id, &mut self.conn, &mut self.base_handler),
-                        HFrame::MaxPushId { .. } => Err(Error::HttpFrameUnexpected),
+                        HFrame::MaxPushId { .. }
+                        | HFrame::PriorityUpdateRequest { .. }
+                        | HFrame::PriorityUpdatePush { .. } => Err(Error::HttpFrameUnexpected),
                         HFrame::Goaway { stream_id } => self.handle_goaway(stream_id),
+                        HFrame::Origin { origins } => {
+                            self.handle_origin(&origins);
+                            Ok(())
+                        }
                         _ => {
                             unreachable!(
                                 "we should only put MaxPushId and Goaway into control_frames."
@@ -604,6 +1321,16 @@ impl Http3Client {
                 }
                 Ok(())
             }
+            HandleReadableOutput::StreamNotFound => {
+                if let Some(session_id) = self.webtransport.stream_session(stream_id) {
+                    self.events
+                        .webtransport_event(WebTransportEvent::DataReadable {
+                            stream_id,
+                            session_id,
+                        });
+                }
+                Ok(())
+            }
             _ => Ok(()),
         }
     }
@@ -616,6 +1343,7 @@ impl Http3Client {
                     stream_id,
                     self.push_handler.clone(),
                     self.events.clone(),
+                    self.base_handler.local_max_field_section_size(),
                 )),
             );
             if force_read {
@@ -630,6 +1358,17 @@ impl Http3Client {
         }
     }
 
+    fn handle_origin(&mut self, origins: &[String]) {
+        qinfo!([self], "handle_origin {:?}", origins);
+        // Entries that are not valid origins are ignored.
+        for o in origins.iter().filter_map(|o| origin::normalize(o).ok()) {
+            if !self.origins.contains(&o) {
+                self.origins.push(o);
+            }
+        }
+        self.events.origin_received();
+    }
+
     fn handle_goaway(&mut self, goaway_stream_id: u64) -> Res<()> {
         qinfo!([self], "handle_goaway {}", goaway_stream_id);
 
@@ -729,9 +1468,11 @@ impl EventProvider for Http3Client {
 #[cfg(test)]
 mod tests {
     use super::{
-        AuthenticationStatus, Connection, Error, HSettings, Header, Http3Client, Http3ClientEvent,
-        Http3Parameters, Http3State, QpackSettings, Rc, RefCell, StreamType,
+        AuthenticationStatus, Connection, Error, ErrorContext, HSettings, Header, Http3Client,
+        Http3ClientEvent, Http3Parameters, Http3State, Priority, QpackSettings, Rc, RefCell,
+        RequestMetrics, StreamType,
     };
+    use crate::control_stream_local::HTTP3_UNI_STREAM_TYPE_CONTROL;
     use crate::hframe::{HFrame, H3_FRAME_TYPE_SETTINGS, H3_RESERVED_FRAME_TYPES};
     use crate::settings::{HSetting, HSettingType, H3_RESERVED_SETTINGS};
     use neqo_common::{event::Provider, Datagram, Decoder, Encoder};
@@ -764,14 +1505,14 @@ mod tests {
             loopback(),
             &CongestionControlAlgorithm::NewReno,
             QuicVersion::default(),
-            &Http3Parameters {
-                qpack_settings: QpackSettings {
+            &Http3Parameters::new(
+                QpackSettings {
                     max_table_size_encoder: 100,
                     max_table_size_decoder: 100,
                     max_blocked_streams: 100,
                 },
-                max_concurrent_push_streams: 5,
-            },
+                5,
+            ),
         )
         .expect("create a default client")
     }
@@ -1144,6 +1885,13 @@ mod tests {
         assert_eq!(header, expected_response_header_0);
     }
 
+    // Trailers must not contain pseudo-header fields. This frame is decoded into "age: 0".
+    const HTTP_TRAILERS_FRAME: &[u8] = &[0x01, 0x03, 0x00, 0x00, 0xc2];
+
+    fn check_trailers(trailers: &[Header]) {
+        assert_eq!(trailers, &[(String::from("age"), String::from("0"))]);
+    }
+
     const HTTP_RESPONSE_1: &[u8] = &[
         // headers
         0x01, 0x06, 0x00, 0x00, 0xd9, 0x54, 0x01, 0x37, // the first data frame
@@ -1615,6 +2363,17 @@ mod tests {
         let out = server.conn.process(None, now());
         client.process(out.dgram(), now());
         assert_closed(&client, &Error::HttpFrameUnexpected);
+        assert_eq!(
+            client.error_context(),
+            Some(&ErrorContext {
+                stream_id: server.control_stream_id.unwrap(),
+                stream_type: Some(HTTP3_UNI_STREAM_TYPE_CONTROL),
+            })
+        );
+        assert_eq!(
+            client.error_context().unwrap().to_string(),
+            format!("stream {} (control)", server.control_stream_id.unwrap())
+        );
     }
 
     fn test_wrong_frame_on_control_stream(v: &[u8]) {
@@ -1631,24 +2390,61 @@ mod tests {
         assert_closed(&client, &Error::HttpFrameUnexpected);
     }
 
-    // send DATA frame on a cortrol stream
+    // send DATA frame on a control stream
     #[test]
     fn test_data_frame_on_control_stream() {
         test_wrong_frame_on_control_stream(&[0x0, 0x2, 0x1, 0x2]);
     }
 
-    // send HEADERS frame on a cortrol stream
+    // send HEADERS frame on a control stream
     #[test]
     fn test_headers_frame_on_control_stream() {
         test_wrong_frame_on_control_stream(&[0x1, 0x2, 0x1, 0x2]);
     }
 
-    // send PUSH_PROMISE frame on a cortrol stream
+    // send PUSH_PROMISE frame on a control stream
     #[test]
     fn test_push_promise_frame_on_control_stream() {
         test_wrong_frame_on_control_stream(&[0x5, 0x2, 0x1, 0x2]);
     }
 
+    // send PRIORITY_UPDATE frame on a control stream
+    #[test]
+    fn test_priority_update_frame_on_control_stream() {
+        test_wrong_frame_on_control_stream(&[0x80, 0x0f, 0x07, 0x00, 0x1, 0x0]);
+    }
+
+    // Client: a PRIORITY_UPDATE frame is sent on the control stream.
+    #[test]
+    fn test_client_priority_update() {
+        let (mut client, mut server, request_stream_id) = connect_and_send_request(true);
+
+        client
+            .priority_update(request_stream_id, Priority::new(1, true))
+            .unwrap();
+        let out = client.process(None, now());
+        let _ = server.conn.process(out.dgram(), now());
+
+        // PRIORITY_UPDATE for stream 0 with the field value "u=1, i".
+        server.read_and_check_stream_data(
+            CLIENT_SIDE_CONTROL_STREAM_ID,
+            &[
+                0x80, 0x0f, 0x07, 0x00, 0x07, 0x00, 0x75, 0x3d, 0x31, 0x2c, 0x20, 0x69,
+            ],
+            false,
+        );
+    }
+
+    // Client: PRIORITY_UPDATE cannot be sent for an unknown request.
+    #[test]
+    fn test_client_priority_update_unknown_stream() {
+        let (mut client, _server) = connect();
+        assert_eq!(
+            client.priority_update(4, Priority::default()),
+            Err(Error::InvalidStreamId)
+        );
+    }
+
     fn test_wrong_frame_on_push_stream(v: &[u8]) {
         let (mut client, mut server, request_stream_id) = connect_and_send_request(false);
 
@@ -1733,6 +2529,29 @@ mod tests {
         assert_eq!(client.state(), Http3State::Connected);
     }
 
+    // A stream of a reserved type that is closed right away is ignored as well.
+    #[test]
+    fn test_client_received_grease_stream_with_fin() {
+        let (mut client, mut server) = connect();
+
+        let new_stream_id = server.conn.stream_create(StreamType::UniDi).unwrap();
+        let mut enc = Encoder::default();
+        enc.encode_varint(0x1f * 3 + 0x21_u64);
+        enc.encode(&[0x1, 0x2, 0x3]);
+        let _ = server.conn.stream_send(new_stream_id, &enc).unwrap();
+        server.conn.stream_close_send(new_stream_id).unwrap();
+        let out = server.conn.process(None, now());
+        let out = client.process(out.dgram(), now());
+        let _ = server.conn.process(out.dgram(), now());
+
+        assert_eq!(client.state(), Http3State::Connected);
+
+        // Requests still work.
+        let request_stream_id = make_request(&mut client, true, &[]);
+        assert_eq!(request_stream_id, 0);
+        assert_eq!(client.state(), Http3State::Connected);
+    }
+
     // Test wrong frame on req/rec stream
     fn test_wrong_frame_on_request_stream(v: &[u8]) {
         let (mut client, mut server, request_stream_id) = connect_and_send_request(false);
@@ -1767,6 +2586,11 @@ mod tests {
         test_wrong_frame_on_request_stream(&[0xd, 0x1, 0x5]);
     }
 
+    #[test]
+    fn test_origin_frame_on_request_stream() {
+        test_wrong_frame_on_request_stream(&[0xc, 0x0]);
+    }
+
     // Test reading of a slowly streamed frame. bytes are received one by one
     #[test]
     fn test_frame_reading() {
@@ -1854,6 +2678,81 @@ mod tests {
         assert_closed(&client, &Error::HttpFrameUnexpected);
     }
 
+    #[test]
+    fn request_metrics() {
+        let (mut client, mut server) = connect();
+        client.set_request_metrics(true);
+        let request_stream_id = make_request_and_exchange_pkts(&mut client, &mut server, true);
+
+        // The response arrives 10ms after the request has been created.
+        let _ = server
+            .conn
+            .stream_send(request_stream_id, HTTP_RESPONSE_1)
+            .unwrap();
+        server.conn.stream_close_send(request_stream_id).unwrap();
+        let out = server.conn.process(None, now());
+        let _ = client.process(out.dgram(), now() + Duration::from_millis(10));
+        let mut buf = [0_u8; 100];
+        let (amount, fin) = client
+            .read_response_data(now(), request_stream_id, &mut buf)
+            .unwrap();
+        assert_eq!(amount, EXPECTED_RESPONSE_DATA_1.len());
+        assert!(fin);
+
+        let metrics = request_metrics_event(&mut client).unwrap();
+        assert_eq!(metrics.stream_id, request_stream_id);
+        assert_eq!(metrics.start, now());
+        assert_eq!(metrics.time_to_first_byte, Some(Duration::from_millis(10)));
+        assert!(!metrics.zero_rtt);
+        // EXPECTED_REQUEST_HEADER_FRAME has a header block of 16 bytes.
+        assert_eq!(metrics.header_bytes_sent, 16);
+        assert_eq!(metrics.header_bytes_sent_uncompressed, 51);
+        assert_eq!(metrics.body_bytes_sent, 0);
+        assert_eq!(metrics.header_bytes_received, 6);
+        assert_eq!(metrics.header_bytes_received_uncompressed, 25);
+        assert_eq!(
+            metrics.body_bytes_received,
+            EXPECTED_RESPONSE_DATA_1.len() as u64
+        );
+        // The metrics are reported only once.
+        let _ = client.process(None, now());
+        assert_eq!(request_metrics_event(&mut client), None);
+    }
+
+    #[test]
+    fn request_metrics_cancel() {
+        let (mut client, mut server) = connect();
+        client.set_request_metrics(true);
+        let request_stream_id = make_request_and_exchange_pkts(&mut client, &mut server, true);
+        assert_eq!(request_metrics_event(&mut client), None);
+
+        client
+            .cancel_request(request_stream_id, Error::HttpRequestCancelled.code())
+            .unwrap();
+        let metrics = request_metrics_event(&mut client).unwrap();
+        assert_eq!(metrics.stream_id, request_stream_id);
+        assert_eq!(metrics.header_bytes_sent, 16);
+        assert_eq!(metrics.time_to_first_byte, None);
+        assert!(client.request_metrics.as_ref().unwrap().is_empty());
+    }
+
+    #[test]
+    fn request_metrics_reset_by_peer() {
+        let (mut client, mut server) = connect();
+        client.set_request_metrics(true);
+        let request_stream_id = make_request_and_exchange_pkts(&mut client, &mut server, true);
+
+        server
+            .conn
+            .stream_reset_send(request_stream_id, Error::HttpRequestRejected.code())
+            .unwrap();
+        let out = server.conn.process(None, now());
+        let _ = client.process(out.dgram(), now());
+        let metrics = request_metrics_event(&mut client).unwrap();
+        assert_eq!(metrics.stream_id, request_stream_id);
+        assert!(client.request_metrics.as_ref().unwrap().is_empty());
+    }
+
     #[test]
     fn fetch_basic() {
         // Connect exchange headers and send a request. Also check if the correct header frame has been sent.
@@ -1909,10 +2808,16 @@ mod tests {
     }
 
     // Helper function: read response when a server sends HTTP_RESPONSE_2.
-    fn read_response(client: &mut Http3Client, server: &mut Connection, request_stream_id: u64) {
+    /// Read the response and return the metrics of the request, if they are collected.
+    fn read_response(
+        client: &mut Http3Client,
+        server: &mut Connection,
+        request_stream_id: u64,
+    ) -> Option<RequestMetrics> {
         let out = server.process(None, now());
         client.process(out.dgram(), now());
 
+        let mut metrics = None;
         while let Some(e) = client.next_event() {
             match e {
                 Http3ClientEvent::HeaderReady {
@@ -1936,6 +2841,10 @@ mod tests {
                     assert_eq!(amount, EXPECTED_RESPONSE_DATA_2_FRAME_1.len());
                     assert_eq!(&buf[..amount], EXPECTED_RESPONSE_DATA_2_FRAME_1);
                 }
+                Http3ClientEvent::RequestMetrics(m) => {
+                    assert_eq!(m.stream_id, request_stream_id);
+                    metrics = Some(m);
+                }
                 _ => {}
             }
         }
@@ -1948,6 +2857,14 @@ mod tests {
         assert_eq!(res.unwrap_err(), Error::InvalidStreamId);
 
         client.close(now(), 0, "");
+        metrics
+    }
+
+    fn request_metrics_event(client: &mut Http3Client) -> Option<RequestMetrics> {
+        client.events().find_map(|e| match e {
+            Http3ClientEvent::RequestMetrics(m) => Some(m),
+            _ => None,
+        })
     }
 
     // Data sent with a request:
@@ -2002,6 +2919,78 @@ mod tests {
         read_response(&mut client, &mut server.conn, request_stream_id);
     }
 
+    // An empty request body buffer is rejected without sending a DATA frame.
+    #[test]
+    fn fetch_with_empty_data() {
+        let (mut client, mut server, request_stream_id) = connect_and_send_request(false);
+
+        let data_writable = |e| matches!(e, Http3ClientEvent::DataWritable { .. });
+        assert!(client.events().any(data_writable));
+        assert_eq!(
+            client.send_request_body(request_stream_id, &[]),
+            Err(Error::InvalidInput)
+        );
+        client.stream_close_send(request_stream_id).unwrap();
+
+        let out = client.process(None, now());
+        let _ = server.conn.process(out.dgram(), now());
+
+        // The server only receives the fin.
+        let mut buf = [0_u8; 100];
+        let (amount, fin) = server
+            .conn
+            .stream_recv(request_stream_id, &mut buf)
+            .unwrap();
+        assert_eq!(amount, 0);
+        assert_eq!(fin, true);
+    }
+
+    // Send a request with the request body followed by trailers.
+    #[test]
+    fn fetch_with_data_and_trailers() {
+        let (mut client, mut server, request_stream_id) = connect_and_send_request(false);
+
+        let data_writable = |e| matches!(e, Http3ClientEvent::DataWritable { .. });
+        assert!(client.events().any(data_writable));
+        let sent = client
+            .send_request_body(request_stream_id, REQUEST_BODY)
+            .unwrap();
+        assert_eq!(sent, REQUEST_BODY.len());
+        client
+            .send_request_trailers(
+                request_stream_id,
+                &[(String::from("my-trailer"), String::from("value"))],
+            )
+            .unwrap();
+
+        // The body cannot be extended and trailers cannot be sent twice.
+        assert_eq!(
+            client.send_request_body(request_stream_id, REQUEST_BODY),
+            Err(Error::AlreadyClosed)
+        );
+        assert_eq!(
+            client.send_request_trailers(request_stream_id, &[]),
+            Err(Error::AlreadyClosed)
+        );
+
+        let out = client.process(None, now());
+        let _ = server.conn.process(out.dgram(), now());
+
+        // The server receives the DATA frame, a HEADERS frame with the trailers and the fin.
+        let mut buf = [0_u8; 100];
+        let (amount, fin) = server
+            .conn
+            .stream_recv(request_stream_id, &mut buf)
+            .unwrap();
+        assert_eq!(fin, true);
+        assert!(amount > EXPECTED_REQUEST_BODY_FRAME.len());
+        assert_eq!(
+            &buf[..EXPECTED_REQUEST_BODY_FRAME.len()],
+            EXPECTED_REQUEST_BODY_FRAME
+        );
+        assert_eq!(buf[EXPECTED_REQUEST_BODY_FRAME.len()], 0x1);
+    }
+
     // send a request with request body containing request_body. We expect to receive expected_data_frame_header.
     fn fetch_with_data_length_xbytes(request_body: &[u8], expected_data_frame_header: &[u8]) {
         // Connect exchange headers and send a request. Also check if the correct header frame has been sent.
@@ -3415,6 +4404,27 @@ mod tests {
             .unwrap()
     }
 
+    #[test]
+    fn take_resumption_token() {
+        let (mut client, mut server) = connect();
+        server
+            .conn
+            .send_ticket(now(), &[])
+            .expect("can send ticket");
+        let out = server.conn.process_output(now());
+        client.process_input(out.dgram().unwrap(), now());
+
+        // The token is available without waiting for the resumption token timer.
+        let token = client.take_resumption_token(now()).expect("have a token");
+        assert!(client.take_resumption_token(now()).is_none());
+
+        let mut client = default_http3_client();
+        client
+            .enable_resumption(now(), &token)
+            .expect("Set resumption token.");
+        assert_eq!(client.state(), Http3State::ZeroRtt);
+    }
+
     fn start_with_0rtt() -> (Http3Client, TestServer) {
         let (mut client, mut server) = connect();
         let token = exchange_token(&mut client, &mut server.conn);
@@ -3469,6 +4479,7 @@ mod tests {
     #[test]
     fn zero_rtt_send_request() {
         let (mut client, mut server) = start_with_0rtt();
+        client.set_request_metrics(true);
 
         let request_stream_id = make_request(
             &mut client,
@@ -3506,7 +4517,8 @@ mod tests {
         assert_eq!(res, Ok(HTTP_RESPONSE_2.len()));
         server.conn.stream_close_send(request_stream_id).unwrap();
 
-        read_response(&mut client, &mut server.conn, request_stream_id);
+        let metrics = read_response(&mut client, &mut server.conn, request_stream_id).unwrap();
+        assert!(metrics.zero_rtt);
 
         assert!(client.tls_info().unwrap().resumed());
         assert!(server.conn.tls_info().unwrap().resumed());
@@ -3526,6 +4538,7 @@ mod tests {
         let token = exchange_token(&mut client, &mut server.conn);
 
         let mut client = default_http3_client();
+        client.set_request_metrics(true);
         let mut server = Connection::new_server(
             test_fixture::DEFAULT_KEYS,
             test_fixture::DEFAULT_ALPN_H3,
@@ -3536,8 +4549,13 @@ mod tests {
         .unwrap();
         // Using a freshly initialized anti-replay context
         // should result in the server rejecting 0-RTT.
-        let ar = AntiReplay::new(now(), test_fixture::ANTI_REPLAY_WINDOW, 1, 3)
-            .expect("setup anti-replay");
+        let ar = AntiReplay::new(
+            &*test_fixture::clock(),
+            test_fixture::ANTI_REPLAY_WINDOW,
+            1,
+            3,
+        )
+        .expect("setup anti-replay");
         server
             .server_enable_0rtt(&ar, AllowZeroRtt {})
             .expect("enable 0-RTT");
@@ -3554,38 +4572,124 @@ mod tests {
         assert!(client_hs.as_dgram_ref().is_some());
 
         // Create a request
-        let request_stream_id = make_request(&mut client, false, &[]);
+        let request_stream_id = make_request(&mut client, true, &[]);
         assert_eq!(request_stream_id, 0);
 
         let client_0rtt = client.process(None, now());
-        assert!(client_0rtt.as_dgram_ref().is_some());
+        assert!(client_0rtt.as_dgram_ref().is_some());
+
+        let server_hs = server.process(client_hs.dgram(), now());
+        assert!(server_hs.as_dgram_ref().is_some()); // Should produce ServerHello etc...
+        let server_ignored = server.process(client_0rtt.dgram(), now());
+        assert!(server_ignored.as_dgram_ref().is_none());
+
+        // The server shouldn't receive that 0-RTT data.
+        let recvd_stream_evt = |e| matches!(e, ConnectionEvent::NewStream { .. });
+        assert!(!server.events().any(recvd_stream_evt));
+
+        // Client should get a rejection.
+        let client_out = client.process(server_hs.dgram(), now());
+        assert!(client_out.as_dgram_ref().is_some());
+        let recvd_0rtt_reject = |e| e == Http3ClientEvent::ZeroRttRejected;
+        assert!(client.events().any(recvd_0rtt_reject));
+
+        // Client will send Setting frame, open new qpack streams and send the request again
+        // on the same stream.
+        let _ = server.process(client_out.dgram(), now());
+        TestServer::new_with_conn(server).check_client_control_qpack_streams(
+            ENCODER_STREAM_DATA,
+            EXPECTED_REQUEST_HEADER_FRAME,
+            true,
+            true,
+        );
+
+        // The metrics count only the request that has been sent again.
+        client
+            .cancel_request(request_stream_id, Error::HttpRequestCancelled.code())
+            .unwrap();
+        let metrics = request_metrics_event(&mut client).unwrap();
+        assert!(!metrics.zero_rtt);
+        assert_eq!(metrics.header_bytes_sent, 16);
+
+        // The next request gets the next stream ID.
+        assert_eq!(make_request(&mut client, false, &[]), 4);
+    }
+
+    // A request with a body is sent again in full after 0-RTT has been rejected, a request
+    // that has been reset is not.
+    #[test]
+    fn zero_rtt_send_reject_retry_with_body() {
+        let (mut client, mut server) = connect();
+        let token = exchange_token(&mut client, &mut server.conn);
+
+        let mut client = default_http3_client();
+        let mut server = Connection::new_server(
+            test_fixture::DEFAULT_KEYS,
+            test_fixture::DEFAULT_ALPN_H3,
+            Rc::new(RefCell::new(FixedConnectionIdManager::new(10))),
+            &CongestionControlAlgorithm::NewReno,
+            QuicVersion::default(),
+        )
+        .unwrap();
+        let ar = AntiReplay::new(
+            &*test_fixture::clock(),
+            test_fixture::ANTI_REPLAY_WINDOW,
+            1,
+            3,
+        )
+        .expect("setup anti-replay");
+        server
+            .server_enable_0rtt(&ar, AllowZeroRtt {})
+            .expect("enable 0-RTT");
+        client
+            .enable_resumption(now(), &token)
+            .expect("Set resumption token.");
+        let client_hs = client.process(None, now());
+
+        // The first request is reset, the second one has a body.
+        let cancelled_stream_id = make_request(&mut client, false, &[]);
+        assert_eq!(cancelled_stream_id, 0);
+        client
+            .stream_reset(cancelled_stream_id, Error::HttpRequestCancelled.code())
+            .unwrap();
+        let request_stream_id = make_request(&mut client, false, &[]);
+        assert_eq!(request_stream_id, 4);
+        assert_eq!(
+            client.send_request_body(request_stream_id, REQUEST_BODY),
+            Ok(REQUEST_BODY.len())
+        );
+        client.stream_close_send(request_stream_id).unwrap();
+        let client_0rtt = client.process(None, now());
 
         let server_hs = server.process(client_hs.dgram(), now());
-        assert!(server_hs.as_dgram_ref().is_some()); // Should produce ServerHello etc...
-        let server_ignored = server.process(client_0rtt.dgram(), now());
-        assert!(server_ignored.as_dgram_ref().is_none());
-
-        // The server shouldn't receive that 0-RTT data.
-        let recvd_stream_evt = |e| matches!(e, ConnectionEvent::NewStream { .. });
-        assert!(!server.events().any(recvd_stream_evt));
-
-        // Client should get a rejection.
+        let _ = server.process(client_0rtt.dgram(), now());
         let client_out = client.process(server_hs.dgram(), now());
-        assert!(client_out.as_dgram_ref().is_some());
         let recvd_0rtt_reject = |e| e == Http3ClientEvent::ZeroRttRejected;
         assert!(client.events().any(recvd_0rtt_reject));
 
-        // ...and the client stream should be gone.
-        let res = client.stream_close_send(request_stream_id);
-        assert!(res.is_err());
-        assert_eq!(res.unwrap_err(), Error::InvalidStreamId);
-
-        // Client will send Setting frame and open new qpack streams.
         let _ = server.process(client_out.dgram(), now());
-        TestServer::new_with_conn(server).check_client_control_qpack_streams_no_resumption();
-
-        // Check that we can send a request and that the stream_id starts again from 0.
-        assert_eq!(make_request(&mut client, false, &[]), 0);
+        let mut cancelled = false;
+        let mut request = false;
+        while let Some(e) = server.next_event() {
+            match e {
+                ConnectionEvent::RecvStreamReset { stream_id, .. } => {
+                    assert_eq!(stream_id, cancelled_stream_id);
+                    cancelled = true;
+                }
+                ConnectionEvent::RecvStreamReadable { stream_id } if stream_id == 4 => {
+                    let mut buf = [0_u8; 100];
+                    let (amount, fin) = server.stream_recv(stream_id, &mut buf).unwrap();
+                    assert_eq!(fin, true);
+                    let header_len = EXPECTED_REQUEST_HEADER_FRAME.len();
+                    assert_eq!(&buf[..header_len], EXPECTED_REQUEST_HEADER_FRAME);
+                    assert_eq!(&buf[header_len..amount], EXPECTED_REQUEST_BODY_FRAME);
+                    request = true;
+                }
+                _ => {}
+            }
+        }
+        assert!(cancelled);
+        assert!(request);
     }
 
     // Connect to a server, get token and reconnect using 0-rtt. Seerver sends new Settings.
@@ -3836,6 +4940,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn zero_rtt_new_server_setting_connect_protocol_disabled() {
+        // Send the new server settings without EnableConnectProtocol
+        zero_rtt_change_settings(
+            &[
+                HSetting::new(HSettingType::MaxTableCapacity, 100),
+                HSetting::new(HSettingType::BlockedStreams, 100),
+                HSetting::new(HSettingType::MaxHeaderListSize, 10000),
+                HSetting::new(HSettingType::EnableConnectProtocol, 1),
+            ],
+            &[
+                HSetting::new(HSettingType::MaxTableCapacity, 100),
+                HSetting::new(HSettingType::BlockedStreams, 100),
+                HSetting::new(HSettingType::MaxHeaderListSize, 10000),
+            ],
+            &Http3State::Closing(CloseError::Application(265)),
+            ENCODER_STREAM_DATA_WITH_CAP_INSTRUCTION,
+        );
+    }
+
     #[test]
     fn zero_rtt_max_table_size_first_omitted() {
         // send server original settings without MaxTableCapacity
@@ -3931,7 +5055,7 @@ mod tests {
             &mut client,
             &mut server,
             request_stream_id,
-            HTTP_HEADER_FRAME_0,
+            HTTP_TRAILERS_FRAME,
             true,
         );
 
@@ -3992,7 +5116,7 @@ mod tests {
             &mut client,
             &mut server,
             request_stream_id,
-            HTTP_HEADER_FRAME_0,
+            HTTP_TRAILERS_FRAME,
             false,
         );
 
@@ -4062,7 +5186,7 @@ mod tests {
             &mut client,
             &mut server,
             request_stream_id,
-            HTTP_HEADER_FRAME_0,
+            HTTP_TRAILERS_FRAME,
             false,
         );
 
@@ -4082,6 +5206,69 @@ mod tests {
         assert_closed(&client, &Error::HttpFrameUnexpected);
     }
 
+    #[test]
+    fn test_trailers_ready_event() {
+        let (mut client, mut server, request_stream_id) = connect_and_send_request(true);
+
+        // Send a response with a body.
+        server_send_response_and_exchange_packet(
+            &mut client,
+            &mut server,
+            request_stream_id,
+            HTTP_RESPONSE_2,
+            false,
+        );
+        let data_readable_event = |e| matches!(e, Http3ClientEvent::DataReadable { .. });
+        assert!(client.events().any(data_readable_event));
+        let mut buf = [0_u8; 100];
+        let (amount, fin) = client
+            .read_response_data(now(), request_stream_id, &mut buf)
+            .unwrap();
+        assert_eq!(fin, false);
+        assert_eq!(&buf[..amount], EXPECTED_RESPONSE_DATA_2_FRAME_1);
+
+        // Send trailers and the fin.
+        server_send_response_and_exchange_packet(
+            &mut client,
+            &mut server,
+            request_stream_id,
+            HTTP_TRAILERS_FRAME,
+            true,
+        );
+
+        let mut trailers_ready = false;
+        let mut data_readable = false;
+        while let Some(e) = client.next_event() {
+            match e {
+                Http3ClientEvent::TrailersReady {
+                    stream_id,
+                    trailers,
+                } => {
+                    assert_eq!(stream_id, request_stream_id);
+                    check_trailers(&trailers);
+                    trailers_ready = true;
+                }
+                Http3ClientEvent::DataReadable { stream_id } => {
+                    assert_eq!(stream_id, request_stream_id);
+                    assert!(trailers_ready);
+                    data_readable = true;
+                }
+                Http3ClientEvent::HeaderReady { .. } => panic!("Trailers are not headers"),
+                _ => {}
+            }
+        }
+        assert!(trailers_ready);
+        assert!(data_readable);
+
+        // Reading from the stream will return fin=true.
+        let mut buf = [0_u8; 100];
+        let (len, fin) = client
+            .read_response_data(now(), request_stream_id, &mut buf)
+            .unwrap();
+        assert_eq!(0, len);
+        assert_eq!(fin, true);
+    }
+
     #[test]
     fn transport_stream_readable_event_after_all_data() {
         let (mut client, mut server, request_stream_id) = connect_and_send_request(false);
@@ -5412,6 +6599,120 @@ mod tests {
         assert_eq!(server.encoder.stats().stream_cancelled_recv, 1);
     }
 
+    #[test]
+    fn cancel_request() {
+        let (mut client, mut server, request_stream_id) = connect_and_send_request(false);
+
+        // The response headers are received but not read by the application.
+        server_send_response_and_exchange_packet(
+            &mut client,
+            &mut server,
+            request_stream_id,
+            HTTP_RESPONSE_HEADER_ONLY_1,
+            false,
+        );
+        client
+            .cancel_request(request_stream_id, Error::HttpRequestCancelled.code())
+            .unwrap();
+
+        // The only event of the request is the Reset.
+        let events: Vec<_> = client.events().collect();
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| matches!(
+                    e,
+                    Http3ClientEvent::HeaderReady { .. }
+                        | Http3ClientEvent::DataReadable { .. }
+                        | Http3ClientEvent::Reset { .. }
+                ))
+                .collect::<Vec<_>>(),
+            vec![&Http3ClientEvent::Reset {
+                stream_id: request_stream_id,
+                error: Error::HttpRequestCancelled.code(),
+                local: true,
+            }]
+        );
+
+        // The server sees both a RESET_STREAM and a STOP_SENDING.
+        let out = client.process(None, now());
+        let _ = server.conn.process(out.dgram(), now());
+        let mut reset = false;
+        let mut stop_sending = false;
+        while let Some(e) = server.conn.next_event() {
+            match e {
+                ConnectionEvent::RecvStreamReset {
+                    stream_id,
+                    app_error,
+                } => {
+                    assert_eq!(stream_id, request_stream_id);
+                    assert_eq!(app_error, Error::HttpRequestCancelled.code());
+                    reset = true;
+                }
+                ConnectionEvent::SendStreamStopSending {
+                    stream_id,
+                    app_error,
+                } => {
+                    assert_eq!(stream_id, request_stream_id);
+                    assert_eq!(app_error, Error::HttpRequestCancelled.code());
+                    stop_sending = true;
+                }
+                _ => {}
+            }
+        }
+        assert!(reset && stop_sending);
+
+        // The request is gone.
+        let mut buf = [0_u8; 100];
+        assert_eq!(
+            client.read_response_data(now(), request_stream_id, &mut buf),
+            Err(Error::InvalidStreamId)
+        );
+        assert_eq!(
+            client.cancel_request(request_stream_id, Error::HttpRequestCancelled.code()),
+            Err(Error::InvalidStreamId)
+        );
+        assert_eq!(client.state(), Http3State::Connected);
+    }
+
+    // Cancelling a request whose response headers are blocked drops the header block.
+    #[test]
+    fn cancel_request_header_blocked() {
+        let (mut client, mut server, request_stream_id) = connect_and_send_request(true);
+        setup_server_side_encoder(&mut client, &mut server);
+
+        let headers = vec![
+            (String::from(":status"), String::from("200")),
+            (String::from("my-header"), String::from("my-header")),
+            (String::from("content-length"), String::from("0")),
+        ];
+        let encoder_inst_pkt =
+            send_headers_using_encoder(&mut client, &mut server, request_stream_id, &headers, &[]);
+        assert!(!check_header_ready(&mut client));
+
+        client
+            .cancel_request(request_stream_id, Error::HttpRequestCancelled.code())
+            .unwrap();
+        let out = client.process(None, now());
+        let _ = server.conn.process(out.dgram(), now());
+        let _ = server
+            .encoder
+            .recv_if_encoder_stream(&mut server.conn, CLIENT_SIDE_DECODER_STREAM_ID);
+        assert_eq!(server.encoder.stats().stream_cancelled_recv, 1);
+
+        // The encoder instructions arrive late; the headers are not decoded for the request.
+        let _ = client.process(encoder_inst_pkt, now());
+        let events: Vec<_> = client.events().collect();
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, Http3ClientEvent::HeaderReady { .. })));
+        assert!(events.iter().any(|e| matches!(
+            e,
+            Http3ClientEvent::Reset { stream_id, local: true, .. } if *stream_id == request_stream_id
+        )));
+        assert_eq!(client.state(), Http3State::Connected);
+    }
+
     fn send_headers_using_encoder(
         client: &mut Http3Client,
         server: &mut TestServer,
@@ -5752,6 +7053,37 @@ mod tests {
         assert!(events.next().is_none());
     }
 
+    // HTTP/3 does not support 101 (Switching Protocols).
+    #[test]
+    fn response_w_101() {
+        let (mut client, mut server, request_stream_id) = connect_and_send_request(true);
+
+        setup_server_side_encoder(&mut client, &mut server);
+
+        let mut d = Encoder::default();
+        let headers = vec![(String::from(":status"), String::from("101"))];
+        server.encode_headers(request_stream_id, &headers, &mut d);
+
+        server_send_response_and_exchange_packet(
+            &mut client,
+            &mut server,
+            request_stream_id,
+            &d,
+            false,
+        );
+
+        // Stream has been reset because of the malformed headers.
+        let e = client.events().next().unwrap();
+        assert_eq!(
+            e,
+            Http3ClientEvent::Reset {
+                stream_id: request_stream_id,
+                error: Error::HttpGeneralProtocolStream.code(),
+                local: true,
+            }
+        );
+    }
+
     #[test]
     fn response_wo_status() {
         let (mut client, mut server, request_stream_id) = connect_and_send_request(true);
@@ -5805,6 +7137,96 @@ mod tests {
         );
     }
 
+    // Requests that the server does not accept according to its SETTINGS_MAX_FIELD_SECTION_SIZE
+    // fail locally.
+    #[test]
+    fn fetch_exceeds_peer_max_field_section_size() {
+        let mut client = default_http3_client();
+        let mut server = TestServer::new_with_settings(&[
+            HSetting::new(HSettingType::MaxTableCapacity, 100),
+            HSetting::new(HSettingType::BlockedStreams, 100),
+            HSetting::new(HSettingType::MaxHeaderListSize, 200),
+        ]);
+        connect_with(&mut client, &mut server);
+
+        let headers = vec![(String::from("myheaders"), String::from("myvalue"))];
+        assert_eq!(
+            client.fetch(now(), "GET", "https", "something.com", "/", &headers),
+            Err(Error::FieldSectionTooLarge)
+        );
+        // Without the additional header the request fits.
+        let request_stream_id = make_request(&mut client, true, &[]);
+        assert_eq!(request_stream_id, 0);
+    }
+
+    // A response larger than the SETTINGS_MAX_FIELD_SECTION_SIZE the client has sent is
+    // discarded and the request is reset.
+    #[test]
+    fn response_exceeds_local_max_field_section_size() {
+        let mut client = default_http3_client();
+        client.set_max_field_section_size(100).unwrap();
+        let mut server = TestServer::new();
+        connect_only_transport_with(&mut client, &mut server);
+
+        // The limit is sent in the SETTINGS frame.
+        let out = client.process(None, now());
+        let _ = server.conn.process(out.dgram(), now());
+        let mut buf = [0_u8; 100];
+        let (amount, _) = server
+            .conn
+            .stream_recv(CLIENT_SIDE_CONTROL_STREAM_ID, &mut buf)
+            .unwrap();
+        let mut dec = Decoder::from(&buf[..amount]);
+        assert_eq!(dec.decode_varint().unwrap(), 0); // control stream type
+        assert_eq!(dec.decode_varint().unwrap(), 4); // SETTINGS
+        assert_eq!(
+            dec.decode_vvec().unwrap(),
+            &[1, 0x40, 0x64, 7, 0x40, 0x64, 6, 0x40, 0x64]
+        );
+
+        server.create_control_stream();
+        server.create_qpack_streams();
+        let out = server.conn.process(None, now());
+        client.process(out.dgram(), now());
+
+        let request_stream_id = make_request(&mut client, true, &[]);
+        let out = client.process(None, now());
+        let _ = server.conn.process(out.dgram(), now());
+
+        let mut d = Encoder::default();
+        let headers = vec![
+            (String::from(":status"), String::from("200")),
+            (String::from("my-header"), "a".repeat(60)),
+        ];
+        server.encode_headers(request_stream_id, &headers, &mut d);
+        server_send_response_and_exchange_packet(
+            &mut client,
+            &mut server,
+            request_stream_id,
+            &d,
+            true,
+        );
+
+        let reset_event = |e| {
+            matches!(e, Http3ClientEvent::Reset {
+                stream_id,
+                error,
+                local: true
+            } if stream_id == request_stream_id && error == Error::HttpExcessiveLoadStream.code())
+        };
+        assert!(client.events().any(reset_event));
+
+        let out = client.process(None, now()).dgram();
+        let _ = server.conn.process(out, now());
+        let stop_sending_event = |e| {
+            matches!(e, ConnectionEvent::SendStreamStopSending {
+            stream_id,
+            app_error
+        } if stream_id == request_stream_id && app_error == Error::HttpExcessiveLoad.code())
+        };
+        assert!(server.conn.events().any(stop_sending_event));
+    }
+
     // Client: receive a push stream
     #[test]
     fn push_single_with_1xx() {
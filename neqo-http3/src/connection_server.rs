@@ -6,13 +6,17 @@
 
 use crate::connection::{HandleReadableOutput, Http3Connection, Http3State};
 use crate::hframe::HFrame;
+use crate::priority::{set_stream_priority, Priority};
 use crate::recv_message::{MessageType, RecvMessage};
 use crate::send_message::SendMessage;
 use crate::server_connection_events::{Http3ServerConnEvent, Http3ServerConnEvents};
-use crate::{Error, Header, Res};
-use neqo_common::{event::Provider, qdebug, qinfo, qtrace};
+use crate::settings::HSetting;
+use crate::webtransport::{WebTransportEvent, WebTransportEvents, WebTransportSessions};
+use crate::{Error, Header, RecvMessageEvents, Res};
+use neqo_common::{event::Provider, metrics::MetricsRef, qdebug, qinfo, qtrace};
 use neqo_qpack::QpackSettings;
 use neqo_transport::{AppError, Connection, ConnectionEvent, StreamType};
+use std::collections::HashMap;
 use std::time::Instant;
 
 #[derive(Debug)]
@@ -20,6 +24,20 @@ pub struct Http3ServerHandler {
     base_handler: Http3Connection,
     events: Http3ServerConnEvents,
     needs_processing: bool,
+    // Whether extended CONNECT requests are accepted.
+    enable_connect_protocol: bool,
+    // Whether WebTransport sessions are accepted.
+    enable_webtransport: bool,
+    webtransport: WebTransportSessions,
+    // The largest push ID allowed by the client, if a MAX_PUSH_ID frame has been received.
+    max_push_id: Option<u64>,
+    // The largest request stream ID opened by the client.
+    largest_request: Option<u64>,
+    // The push ID of the last GOAWAY frame received from the client.
+    peer_goaway: Option<u64>,
+    // Priorities received in PRIORITY_UPDATE frames. They are kept until the request headers
+    // arrive, because they take precedence over the `priority` header field.
+    priority_updates: HashMap<u64, Priority>,
 }
 
 impl ::std::fmt::Display for Http3ServerHandler {
@@ -29,31 +47,316 @@ impl ::std::fmt::Display for Http3ServerHandler {
 }
 
 impl Http3ServerHandler {
-    pub(crate) fn new(qpack_settings: QpackSettings) -> Self {
+    pub(crate) fn new(
+        qpack_settings: QpackSettings,
+        enable_connect_protocol: bool,
+        enable_webtransport: bool,
+        enable_h3_datagram: bool,
+    ) -> Self {
+        // WebTransport sessions are extended CONNECT requests.
+        let enable_connect_protocol = enable_connect_protocol || enable_webtransport;
         Self {
-            base_handler: Http3Connection::new(qpack_settings),
+            base_handler: Http3Connection::new(
+                qpack_settings,
+                enable_connect_protocol,
+                enable_webtransport,
+                enable_h3_datagram,
+            ),
+            enable_connect_protocol,
+            enable_webtransport,
+            webtransport: WebTransportSessions::default(),
             events: Http3ServerConnEvents::default(),
             needs_processing: false,
+            max_push_id: None,
+            largest_request: None,
+            peer_goaway: None,
+            priority_updates: HashMap::new(),
         }
     }
 
+    /// Set the settings that are sent in addition to the ones managed by neqo-http3.
+    pub(crate) fn set_extension_settings(&mut self, settings: Vec<HSetting>) {
+        self.base_handler.set_extension_settings(settings);
+    }
+
+    /// Set where QPACK metrics are reported.
+    pub(crate) fn set_metrics(&mut self, metrics: MetricsRef) {
+        self.base_handler.qpack_encoder.set_metrics(metrics);
+    }
+
+    /// Set the origins that are sent in an ORIGIN frame.
+    pub(crate) fn set_origins(&mut self, origins: Vec<String>) {
+        self.base_handler.set_origins(origins);
+    }
+
+    /// Send frames and a stream of reserved types.
+    pub(crate) fn set_grease(&mut self, grease: bool) {
+        self.base_handler.set_grease(grease);
+    }
+
+    /// Set the SETTINGS_MAX_FIELD_SECTION_SIZE that is sent.
+    pub(crate) fn set_max_field_section_size(&mut self, max: u64) {
+        self.base_handler.set_max_field_section_size(max);
+    }
+
+    /// The settings received from the client that are not managed by neqo-http3.
+    pub(crate) fn peer_extension_settings(&self) -> Vec<(u64, u64)> {
+        self.base_handler.peer_extension_settings()
+    }
+
+    /// Whether extended CONNECT requests, i.e. requests with a `:protocol` pseudo-header, have
+    /// been enabled with `SETTINGS_ENABLE_CONNECT_PROTOCOL`.
+    pub(crate) fn enables_connect_protocol(&self) -> bool {
+        self.enable_connect_protocol
+    }
+
+    /// Mark a request as a CONNECT tunnel, so that a reset of either direction fails the tunnel
+    /// with `H3_CONNECT_ERROR`.
+    pub(crate) fn add_connect_tunnel(&mut self, stream_id: u64) {
+        self.base_handler.add_connect_tunnel(stream_id);
+    }
+
+    /// Whether WebTransport has been enabled with `SETTINGS_ENABLE_WEBTRANSPORT`.
+    pub(crate) fn enables_webtransport(&self) -> bool {
+        self.enable_webtransport
+    }
+
+    /// A request has been found to open a WebTransport session. From now on the data of the
+    /// request are read by the handler.
+    pub(crate) fn webtransport_new_session(&mut self, session_id: u64) {
+        self.webtransport.add_session(session_id);
+        // Data may have arrived with the headers.
+        self.needs_processing = true;
+    }
+
+    /// Whether a stream is the CONNECT stream of a WebTransport session.
+    pub(crate) fn is_webtransport_session(&self, stream_id: u64) -> bool {
+        self.webtransport.is_session(stream_id)
+    }
+
+    /// Accept a WebTransport session with a 200 response or reject it with a 404 response.
+    pub(crate) fn webtransport_session_response(
+        &mut self,
+        conn: &mut Connection,
+        session_id: u64,
+        accept: bool,
+    ) -> Res<()> {
+        let status = if accept { "200" } else { "404" };
+        let headers = [(String::from(":status"), String::from(status))];
+        if accept {
+            self.set_response_headers(session_id, &headers)
+        } else {
+            self.webtransport.close_locally(conn, session_id);
+            self.set_response(session_id, &headers, &[], None)
+        }
+    }
+
+    pub(crate) fn webtransport_close_session(
+        &mut self,
+        conn: &mut Connection,
+        session_id: u64,
+        error: u32,
+        message: &str,
+    ) -> Res<()> {
+        self.webtransport.close_session(
+            conn,
+            &mut self.base_handler,
+            session_id,
+            error,
+            message,
+        )?;
+        self.needs_processing = true;
+        Ok(())
+    }
+
+    pub(crate) fn webtransport_create_stream(
+        &mut self,
+        conn: &mut Connection,
+        session_id: u64,
+        stream_type: StreamType,
+    ) -> Res<u64> {
+        let stream_id = self
+            .webtransport
+            .create_stream(conn, session_id, stream_type)?;
+        self.needs_processing = true;
+        Ok(stream_id)
+    }
+
+    pub(crate) fn webtransport_send_datagram(
+        &mut self,
+        conn: &mut Connection,
+        session_id: u64,
+        datagram: &[u8],
+    ) -> Res<bool> {
+        let sent =
+            self.webtransport
+                .send_datagram(conn, &mut self.base_handler, session_id, datagram)?;
+        self.needs_processing = true;
+        Ok(sent)
+    }
+
+    pub(crate) fn webtransport_stream_send(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        buf: &[u8],
+    ) -> Res<usize> {
+        let sent = self.webtransport.stream_send(conn, stream_id, buf)?;
+        self.needs_processing = true;
+        Ok(sent)
+    }
+
+    pub(crate) fn webtransport_stream_recv(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        buf: &mut [u8],
+    ) -> Res<(usize, bool)> {
+        self.webtransport.stream_recv(conn, stream_id, buf)
+    }
+
+    pub(crate) fn webtransport_stream_close_send(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+    ) -> Res<()> {
+        self.webtransport.stream_close_send(conn, stream_id)?;
+        self.needs_processing = true;
+        Ok(())
+    }
+
+    pub(crate) fn webtransport_stream_reset(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        error: AppError,
+    ) -> Res<()> {
+        self.webtransport.stream_reset(conn, stream_id, error)?;
+        self.needs_processing = true;
+        Ok(())
+    }
+
     /// Supply a response for a request.
     pub(crate) fn set_response(
         &mut self,
         stream_id: u64,
         headers: &[Header],
         data: &[u8],
+        trailers: Option<&[Header]>,
+    ) -> Res<()> {
+        self.base_handler.check_peer_field_section_size(headers)?;
+        if let Some(t) = trailers {
+            self.base_handler.check_peer_field_section_size(t)?;
+        }
+        self.base_handler
+            .send_streams
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?
+            .set_message(headers, Some(data), trailers)?;
+        self.base_handler
+            .insert_streams_have_data_to_send(stream_id);
+        Ok(())
+    }
+
+    /// Queue an interim (1xx) response for a request. It is sent before the final response.
+    pub(crate) fn set_interim_response(&mut self, stream_id: u64, headers: &[Header]) -> Res<()> {
+        self.base_handler.check_peer_field_section_size(headers)?;
+        self.base_handler
+            .send_streams
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?
+            .set_interim_headers(headers)?;
+        self.base_handler
+            .insert_streams_have_data_to_send(stream_id);
+        Ok(())
+    }
+
+    /// Supply response headers for a request without closing the stream. The response body
+    /// is sent using `send_data`, e.g. the data of a CONNECT tunnel.
+    pub(crate) fn set_response_headers(&mut self, stream_id: u64, headers: &[Header]) -> Res<()> {
+        self.base_handler.check_peer_field_section_size(headers)?;
+        self.base_handler
+            .send_streams
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?
+            .set_headers(headers)?;
+        self.base_handler
+            .insert_streams_have_data_to_send(stream_id);
+        Ok(())
+    }
+
+    /// Send response data after the response headers set with `set_response_headers`.
+    pub(crate) fn send_data(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        buf: &[u8],
+    ) -> Res<usize> {
+        let sent = self
+            .base_handler
+            .send_streams
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?
+            .send_body(conn, buf)?;
+        self.needs_processing = true;
+        Ok(sent)
+    }
+
+    /// Send trailers after response data sent with `send_data`. This closes the response.
+    pub(crate) fn send_response_trailers(
+        &mut self,
+        stream_id: u64,
+        trailers: &[Header],
     ) -> Res<()> {
+        self.base_handler.check_peer_field_section_size(trailers)?;
         self.base_handler
             .send_streams
             .get_mut(&stream_id)
             .ok_or(Error::InvalidStreamId)?
-            .set_message(headers, Some(data))?;
+            .set_trailers(trailers)?;
         self.base_handler
             .insert_streams_have_data_to_send(stream_id);
         Ok(())
     }
 
+    /// Send a UDP payload on a connect-udp session.
+    pub(crate) fn send_udp_payload(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        payload: &[u8],
+    ) -> Res<bool> {
+        let sent = self
+            .base_handler
+            .send_udp_payload(conn, stream_id, payload)?;
+        self.needs_processing = true;
+        Ok(sent)
+    }
+
+    /// Send an HTTP Datagram that belongs to a request.
+    pub(crate) fn send_datagram(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        buf: &[u8],
+    ) -> Res<()> {
+        self.base_handler.send_datagram(conn, stream_id, buf)?;
+        self.needs_processing = true;
+        Ok(())
+    }
+
+    /// The largest HTTP Datagram that can be sent on a request.
+    pub(crate) fn max_datagram_size(&self, conn: &Connection, stream_id: u64) -> Res<u64> {
+        self.base_handler.max_datagram_size(conn, stream_id)
+    }
+
+    /// Close the sending side of a response.
+    pub(crate) fn stream_close_send(&mut self, conn: &mut Connection, stream_id: u64) -> Res<()> {
+        self.base_handler.stream_close_send(conn, stream_id)?;
+        self.needs_processing = true;
+        Ok(())
+    }
+
     /// Reset a request.
     pub fn stream_reset(
         &mut self,
@@ -67,6 +370,58 @@ impl Http3ServerHandler {
         Ok(())
     }
 
+    // Reset a malformed request and tell the application about it.
+    fn reset_stream_on_error(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        app_error: AppError,
+    ) {
+        qinfo!([self], "Reset stream {} on error {}.", stream_id, app_error);
+        let _ = self.base_handler.stream_reset(conn, stream_id, app_error);
+        self.events.reset(stream_id, app_error, true);
+        self.needs_processing = true;
+    }
+
+    /// Set the priority of the response to a request whose headers have been received.
+    /// # Errors
+    /// An error from the transport, other than the stream being closed already.
+    pub(crate) fn set_request_priority(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        headers: &[Header],
+    ) -> Res<()> {
+        let priority = self
+            .priority_updates
+            .remove(&stream_id)
+            .or_else(|| Priority::from_headers(headers));
+        if let Some(p) = priority {
+            qdebug!([self], "Stream {} has priority {:?}", stream_id, p);
+            set_stream_priority(conn, stream_id, p)?;
+        }
+        Ok(())
+    }
+
+    /// Start a graceful shutdown of the connection. A GOAWAY frame is sent with the ID of the
+    /// first request stream that has not been opened by the client yet. Requests that have
+    /// already been opened can be completed, newer ones are rejected with
+    /// `H3_REQUEST_REJECTED`. This has no effect if the connection is not connected or if a
+    /// GOAWAY frame has already been sent.
+    pub(crate) fn goaway(&mut self) {
+        if self.base_handler.state() != Http3State::Connected {
+            return;
+        }
+        let stream_id = self.largest_request.map_or(0, |id| id + 4);
+        qinfo!([self], "Send GOAWAY stream_id={}", stream_id);
+        self.base_handler
+            .queue_control_frame(&HFrame::Goaway { stream_id });
+        self.base_handler.state = Http3State::GoingAway(stream_id);
+        self.events
+            .connection_state_change(self.base_handler.state());
+        self.needs_processing = true;
+    }
+
     /// Process HTTTP3 layer.
     pub fn process_http3(&mut self, conn: &mut Connection, now: Instant) {
         qtrace!([self], "Process http3 internal.");
@@ -76,9 +431,27 @@ impl Http3ServerHandler {
 
         let res = self.check_connection_events(conn, now);
         if !self.check_result(conn, now, &res) && self.base_handler.state().active() {
-            let res = self.base_handler.process_sending(conn);
-            self.check_result(conn, now, &res);
+            let res = self
+                .webtransport
+                .process(conn, &mut self.base_handler, &self.events);
+            if !self.check_result(conn, now, &res) {
+                let res = self.base_handler.process_sending(conn);
+                self.check_result(conn, now, &res);
+            }
         }
+        self.remove_stale_priority_updates();
+    }
+
+    // Forget updates for requests that are already done; updates for requests that have not
+    // been opened yet are kept.
+    fn remove_stale_priority_updates(&mut self) {
+        let largest_request = self.largest_request;
+        let base_handler = &self.base_handler;
+        self.priority_updates.retain(|id, _| {
+            largest_request.map_or(true, |largest| *id > largest)
+                || base_handler.recv_streams.contains_key(id)
+                || base_handler.send_streams.contains_key(id)
+        });
     }
 
     /// Take the next available event.
@@ -108,7 +481,10 @@ impl Http3ServerHandler {
     }
 
     fn close(&mut self, conn: &mut Connection, now: Instant, err: &Error) {
-        qinfo!([self], "Connection error: {}.", err);
+        match self.base_handler.error_context() {
+            Some(context) => qinfo!([self], "Connection error: {} ({}).", err, context),
+            None => qinfo!([self], "Connection error: {}.", err),
+        }
         conn.close(now, err.code(), &format!("{}", err));
         self.base_handler.close(err.code());
         self.events
@@ -122,16 +498,29 @@ impl Http3ServerHandler {
             qdebug!([self], "check_connection_events - event {:?}.", e);
             match e {
                 ConnectionEvent::NewStream { stream_id } => match stream_id.stream_type() {
-                    StreamType::BiDi => self.base_handler.add_streams(
-                        stream_id.as_u64(),
-                        SendMessage::new(stream_id.as_u64(), Box::new(self.events.clone())),
-                        Box::new(RecvMessage::new(
-                            MessageType::Request,
+                    StreamType::BiDi => {
+                        if let Http3State::GoingAway(goaway_stream_id) = self.base_handler.state() {
+                            if stream_id.as_u64() >= goaway_stream_id {
+                                qdebug!([self], "Reject request {} after GOAWAY.", stream_id);
+                                let error = Error::HttpRequestRejected.code();
+                                let _ = conn.stream_stop_sending(stream_id.as_u64(), error);
+                                let _ = conn.stream_reset_send(stream_id.as_u64(), error);
+                                continue;
+                            }
+                        }
+                        self.largest_request = self.largest_request.max(Some(stream_id.as_u64()));
+                        self.base_handler.add_streams(
                             stream_id.as_u64(),
-                            Box::new(self.events.clone()),
-                            None,
-                        )),
-                    ),
+                            SendMessage::new(stream_id.as_u64(), Box::new(self.events.clone())),
+                            Box::new(RecvMessage::new(
+                                MessageType::Request,
+                                stream_id.as_u64(),
+                                Box::new(self.events.clone()),
+                                None,
+                                self.base_handler.local_max_field_section_size(),
+                            )),
+                        )
+                    }
                     StreamType::UniDi => {
                         if self
                             .base_handler
@@ -142,21 +531,28 @@ impl Http3ServerHandler {
                     }
                 },
                 ConnectionEvent::RecvStreamReadable { stream_id } => {
-                    self.handle_stream_readable(conn, stream_id)?
+                    if let Err(e) = self.handle_stream_readable(conn, stream_id) {
+                        if e.stream_reset_error() {
+                            self.reset_stream_on_error(conn, stream_id, e.code());
+                        } else {
+                            self.base_handler.set_error_context(stream_id);
+                            return Err(e);
+                        }
+                    }
                 }
                 ConnectionEvent::RecvStreamReset {
                     stream_id,
                     app_error,
                 } => {
                     self.base_handler
-                        .handle_stream_reset(stream_id, app_error)?;
+                        .handle_stream_reset(conn, stream_id, app_error)?;
                 }
                 ConnectionEvent::SendStreamStopSending {
                     stream_id,
                     app_error,
                 } => self
                     .base_handler
-                    .handle_stream_stop_sending(stream_id, app_error)?,
+                    .handle_stream_stop_sending(conn, stream_id, app_error)?,
                 ConnectionEvent::StateChange(state) => {
                     if self.base_handler.handle_state_change(conn, &state)? {
                         if self.base_handler.state() == Http3State::Connected {
@@ -170,9 +566,32 @@ impl Http3ServerHandler {
                 ConnectionEvent::AuthenticationNeeded
                 | ConnectionEvent::ZeroRttRejected
                 | ConnectionEvent::ResumptionToken(..) => return Err(Error::HttpInternal),
-                ConnectionEvent::SendStreamWritable { .. }
-                | ConnectionEvent::SendStreamComplete { .. }
-                | ConnectionEvent::SendStreamCreatable { .. } => {}
+                ConnectionEvent::SendStreamWritable { stream_id } => {
+                    if let Some(s) = self.base_handler.send_streams.get_mut(&stream_id.as_u64()) {
+                        s.stream_writable();
+                    }
+                }
+                ConnectionEvent::SendStreamComplete { stream_id } => {
+                    // Only report responses, not the streams of WebTransport sessions.
+                    if stream_id % 4 == 0
+                        && !self.webtransport.is_session(stream_id)
+                        && self.webtransport.stream_session(stream_id).is_none()
+                    {
+                        self.events.response_complete(stream_id);
+                    }
+                }
+                ConnectionEvent::SendStreamCreatable { .. } => {}
+                ConnectionEvent::Datagram(datagram) => {
+                    if let Some((stream_id, payload)) =
+                        self.base_handler.handle_datagram(&datagram)?
+                    {
+                        if self.webtransport.is_session(stream_id) {
+                            self.webtransport.datagram(&self.events, stream_id, payload);
+                        } else {
+                            self.events.datagram(stream_id, payload);
+                        }
+                    }
+                }
             }
         }
         Ok(())
@@ -184,29 +603,134 @@ impl Http3ServerHandler {
             HandleReadableOutput::ControlFrames(control_frames) => {
                 for f in control_frames {
                     match f {
-                        HFrame::MaxPushId { .. } => {
-                            // TODO implement push
-                            Ok(())
-                        }
-                        HFrame::Goaway { .. } | HFrame::CancelPush { .. } => {
-                            Err(Error::HttpFrameUnexpected)
+                        HFrame::MaxPushId { push_id } => self.handle_max_push_id(push_id),
+                        HFrame::CancelPush { push_id } => self.handle_cancel_push(push_id),
+                        HFrame::PriorityUpdateRequest {
+                            element_id,
+                            priority,
+                        } => self.handle_priority_update_request(conn, element_id, priority),
+                        HFrame::PriorityUpdatePush { element_id, .. } => {
+                            self.handle_priority_update_push(element_id)
                         }
+                        HFrame::Goaway { stream_id } => self.handle_goaway(stream_id),
+                        // Only servers send ORIGIN frames; there is nothing to do with one
+                        // from a client.
+                        HFrame::Origin { .. } => Ok(()),
                         _ => unreachable!(
-                            "we should only put MaxPushId and Goaway into control_frames."
+                            "we should only put MaxPushId, CancelPush, PriorityUpdate and Goaway into control_frames."
                         ),
                     }?;
                 }
                 Ok(())
             }
+            HandleReadableOutput::StreamNotFound => {
+                if let Some(session_id) = self.webtransport.stream_session(stream_id) {
+                    self.events
+                        .webtransport_event(WebTransportEvent::DataReadable {
+                            stream_id,
+                            session_id,
+                        });
+                }
+                Ok(())
+            }
             _ => Ok(()),
         }
     }
 
-    /// Response data are read directly into a buffer supplied as a parameter of this function to avoid copying
+    fn handle_max_push_id(&mut self, push_id: u64) -> Res<()> {
+        qdebug!(
+            [self],
+            "MAX_PUSH_ID frame has been received, push_id={}",
+            push_id
+        );
+        // A client must not reduce the maximum push ID.
+        if self.max_push_id.map_or(false, |max| push_id < max) {
+            return Err(Error::HttpId);
+        }
+        self.max_push_id = Some(push_id);
+        Ok(())
+    }
+
+    fn handle_goaway(&mut self, push_id: u64) -> Res<()> {
+        qinfo!(
+            [self],
+            "GOAWAY frame has been received, push_id={}",
+            push_id
+        );
+        // The ID in a GOAWAY frame sent by a client is a push ID, it must not increase.
+        if self.peer_goaway.map_or(false, |last| push_id > last) {
+            return Err(Error::HttpId);
+        }
+        // The server never promises a push, so no push needs to be cancelled.
+        self.peer_goaway = Some(push_id);
+        Ok(())
+    }
+
+    fn handle_cancel_push(&mut self, push_id: u64) -> Res<()> {
+        qdebug!(
+            [self],
+            "CANCEL_PUSH frame has been received, push_id={}",
+            push_id
+        );
+        // A push ID that the client has not allowed yet cannot refer to a push.
+        if self.max_push_id.map_or(true, |max| push_id > max) {
+            return Err(Error::HttpId);
+        }
+        // The server never promises a push, so there is no push stream to clean up.
+        Ok(())
+    }
+
+    fn handle_priority_update_request(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        priority: Priority,
+    ) -> Res<()> {
+        qdebug!(
+            [self],
+            "PRIORITY_UPDATE frame has been received, stream_id={} priority={:?}",
+            stream_id,
+            priority
+        );
+        // Only client-initiated bidirectional streams carry requests, and only those that the
+        // client is allowed to open can be updated. This bounds `priority_updates`.
+        if stream_id & 0x3 != 0 || !conn.peer_stream_allowed(stream_id) {
+            return Err(Error::HttpId);
+        }
+        let opened = self
+            .largest_request
+            .map_or(false, |largest| stream_id <= largest);
+        if opened && !self.base_handler.recv_streams.contains_key(&stream_id) {
+            qdebug!([self], "Request {} is already done", stream_id);
+            return Ok(());
+        }
+        // Keep the update for a request whose headers have not been read yet, as it takes
+        // precedence over their priority field. `remove_stale_priority_updates` drops it once
+        // the request is done.
+        set_stream_priority(conn, stream_id, priority)?;
+        self.priority_updates.insert(stream_id, priority);
+        Ok(())
+    }
+
+    fn handle_priority_update_push(&mut self, push_id: u64) -> Res<()> {
+        qdebug!(
+            [self],
+            "PRIORITY_UPDATE frame for a push has been received, push_id={}",
+            push_id
+        );
+        // As with CANCEL_PUSH, the push ID must have been allowed by the client.
+        if self.max_push_id.map_or(true, |max| push_id > max) {
+            return Err(Error::HttpId);
+        }
+        Ok(())
+    }
+
+    /// Request data are read directly into a buffer supplied as a parameter of this function to avoid copying
     /// data.
     /// # Errors
-    /// It returns an error if a stream does not exist or an error happen while reading a stream, e.g.
-    /// early close, protocol error, etc.
+    /// `InvalidStreamId` if the stream does not exist, e.g. because all data and the fin have
+    /// already been read. Other errors happen while reading a stream, e.g. early close,
+    /// protocol error, etc., and close the connection.
     pub fn read_request_data(
         &mut self,
         conn: &mut Connection,
@@ -216,10 +740,7 @@ impl Http3ServerHandler {
     ) -> Res<(usize, bool)> {
         qinfo!([self], "read_data from stream {}.", stream_id);
         match self.base_handler.recv_streams.get_mut(&stream_id) {
-            None => {
-                self.close(conn, now, &Error::Internal);
-                Err(Error::Internal)
-            }
+            None => Err(Error::InvalidStreamId),
             Some(recv_stream) => {
                 match recv_stream.read_data(conn, &mut self.base_handler.qpack_decoder, buf) {
                     Ok((amount, fin)) => {
@@ -229,7 +750,11 @@ impl Http3ServerHandler {
                         Ok((amount, fin))
                     }
                     Err(e) => {
-                        self.close(conn, now, &e);
+                        if e.stream_reset_error() {
+                            self.reset_stream_on_error(conn, stream_id, e.code());
+                        } else {
+                            self.close(conn, now, &e);
+                        }
                         Err(e)
                     }
                 }
@@ -0,0 +1,317 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checks for malformed messages (RFC 9114, Section 4.1.2). A malformed message is reset with
+// H3_MESSAGE_ERROR.
+
+use crate::recv_message::MessageType;
+use crate::{Error, Header, Res};
+use std::convert::TryFrom;
+
+const REQUEST_PSEUDO_HEADERS: &[&str] = &[":method", ":scheme", ":authority", ":path", ":protocol"];
+const RESPONSE_PSEUDO_HEADERS: &[&str] = &[":status"];
+
+// Header fields that are specific to a HTTP/1.1 connection and must not be used in HTTP/3.
+// `te` is handled separately, it is allowed with the value "trailers".
+const CONNECTION_SPECIFIC_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+fn check_field_name(name: &str) -> Res<()> {
+    // Field names must be lowercase; the other characters are the token characters of RFC 9110.
+    let valid =
+        |c: u8| c.is_ascii_lowercase() || c.is_ascii_digit() || b"!#$%&'*+-.^_`|~".contains(&c);
+    let name = name.strip_prefix(':').unwrap_or(name);
+    if name.is_empty() || !name.bytes().all(valid) {
+        return Err(Error::HttpMessageError);
+    }
+    Ok(())
+}
+
+fn check_field_value(value: &str) -> Res<()> {
+    // NUL, CR and LF are never allowed and a value must not start or end with whitespace.
+    if value.bytes().any(|c| c == 0 || c == b'\r' || c == b'\n')
+        || value.starts_with(|c| c == ' ' || c == '\t')
+        || value.ends_with(|c| c == ' ' || c == '\t')
+    {
+        return Err(Error::HttpMessageError);
+    }
+    Ok(())
+}
+
+/// Check the field names and values of headers or trailers, and that connection-specific
+/// header fields are absent.
+fn check_fields(headers: &[Header]) -> Res<()> {
+    for (name, value) in headers {
+        check_field_name(name)?;
+        check_field_value(value)?;
+        if CONNECTION_SPECIFIC_HEADERS.contains(&name.as_str())
+            || (name == "te" && value != "trailers")
+        {
+            return Err(Error::HttpMessageError);
+        }
+    }
+    Ok(())
+}
+
+/// The size of a field section that is compared with SETTINGS_MAX_FIELD_SECTION_SIZE: the
+/// length of each name and value plus an overhead of 32 bytes per field (RFC 9114, Section 4.2.2).
+pub fn field_section_size(headers: &[Header]) -> u64 {
+    headers
+        .iter()
+        .map(|(name, value)| u64::try_from(name.len() + value.len() + 32).unwrap())
+        .sum()
+}
+
+/// Get the value of the `content-length` header field. Repeated values must be the same.
+/// # Errors
+/// `HttpMessageError` if the value is not a number or if values differ.
+pub fn content_length(headers: &[Header]) -> Res<Option<u64>> {
+    let mut length = None;
+    for (_, value) in headers.iter().filter(|(name, _)| name == "content-length") {
+        if value.is_empty() || !value.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(Error::HttpMessageError);
+        }
+        let v = value.parse::<u64>().map_err(|_| Error::HttpMessageError)?;
+        if length.map_or(false, |l| l != v) {
+            return Err(Error::HttpMessageError);
+        }
+        length = Some(v);
+    }
+    Ok(length)
+}
+
+/// Check the headers of a request or a response. Pseudo-header fields must come first, must
+/// not be repeated and must belong to the message type. A request needs `:method` and, apart
+/// from a CONNECT request that is not an extended CONNECT, `:scheme` and a non-empty `:path`.
+/// # Errors
+/// `HttpMessageError` if the message is malformed.
+pub fn check_headers(message_type: &MessageType, headers: &[Header]) -> Res<()> {
+    let allowed = match message_type {
+        MessageType::Request => REQUEST_PSEUDO_HEADERS,
+        MessageType::Response => RESPONSE_PSEUDO_HEADERS,
+    };
+    let mut pseudo = Vec::new();
+    let mut regular_seen = false;
+    for (name, _) in headers {
+        if name.starts_with(':') {
+            if regular_seen || !allowed.contains(&name.as_str()) || pseudo.contains(&name) {
+                return Err(Error::HttpMessageError);
+            }
+            pseudo.push(name);
+        } else {
+            regular_seen = true;
+        }
+    }
+    check_fields(headers)?;
+    content_length(headers)?;
+
+    let get = |n: &str| {
+        headers
+            .iter()
+            .find(|(name, _)| name == n)
+            .map(|(_, value)| value.as_str())
+    };
+    let valid = match message_type {
+        MessageType::Request => match (get(":method"), get(":protocol")) {
+            (None, _) => false,
+            (Some("CONNECT"), None) => {
+                get(":authority").is_some() && get(":scheme").is_none() && get(":path").is_none()
+            }
+            (Some(m), protocol) => {
+                (protocol.is_none() || m == "CONNECT")
+                    && get(":scheme").is_some()
+                    && get(":path").map_or(false, |p| !p.is_empty())
+            }
+        },
+        MessageType::Response => get(":status").is_some(),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::HttpMessageError)
+    }
+}
+
+/// Check trailers. They must not contain pseudo-header fields.
+/// # Errors
+/// `HttpMessageError` if the trailers are malformed.
+pub fn check_trailers(trailers: &[Header]) -> Res<()> {
+    if trailers.iter().any(|(name, _)| name.starts_with(':')) {
+        return Err(Error::HttpMessageError);
+    }
+    check_fields(trailers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_headers, check_trailers, content_length, field_section_size};
+    use crate::recv_message::MessageType;
+    use crate::{Error, Header};
+
+    fn h(fields: &[(&str, &str)]) -> Vec<Header> {
+        fields
+            .iter()
+            .map(|(n, v)| (String::from(*n), String::from(*v)))
+            .collect()
+    }
+
+    fn request(extra: &[(&str, &str)]) -> Vec<Header> {
+        let mut headers = h(&[
+            (":method", "GET"),
+            (":scheme", "https"),
+            (":authority", "something.com"),
+            (":path", "/"),
+        ]);
+        headers.extend(h(extra));
+        headers
+    }
+
+    #[test]
+    fn valid_messages() {
+        check_headers(&MessageType::Request, &request(&[("te", "trailers")])).unwrap();
+        check_headers(
+            &MessageType::Request,
+            &h(&[(":method", "CONNECT"), (":authority", "something.com:443")]),
+        )
+        .unwrap();
+        check_headers(
+            &MessageType::Response,
+            &h(&[(":status", "200"), ("content-length", "3")]),
+        )
+        .unwrap();
+        check_trailers(&h(&[("something", "3")])).unwrap();
+    }
+
+    #[test]
+    fn pseudo_headers() {
+        // A pseudo-header after a regular field.
+        let mut headers = request(&[("accept", "*/*")]);
+        headers.push((String::from(":protocol"), String::from("websocket")));
+        assert_eq!(
+            check_headers(&MessageType::Request, &headers),
+            Err(Error::HttpMessageError)
+        );
+        // A repeated pseudo-header.
+        assert_eq!(
+            check_headers(
+                &MessageType::Request,
+                &h(&[
+                    (":method", "GET"),
+                    (":method", "GET"),
+                    (":scheme", "https"),
+                    (":path", "/")
+                ])
+            ),
+            Err(Error::HttpMessageError)
+        );
+        // A response pseudo-header in a request and the other way round.
+        assert_eq!(
+            check_headers(&MessageType::Request, &request(&[(":status", "200")])),
+            Err(Error::HttpMessageError)
+        );
+        assert_eq!(
+            check_headers(
+                &MessageType::Response,
+                &h(&[(":status", "200"), (":path", "/")])
+            ),
+            Err(Error::HttpMessageError)
+        );
+        // Missing or empty :path.
+        assert_eq!(
+            check_headers(
+                &MessageType::Request,
+                &h(&[(":method", "GET"), (":scheme", "https")])
+            ),
+            Err(Error::HttpMessageError)
+        );
+        assert_eq!(
+            check_headers(
+                &MessageType::Request,
+                &h(&[(":method", "GET"), (":scheme", "https"), (":path", "")])
+            ),
+            Err(Error::HttpMessageError)
+        );
+        // :protocol without CONNECT and CONNECT with :path.
+        assert_eq!(
+            check_headers(
+                &MessageType::Request,
+                &request(&[(":protocol", "websocket")])
+            ),
+            Err(Error::HttpMessageError)
+        );
+        assert_eq!(
+            check_headers(
+                &MessageType::Request,
+                &h(&[
+                    (":method", "CONNECT"),
+                    (":authority", "a.com:443"),
+                    (":path", "/")
+                ])
+            ),
+            Err(Error::HttpMessageError)
+        );
+        // Pseudo-headers in trailers.
+        assert_eq!(
+            check_trailers(&h(&[(":status", "200")])),
+            Err(Error::HttpMessageError)
+        );
+    }
+
+    #[test]
+    fn field_names_and_values() {
+        for fields in &[
+            [("Accept", "*/*")],
+            [("", "*/*")],
+            [("a b", "*/*")],
+            [("accept", " */*")],
+            [("accept", "*/*\r\n")],
+            [("connection", "close")],
+            [("transfer-encoding", "chunked")],
+            [("te", "gzip")],
+        ] {
+            assert_eq!(
+                check_headers(&MessageType::Request, &request(fields)),
+                Err(Error::HttpMessageError)
+            );
+            assert_eq!(check_trailers(&h(fields)), Err(Error::HttpMessageError));
+        }
+    }
+
+    #[test]
+    fn content_length_values() {
+        assert_eq!(content_length(&request(&[])), Ok(None));
+        assert_eq!(
+            content_length(&request(&[
+                ("content-length", "3"),
+                ("content-length", "3")
+            ])),
+            Ok(Some(3))
+        );
+        for fields in &[
+            [("content-length", "3"), ("content-length", "4")],
+            [("content-length", "-3"), ("accept", "*/*")],
+            [("content-length", "3 "), ("accept", "*/*")],
+            [("content-length", ""), ("accept", "*/*")],
+        ] {
+            assert_eq!(
+                content_length(&request(fields)),
+                Err(Error::HttpMessageError)
+            );
+        }
+    }
+
+    #[test]
+    fn field_section_sizes() {
+        assert_eq!(field_section_size(&[]), 0);
+        assert_eq!(field_section_size(&h(&[(":status", "200")])), 42);
+        assert_eq!(field_section_size(&request(&[])), 4 * 32 + 51);
+    }
+}
@@ -4,6 +4,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use crate::priority::Priority;
 use crate::settings::HSettings;
 use neqo_common::{
     hex_with_len, qtrace, Decoder, Encoder, IncrementalDecoderBuffer, IncrementalDecoderIgnore,
@@ -11,6 +12,7 @@ use neqo_common::{
 };
 use neqo_crypto::random;
 use neqo_transport::Connection;
+use std::cmp::min;
 use std::convert::TryFrom;
 use std::mem;
 
@@ -24,11 +26,27 @@ const H3_FRAME_TYPE_CANCEL_PUSH: HFrameType = 0x3;
 pub(crate) const H3_FRAME_TYPE_SETTINGS: HFrameType = 0x4;
 const H3_FRAME_TYPE_PUSH_PROMISE: HFrameType = 0x5;
 const H3_FRAME_TYPE_GOAWAY: HFrameType = 0x7;
+const H3_FRAME_TYPE_ORIGIN: HFrameType = 0xc;
 const H3_FRAME_TYPE_MAX_PUSH_ID: HFrameType = 0xd;
+const H3_FRAME_TYPE_PRIORITY_UPDATE_REQUEST: HFrameType = 0xf0700;
+const H3_FRAME_TYPE_PRIORITY_UPDATE_PUSH: HFrameType = 0xf0701;
+const H3_FRAME_TYPE_WEBTRANSPORT_STREAM: HFrameType = 0x41;
 
 pub const H3_RESERVED_FRAME_TYPES: &[HFrameType] = &[0x2, 0x6, 0x8, 0x9];
 
 const MAX_READ_SIZE: usize = 4096;
+
+/// The largest frame payload that `HFrameReader` buffers by default. DATA and
+/// WEBTRANSPORT_STREAM payloads are not buffered and are not subject to this limit.
+pub const MAX_BUFFERED_FRAME_LEN: u64 = 1 << 20;
+
+/// A random value of the form `0x1f * N + 0x21`. These frame, stream and setting types are
+/// reserved (RFC 9114, Section 7.2.8) and a peer must ignore them.
+pub(crate) fn grease_value() -> u64 {
+    let r = random(7);
+    Decoder::from(&r).decode_uint(7).unwrap() * 0x1f + 0x21
+}
+
 // data for DATA frame is not read into HFrame::Data.
 #[derive(PartialEq, Debug)]
 pub enum HFrame {
@@ -54,6 +72,23 @@ pub enum HFrame {
     MaxPushId {
         push_id: u64,
     },
+    // The origins that the server is authoritative for (RFC 9412).
+    Origin {
+        origins: Vec<String>,
+    },
+    PriorityUpdateRequest {
+        element_id: u64,
+        priority: Priority,
+    },
+    PriorityUpdatePush {
+        element_id: u64,
+        priority: Priority,
+    },
+    // Not a real frame: a WebTransport bidirectional stream starts with this type followed by
+    // the session ID, the rest of the stream is WebTransport data.
+    WebTransportStream {
+        session_id: u64,
+    },
     Grease,
 }
 
@@ -67,10 +102,11 @@ impl HFrame {
             Self::PushPromise { .. } => H3_FRAME_TYPE_PUSH_PROMISE,
             Self::Goaway { .. } => H3_FRAME_TYPE_GOAWAY,
             Self::MaxPushId { .. } => H3_FRAME_TYPE_MAX_PUSH_ID,
-            Self::Grease => {
-                let r = random(7);
-                Decoder::from(&r).decode_uint(7).unwrap() * 0x1f + 0x21
-            }
+            Self::Origin { .. } => H3_FRAME_TYPE_ORIGIN,
+            Self::PriorityUpdateRequest { .. } => H3_FRAME_TYPE_PRIORITY_UPDATE_REQUEST,
+            Self::PriorityUpdatePush { .. } => H3_FRAME_TYPE_PRIORITY_UPDATE_PUSH,
+            Self::WebTransportStream { .. } => H3_FRAME_TYPE_WEBTRANSPORT_STREAM,
+            Self::Grease => grease_value(),
         }
     }
 
@@ -111,6 +147,31 @@ impl HFrame {
                     enc_inner.encode_varint(*push_id);
                 });
             }
+            Self::Origin { origins } => {
+                enc.encode_vvec_with(|enc_inner| {
+                    for origin in origins {
+                        enc_inner.encode_uint(2, origin.len() as u64);
+                        enc_inner.encode(origin.as_bytes());
+                    }
+                });
+            }
+            Self::PriorityUpdateRequest {
+                element_id,
+                priority,
+            }
+            | Self::PriorityUpdatePush {
+                element_id,
+                priority,
+            } => {
+                enc.encode_vvec_with(|enc_inner| {
+                    enc_inner.encode_varint(*element_id);
+                    enc_inner.encode(priority.to_string().as_bytes());
+                });
+            }
+            Self::WebTransportStream { session_id } => {
+                // There is no length, only the session ID.
+                enc.encode_varint(*session_id);
+            }
             Self::Grease => {
                 // Encode some number of random bytes.
                 let r = random(8);
@@ -134,6 +195,8 @@ pub struct HFrameReader {
     hframe_type: u64,
     hframe_len: u64,
     payload: Vec<u8>,
+    max_buffered_len: u64,
+    max_field_section_size: Option<u64>,
 }
 
 impl Default for HFrameReader {
@@ -145,6 +208,12 @@ impl Default for HFrameReader {
 impl HFrameReader {
     #[must_use]
     pub fn new() -> Self {
+        Self::with_max_buffered_len(MAX_BUFFERED_FRAME_LEN)
+    }
+
+    /// A reader that refuses to buffer a frame payload longer than `max_buffered_len`.
+    #[must_use]
+    pub fn with_max_buffered_len(max_buffered_len: u64) -> Self {
         Self {
             state: HFrameReaderState::GetType {
                 decoder: IncrementalDecoderUint::default(),
@@ -152,9 +221,21 @@ impl HFrameReader {
             hframe_type: 0,
             hframe_len: 0,
             payload: Vec::new(),
+            max_buffered_len,
+            max_field_section_size: None,
         }
     }
 
+    /// Also refuse HEADERS and PUSH_PROMISE frames whose header block is longer than
+    /// `max_field_section_size`, the limit that has been advertised in SETTINGS. The size
+    /// counted for the limit adds 32 bytes for each field, so an encoded field section is not
+    /// longer than that unless it uses Huffman coding where it does not help.
+    #[must_use]
+    pub fn with_max_field_section_size(mut self, max_field_section_size: Option<u64>) -> Self {
+        self.max_field_section_size = max_field_section_size;
+        self
+    }
+
     fn reset(&mut self) {
         self.state = HFrameReaderState::GetType {
             decoder: IncrementalDecoderUint::default(),
@@ -182,6 +263,8 @@ impl HFrameReader {
     /// returns true if quic stream was closed.
     /// # Errors
     /// May return `HttpFrame` if a frame cannot be decoded.
+    /// `HttpExcessiveLoad` or `HttpExcessiveLoadStream` if a frame is longer than the reader
+    /// is willing to buffer,
     /// and `TransportStreamDoesNotExist` if `stream_recv` fails.
     pub fn receive(
         &mut self,
@@ -189,23 +272,44 @@ impl HFrameReader {
         stream_id: u64,
     ) -> Res<(Option<HFrame>, bool)> {
         loop {
-            let to_read = std::cmp::min(self.min_remaining(), MAX_READ_SIZE);
-            let mut buf = vec![0; to_read];
-            let (output, read, fin) = match conn
-                .stream_recv(stream_id, &mut buf)
-                .map_err(|e| Error::map_stream_recv_errors(&e))?
-            {
-                (0, f) => (None, false, f),
-                (amount, f) => {
-                    qtrace!(
-                        [conn],
-                        "HFrameReader::receive: reading {} byte, fin={}",
-                        amount,
-                        f
-                    );
-                    (self.consume(Decoder::from(&buf[..amount]))?, true, f)
-                }
-            };
+            let (output, read, fin) =
+                if let HFrameReaderState::GetData { decoder } = &mut self.state {
+                    // The payload is read straight into the frame buffer, one chunk at a time.
+                    let before = decoder.min_remaining();
+                    let mut fin = false;
+                    let data = decoder
+                        .read_with(MAX_READ_SIZE, |buf| {
+                            conn.stream_recv(stream_id, buf).map(|(amount, f)| {
+                                fin = f;
+                                amount
+                            })
+                        })
+                        .map_err(|e| Error::map_stream_recv_errors(&e))?;
+                    let read = decoder.min_remaining() < before;
+                    let output = match data {
+                        Some(data) => Some(self.payload_received(data)?),
+                        None => None,
+                    };
+                    (output, read, fin)
+                } else {
+                    let to_read = std::cmp::min(self.min_remaining(), MAX_READ_SIZE);
+                    let mut buf = vec![0; to_read];
+                    match conn
+                        .stream_recv(stream_id, &mut buf)
+                        .map_err(|e| Error::map_stream_recv_errors(&e))?
+                    {
+                        (0, f) => (None, false, f),
+                        (amount, f) => {
+                            qtrace!(
+                                [conn],
+                                "HFrameReader::receive: reading {} byte, fin={}",
+                                amount,
+                                f
+                            );
+                            (self.consume(Decoder::from(&buf[..amount]))?, true, f)
+                        }
+                    }
+                };
 
             if output.is_some() {
                 break Ok((output, fin));
@@ -227,7 +331,8 @@ impl HFrameReader {
     }
 
     /// # Errors
-    /// May return `HttpFrame` if a frame cannot be decoded.
+    /// May return `HttpFrame` if a frame cannot be decoded and `HttpExcessiveLoad` or
+    /// `HttpExcessiveLoadStream` if a frame is too long to buffer.
     fn consume(&mut self, mut input: Decoder) -> Res<Option<HFrame>> {
         match &mut self.state {
             HFrameReaderState::GetType { decoder } => {
@@ -253,7 +358,9 @@ impl HFrameReader {
                     self.hframe_len = len;
                     self.state = match self.hframe_type {
                         // DATA payload are left on the quic stream and picked up separately
-                        H3_FRAME_TYPE_DATA => {
+                        // For WEBTRANSPORT_STREAM the session ID is read in place of the
+                        // length, the rest of the stream is WebTransport data.
+                        H3_FRAME_TYPE_DATA | H3_FRAME_TYPE_WEBTRANSPORT_STREAM => {
                             return Ok(Some(self.get_frame()?));
                         }
 
@@ -262,10 +369,15 @@ impl HFrameReader {
                         | H3_FRAME_TYPE_SETTINGS
                         | H3_FRAME_TYPE_GOAWAY
                         | H3_FRAME_TYPE_MAX_PUSH_ID
+                        | H3_FRAME_TYPE_ORIGIN
+                        | H3_FRAME_TYPE_PRIORITY_UPDATE_REQUEST
+                        | H3_FRAME_TYPE_PRIORITY_UPDATE_PUSH
                         | H3_FRAME_TYPE_PUSH_PROMISE
                         | H3_FRAME_TYPE_HEADERS => {
                             if len == 0 {
                                 return Ok(Some(self.get_frame()?));
+                            } else if len > self.max_len() {
+                                return Err(self.too_long());
                             } else {
                                 HFrameReaderState::GetData {
                                     decoder: IncrementalDecoderBuffer::new(
@@ -292,13 +404,7 @@ impl HFrameReader {
             }
             HFrameReaderState::GetData { decoder } => {
                 if let Some(data) = decoder.consume(&mut input) {
-                    qtrace!(
-                        "received frame {}: {}",
-                        self.hframe_type,
-                        hex_with_len(&data[..])
-                    );
-                    self.payload = data;
-                    return Ok(Some(self.get_frame()?));
+                    return Ok(Some(self.payload_received(data)?));
                 }
             }
             HFrameReaderState::UnknownFrameDischargeData { decoder } => {
@@ -310,17 +416,56 @@ impl HFrameReader {
         Ok(None)
     }
 
+    /// The longest payload that is buffered for a frame of the current type.
+    fn max_len(&self) -> u64 {
+        let max_header_block = self.max_field_section_size.unwrap_or(u64::MAX);
+        let max = match self.hframe_type {
+            H3_FRAME_TYPE_HEADERS => max_header_block,
+            // The header block follows the push ID, a varint of up to 8 bytes.
+            H3_FRAME_TYPE_PUSH_PROMISE => max_header_block.saturating_add(8),
+            _ => u64::MAX,
+        };
+        min(max, self.max_buffered_len)
+    }
+
+    /// HEADERS and PUSH_PROMISE arrive on request streams, so only the stream is reset;
+    /// the other buffered frames belong to the control stream.
+    fn too_long(&self) -> Error {
+        qtrace!(
+            "HFrameReader: frame type {} length {} is too long",
+            self.hframe_type,
+            self.hframe_len
+        );
+        match self.hframe_type {
+            H3_FRAME_TYPE_HEADERS | H3_FRAME_TYPE_PUSH_PROMISE => Error::HttpExcessiveLoadStream,
+            _ => Error::HttpExcessiveLoad,
+        }
+    }
+
+    /// # Errors
+    /// May return `HttpFrame` if a frame cannot be decoded.
+    fn payload_received(&mut self, data: Vec<u8>) -> Res<HFrame> {
+        qtrace!(
+            "received frame {}: {}",
+            self.hframe_type,
+            hex_with_len(&data[..])
+        );
+        self.payload = data;
+        self.get_frame()
+    }
+
     /// # Errors
     /// May return `HttpFrame` if a frame cannot be decoded.
     fn get_frame(&mut self) -> Res<HFrame> {
-        let payload = mem::replace(&mut self.payload, Vec::new());
+        let mut payload = mem::replace(&mut self.payload, Vec::new());
         let mut dec = Decoder::from(&payload[..]);
         let f = match self.hframe_type {
             H3_FRAME_TYPE_DATA => HFrame::Data {
                 len: self.hframe_len,
             },
+            // The header block is the payload, or the end of it, which is kept rather than copied.
             H3_FRAME_TYPE_HEADERS => HFrame::Headers {
-                header_block: dec.decode_remainder().to_vec(),
+                header_block: payload,
             },
             H3_FRAME_TYPE_CANCEL_PUSH => HFrame::CancelPush {
                 push_id: dec.decode_varint().ok_or(Error::HttpFrame)?,
@@ -336,16 +481,43 @@ impl HFrameReader {
                 })?;
                 HFrame::Settings { settings }
             }
-            H3_FRAME_TYPE_PUSH_PROMISE => HFrame::PushPromise {
-                push_id: dec.decode_varint().ok_or(Error::HttpFrame)?,
-                header_block: dec.decode_remainder().to_vec(),
-            },
+            H3_FRAME_TYPE_PUSH_PROMISE => {
+                let push_id = dec.decode_varint().ok_or(Error::HttpFrame)?;
+                let start = payload.len() - dec.remaining();
+                payload.drain(..start);
+                HFrame::PushPromise {
+                    push_id,
+                    header_block: payload,
+                }
+            }
             H3_FRAME_TYPE_GOAWAY => HFrame::Goaway {
                 stream_id: dec.decode_varint().ok_or(Error::HttpFrame)?,
             },
             H3_FRAME_TYPE_MAX_PUSH_ID => HFrame::MaxPushId {
                 push_id: dec.decode_varint().ok_or(Error::HttpFrame)?,
             },
+            H3_FRAME_TYPE_ORIGIN => {
+                let mut origins = Vec::new();
+                while dec.remaining() > 0 {
+                    let len = dec.decode_uint(2).ok_or(Error::HttpFrame)?;
+                    let origin = dec
+                        .decode(usize::try_from(len).or(Err(Error::HttpFrame))?)
+                        .ok_or(Error::HttpFrame)?;
+                    origins.push(String::from_utf8(origin.to_vec()).map_err(|_| Error::HttpFrame)?);
+                }
+                HFrame::Origin { origins }
+            }
+            H3_FRAME_TYPE_PRIORITY_UPDATE_REQUEST => HFrame::PriorityUpdateRequest {
+                element_id: dec.decode_varint().ok_or(Error::HttpFrame)?,
+                priority: Priority::from_bytes(dec.decode_remainder()),
+            },
+            H3_FRAME_TYPE_PRIORITY_UPDATE_PUSH => HFrame::PriorityUpdatePush {
+                element_id: dec.decode_varint().ok_or(Error::HttpFrame)?,
+                priority: Priority::from_bytes(dec.decode_remainder()),
+            },
+            H3_FRAME_TYPE_WEBTRANSPORT_STREAM => HFrame::WebTransportStream {
+                session_id: self.hframe_len,
+            },
             _ => panic!("We should not be calling this function with unknown frame type!"),
         };
         self.reset();
@@ -355,8 +527,14 @@ impl HFrameReader {
 
 #[cfg(test)]
 mod tests {
-    use super::{Decoder, Encoder, Error, HFrame, HFrameReader, HSettings};
-    use crate::settings::{HSetting, HSettingType};
+    use super::{
+        grease_value, Decoder, Encoder, Error, HFrame, HFrameReader, HFrameType, HSettings,
+        H3_FRAME_TYPE_HEADERS, H3_FRAME_TYPE_PUSH_PROMISE, H3_FRAME_TYPE_SETTINGS,
+        MAX_BUFFERED_FRAME_LEN,
+    };
+    use crate::priority::Priority;
+    use crate::settings::{HSetting, HSettingType, MAX_EXTENSION_SETTINGS};
+    use crate::Res;
     use neqo_crypto::AuthenticationStatus;
     use neqo_transport::{Connection, StreamType};
     use test_fixture::{connect, default_client, default_server, fixture_init, now};
@@ -431,6 +609,14 @@ mod tests {
         enc_dec(&f, "04020604", 0);
     }
 
+    #[test]
+    fn test_settings_frame_enable_connect_protocol() {
+        let f = HFrame::Settings {
+            settings: HSettings::new(&[HSetting::new(HSettingType::EnableConnectProtocol, 1)]),
+        };
+        enc_dec(&f, "04020801", 0);
+    }
+
     #[test]
     fn test_push_promise_frame4() {
         let f = HFrame::PushPromise {
@@ -452,6 +638,46 @@ mod tests {
         enc_dec(&f, "0d0105", 0);
     }
 
+    #[test]
+    fn test_origin_frame() {
+        let f = HFrame::Origin {
+            origins: vec![
+                String::from("https://a.com"),
+                String::from("https://b.org:8443"),
+            ],
+        };
+        enc_dec(
+            &f,
+            "0c23000d68747470733a2f2f612e636f6d001268747470733a2f2f622e6f72673a38343433",
+            0,
+        );
+        enc_dec(&HFrame::Origin { origins: vec![] }, "0c00", 0);
+    }
+
+    #[test]
+    fn test_priority_update_request_frame4() {
+        let f = HFrame::PriorityUpdateRequest {
+            element_id: 4,
+            priority: Priority::new(1, true),
+        };
+        enc_dec(&f, "800f07000704753d312c2069", 0);
+    }
+
+    #[test]
+    fn test_priority_update_push_frame4() {
+        let f = HFrame::PriorityUpdatePush {
+            element_id: 5,
+            priority: Priority::default(),
+        };
+        enc_dec(&f, "800f07010105", 0);
+    }
+
+    #[test]
+    fn test_webtransport_stream_frame() {
+        let f = HFrame::WebTransportStream { session_id: 4 };
+        enc_dec(&f, "404104", 0);
+    }
+
     #[test]
     fn grease() {
         fn make_grease() -> u64 {
@@ -505,18 +731,19 @@ mod tests {
     fn test_frame_reading_with_stream_settings1() {
         let mut fr = HFrameReaderTest::new();
 
-        // Send and read settings frame 040406040804
+        // Send and read settings frame 040406040904
         assert!(fr.process(&[0x4]).is_none());
         assert!(fr.process(&[0x4]).is_none());
         assert!(fr.process(&[0x6]).is_none());
         assert!(fr.process(&[0x4]).is_none());
-        assert!(fr.process(&[0x8]).is_none());
+        assert!(fr.process(&[0x9]).is_none());
         let frame = fr.process(&[0x4]);
 
         assert!(frame.is_some());
         if let HFrame::Settings { settings } = frame.unwrap() {
-            assert!(settings.len() == 1);
+            assert!(settings.len() == 2);
             assert!(settings[0] == HSetting::new(HSettingType::MaxHeaderListSize, 4));
+            assert!(settings[1] == HSetting::new(HSettingType::Extension(9), 4));
         } else {
             panic!("wrong frame type");
         }
@@ -527,21 +754,40 @@ mod tests {
     fn test_frame_reading_with_stream_settings2() {
         let mut fr = HFrameReaderTest::new();
 
-        // Read settings frame 400406064004084100
-        for i in &[0x40, 0x04, 0x06, 0x06, 0x40, 0x04, 0x08, 0x41] {
+        // Read settings frame 400406064004094100
+        for i in &[0x40, 0x04, 0x06, 0x06, 0x40, 0x04, 0x09, 0x41] {
             assert!(fr.process(&[*i]).is_none());
         }
         let frame = fr.process(&[0x0]);
 
         assert!(frame.is_some());
         if let HFrame::Settings { settings } = frame.unwrap() {
-            assert!(settings.len() == 1);
+            assert!(settings.len() == 2);
             assert!(settings[0] == HSetting::new(HSettingType::MaxHeaderListSize, 4));
+            assert!(settings[1] == HSetting::new(HSettingType::Extension(9), 0x100));
         } else {
             panic!("wrong frame type");
         }
     }
 
+    // Only a limited number of unknown settings are kept.
+    #[test]
+    fn test_settings_extensions_limit() {
+        let mut enc = Encoder::default();
+        enc.encode_varint(0x6_u64).encode_varint(4_u64);
+        for id in 0..(MAX_EXTENSION_SETTINGS as u64) * 2 {
+            enc.encode_varint(0x1000 + id).encode_varint(id);
+        }
+        let mut settings = HSettings::new(&[]);
+        settings
+            .decode_frame_contents(&mut Decoder::from(&enc[..]))
+            .unwrap();
+        assert_eq!(settings.len(), MAX_EXTENSION_SETTINGS + 1);
+        assert_eq!(settings.get(HSettingType::MaxHeaderListSize), 4);
+        assert_eq!(settings.extensions().len(), MAX_EXTENSION_SETTINGS);
+        assert_eq!(settings.extensions()[0], (0x1000, 0));
+    }
+
     // Test receiving byte by byte for a PUSH_PROMISE frame.
     #[test]
     fn test_frame_reading_with_stream_push_promise() {
@@ -607,6 +853,90 @@ mod tests {
         }
     }
 
+    // Test a large frame of a reserved type that is received in pieces between other frames.
+    #[test]
+    fn test_grease_frame_split() {
+        const GREASE_FRAME_LEN: usize = 5000;
+
+        let mut fr = HFrameReaderTest::new();
+        assert!(matches!(
+            fr.process(&[0x03, 0x01, 0x05]),
+            Some(HFrame::CancelPush { push_id: 5 })
+        ));
+
+        let mut enc = Encoder::default();
+        enc.encode_varint(grease_value());
+        enc.encode_varint(GREASE_FRAME_LEN as u64);
+        let mut buf: Vec<_> = enc.into();
+        buf.resize(GREASE_FRAME_LEN + buf.len(), 0xaa);
+        for chunk in buf.chunks(500) {
+            assert!(fr.process(chunk).is_none());
+        }
+
+        assert!(matches!(
+            fr.process(&[0x07, 0x01, 0x08]),
+            Some(HFrame::Goaway { stream_id: 8 })
+        ));
+    }
+
+    // A frame that is longer than the reader buffers is rejected once its length is known.
+    #[test]
+    fn test_frame_too_long() {
+        fn too_long(frame_type: HFrameType, max: u64) -> Res<(Option<HFrame>, bool)> {
+            let mut fr = HFrameReaderTest::new();
+            fr.fr = HFrameReader::with_max_buffered_len(max);
+            let mut enc = Encoder::default();
+            enc.encode_varint(frame_type);
+            enc.encode_varint(max + 1);
+            fr.conn_s.stream_send(fr.stream_id, &enc).unwrap();
+            let out = fr.conn_s.process(None, now());
+            let _ = fr.conn_c.process(out.dgram(), now());
+            fr.fr.receive(&mut fr.conn_c, fr.stream_id)
+        }
+
+        assert_eq!(
+            too_long(H3_FRAME_TYPE_SETTINGS, 100),
+            Err(Error::HttpExcessiveLoad)
+        );
+        assert_eq!(
+            too_long(H3_FRAME_TYPE_HEADERS, 100),
+            Err(Error::HttpExcessiveLoadStream)
+        );
+        assert_eq!(
+            too_long(H3_FRAME_TYPE_PUSH_PROMISE, MAX_BUFFERED_FRAME_LEN),
+            Err(Error::HttpExcessiveLoadStream)
+        );
+    }
+
+    // HEADERS and PUSH_PROMISE frames are also limited by the advertised field section size.
+    #[test]
+    fn test_header_block_too_long() {
+        fn receive(frame_type: HFrameType, len: u64) -> Res<(Option<HFrame>, bool)> {
+            let mut fr = HFrameReaderTest::new();
+            fr.fr = HFrameReader::new().with_max_field_section_size(Some(100));
+            let mut enc = Encoder::default();
+            enc.encode_varint(frame_type);
+            enc.encode_varint(len);
+            fr.conn_s.stream_send(fr.stream_id, &enc).unwrap();
+            let out = fr.conn_s.process(None, now());
+            let _ = fr.conn_c.process(out.dgram(), now());
+            fr.fr.receive(&mut fr.conn_c, fr.stream_id)
+        }
+
+        assert_eq!(receive(H3_FRAME_TYPE_HEADERS, 100), Ok((None, false)));
+        assert_eq!(
+            receive(H3_FRAME_TYPE_HEADERS, 101),
+            Err(Error::HttpExcessiveLoadStream)
+        );
+        assert_eq!(receive(H3_FRAME_TYPE_PUSH_PROMISE, 108), Ok((None, false)));
+        assert_eq!(
+            receive(H3_FRAME_TYPE_PUSH_PROMISE, 109),
+            Err(Error::HttpExcessiveLoadStream)
+        );
+        // Other frames are not affected.
+        assert_eq!(receive(H3_FRAME_TYPE_SETTINGS, 101), Ok((None, false)));
+    }
+
     enum FrameReadingTestSend {
         OnlyData,
         DataWithFin,
@@ -8,23 +8,32 @@
 #![warn(clippy::pedantic)]
 #![allow(clippy::pub_enum_variant_names)]
 
+mod capsule;
 mod client_events;
+mod connect_udp;
 mod connection;
 pub mod connection_client;
 mod connection_server;
 mod control_stream_local;
 mod control_stream_remote;
+mod headers_checks;
 pub mod hframe;
+mod origin;
+mod priority;
 mod push_controller;
 mod push_stream;
 mod qlog;
 mod recv_message;
+mod request;
+mod request_handler;
+mod request_metrics;
 mod send_message;
 pub mod server;
 mod server_connection_events;
 mod server_events;
 mod settings;
 mod stream_type_reader;
+mod webtransport;
 
 use neqo_qpack::decoder::QPackDecoder;
 use neqo_qpack::Error as QpackError;
@@ -33,13 +42,21 @@ use neqo_transport::{AppError, Connection, Error as TransportError};
 use std::fmt::Debug;
 
 pub use client_events::Http3ClientEvent;
-pub use connection::Http3State;
+pub use connect_udp::{ConnectUdpReader, ConnectUdpTarget};
+pub use connection::{ErrorContext, Http3State};
 pub use connection_client::Http3Client;
 pub use connection_client::Http3Parameters;
 pub use hframe::HFrameReader;
 pub use neqo_qpack::Header;
+pub use priority::Priority;
+pub use request::Request;
+pub use request_handler::RequestHandler;
+pub use request_metrics::RequestMetrics;
 pub use server::Http3Server;
-pub use server_events::Http3ServerEvent;
+pub use server_events::{
+    ClientRequestStream, Http3ServerEvent, WebTransportServerEvent, WebTransportSession,
+};
+pub use webtransport::{WebTransportEvent, WEBTRANSPORT_SESSION_GONE};
 
 type Res<T> = Result<T, Error>;
 
@@ -54,14 +71,18 @@ pub enum Error {
     HttpFrameUnexpected,
     HttpFrame,
     HttpExcessiveLoad,
+    HttpExcessiveLoadStream, // the same as the above, but it only resets a stream.
     HttpId,
     HttpSettings,
     HttpMissingSettings,
     HttpRequestRejected,
     HttpRequestCancelled,
     HttpRequestIncomplete,
+    HttpMessageError,
     HttpConnect,
     HttpVersionFallback,
+    HttpDatagram,
+    HttpDatagramStream, // the same as the above, but it only resets a stream.
     QpackError(neqo_qpack::Error),
 
     // Internal errors from here.
@@ -82,6 +103,7 @@ pub enum Error {
     TransportStreamDoesNotExist,
     InvalidInput,
     FatalError,
+    FieldSectionTooLarge,
 }
 
 impl Error {
@@ -95,18 +117,20 @@ impl Error {
             Self::HttpClosedCriticalStream => 0x104,
             Self::HttpFrameUnexpected => 0x105,
             Self::HttpFrame => 0x106,
-            Self::HttpExcessiveLoad => 0x107,
+            Self::HttpExcessiveLoad | Self::HttpExcessiveLoadStream => 0x107,
             Self::HttpId => 0x108,
             Self::HttpSettings => 0x109,
             Self::HttpMissingSettings => 0x10a,
             Self::HttpRequestRejected => 0x10b,
             Self::HttpRequestCancelled => 0x10c,
             Self::HttpRequestIncomplete => 0x10d,
+            Self::HttpMessageError => 0x10e,
             Self::HttpConnect => 0x10f,
             Self::HttpVersionFallback => 0x110,
+            Self::HttpDatagram | Self::HttpDatagramStream => 0x33,
             Self::QpackError(e) => e.code(),
             // These are all internal errors.
-            _ => 3,
+            _ => 0x102,
         }
     }
 
@@ -123,6 +147,7 @@ impl Error {
             | Self::HttpId
             | Self::HttpSettings
             | Self::HttpMissingSettings
+            | Self::HttpDatagram
             | Self::QpackError(QpackError::EncoderStream)
             | Self::QpackError(QpackError::DecoderStream) => true,
             _ => false,
@@ -131,7 +156,13 @@ impl Error {
 
     #[must_use]
     pub fn stream_reset_error(&self) -> bool {
-        matches!(self, Self::HttpGeneralProtocolStream)
+        matches!(
+            self,
+            Self::HttpGeneralProtocolStream
+                | Self::HttpExcessiveLoadStream
+                | Self::HttpMessageError
+                | Self::HttpDatagramStream
+        )
     }
 
     #[must_use]
@@ -224,8 +255,10 @@ impl From<AppError> for Error {
             0x10b => Self::HttpRequestRejected,
             0x10c => Self::HttpRequestCancelled,
             0x10d => Self::HttpRequestIncomplete,
+            0x10e => Self::HttpMessageError,
             0x10f => Self::HttpConnect,
             0x110 => Self::HttpVersionFallback,
+            0x33 => Self::HttpDatagram,
             0x200 => Self::QpackError(QpackError::DecompressionFailed),
             0x201 => Self::QpackError(QpackError::EncoderStream),
             0x202 => Self::QpackError(QpackError::DecoderStream),
@@ -267,11 +300,16 @@ pub trait RecvStream: Debug {
         decoder: &mut QPackDecoder,
         buf: &mut [u8],
     ) -> Res<(usize, bool)>;
+    /// The session ID if the stream has turned out to be a WebTransport stream.
+    fn webtransport_session(&self) -> Option<u64> {
+        None
+    }
 }
 
 pub(crate) trait RecvMessageEvents: Debug {
     fn header_ready(&self, stream_id: u64, headers: Vec<Header>, interim: bool, fin: bool);
     fn data_readable(&self, stream_id: u64);
+    fn trailers_ready(&self, stream_id: u64, trailers: Vec<Header>);
     fn reset(&self, stream_id: u64, error: AppError, local: bool);
 }
 
@@ -0,0 +1,74 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Origins for the ORIGIN frame (RFC 9412). A server lists the origins it is authoritative for;
+// a client may send requests for those origins on the connection, as long as the server
+// certificate is valid for them.
+
+use crate::{Error, Res};
+
+/// Bring an origin of the form `scheme://host[:port]` into the form in which origins are
+/// compared: lowercase and without the default port of the scheme.
+/// # Errors
+/// `InvalidInput` if `origin` is not ASCII, has no scheme or host, has anything after the
+/// host and port, or is too long for an ORIGIN frame entry.
+pub(crate) fn normalize(origin: &str) -> Res<String> {
+    if !origin.is_ascii() || origin.len() > usize::from(u16::MAX) {
+        return Err(Error::InvalidInput);
+    }
+    let origin = origin.to_ascii_lowercase();
+    let sep = origin.find("://").ok_or(Error::InvalidInput)?;
+    let (scheme, authority) = (&origin[..sep], &origin[sep + 3..]);
+    if scheme.is_empty()
+        || authority.is_empty()
+        || authority.contains(|c| c == '/' || c == '?' || c == '#' || c == '@')
+    {
+        return Err(Error::InvalidInput);
+    }
+    let default_port = match scheme {
+        "https" => ":443",
+        "http" => ":80",
+        _ => return Ok(origin),
+    };
+    if authority.ends_with(default_port) {
+        Ok(String::from(&origin[..origin.len() - default_port.len()]))
+    } else {
+        Ok(origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize;
+    use crate::Error;
+
+    #[test]
+    fn normalize_origins() {
+        assert_eq!(normalize("https://a.com").unwrap(), "https://a.com");
+        assert_eq!(normalize("HTTPS://A.com:443").unwrap(), "https://a.com");
+        assert_eq!(
+            normalize("https://a.com:8443").unwrap(),
+            "https://a.com:8443"
+        );
+        assert_eq!(normalize("http://a.com:80").unwrap(), "http://a.com");
+        assert_eq!(normalize("https://[::1]:443").unwrap(), "https://[::1]");
+    }
+
+    #[test]
+    fn invalid_origins() {
+        for origin in &[
+            "a.com",
+            "https://",
+            "://a.com",
+            "https://a.com/",
+            "https://a.com?x",
+            "https://user@a.com",
+            "https://ä.com",
+        ] {
+            assert_eq!(normalize(origin), Err(Error::InvalidInput));
+        }
+    }
+}
@@ -0,0 +1,181 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::{Error, Header, Res};
+use neqo_transport::{Connection, Error as TransportError, StreamPriority};
+use std::fmt;
+
+const PRIORITY_HEADER: &str = "priority";
+const MAX_URGENCY: u8 = 7;
+
+/// The priority of a request or push, as defined by the Extensible Priority Scheme for HTTP
+/// (RFC 9218).  Lower urgency values are more important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Priority {
+    urgency: u8,
+    incremental: bool,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self {
+            urgency: 3,
+            incremental: false,
+        }
+    }
+}
+
+impl Priority {
+    /// # Panics
+    /// If `urgency` is larger than 7.
+    #[must_use]
+    pub fn new(urgency: u8, incremental: bool) -> Self {
+        assert!(urgency <= MAX_URGENCY);
+        Self {
+            urgency,
+            incremental,
+        }
+    }
+
+    #[must_use]
+    pub fn urgency(self) -> u8 {
+        self.urgency
+    }
+
+    #[must_use]
+    pub fn incremental(self) -> bool {
+        self.incremental
+    }
+
+    /// Parse a priority field value, e.g. `u=1, i`.  Parameters that are unknown or that
+    /// have an invalid value are ignored, as required by RFC 9218.
+    #[must_use]
+    pub fn from_bytes(value: &[u8]) -> Self {
+        let mut priority = Self::default();
+        let value = match std::str::from_utf8(value) {
+            Ok(v) => v,
+            Err(_) => return priority,
+        };
+        for member in value.split(',') {
+            let member = member.split(';').next().unwrap().trim();
+            let (key, v) = match member.find('=') {
+                Some(i) => (&member[..i], Some(&member[i + 1..])),
+                None => (member, None),
+            };
+            match (key, v) {
+                ("u", Some(v)) => {
+                    if let Ok(u) = v.parse::<u8>() {
+                        if u <= MAX_URGENCY {
+                            priority.urgency = u;
+                        }
+                    }
+                }
+                ("i", None) | ("i", Some("?1")) => priority.incremental = true,
+                ("i", Some("?0")) => priority.incremental = false,
+                _ => {}
+            }
+        }
+        priority
+    }
+
+    /// Get the priority from the `priority` header field of a request, if present.
+    #[must_use]
+    pub fn from_headers(headers: &[Header]) -> Option<Self> {
+        headers
+            .iter()
+            .find(|(name, _)| name == PRIORITY_HEADER)
+            .map(|(_, value)| Self::from_bytes(value.as_bytes()))
+    }
+
+    /// A `priority` header field carrying this priority.  Default parameters are omitted.
+    #[must_use]
+    pub fn header(self) -> Option<Header> {
+        if self == Self::default() {
+            None
+        } else {
+            Some((String::from(PRIORITY_HEADER), self.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.urgency, self.incremental) {
+            (3, false) => Ok(()),
+            (3, true) => write!(f, "i"),
+            (u, false) => write!(f, "u={}", u),
+            (u, true) => write!(f, "u={}, i", u),
+        }
+    }
+}
+
+impl From<Priority> for StreamPriority {
+    fn from(p: Priority) -> Self {
+        Self::new(p.urgency, p.incremental)
+    }
+}
+
+/// Set the priority of the send side of a stream. It may have been closed already, in which
+/// case there is nothing left to prioritize.
+pub(crate) fn set_stream_priority(
+    conn: &mut Connection,
+    stream_id: u64,
+    priority: Priority,
+) -> Res<()> {
+    match conn.stream_priority(stream_id, priority.into()) {
+        Ok(()) | Err(TransportError::InvalidStreamId) => Ok(()),
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Priority;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Priority::from_bytes(b""), Priority::default());
+        assert_eq!(Priority::from_bytes(b"u=5"), Priority::new(5, false));
+        assert_eq!(Priority::from_bytes(b"i"), Priority::new(3, true));
+        assert_eq!(Priority::from_bytes(b"u=0, i"), Priority::new(0, true));
+        assert_eq!(Priority::from_bytes(b"i=?1,u=1"), Priority::new(1, true));
+        assert_eq!(Priority::from_bytes(b"u=1, i=?0"), Priority::new(1, false));
+    }
+
+    #[test]
+    fn parse_ignores_invalid() {
+        assert_eq!(Priority::from_bytes(b"u=8"), Priority::default());
+        assert_eq!(Priority::from_bytes(b"u=x, i=3"), Priority::default());
+        assert_eq!(
+            Priority::from_bytes(b"foo, u=2;bar"),
+            Priority::new(2, false)
+        );
+        assert_eq!(Priority::from_bytes(&[0xff, 0xfe]), Priority::default());
+    }
+
+    #[test]
+    fn header() {
+        assert_eq!(Priority::default().header(), None);
+        assert_eq!(
+            Priority::new(1, true).header(),
+            Some((String::from("priority"), String::from("u=1, i")))
+        );
+        assert_eq!(
+            Priority::new(3, true).header(),
+            Some((String::from("priority"), String::from("i")))
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for u in 0..=7 {
+            for &i in &[false, true] {
+                let p = Priority::new(u, i);
+                assert_eq!(Priority::from_bytes(p.to_string().as_bytes()), p);
+            }
+        }
+    }
+}
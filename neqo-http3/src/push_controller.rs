@@ -23,9 +23,9 @@ use std::slice::SliceIndex;
 ///   `Init`: there is no push stream nor a push promise. This state is only used to keep track of opened and closed
 ///           push streams.
 ///   `PushPromise`: the push has only ever receive a pushpromise frame
-///   `OnlyPushStream`: there is only a push stream. All push stream events, i.e. `PushHeaderReady` and
-///                     `PushDataReadable` will be delayed until a push promise is received (they are kept in
-///                     `events`).
+///   `OnlyPushStream`: there is only a push stream. All push stream events, i.e. `PushHeaderReady`,
+///                     `PushDataReadable` and `PushTrailersReady` will be delayed until a push promise
+///                     is received (they are kept in `events`).
 ///   `Active`: there is a push steam and at least one push promise frame.
 ///   `Close`: the push stream has been closed or reset already.
 #[derive(Debug, PartialEq, Clone)]
@@ -482,5 +482,15 @@ impl RecvMessageEvents for RecvPushEvents {
         );
     }
 
+    fn trailers_ready(&self, _stream_id: u64, trailers: Vec<Header>) {
+        self.push_handler.borrow_mut().new_stream_event(
+            self.push_id,
+            Http3ClientEvent::PushTrailersReady {
+                push_id: self.push_id,
+                trailers,
+            },
+        );
+    }
+
     fn reset(&self, _stream_id: u64, _error: AppError, _local: bool) {}
 }
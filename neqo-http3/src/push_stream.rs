@@ -61,6 +61,7 @@ pub(crate) struct PushStream {
     stream_id: u64,
     push_handler: Rc<RefCell<PushController>>,
     events: Http3ClientEvents,
+    max_field_section_size: Option<u64>,
 }
 
 impl PushStream {
@@ -68,12 +69,14 @@ impl PushStream {
         stream_id: u64,
         push_handler: Rc<RefCell<PushController>>,
         events: Http3ClientEvents,
+        max_field_section_size: Option<u64>,
     ) -> Self {
         Self {
             state: PushStreamState::ReadPushId(NewStreamTypeReader::new()),
             stream_id,
             push_handler,
             events,
+            max_field_section_size,
         }
     }
 }
@@ -108,6 +111,7 @@ impl RecvStream for PushStream {
                                     self.stream_id,
                                     Box::new(RecvPushEvents::new(p, self.push_handler.clone())),
                                     None,
+                                    self.max_field_section_size,
                                 ),
                             };
                         } else {
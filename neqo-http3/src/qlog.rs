@@ -6,9 +6,9 @@
 
 use std::convert::TryFrom;
 
-use qlog::{self, event::Event, H3DataRecipient};
+use qlog::{self, H3DataRecipient};
 
-use neqo_common::qlog::NeqoQlog;
+use neqo_common::qlog::{Event, NeqoQlog};
 
 pub fn h3_data_moved_up(qlog: &mut NeqoQlog, stream_id: u64, amount: usize) {
     qlog.add_event(|| {
@@ -4,9 +4,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use crate::headers_checks::{check_headers, check_trailers, content_length, field_section_size};
 use crate::hframe::{HFrame, HFrameReader};
 use crate::push_controller::PushController;
 use crate::qlog;
+use crate::request_metrics::RequestMetrics;
 use crate::{Error, Header, Res};
 use crate::{RecvMessageEvents, RecvStream, ResetType};
 
@@ -38,6 +40,9 @@ pub enum MessageType {
  *    ReadingData : we got a DATA frame, now we letting the app read payload.
  *                  From here we will go back to WaitingForData state to wait
  *                  for more data frames or to CLosed state
+ *    DecodingTrailers : In this step the trailers will be decoded. The stream
+ *                       may be blocked in this state on encoder instructions.
+ *    WaitingForFinAfterTrailers : we got trailers, only the fin may follow.
  *    ClosePending : waiting for app to pick up data, after that we can delete
  * the TransactionClient.
  *    Closed
@@ -48,6 +53,7 @@ enum RecvMessageState {
     DecodingHeaders { header_block: Vec<u8>, fin: bool },
     WaitingForData { frame_reader: HFrameReader },
     ReadingData { remaining_data_len: usize },
+    DecodingTrailers { header_block: Vec<u8>, fin: bool },
     WaitingForFinAfterTrailers { frame_reader: HFrameReader },
     ClosePending, // Close must first be read by application
     Closed,
@@ -67,6 +73,14 @@ pub(crate) struct RecvMessage {
     push_handler: Option<Rc<RefCell<PushController>>>,
     stream_id: u64,
     blocked_push_promise: VecDeque<PushInfo>,
+    // Set if the stream is a WebTransport stream instead of a request.
+    webtransport_session: Option<u64>,
+    // The value of the `content-length` header field and the length of the DATA frames so far.
+    content_length: Option<u64>,
+    body_len: u64,
+    // The SETTINGS_MAX_FIELD_SECTION_SIZE that has been sent, if any.
+    max_field_section_size: Option<u64>,
+    metrics: Option<Rc<RefCell<RequestMetrics>>>,
 }
 
 impl ::std::fmt::Display for RecvMessage {
@@ -81,16 +95,34 @@ impl RecvMessage {
         stream_id: u64,
         conn_events: Box<dyn RecvMessageEvents>,
         push_handler: Option<Rc<RefCell<PushController>>>,
+        max_field_section_size: Option<u64>,
     ) -> Self {
         Self {
             state: RecvMessageState::WaitingForResponseHeaders {
-                frame_reader: HFrameReader::new(),
+                frame_reader: HFrameReader::new()
+                    .with_max_field_section_size(max_field_section_size),
             },
             message_type,
             conn_events,
             push_handler,
             stream_id,
             blocked_push_promise: VecDeque::new(),
+            webtransport_session: None,
+            content_length: None,
+            body_len: 0,
+            max_field_section_size,
+            metrics: None,
+        }
+    }
+
+    /// Count the header and body bytes that are received in `metrics`.
+    pub fn set_metrics(&mut self, metrics: Rc<RefCell<RequestMetrics>>) {
+        self.metrics = Some(metrics);
+    }
+
+    fn record_headers(&self, encoded_len: usize, headers: &[Header]) {
+        if let Some(m) = &self.metrics {
+            m.borrow_mut().headers_received(encoded_len, headers);
         }
     }
 
@@ -104,8 +136,7 @@ impl RecvMessage {
                 }
              }
             RecvMessageState::WaitingForData { ..} => {
-                // TODO implement trailers, for now just ignore them.
-                self.state = RecvMessageState::WaitingForFinAfterTrailers{frame_reader: HFrameReader::new()};
+                self.state = RecvMessageState::DecodingTrailers { header_block, fin };
             }
             RecvMessageState::WaitingForFinAfterTrailers {..} => {
                 return Err(Error::HttpFrameUnexpected);
@@ -121,6 +152,13 @@ impl RecvMessage {
                 return Err(Error::HttpFrameUnexpected);
             }
             RecvMessageState::WaitingForData {..} => {
+                self.body_len += len;
+                if let Some(m) = &self.metrics {
+                    m.borrow_mut().body_bytes_received += len;
+                }
+                if self.content_length.map_or(false, |l| self.body_len > l) {
+                    return Err(Error::HttpMessageError);
+                }
                 if len > 0 {
                     if fin {
                         return Err(Error::HttpFrame);
@@ -135,17 +173,41 @@ impl RecvMessage {
         Ok(())
     }
 
+    fn handle_webtransport_stream(&mut self, session_id: u64) -> Res<()> {
+        // Only a client opens WebTransport streams and the signal must start the stream.
+        if !matches!(self.message_type, MessageType::Request)
+            || !matches!(
+                self.state,
+                RecvMessageState::WaitingForResponseHeaders { .. }
+            )
+        {
+            return Err(Error::HttpFrameUnexpected);
+        }
+        qinfo!([self], "WebTransport stream of session {}", session_id);
+        self.webtransport_session = Some(session_id);
+        self.state = RecvMessageState::Closed;
+        Ok(())
+    }
+
     fn add_headers(
         &mut self,
         headers: Vec<Header>,
         fin: bool,
         decoder: &mut QPackDecoder,
     ) -> Res<()> {
+        self.check_field_section_size(&headers)?;
         let interim = self.is_interim(&headers)?;
 
         if fin && interim {
             return Err(Error::HttpGeneralProtocolStream);
         }
+        check_headers(&self.message_type, &headers)?;
+        if !interim {
+            self.content_length = content_length(&headers)?;
+            if fin {
+                self.check_body_len()?;
+            }
+        }
 
         self.conn_events
             .header_ready(self.stream_id, headers, interim, fin);
@@ -155,11 +217,11 @@ impl RecvMessage {
         } else {
             self.state = if interim {
                 RecvMessageState::WaitingForResponseHeaders {
-                    frame_reader: HFrameReader::new(),
+                    frame_reader: self.frame_reader(),
                 }
             } else {
                 RecvMessageState::WaitingForData {
-                    frame_reader: HFrameReader::new(),
+                    frame_reader: self.frame_reader(),
                 }
             };
         }
@@ -178,6 +240,7 @@ impl RecvMessage {
             RecvMessageState::ReadingData { .. } => {}
             RecvMessageState::WaitingForData { .. }
             | RecvMessageState::WaitingForFinAfterTrailers { .. } => {
+                self.check_body_len()?;
                 if post_readable_event {
                     self.conn_events.data_readable(self.stream_id)
                 }
@@ -256,12 +319,21 @@ impl RecvMessage {
                                     push_id,
                                     header_block,
                                 } => self.handle_push_promise(push_id, header_block, decoder)?,
+                                HFrame::WebTransportStream { session_id } => {
+                                    self.handle_webtransport_stream(session_id)?
+                                }
                                 _ => break Err(Error::HttpFrameUnexpected),
                             }
                             if matches!(self.state, RecvMessageState::Closed) {
                                 break Ok(());
                             }
-                            if fin && !matches!(self.state, RecvMessageState::DecodingHeaders{..}) {
+                            if fin
+                                && !matches!(
+                                    self.state,
+                                    RecvMessageState::DecodingHeaders { .. }
+                                        | RecvMessageState::DecodingTrailers { .. }
+                                )
+                            {
                                 break self.set_state_to_close_pending(post_readable_event);
                             }
                         }
@@ -281,9 +353,11 @@ impl RecvMessage {
                         break Ok(());
                     }
                     let done = *fin;
+                    let encoded_len = header_block.len();
                     if let Some(headers) =
                         decoder.decode_header_block(header_block, self.stream_id)?
                     {
+                        self.record_headers(encoded_len, &headers);
                         self.add_headers(headers, done, decoder)?;
                         if matches!(self.state, RecvMessageState::Closed) {
                             break Ok(());
@@ -293,6 +367,31 @@ impl RecvMessage {
                         break Ok(());
                     }
                 }
+                RecvMessageState::DecodingTrailers {
+                    ref header_block,
+                    fin,
+                } => {
+                    let done = *fin;
+                    let encoded_len = header_block.len();
+                    if let Some(trailers) =
+                        decoder.decode_header_block(header_block, self.stream_id)?
+                    {
+                        self.record_headers(encoded_len, &trailers);
+                        self.check_field_section_size(&trailers)?;
+                        check_trailers(&trailers)?;
+                        self.check_body_len()?;
+                        self.conn_events.trailers_ready(self.stream_id, trailers);
+                        self.state = RecvMessageState::WaitingForFinAfterTrailers {
+                            frame_reader: self.frame_reader(),
+                        };
+                        if done {
+                            break self.set_state_to_close_pending(post_readable_event);
+                        }
+                    } else {
+                        qinfo!([self], "decoding trailers is blocked.");
+                        break Ok(());
+                    }
+                }
                 RecvMessageState::ReadingData { .. } => {
                     if post_readable_event {
                         self.conn_events.data_readable(self.stream_id);
@@ -320,15 +419,53 @@ impl RecvMessage {
         )
     }
 
+    // The body of a request must have the length given in `content-length`. A response body
+    // is only checked for being too long, because the response to a HEAD request has no body.
+    fn check_body_len(&self) -> Res<()> {
+        if matches!(self.message_type, MessageType::Request)
+            && self.content_length.map_or(false, |l| l != self.body_len)
+        {
+            return Err(Error::HttpMessageError);
+        }
+        Ok(())
+    }
+
+    // A reader for the next frames of the message. A HEADERS or PUSH_PROMISE frame that is
+    // longer than the limit we have advertised is refused before it is read.
+    fn frame_reader(&self) -> HFrameReader {
+        HFrameReader::new().with_max_field_section_size(self.max_field_section_size)
+    }
+
+    // A field section larger than the limit we have advertised is discarded and the stream is
+    // reset.
+    fn check_field_section_size(&self, headers: &[Header]) -> Res<()> {
+        if self
+            .max_field_section_size
+            .map_or(false, |max| field_section_size(headers) > max)
+        {
+            qinfo!([self], "The field section exceeds the local limit.");
+            return Err(Error::HttpExcessiveLoadStream);
+        }
+        Ok(())
+    }
+
     fn is_interim(&self, headers: &[Header]) -> Res<bool> {
         match self.message_type {
             MessageType::Response => {
                 let status = headers.iter().find(|(name, _value)| name == ":status");
                 if let Some((_name, value)) = status {
+                    // A status code has three digits. HTTP/3 does not support 101 (Switching
+                    // Protocols), all other 1xx responses are interim.
+                    if value.len() != 3 || !value.bytes().all(|b| b.is_ascii_digit()) {
+                        return Err(Error::HttpGeneralProtocolStream);
+                    }
                     let status_code = value
                         .parse::<i32>()
                         .map_err(|_| Error::HttpGeneralProtocolStream)?;
-                    Ok(status_code >= 100 && status_code < 200)
+                    if status_code < 100 || status_code == 101 {
+                        return Err(Error::HttpGeneralProtocolStream);
+                    }
+                    Ok(status_code < 200)
                 } else {
                     Err(Error::HttpGeneralProtocolStream)
                 }
@@ -365,6 +502,10 @@ impl RecvStream for RecvMessage {
         matches!(self.state, RecvMessageState::Closed)
     }
 
+    fn webtransport_session(&self) -> Option<u64> {
+        self.webtransport_session
+    }
+
     fn stream_reset(&self, app_error: AppError, decoder: &mut QPackDecoder, reset_type: ResetType) {
         if !self.closing() || !self.blocked_push_promise.is_empty() {
             decoder.cancel_stream(self.stream_id);
@@ -406,11 +547,12 @@ impl RecvStream for RecvMessage {
                         if *remaining_data_len > 0 {
                             return Err(Error::HttpFrame);
                         }
+                        self.check_body_len()?;
                         self.set_closed(decoder);
                         break Ok((written, fin));
                     } else if *remaining_data_len == 0 {
                         self.state = RecvMessageState::WaitingForData {
-                            frame_reader: HFrameReader::new(),
+                            frame_reader: self.frame_reader(),
                         };
                         self.receive_internal(conn, decoder, false)?;
                     } else {
@@ -0,0 +1,219 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::{Error, Header, Res};
+
+/// A complete request that is sent with `Http3Client::send_request`. The pseudo-header
+/// fields are taken from a URL, header field names are lowercased and a `content-length`
+/// header field is added if the request has a body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    method: String,
+    scheme: String,
+    authority: String,
+    path: String,
+    headers: Vec<Header>,
+    body: Option<Vec<u8>>,
+}
+
+impl Request {
+    /// A request for `url`, which has the form `scheme://authority[/path][?query]`. A fragment
+    /// is not sent; the path is `/` if the URL does not have one.
+    /// # Errors
+    /// `InvalidInput` if the URL does not have a scheme or an authority, or if the authority
+    /// has user information.
+    pub fn new(method: &str, url: &str) -> Res<Self> {
+        let sep = url.find("://").ok_or(Error::InvalidInput)?;
+        let scheme = &url[..sep];
+        let rest = &url[sep + 3..];
+        let rest = rest.find('#').map_or(rest, |f| &rest[..f]);
+        let (authority, path) = match rest.find(|c| c == '/' || c == '?') {
+            Some(p) => rest.split_at(p),
+            None => (rest, ""),
+        };
+        if method.is_empty() || scheme.is_empty() || authority.is_empty() || authority.contains('@')
+        {
+            return Err(Error::InvalidInput);
+        }
+        let path = if path.is_empty() {
+            String::from("/")
+        } else if path.starts_with('?') {
+            format!("/{}", path)
+        } else {
+            path.to_owned()
+        };
+        Ok(Self {
+            method: method.to_owned(),
+            scheme: scheme.to_ascii_lowercase(),
+            authority: authority.to_owned(),
+            path,
+            headers: Vec::new(),
+            body: None,
+        })
+    }
+
+    /// A GET request for `url`.
+    /// # Errors
+    /// `InvalidInput` if the URL cannot be used, see `new`.
+    pub fn get(url: &str) -> Res<Self> {
+        Self::new("GET", url)
+    }
+
+    /// A HEAD request for `url`.
+    /// # Errors
+    /// `InvalidInput` if the URL cannot be used, see `new`.
+    pub fn head(url: &str) -> Res<Self> {
+        Self::new("HEAD", url)
+    }
+
+    /// A POST request for `url` with `body`.
+    /// # Errors
+    /// `InvalidInput` if the URL cannot be used, see `new`.
+    pub fn post(url: &str, body: &[u8]) -> Res<Self> {
+        Ok(Self::new("POST", url)?.body(body))
+    }
+
+    /// Add a header field. The name is lowercased.
+    #[must_use]
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .push((name.to_ascii_lowercase(), value.to_owned()));
+        self
+    }
+
+    /// Set the request body. A `content-length` header field with its length is added, unless
+    /// there is one already.
+    #[must_use]
+    pub fn body(mut self, body: &[u8]) -> Self {
+        self.body = Some(body.to_vec());
+        self
+    }
+
+    #[must_use]
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    #[must_use]
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    #[must_use]
+    pub fn authority(&self) -> &str {
+        &self.authority
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The header fields that have been added, without the pseudo-header fields.
+    #[must_use]
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    /// All header fields of the request, starting with the pseudo-header fields.
+    pub(crate) fn header_list(&self) -> Vec<Header> {
+        let mut headers = vec![
+            (String::from(":method"), self.method.clone()),
+            (String::from(":scheme"), self.scheme.clone()),
+            (String::from(":authority"), self.authority.clone()),
+            (String::from(":path"), self.path.clone()),
+        ];
+        headers.extend_from_slice(&self.headers);
+        if let Some(body) = &self.body {
+            if !self.headers.iter().any(|(n, _)| n == "content-length") {
+                headers.push((String::from("content-length"), body.len().to_string()));
+            }
+        }
+        headers
+    }
+
+    pub(crate) fn body_data(&self) -> Option<&[u8]> {
+        self.body.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Request;
+    use crate::Error;
+
+    #[test]
+    fn url() {
+        let r = Request::get("HTTPS://example.com:8443/a/b?c=d#e").unwrap();
+        assert_eq!(r.method(), "GET");
+        assert_eq!(r.scheme(), "https");
+        assert_eq!(r.authority(), "example.com:8443");
+        assert_eq!(r.path(), "/a/b?c=d");
+
+        assert_eq!(Request::head("https://example.com").unwrap().path(), "/");
+        assert_eq!(
+            Request::get("https://example.com?x=1").unwrap().path(),
+            "/?x=1"
+        );
+        assert_eq!(
+            Request::get("https://[::1]:443/").unwrap().authority(),
+            "[::1]:443"
+        );
+    }
+
+    #[test]
+    fn invalid_url() {
+        for url in &[
+            "example.com/",
+            "://example.com",
+            "https://",
+            "https:///a",
+            "https://u@a.com/",
+        ] {
+            assert_eq!(Request::get(url), Err(Error::InvalidInput));
+        }
+        assert_eq!(
+            Request::new("", "https://example.com"),
+            Err(Error::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn header_list() {
+        let r = Request::post("https://example.com/upload", b"abc")
+            .unwrap()
+            .header("Content-Type", "text/plain");
+        assert_eq!(r.body_data(), Some(&b"abc"[..]));
+        assert_eq!(
+            r.header_list(),
+            vec![
+                (String::from(":method"), String::from("POST")),
+                (String::from(":scheme"), String::from("https")),
+                (String::from(":authority"), String::from("example.com")),
+                (String::from(":path"), String::from("/upload")),
+                (String::from("content-type"), String::from("text/plain")),
+                (String::from("content-length"), String::from("3")),
+            ]
+        );
+
+        // A content-length that is set explicitly is kept.
+        let r = Request::post("https://example.com/", &[])
+            .unwrap()
+            .header("Content-Length", "0");
+        assert_eq!(
+            r.header_list()
+                .iter()
+                .filter(|(n, _)| n == "content-length")
+                .count(),
+            1
+        );
+
+        // No content-length without a body.
+        let r = Request::get("https://example.com/").unwrap();
+        assert_eq!(r.body_data(), None);
+        assert_eq!(r.header_list().len(), 4);
+    }
+}
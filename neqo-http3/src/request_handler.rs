@@ -0,0 +1,86 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// A callback interface for the requests of an `Http3Server`. It is an alternative to matching
+// on `Http3ServerEvent`s: `Http3Server::dispatch_events` takes the pending events and calls
+// the handler for each of them.
+
+use crate::server_events::{ClientRequestStream, Http3ServerEvent};
+use crate::Header;
+use neqo_transport::AppError;
+
+/// The callbacks for the life of a request. Only `on_request` must be implemented; the other
+/// callbacks do nothing by default. A request goes through `on_request`, then possibly
+/// `on_data` and `on_trailers`, and ends with either `on_complete` or `on_reset`.
+/// The `ClientRequestStream` is used to send the response: `set_response` for a complete
+/// response, or `set_response_headers`, `send_data` and `send_trailers` (or
+/// `stream_close_send`) to stream it.
+pub trait RequestHandler {
+    /// A new request has been received. `method` and `path` are taken from the pseudo-header
+    /// fields and are empty if they are absent, e.g. `:path` of a CONNECT request. `fin` is
+    /// set if the request has no body.
+    fn on_request(
+        &mut self,
+        request: ClientRequestStream,
+        method: &str,
+        path: &str,
+        headers: &[Header],
+        fin: bool,
+    );
+
+    /// A part of the request body has been received. `fin` is set for the last part.
+    fn on_data(&mut self, _request: ClientRequestStream, _data: &[u8], _fin: bool) {}
+
+    /// Request body data can be read with `ClientRequestStream::read_data`. This is only
+    /// used if the server has been configured with `set_manual_request_reads`.
+    fn on_data_readable(&mut self, _request: ClientRequestStream) {}
+
+    /// Request trailers have been received; the request is complete.
+    fn on_trailers(&mut self, _request: ClientRequestStream, _trailers: &[Header]) {}
+
+    /// More response data can be sent with `ClientRequestStream::send_data`.
+    fn on_data_writable(&mut self, _request: ClientRequestStream) {}
+
+    /// The client has acknowledged the whole response.
+    fn on_complete(&mut self, _request: ClientRequestStream) {}
+
+    /// The request has been reset, by the client or locally (`local`) because of an error.
+    fn on_reset(&mut self, _request: ClientRequestStream, _error: AppError, _local: bool) {}
+
+    /// Any other event, e.g. a connection state change, a WebTransport event or an HTTP
+    /// Datagram.
+    fn on_event(&mut self, _event: Http3ServerEvent) {}
+}
+
+/// Call the handler for an event.
+pub(crate) fn dispatch_event(handler: &mut impl RequestHandler, event: Http3ServerEvent) {
+    match event {
+        Http3ServerEvent::Headers {
+            request,
+            headers,
+            fin,
+        } => {
+            let get = |n: &str| {
+                headers
+                    .iter()
+                    .find(|(name, _)| name == n)
+                    .map_or("", |(_, value)| value.as_str())
+            };
+            handler.on_request(request, get(":method"), get(":path"), &headers, fin);
+        }
+        Http3ServerEvent::Data { request, data, fin } => handler.on_data(request, &data, fin),
+        Http3ServerEvent::DataReadable { request } => handler.on_data_readable(request),
+        Http3ServerEvent::Trailers { request, trailers } => handler.on_trailers(request, &trailers),
+        Http3ServerEvent::DataWritable { request } => handler.on_data_writable(request),
+        Http3ServerEvent::ResponseComplete { request } => handler.on_complete(request),
+        Http3ServerEvent::Reset {
+            request,
+            error,
+            local,
+        } => handler.on_reset(request, error, local),
+        e => handler.on_event(e),
+    }
+}
@@ -0,0 +1,104 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Timing and transfer metrics of a single request.
+
+use crate::Header;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+/// Metrics of a request, see `Http3Client::set_request_metrics`. Header bytes are counted
+/// for all field sections, i.e. interim responses and trailers are included. The compressed
+/// size is the size of the QPACK encoded field sections; the uncompressed size is the sum of
+/// the lengths of the names and values. Body bytes are the payload of DATA frames.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(clippy::module_name_repetitions)]
+pub struct RequestMetrics {
+    pub stream_id: u64,
+    pub start: Instant,
+    /// The time from creating the request until the first byte of the response is received.
+    pub time_to_first_byte: Option<Duration>,
+    /// Whether the request has been sent in 0-RTT and the server has accepted it.
+    pub zero_rtt: bool,
+    pub header_bytes_sent: u64,
+    pub header_bytes_sent_uncompressed: u64,
+    pub body_bytes_sent: u64,
+    pub header_bytes_received: u64,
+    pub header_bytes_received_uncompressed: u64,
+    pub body_bytes_received: u64,
+}
+
+impl RequestMetrics {
+    pub(crate) fn new(stream_id: u64, start: Instant, zero_rtt: bool) -> Self {
+        Self {
+            stream_id,
+            start,
+            time_to_first_byte: None,
+            zero_rtt,
+            header_bytes_sent: 0,
+            header_bytes_sent_uncompressed: 0,
+            body_bytes_sent: 0,
+            header_bytes_received: 0,
+            header_bytes_received_uncompressed: 0,
+            body_bytes_received: 0,
+        }
+    }
+
+    pub(crate) fn headers_sent(&mut self, encoded_len: usize, headers: &[Header]) {
+        self.header_bytes_sent += u64::try_from(encoded_len).unwrap();
+        self.header_bytes_sent_uncompressed += uncompressed_len(headers);
+    }
+
+    pub(crate) fn headers_received(&mut self, encoded_len: usize, headers: &[Header]) {
+        self.header_bytes_received += u64::try_from(encoded_len).unwrap();
+        self.header_bytes_received_uncompressed += uncompressed_len(headers);
+    }
+
+    pub(crate) fn first_byte_received(&mut self, now: Instant) {
+        if self.time_to_first_byte.is_none() {
+            self.time_to_first_byte = Some(now.saturating_duration_since(self.start));
+        }
+    }
+}
+
+fn uncompressed_len(headers: &[Header]) -> u64 {
+    headers
+        .iter()
+        .map(|(n, v)| u64::try_from(n.len() + v.len()).unwrap())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestMetrics;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn header_sizes() {
+        let mut m = RequestMetrics::new(0, Instant::now(), false);
+        let headers = vec![
+            (String::from(":method"), String::from("GET")),
+            (String::from("accept"), String::from("*/*")),
+        ];
+        m.headers_sent(5, &headers);
+        m.headers_sent(3, &headers[1..]);
+        assert_eq!(m.header_bytes_sent, 8);
+        assert_eq!(m.header_bytes_sent_uncompressed, 10 + 9 + 9);
+        m.headers_received(2, &headers[..1]);
+        assert_eq!(m.header_bytes_received, 2);
+        assert_eq!(m.header_bytes_received_uncompressed, 10);
+    }
+
+    #[test]
+    fn time_to_first_byte() {
+        let start = Instant::now();
+        let mut m = RequestMetrics::new(4, start, true);
+        assert_eq!(m.time_to_first_byte, None);
+        m.first_byte_received(start + Duration::from_millis(20));
+        m.first_byte_received(start + Duration::from_millis(30));
+        assert_eq!(m.time_to_first_byte, Some(Duration::from_millis(20)));
+    }
+}
@@ -6,14 +6,19 @@
 
 use crate::hframe::HFrame;
 use crate::qlog;
+use crate::request_metrics::RequestMetrics;
 use crate::Header;
 use crate::{Error, Res};
 
 use neqo_common::{qdebug, qinfo, qtrace, Encoder};
 use neqo_qpack::encoder::QPackEncoder;
 use neqo_transport::{AppError, Connection};
+use std::cell::RefCell;
 use std::cmp::min;
+use std::convert::TryFrom;
 use std::fmt::Debug;
+use std::mem;
+use std::rc::Rc;
 
 const MAX_DATA_HEADER_SIZE_2: usize = (1 << 6) - 1; // Maximal amount of data with DATA frame header size 2
 const MAX_DATA_HEADER_SIZE_2_LIMIT: usize = MAX_DATA_HEADER_SIZE_2 + 3; // 63 + 3 (size of the next buffer data frame header)
@@ -34,11 +39,14 @@ pub(crate) trait SendMessageEvents: Debug {
  *    Initialized : Headers are present but still not encoded. A message body may be present as well.
  *                  The client side sends a message body using the send_body() function that directly
  *                  writes into a transport stream. The server side sets headers and body when
- *                  initializing a send message (TODO: make server use send_body as well)
+ *                  initializing a send message, or only headers if the body is sent using
+ *                  send_body(), e.g. for a CONNECT tunnel.
  *    SendingInitialMessage : sending headers and maybe message body. From here we may switch to
  *                     SendingData or Closed (if the app does not want to send data and
  *                     has already closed the send stream).
- *    SendingData : We are sending request data until the app closes the stream.
+ *    SendingData : We are sending request data until the app closes the stream or supplies trailers.
+ *    TrailersInitialized : The client side has supplied trailers after the message body. They
+ *                          are sent like the initial message and the stream is closed afterwards.
  *    Closed
  */
 
@@ -48,8 +56,12 @@ enum SendMessageState {
     Initialized {
         headers: Vec<Header>,
         data: Option<Vec<u8>>,
+        trailers: Option<Vec<Header>>,
         fin: bool,
     },
+    TrailersInitialized {
+        trailers: Vec<Header>,
+    },
     SendingInitialMessage {
         buf: Vec<u8>,
         fin: bool,
@@ -63,7 +75,7 @@ impl SendMessageState {
         match self {
             Self::Initialized { fin, .. } | Self::SendingInitialMessage { fin, .. } => *fin,
             Self::SendingData => false,
-            _ => true,
+            Self::Uninitialized | Self::TrailersInitialized { .. } | Self::Closed => true,
         }
     }
 
@@ -81,6 +93,13 @@ pub(crate) struct SendMessage {
     state: SendMessageState,
     stream_id: u64,
     conn_events: Box<dyn SendMessageEvents>,
+    /// Interim (1xx) responses that are sent before the message headers. They are encoded
+    /// into `interim_buf` when the stream is processed.
+    interim: Vec<Vec<Header>>,
+    interim_buf: Vec<u8>,
+    // Whether a frame of a reserved type is sent before the headers.
+    grease: bool,
+    metrics: Option<Rc<RefCell<RequestMetrics>>>,
 }
 
 impl SendMessage {
@@ -90,6 +109,10 @@ impl SendMessage {
             state: SendMessageState::Uninitialized,
             stream_id,
             conn_events,
+            interim: Vec::new(),
+            interim_buf: Vec::new(),
+            grease: false,
+            metrics: None,
         }
     }
 
@@ -103,14 +126,51 @@ impl SendMessage {
             state: SendMessageState::Initialized {
                 headers,
                 data: None,
+                trailers: None,
                 fin: false,
             },
             stream_id,
             conn_events,
+            interim: Vec::new(),
+            interim_buf: Vec::new(),
+            grease: false,
+            metrics: None,
+        }
+    }
+
+    /// Create a message that has headers and a body that is already known, e.g. a request that
+    /// is sent again after 0-RTT has been rejected. If `fin` is false, more data can be sent
+    /// with `send_body` afterwards.
+    pub fn new_with_body(
+        stream_id: u64,
+        headers: Vec<Header>,
+        body: Vec<u8>,
+        fin: bool,
+        conn_events: Box<dyn SendMessageEvents>,
+    ) -> Self {
+        qinfo!("Create a request stream_id={}", stream_id);
+        Self {
+            state: SendMessageState::Initialized {
+                headers,
+                data: if body.is_empty() { None } else { Some(body) },
+                trailers: None,
+                fin,
+            },
+            stream_id,
+            conn_events,
+            interim: Vec::new(),
+            interim_buf: Vec::new(),
+            grease: false,
+            metrics: None,
         }
     }
 
-    pub fn set_message(&mut self, headers: &[Header], data: Option<&[u8]>) -> Res<()> {
+    pub fn set_message(
+        &mut self,
+        headers: &[Header],
+        data: Option<&[u8]>,
+        trailers: Option<&[Header]>,
+    ) -> Res<()> {
         if !matches!(self.state, SendMessageState::Uninitialized) {
             return Err(Error::AlreadyInitialized);
         }
@@ -122,11 +182,113 @@ impl SendMessage {
             } else {
                 None
             },
+            trailers: trailers.map(<[Header]>::to_vec),
             fin: true,
         };
         Ok(())
     }
 
+    /// Send `buf` in a single DATA frame if flow control allows it, otherwise send nothing.
+    /// This is used for capsules that must not be split, e.g. HTTP Datagrams, which may be
+    /// dropped instead. Returns whether `buf` has been sent.
+    pub fn send_body_atomic(&mut self, conn: &mut Connection, buf: &[u8]) -> Res<bool> {
+        if !matches!(self.state, SendMessageState::SendingData) {
+            return Ok(false);
+        }
+        let available = conn
+            .stream_avail_send_space(self.stream_id)
+            .map_err(|e| Error::map_stream_send_errors(&e))?;
+        // The DATA frame header takes at most 9 bytes.
+        if available < buf.len() + 9 {
+            return Ok(false);
+        }
+        Ok(self.send_body(conn, buf)? == buf.len())
+    }
+
+    /// Queue an interim (1xx) response, e.g. 103 Early Hints. It is sent before the final
+    /// response, therefore the final response must not have been supplied yet.
+    /// # Errors
+    /// `InvalidInput` if `headers` do not carry a 1xx status code (101 is not allowed in
+    /// HTTP/3), `AlreadyInitialized` if the final response has been supplied already.
+    pub fn set_interim_headers(&mut self, headers: &[Header]) -> Res<()> {
+        let status = headers
+            .iter()
+            .find(|(name, _)| name == ":status")
+            .and_then(|(_, value)| value.parse::<u16>().ok());
+        if !matches!(status, Some(s) if (100..200).contains(&s) && s != 101) {
+            return Err(Error::InvalidInput);
+        }
+        if !matches!(self.state, SendMessageState::Uninitialized) {
+            return Err(Error::AlreadyInitialized);
+        }
+        self.interim.push(headers.to_vec());
+        Ok(())
+    }
+
+    /// Send the queued interim responses. Returns whether all of them have been sent.
+    fn send_interim(&mut self, conn: &mut Connection, encoder: &mut QPackEncoder) -> Res<bool> {
+        if !self.interim.is_empty() {
+            let mut d = Encoder::default();
+            for headers in mem::take(&mut self.interim) {
+                qdebug!([self], "Encoding interim headers");
+                self.encode_headers_frame(conn, encoder, &headers, &mut d)?;
+            }
+            self.interim_buf.extend_from_slice(&d);
+        }
+        if self.interim_buf.is_empty() {
+            return Ok(true);
+        }
+        let sent = conn
+            .stream_send(self.stream_id, &self.interim_buf)
+            .map_err(|_| Error::map_send_errors())?;
+        qlog::h3_data_moved_down(&mut conn.qlog_mut(), self.stream_id, sent);
+        self.interim_buf.drain(..sent);
+        Ok(self.interim_buf.is_empty())
+    }
+
+    /// Set headers of a message whose body will be sent using `send_body`.
+    pub fn set_headers(&mut self, headers: &[Header]) -> Res<()> {
+        if !matches!(self.state, SendMessageState::Uninitialized) {
+            return Err(Error::AlreadyInitialized);
+        }
+
+        self.state = SendMessageState::Initialized {
+            headers: headers.to_vec(),
+            data: None,
+            trailers: None,
+            fin: false,
+        };
+        Ok(())
+    }
+
+    /// Send a frame of a reserved type before the headers. The peer must ignore it.
+    pub fn set_grease(&mut self) {
+        self.grease = true;
+    }
+
+    /// Supply trailers after the message body. This also closes the sending side.
+    /// # Errors
+    /// `InvalidState` if the headers have not been sent yet,
+    /// `AlreadyClosed` if the sending side has already been closed.
+    /// Count the header and body bytes that are sent in `metrics`.
+    pub fn set_metrics(&mut self, metrics: Rc<RefCell<RequestMetrics>>) {
+        self.metrics = Some(metrics);
+    }
+
+    pub fn set_trailers(&mut self, trailers: &[Header]) -> Res<()> {
+        match self.state {
+            SendMessageState::SendingData => {
+                self.state = SendMessageState::TrailersInitialized {
+                    trailers: trailers.to_vec(),
+                };
+                self.conn_events.remove_send_side_event(self.stream_id);
+                Ok(())
+            }
+            _ if self.state.is_sending_closed() => Err(Error::AlreadyClosed),
+            _ => Err(Error::InvalidState),
+        }
+    }
+
     pub fn send_body(&mut self, conn: &mut Connection, buf: &[u8]) -> Res<usize> {
         qtrace!(
             [self],
@@ -139,6 +301,10 @@ impl SendMessage {
             | SendMessageState::Initialized { .. }
             | SendMessageState::SendingInitialMessage { .. } => Ok(0),
             SendMessageState::SendingData => {
+                if buf.is_empty() {
+                    // Do not send a DATA frame header without any data.
+                    return Err(Error::InvalidInput);
+                }
                 let available = conn
                     .stream_avail_send_space(self.stream_id)
                     .map_err(|e| Error::map_stream_send_errors(&e))?;
@@ -180,9 +346,14 @@ impl SendMessage {
                     .stream_send(self.stream_id, &buf[..to_send])
                     .map_err(|e| Error::map_stream_send_errors(&e))?;
                 qlog::h3_data_moved_down(&mut conn.qlog_mut(), self.stream_id, to_send);
+                if let Some(m) = &self.metrics {
+                    m.borrow_mut().body_bytes_sent += u64::try_from(sent).unwrap();
+                }
                 Ok(sent)
             }
-            SendMessageState::Closed => Err(Error::AlreadyClosed),
+            SendMessageState::TrailersInitialized { .. } | SendMessageState::Closed => {
+                Err(Error::AlreadyClosed)
+            }
         }
     }
 
@@ -200,28 +371,69 @@ impl SendMessage {
     /// `ClosedCriticalStream` if the encoder stream is closed.
     /// `InternalError` if an unexpected error occurred.
     fn ensure_encoded(&mut self, conn: &mut Connection, encoder: &mut QPackEncoder) -> Res<()> {
-        if let SendMessageState::Initialized { headers, data, fin } = &self.state {
-            qdebug!([self], "Encoding headers");
-            let header_block = encoder.encode_header_block(conn, &headers, self.stream_id)?;
-            let hframe = HFrame::Headers {
-                header_block: header_block.to_vec(),
-            };
-            let mut d = Encoder::default();
-            hframe.encode(&mut d);
-            if let Some(buf) = data {
-                qdebug!([self], "Encoding data");
-                let d_frame = HFrame::Data {
-                    len: buf.len() as u64,
+        match &self.state {
+            SendMessageState::Initialized {
+                headers,
+                data,
+                trailers,
+                fin,
+            } => {
+                qdebug!([self], "Encoding headers");
+                let mut d = Encoder::default();
+                if self.grease {
+                    HFrame::Grease.encode(&mut d);
+                }
+                self.encode_headers_frame(conn, encoder, headers, &mut d)?;
+                if let Some(buf) = data {
+                    qdebug!([self], "Encoding data");
+                    let d_frame = HFrame::Data {
+                        len: buf.len() as u64,
+                    };
+                    d_frame.encode(&mut d);
+                    d.encode(&buf);
+                    if let Some(m) = &self.metrics {
+                        m.borrow_mut().body_bytes_sent += u64::try_from(buf.len()).unwrap();
+                    }
+                }
+                if let Some(t) = trailers {
+                    qdebug!([self], "Encoding trailers");
+                    self.encode_headers_frame(conn, encoder, t, &mut d)?;
+                }
+
+                self.state = SendMessageState::SendingInitialMessage {
+                    buf: d.into(),
+                    fin: *fin,
                 };
-                d_frame.encode(&mut d);
-                d.encode(&buf);
             }
+            SendMessageState::TrailersInitialized { trailers } => {
+                qdebug!([self], "Encoding trailers");
+                let mut d = Encoder::default();
+                self.encode_headers_frame(conn, encoder, trailers, &mut d)?;
+                self.state = SendMessageState::SendingInitialMessage {
+                    buf: d.into(),
+                    fin: true,
+                };
+            }
+            _ => {}
+        }
+        Ok(())
+    }
 
-            self.state = SendMessageState::SendingInitialMessage {
-                buf: d.into(),
-                fin: *fin,
-            };
+    fn encode_headers_frame(
+        &self,
+        conn: &mut Connection,
+        encoder: &mut QPackEncoder,
+        headers: &[Header],
+        enc: &mut Encoder,
+    ) -> Res<()> {
+        let header_block = encoder.encode_header_block(conn, headers, self.stream_id)?;
+        if let Some(m) = &self.metrics {
+            m.borrow_mut().headers_sent(header_block.len(), headers);
         }
+        let hframe = HFrame::Headers {
+            header_block: header_block.to_vec(),
+        };
+        hframe.encode(enc);
         Ok(())
     }
 
@@ -233,6 +445,9 @@ impl SendMessage {
     /// `TransportStreamDoesNotExist` if the transport stream does not exist (this may happen if `process_output`
     /// has not been called when needed, and HTTP3 layer has not picked up the info that the stream has been closed.)
     pub fn send(&mut self, conn: &mut Connection, encoder: &mut QPackEncoder) -> Res<()> {
+        if !self.send_interim(conn, encoder)? {
+            return Ok(());
+        }
         self.ensure_encoded(conn, encoder)?;
 
         let label = if ::log::log_enabled!(::log::Level::Debug) {
@@ -272,7 +487,9 @@ impl SendMessage {
     // This method returns if they're still being sent. Request body (if any) is sent by
     // http client afterwards using `send_request_body` after receiving DataWritable event.
     pub fn has_data_to_send(&self) -> bool {
-        matches!(self.state, SendMessageState::Initialized {..} | SendMessageState::SendingInitialMessage { .. } )
+        !self.interim.is_empty()
+            || !self.interim_buf.is_empty()
+            || matches!(self.state, SendMessageState::Initialized {..} | SendMessageState::TrailersInitialized {..} | SendMessageState::SendingInitialMessage { .. } )
     }
 
     pub fn close(&mut self, conn: &mut Connection) -> Res<()> {
@@ -281,6 +498,7 @@ impl SendMessage {
             | SendMessageState::Initialized { ref mut fin, .. } => {
                 *fin = true;
             }
+            SendMessageState::TrailersInitialized { .. } => {}
             _ => {
                 self.state = SendMessageState::Closed;
                 conn.stream_close_send(self.stream_id)?;
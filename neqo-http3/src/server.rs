@@ -6,13 +6,20 @@
 
 #![allow(clippy::module_name_repetitions)]
 
-use crate::connection::Http3State;
+use crate::connection::{Http3State, LOCAL_MAX_DATAGRAM_FRAME_SIZE};
 use crate::connection_server::Http3ServerHandler;
+use crate::origin;
+use crate::request_handler::{dispatch_event, RequestHandler};
 use crate::server_connection_events::Http3ServerConnEvent;
-use crate::server_events::{ClientRequestStream, Http3ServerEvent, Http3ServerEvents};
-use crate::settings::HttpZeroRttChecker;
-use crate::Res;
-use neqo_common::{qtrace, Datagram};
+use crate::server_events::{
+    ClientRequestStream, Http3ServerEvent, Http3ServerEvents, WebTransportServerEvent,
+    WebTransportSession,
+};
+use crate::settings::{extension_settings, HSetting, HttpZeroRttChecker};
+use crate::webtransport::{WebTransportEvent, WEBTRANSPORT_PROTOCOL};
+use crate::{Error, Res};
+use neqo_common::metrics::{self, MetricsRef};
+use neqo_common::{event::Provider as EventProvider, qtrace, qwarn, Datagram};
 use neqo_crypto::{AntiReplay, Cipher};
 use neqo_qpack::QpackSettings;
 use neqo_transport::server::{ActiveConnectionRef, Server, ValidateAddress};
@@ -22,7 +29,7 @@ use std::cell::RefMut;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::rc::Rc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 type HandlerRef = Rc<RefCell<Http3ServerHandler>>;
 
@@ -31,6 +38,15 @@ const MAX_EVENT_DATA_SIZE: usize = 1024;
 pub struct Http3Server {
     server: Server,
     qpack_settings: QpackSettings,
+    enable_connect_protocol: bool,
+    enable_webtransport: bool,
+    enable_h3_datagram: bool,
+    manual_request_reads: bool,
+    extension_settings: Vec<HSetting>,
+    grease: bool,
+    max_field_section_size: Option<u64>,
+    origins: Vec<String>,
+    metrics: MetricsRef,
     http3_handlers: HashMap<ActiveConnectionRef, HandlerRef>,
     events: Http3ServerEvents,
 }
@@ -63,6 +79,15 @@ impl Http3Server {
                 cid_manager,
             )?,
             qpack_settings,
+            enable_connect_protocol: false,
+            enable_webtransport: false,
+            enable_h3_datagram: false,
+            manual_request_reads: false,
+            extension_settings: Vec::new(),
+            grease: false,
+            max_field_section_size: None,
+            origins: Vec::new(),
+            metrics: metrics::no_metrics(),
             http3_handlers: HashMap::new(),
             events: Http3ServerEvents::default(),
         })
@@ -72,14 +97,131 @@ impl Http3Server {
         self.server.set_qlog_dir(dir)
     }
 
+    /// Dump the packets of each connection to a file in `dir`, see `PacketDump`.
+    pub fn set_packet_dump_dir(&mut self, dir: Option<PathBuf>) {
+        self.server.set_packet_dump_dir(dir);
+    }
+
     pub fn set_validation(&mut self, v: ValidateAddress) {
         self.server.set_validation(v);
     }
 
+    /// Set how long the tokens that are sent in NEW_TOKEN frames can be used for.
+    pub fn set_token_lifetime(&mut self, lifetime: Duration) {
+        self.server.set_token_lifetime(lifetime);
+    }
+
+    /// Set the key that protects address validation tokens, so that servers that share
+    /// it accept each other's tokens.
+    ///
+    /// # Errors
+    /// When the key is not 32 bytes long, or when it can't be imported.
+    pub fn set_token_key(&mut self, key: &[u8]) -> Res<()> {
+        self.server.set_token_key(key)?;
+        Ok(())
+    }
+
+    /// Set where the metrics of connections and of QPACK are reported. This applies to
+    /// connections that are created afterwards.
+    pub fn set_metrics(&mut self, metrics: MetricsRef) {
+        self.server.set_metrics(Rc::clone(&metrics));
+        self.metrics = metrics;
+    }
+
+    /// Accept extended CONNECT requests (RFC 9220), e.g. for connect-udp. This is advertised
+    /// with `SETTINGS_ENABLE_CONNECT_PROTOCOL` on connections that are created afterwards;
+    /// other connections reset requests that carry a `:protocol` pseudo-header.
+    pub fn set_enable_connect_protocol(&mut self, enable: bool) {
+        self.enable_connect_protocol = enable;
+    }
+
+    /// Accept WebTransport sessions. This is advertised with `SETTINGS_ENABLE_WEBTRANSPORT`
+    /// on connections that are created afterwards; because sessions are extended CONNECT
+    /// requests, it enables extended CONNECT as well.
+    pub fn set_enable_webtransport(&mut self, enable: bool) {
+        self.enable_webtransport = enable;
+    }
+
+    /// Accept HTTP Datagrams (RFC 9297). This is advertised with `SETTINGS_H3_DATAGRAM` and
+    /// the `max_datagram_frame_size` transport parameter on connections that are created
+    /// afterwards. Datagrams are received in `Datagram` events and sent with
+    /// `ClientRequestStream::send_datagram`.
+    pub fn set_enable_h3_datagram(&mut self, enable: bool) {
+        self.enable_h3_datagram = enable;
+        self.server.set_max_datagram_frame_size(if enable {
+            LOCAL_MAX_DATAGRAM_FRAME_SIZE
+        } else {
+            0
+        });
+    }
+
+    /// Let the application read request bodies with `ClientRequestStream::read_data` after a
+    /// `DataReadable` event, instead of receiving them in `Data` events. Data that has not been
+    /// read yet is left in the transport, so the client is flow-controlled by the application.
+    pub fn set_manual_request_reads(&mut self, manual: bool) {
+        self.manual_request_reads = manual;
+    }
+
+    /// Send settings in addition to the ones managed by neqo-http3, e.g. for an extension, as
+    /// identifier and value pairs. They are sent on connections that are created afterwards.
+    /// # Errors
+    /// `InvalidInput` if an identifier is used twice or it is one of the settings managed by
+    /// neqo-http3 or a reserved one.
+    pub fn set_extension_settings(&mut self, settings: &[(u64, u64)]) -> Res<()> {
+        self.extension_settings = extension_settings(settings)?;
+        Ok(())
+    }
+
+    /// Advertise the origins this server is authoritative for in an ORIGIN frame (RFC 9412) on
+    /// connections that are created afterwards. Clients may then send requests for these
+    /// origins on the same connection if the certificate is valid for them. Origins have the
+    /// form `scheme://host[:port]`. No ORIGIN frame is sent if `origins` is empty.
+    /// # Errors
+    /// `InvalidInput` if an origin is malformed.
+    pub fn set_origins(&mut self, origins: &[impl AsRef<str>]) -> Res<()> {
+        self.origins = origins
+            .iter()
+            .map(|o| origin::normalize(o.as_ref()))
+            .collect::<Res<_>>()?;
+        Ok(())
+    }
+
+    /// Send frames and a stream of reserved types (RFC 9114, Section 9) on connections that are
+    /// created afterwards, so that clients that do not ignore unknown types fail early.
+    pub fn set_grease(&mut self, grease: bool) {
+        self.grease = grease;
+    }
+
+    /// Limit the size of request headers and trailers (RFC 9114, Section 4.2.2) on connections
+    /// that are created afterwards. The limit is sent to clients in
+    /// SETTINGS_MAX_FIELD_SECTION_SIZE. A request with a larger field section is discarded and
+    /// reset with `H3_EXCESSIVE_LOAD`. By default there is no limit.
+    pub fn set_max_field_section_size(&mut self, max: u64) {
+        self.max_field_section_size = Some(max);
+    }
+
+    /// The settings received from the client on `conn` that are not managed by neqo-http3, as
+    /// identifier and value pairs. This is empty until the client SETTINGS frame is received.
+    #[must_use]
+    pub fn peer_extension_settings(&self, conn: &ActiveConnectionRef) -> Vec<(u64, u64)> {
+        self.http3_handlers
+            .get(conn)
+            .map_or_else(Vec::new, |h| h.borrow().peer_extension_settings())
+    }
+
     pub fn set_ciphers(&mut self, ciphers: impl AsRef<[Cipher]>) {
         self.server.set_ciphers(ciphers);
     }
 
+    /// Start a graceful shutdown of all connections. A GOAWAY frame is sent on each connection;
+    /// requests that the client has already opened can be completed, newer requests are
+    /// rejected. Each connection reports a `StateChange` event with the `GoingAway` state.
+    pub fn goaway(&mut self) {
+        for handler in self.http3_handlers.values() {
+            handler.borrow_mut().goaway();
+        }
+    }
+
     pub fn process(&mut self, dgram: Option<Datagram>, now: Instant) -> Output {
         qtrace!([self], "Process.");
         let out = self.server.process(dgram, now);
@@ -120,11 +262,31 @@ impl Http3Server {
             .iter()
             .for_each(|conn| self.server.add_to_waiting(conn.clone()));
         let qpack_settings = self.qpack_settings;
+        let enable_connect_protocol = self.enable_connect_protocol;
+        let enable_webtransport = self.enable_webtransport;
+        let enable_h3_datagram = self.enable_h3_datagram;
+        let extension_settings = self.extension_settings.clone();
+        let grease = self.grease;
+        let max_field_section_size = self.max_field_section_size;
+        let origins = self.origins.clone();
+        let metrics = Rc::clone(&self.metrics);
         for mut conn in active_conns {
-            let handler = self
-                .http3_handlers
-                .entry(conn.clone())
-                .or_insert_with(|| Rc::new(RefCell::new(Http3ServerHandler::new(qpack_settings))));
+            let handler = self.http3_handlers.entry(conn.clone()).or_insert_with(|| {
+                let mut handler = Http3ServerHandler::new(
+                    qpack_settings,
+                    enable_connect_protocol,
+                    enable_webtransport,
+                    enable_h3_datagram,
+                );
+                handler.set_extension_settings(extension_settings.clone());
+                handler.set_grease(grease);
+                if let Some(max) = max_field_section_size {
+                    handler.set_max_field_section_size(max);
+                }
+                handler.set_origins(origins.clone());
+                handler.set_metrics(Rc::clone(&metrics));
+                Rc::new(RefCell::new(handler))
+            });
 
             handler
                 .borrow_mut()
@@ -138,12 +300,79 @@ impl Http3Server {
                             stream_id,
                             headers,
                             fin,
-                        } => self.events.headers(
-                            ClientRequestStream::new(conn.clone(), handler.clone(), stream_id),
-                            headers,
-                            fin,
-                        ),
+                        } => {
+                            if !handler_borrowed.enables_connect_protocol()
+                                && headers.iter().any(|(name, _)| name == ":protocol")
+                            {
+                                // Extended CONNECT has not been negotiated on this connection,
+                                // therefore the request is malformed.
+                                let error = Error::HttpMessageError.code();
+                                let _ = handler_borrowed.stream_reset(
+                                    &mut conn.borrow_mut(),
+                                    stream_id,
+                                    error,
+                                );
+                                self.events.reset(
+                                    ClientRequestStream::new(
+                                        conn.clone(),
+                                        handler.clone(),
+                                        stream_id,
+                                    ),
+                                    error,
+                                    true,
+                                );
+                                continue;
+                            }
+                            if handler_borrowed.enables_webtransport()
+                                && headers.iter().any(|(name, value)| {
+                                    name == ":protocol" && value == WEBTRANSPORT_PROTOCOL
+                                })
+                            {
+                                handler_borrowed.webtransport_new_session(stream_id);
+                                self.events
+                                    .webtransport(WebTransportServerEvent::NewSession {
+                                        session: WebTransportSession::new(
+                                            conn.clone(),
+                                            handler.clone(),
+                                            stream_id,
+                                        ),
+                                        headers,
+                                    });
+                                continue;
+                            }
+                            if headers
+                                .iter()
+                                .any(|(n, v)| n == ":method" && v == "CONNECT")
+                                && !headers.iter().any(|(n, _)| n == ":protocol")
+                            {
+                                handler_borrowed.add_connect_tunnel(stream_id);
+                            }
+                            if let Err(e) = handler_borrowed.set_request_priority(
+                                &mut conn.borrow_mut(),
+                                stream_id,
+                                &headers,
+                            ) {
+                                qwarn!("Cannot set the priority of stream {}: {:?}", stream_id, e);
+                            }
+                            self.events.headers(
+                                ClientRequestStream::new(conn.clone(), handler.clone(), stream_id),
+                                headers,
+                                fin,
+                            )
+                        }
                         Http3ServerConnEvent::DataReadable { stream_id } => {
+                            // The data of a WebTransport session are read by the handler.
+                            if handler_borrowed.is_webtransport_session(stream_id) {
+                                continue;
+                            }
+                            if self.manual_request_reads {
+                                self.events.data_readable(ClientRequestStream::new(
+                                    conn.clone(),
+                                    handler.clone(),
+                                    stream_id,
+                                ));
+                                continue;
+                            }
                             prepare_data(
                                 stream_id,
                                 &mut handler_borrowed,
@@ -153,6 +382,46 @@ impl Http3Server {
                                 &mut self.events,
                             );
                         }
+                        Http3ServerConnEvent::DataWritable { stream_id } => {
+                            self.events.data_writable(ClientRequestStream::new(
+                                conn.clone(),
+                                handler.clone(),
+                                stream_id,
+                            ))
+                        }
+                        Http3ServerConnEvent::Datagram {
+                            stream_id,
+                            datagram,
+                        } => self.events.datagram(
+                            ClientRequestStream::new(conn.clone(), handler.clone(), stream_id),
+                            datagram,
+                        ),
+                        Http3ServerConnEvent::ResponseComplete { stream_id } => {
+                            self.events.response_complete(ClientRequestStream::new(
+                                conn.clone(),
+                                handler.clone(),
+                                stream_id,
+                            ))
+                        }
+                        Http3ServerConnEvent::Reset {
+                            stream_id,
+                            error,
+                            local,
+                        } => self.events.reset(
+                            ClientRequestStream::new(conn.clone(), handler.clone(), stream_id),
+                            error,
+                            local,
+                        ),
+                        Http3ServerConnEvent::Trailers {
+                            stream_id,
+                            trailers,
+                        } => self.events.trailers(
+                            ClientRequestStream::new(conn.clone(), handler.clone(), stream_id),
+                            trailers,
+                        ),
+                        Http3ServerConnEvent::WebTransport(e) => {
+                            webtransport_event(e, &conn, &handler, &mut self.events)
+                        }
                         Http3ServerConnEvent::StateChange(state) => {
                             self.events
                                 .connection_state_change(conn.clone(), state.clone());
@@ -175,19 +444,42 @@ impl Http3Server {
         self.events.events()
     }
 
-    /// Return true if there are outstanding events.
+    /// Return true if there are outstanding events. The same as `Provider::has_events`.
     #[must_use]
     pub fn has_events(&self) -> bool {
+        EventProvider::has_events(self)
+    }
+
+    /// Get the next event. The same as `Provider::next_event`.
+    pub fn next_event(&mut self) -> Option<Http3ServerEvent> {
+        EventProvider::next_event(self)
+    }
+
+    /// Take all pending events and pass them to `handler`. This is an alternative to
+    /// `next_event`; call it after `process`.
+    pub fn dispatch_events(&mut self, handler: &mut impl RequestHandler) {
+        while let Some(event) = self.events.next_event() {
+            dispatch_event(handler, event);
+        }
+    }
+}
+
+impl EventProvider for Http3Server {
+    type Event = Http3ServerEvent;
+
+    /// Return true if there are outstanding events.
+    fn has_events(&self) -> bool {
         self.events.has_events()
     }
 
     /// Get events that indicate state changes on the connection. This method
     /// correctly handles cases where handling one event can obsolete
     /// previously-queued events, or cause new events to be generated.
-    pub fn next_event(&mut self) -> Option<Http3ServerEvent> {
+    fn next_event(&mut self) -> Option<Self::Event> {
         self.events.next_event()
     }
 }
+
 fn prepare_data(
     stream_id: u64,
     handler_borrowed: &mut RefMut<Http3ServerHandler>,
@@ -201,7 +493,9 @@ fn prepare_data(
         let res =
             handler_borrowed.read_request_data(&mut conn.borrow_mut(), now, stream_id, &mut data);
         if let Ok((amount, fin)) = res {
-            if amount > 0 {
+            // A fin without data is delivered too; otherwise the end of a request that comes
+            // after its last data, like the close of a CONNECT tunnel, would never be seen.
+            if amount > 0 || fin {
                 if amount < MAX_EVENT_DATA_SIZE {
                     data.resize(amount, 0);
                 }
@@ -215,16 +509,61 @@ fn prepare_data(
                 break;
             }
         } else {
-            // Any error will closed the handler, just ignore this event, the next event must
-            // be a state change event.
+            // An error either closes the connection or resets the request. Either way just
+            // ignore this event, the next event is a state change or a reset event.
             break;
         }
     }
 }
 
+fn webtransport_event(
+    event: WebTransportEvent,
+    conn: &ActiveConnectionRef,
+    handler: &HandlerRef,
+    events: &mut Http3ServerEvents,
+) {
+    let session = |session_id| WebTransportSession::new(conn.clone(), handler.clone(), session_id);
+    let e = match event {
+        // Responses are only received by clients.
+        WebTransportEvent::Session { .. } => return,
+        WebTransportEvent::SessionClosed {
+            session_id,
+            error,
+            message,
+        } => WebTransportServerEvent::SessionClosed {
+            session: session(session_id),
+            error,
+            message,
+        },
+        WebTransportEvent::NewStream {
+            stream_id,
+            session_id,
+        } => WebTransportServerEvent::NewStream {
+            session: session(session_id),
+            stream_id,
+        },
+        WebTransportEvent::DataReadable {
+            stream_id,
+            session_id,
+        } => WebTransportServerEvent::DataReadable {
+            session: session(session_id),
+            stream_id,
+        },
+        WebTransportEvent::Datagram {
+            session_id,
+            datagram,
+        } => WebTransportServerEvent::Datagram {
+            session: session(session_id),
+            datagram,
+        },
+    };
+    events.webtransport(e);
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Http3Server, Http3ServerEvent, Http3State, Rc, RefCell};
+    use crate::hframe::{HFrame, HFrameReader};
     use crate::{Error, Header};
     use neqo_common::event::Provider;
     use neqo_crypto::AuthenticationStatus;
@@ -518,24 +857,126 @@ mod tests {
         assert_closed(&mut hconn, &Error::HttpFrameUnexpected);
     }
 
-    // send DATA frame on a cortrol stream
+    // send DATA frame on a control stream
     #[test]
     fn test_server_data_frame_on_control_stream() {
         test_wrong_frame_on_control_stream(&[0x0, 0x2, 0x1, 0x2]);
     }
 
-    // send HEADERS frame on a cortrol stream
+    // send HEADERS frame on a control stream
     #[test]
     fn test_server_headers_frame_on_control_stream() {
         test_wrong_frame_on_control_stream(&[0x1, 0x2, 0x1, 0x2]);
     }
 
-    // send PUSH_PROMISE frame on a cortrol stream
+    // send PUSH_PROMISE frame on a control stream
     #[test]
     fn test_server_push_promise_frame_on_control_stream() {
         test_wrong_frame_on_control_stream(&[0x5, 0x2, 0x1, 0x2]);
     }
 
+    // Server: a CANCEL_PUSH frame for a push ID within MAX_PUSH_ID is accepted.
+    #[test]
+    fn test_server_cancel_push() {
+        let (mut hconn, mut peer_conn) = connect();
+        // MAX_PUSH_ID with push_id 5, then CANCEL_PUSH with push_id 3.
+        peer_conn.control_send(&[0xd, 0x1, 0x5, 0x3, 0x1, 0x3]);
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+        assert_not_closed(&mut hconn);
+    }
+
+    // Server: a CANCEL_PUSH frame before any MAX_PUSH_ID frame causes HTTP_ID_ERROR.
+    #[test]
+    fn test_server_cancel_push_without_max_push_id() {
+        let (mut hconn, mut peer_conn) = connect();
+        peer_conn.control_send(&[0x3, 0x1, 0x0]);
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+        assert_closed(&mut hconn, &Error::HttpId);
+    }
+
+    // Server: a CANCEL_PUSH frame for a push ID above MAX_PUSH_ID causes HTTP_ID_ERROR.
+    #[test]
+    fn test_server_cancel_push_exceeds_max_push_id() {
+        let (mut hconn, mut peer_conn) = connect();
+        peer_conn.control_send(&[0xd, 0x1, 0x5, 0x3, 0x1, 0x6]);
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+        assert_closed(&mut hconn, &Error::HttpId);
+    }
+
+    // Server: a MAX_PUSH_ID frame that reduces the maximum push ID causes HTTP_ID_ERROR.
+    #[test]
+    fn test_server_max_push_id_reduced() {
+        let (mut hconn, mut peer_conn) = connect();
+        peer_conn.control_send(&[0xd, 0x1, 0x5, 0xd, 0x1, 0x4]);
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+        assert_closed(&mut hconn, &Error::HttpId);
+    }
+
+    // Server: a GOAWAY frame from the client carries a push ID that must not increase.
+    #[test]
+    fn test_server_receive_goaway() {
+        let (mut hconn, mut peer_conn) = connect();
+        peer_conn.control_send(&[0x7, 0x1, 0x5, 0x7, 0x1, 0x3]);
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+        assert_not_closed(&mut hconn);
+    }
+
+    #[test]
+    fn test_server_receive_goaway_increased() {
+        let (mut hconn, mut peer_conn) = connect();
+        peer_conn.control_send(&[0x7, 0x1, 0x3, 0x7, 0x1, 0x5]);
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+        assert_closed(&mut hconn, &Error::HttpId);
+    }
+
+    // Server: a PRIORITY_UPDATE frame for a request that has not been opened yet is accepted.
+    #[test]
+    fn test_server_priority_update() {
+        let (mut hconn, mut peer_conn) = connect();
+        // PRIORITY_UPDATE for stream 0 with the field value "u=1".
+        peer_conn.control_send(&[0x80, 0x0f, 0x07, 0x00, 0x4, 0x0, 0x75, 0x3d, 0x31]);
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+        assert_not_closed(&mut hconn);
+    }
+
+    // Server: a PRIORITY_UPDATE frame for a stream that cannot be a request causes HTTP_ID_ERROR.
+    #[test]
+    fn test_server_priority_update_wrong_stream() {
+        let (mut hconn, mut peer_conn) = connect();
+        peer_conn.control_send(&[0x80, 0x0f, 0x07, 0x00, 0x1, 0x2]);
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+        assert_closed(&mut hconn, &Error::HttpId);
+    }
+
+    // Server: a PRIORITY_UPDATE frame for a request beyond the stream limit causes HTTP_ID_ERROR.
+    #[test]
+    fn test_server_priority_update_beyond_stream_limit() {
+        let (mut hconn, mut peer_conn) = connect();
+        // PRIORITY_UPDATE for stream 4000.
+        peer_conn.control_send(&[0x80, 0x0f, 0x07, 0x00, 0x2, 0x4f, 0xa0]);
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+        assert_closed(&mut hconn, &Error::HttpId);
+    }
+
+    // Server: a PRIORITY_UPDATE frame for a push that was not allowed causes HTTP_ID_ERROR.
+    #[test]
+    fn test_server_priority_update_push_without_max_push_id() {
+        let (mut hconn, mut peer_conn) = connect();
+        peer_conn.control_send(&[0x80, 0x0f, 0x07, 0x01, 0x1, 0x0]);
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+        assert_closed(&mut hconn, &Error::HttpId);
+    }
+
     // Server: receive unknown stream type
     // also test getting stream id that does not fit into a single byte.
     #[test]
@@ -692,6 +1133,8 @@ mod tests {
         0x0, 0x3, 0x64, 0x65, 0x66,
     ];
     const REQUEST_BODY: &[u8] = &[0x61, 0x62, 0x63, 0x64, 0x65, 0x66];
+    // A HEADERS frame with the trailers "age: 0".
+    const REQUEST_TRAILERS: &[u8] = &[0x01, 0x03, 0x00, 0x00, 0xc2];
 
     const RESPONSE_BODY: &[u8] = &[0x67, 0x68, 0x69];
 
@@ -768,6 +1211,325 @@ mod tests {
         assert_eq!(data_received, 1);
     }
 
+    // Server: interim responses are sent before the final response.
+    #[test]
+    fn test_server_interim_response() {
+        let (mut hconn, mut peer_conn) = connect();
+
+        let stream_id = peer_conn.stream_create(StreamType::BiDi).unwrap();
+        peer_conn.stream_send(stream_id, REQUEST_WITH_BODY).unwrap();
+        peer_conn.stream_close_send(stream_id).unwrap();
+
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+
+        let mut request = hconn
+            .events()
+            .find_map(|e| {
+                if let Http3ServerEvent::Headers { request, .. } = e {
+                    Some(request)
+                } else {
+                    None
+                }
+            })
+            .unwrap();
+
+        // Only 1xx status codes other than 101 are allowed.
+        for status in &["101", "200", "abc"] {
+            assert_eq!(
+                request.set_interim_response(&[(String::from(":status"), (*status).to_string())]),
+                Err(Error::InvalidInput)
+            );
+        }
+        request
+            .set_interim_response(&[
+                (String::from(":status"), String::from("103")),
+                (
+                    String::from("link"),
+                    String::from("</style.css>; rel=preload"),
+                ),
+            ])
+            .unwrap();
+        request
+            .set_response(
+                &[
+                    (String::from(":status"), String::from("200")),
+                    (String::from("content-length"), String::from("3")),
+                ],
+                RESPONSE_BODY,
+            )
+            .unwrap();
+
+        // Interim responses cannot follow the final response.
+        assert_eq!(
+            request.set_interim_response(&[(String::from(":status"), String::from("100"))]),
+            Err(Error::AlreadyInitialized)
+        );
+
+        // The response starts with two HEADERS frames, followed by a DATA frame and the fin.
+        let out = hconn.process(None, now());
+        let _ = peer_conn.process(out.dgram(), now());
+        let mut fr = HFrameReader::new();
+        let (frame, fin) = fr.receive(&mut peer_conn, stream_id).unwrap();
+        assert!(matches!(frame, Some(HFrame::Headers { .. })));
+        assert_eq!(fin, false);
+        let (frame, fin) = fr.receive(&mut peer_conn, stream_id).unwrap();
+        assert!(matches!(frame, Some(HFrame::Headers { .. })));
+        assert_eq!(fin, false);
+        let (frame, fin) = fr.receive(&mut peer_conn, stream_id).unwrap();
+        assert_eq!(frame, Some(HFrame::Data { len: 3 }));
+        assert_eq!(fin, false);
+        let mut buf = [0_u8; 3];
+        assert_eq!(peer_conn.stream_recv(stream_id, &mut buf), Ok((3, true)));
+        assert_eq!(&buf, RESPONSE_BODY);
+    }
+
+    // Server: with manual reads the application reads the request body in parts.
+    #[test]
+    fn test_server_request_with_body_manual_reads() {
+        let (mut hconn, mut peer_conn) = connect();
+        hconn.set_manual_request_reads(true);
+
+        let stream_id = peer_conn.stream_create(StreamType::BiDi).unwrap();
+        peer_conn.stream_send(stream_id, REQUEST_WITH_BODY).unwrap();
+        peer_conn.stream_close_send(stream_id).unwrap();
+
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+
+        let mut headers_frames = 0;
+        let mut request = None;
+        while let Some(event) = hconn.next_event() {
+            match event {
+                Http3ServerEvent::Headers { headers, fin, .. } => {
+                    check_request_header(&headers);
+                    assert_eq!(fin, false);
+                    headers_frames += 1;
+                }
+                Http3ServerEvent::DataReadable { request: r } => {
+                    assert!(request.is_none());
+                    request = Some(r);
+                }
+                Http3ServerEvent::Data { .. } => {
+                    panic!("We should not have a Data event");
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(headers_frames, 1);
+        let mut request = request.unwrap();
+
+        let mut buf = [0_u8; 4];
+        assert_eq!(request.read_data(now(), &mut buf), Ok((4, false)));
+        assert_eq!(&buf, &REQUEST_BODY[..4]);
+        assert_eq!(request.read_data(now(), &mut buf), Ok((2, true)));
+        assert_eq!(&buf[..2], &REQUEST_BODY[4..]);
+
+        // The request body has been read completely.
+        assert_eq!(
+            request.read_data(now(), &mut buf),
+            Err(Error::InvalidStreamId)
+        );
+        assert_not_closed(&mut hconn);
+    }
+
+    // A malformed request is reset with H3_MESSAGE_ERROR, the connection stays open.
+    fn test_malformed_request(request: &[u8]) {
+        let (mut hconn, mut peer_conn) = connect();
+        let stream_id = peer_conn.stream_create(StreamType::BiDi).unwrap();
+        peer_conn.stream_send(stream_id, request).unwrap();
+        peer_conn.stream_close_send(stream_id).unwrap();
+        let out = peer_conn.process(None, now());
+        let out = hconn.process(out.dgram(), now());
+        let _ = peer_conn.process(out.dgram(), now());
+
+        let events: Vec<_> = hconn.events().collect();
+        assert!(events.iter().any(|e| matches!(e,
+            Http3ServerEvent::Reset { error, local: true, .. }
+              if *error == Error::HttpMessageError.code())));
+        assert!(!events.iter().any(|e| matches!(
+            e,
+            Http3ServerEvent::Headers { .. }
+                | Http3ServerEvent::StateChange {
+                    state: Http3State::Closing(..),
+                    ..
+                }
+        )));
+
+        let stop_sending = |e| {
+            matches!(e, ConnectionEvent::SendStreamStopSending { stream_id: id, app_error }
+              if id == stream_id && app_error == Error::HttpMessageError.code())
+        };
+        assert!(peer_conn.events().any(stop_sending));
+    }
+
+    #[test]
+    fn test_server_request_uppercase_header() {
+        // The request headers of REQUEST_WITH_BODY followed by "A: b".
+        let mut request = vec![0x01, 0x14];
+        request.extend_from_slice(&REQUEST_WITH_BODY[2..18]);
+        request.extend_from_slice(&[0x21, 0x41, 0x01, 0x62]);
+        test_malformed_request(&request);
+    }
+
+    #[test]
+    fn test_server_request_body_longer_than_content_length() {
+        // The request headers of REQUEST_WITH_BODY followed by "content-length: 2", and a
+        // body of 6 bytes.
+        let mut request = vec![0x01, 0x13];
+        request.extend_from_slice(&REQUEST_WITH_BODY[2..18]);
+        request.extend_from_slice(&[0x54, 0x01, 0x32]);
+        request.extend_from_slice(&REQUEST_WITH_BODY[18..]);
+        test_malformed_request(&request);
+    }
+
+    #[test]
+    fn test_server_request_pseudo_header_trailers() {
+        // The request headers are sent again as trailers.
+        let mut request = REQUEST_WITH_BODY.to_vec();
+        request.extend_from_slice(&REQUEST_WITH_BODY[..18]);
+        test_malformed_request(&request);
+    }
+
+    #[test]
+    fn test_server_request_with_trailers() {
+        let (mut hconn, mut peer_conn) = connect();
+
+        // Send a request with a body, followed by trailers.
+        let stream_id = peer_conn.stream_create(StreamType::BiDi).unwrap();
+        peer_conn.stream_send(stream_id, REQUEST_WITH_BODY).unwrap();
+        peer_conn.stream_send(stream_id, REQUEST_TRAILERS).unwrap();
+        peer_conn.stream_close_send(stream_id).unwrap();
+
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+
+        let mut data_received = 0;
+        let mut trailers_received = 0;
+        while let Some(event) = hconn.next_event() {
+            match event {
+                Http3ServerEvent::Data { data, fin, .. } => {
+                    assert_eq!(data, REQUEST_BODY);
+                    assert_eq!(fin, true);
+                    data_received += 1;
+                }
+                Http3ServerEvent::Trailers {
+                    mut request,
+                    trailers,
+                } => {
+                    assert_eq!(trailers, &[(String::from("age"), String::from("0"))]);
+                    request
+                        .set_response_with_trailers(
+                            &[
+                                (String::from(":status"), String::from("200")),
+                                (String::from("content-length"), String::from("3")),
+                            ],
+                            RESPONSE_BODY,
+                            &[(String::from("my-trailer"), String::from("value"))],
+                        )
+                        .unwrap();
+                    trailers_received += 1;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(data_received, 1);
+        assert_eq!(trailers_received, 1);
+
+        // The response is a HEADERS frame, a DATA frame and a HEADERS frame carrying the
+        // trailers, followed by the fin.
+        let out = hconn.process(None, now());
+        let _ = peer_conn.process(out.dgram(), now());
+        let mut fr = HFrameReader::new();
+        let (frame, fin) = fr.receive(&mut peer_conn, stream_id).unwrap();
+        assert!(matches!(frame, Some(HFrame::Headers { .. })));
+        assert_eq!(fin, false);
+        let (frame, fin) = fr.receive(&mut peer_conn, stream_id).unwrap();
+        assert_eq!(frame, Some(HFrame::Data { len: 3 }));
+        assert_eq!(fin, false);
+        let mut buf = [0_u8; 3];
+        assert_eq!(peer_conn.stream_recv(stream_id, &mut buf), Ok((3, false)));
+        assert_eq!(&buf, RESPONSE_BODY);
+        let (frame, fin) = fr.receive(&mut peer_conn, stream_id).unwrap();
+        assert!(matches!(frame, Some(HFrame::Headers { .. })));
+        assert_eq!(fin, true);
+    }
+
+    // Server: after a GOAWAY frame has been sent, requests that have already been opened can
+    // be answered and new requests are rejected.
+    #[test]
+    fn test_server_goaway() {
+        let (mut hconn, mut peer_conn) = connect();
+
+        let stream_id = peer_conn.stream_create(StreamType::BiDi).unwrap();
+        peer_conn.stream_send(stream_id, REQUEST_WITH_BODY).unwrap();
+        peer_conn.stream_close_send(stream_id).unwrap();
+        let out = peer_conn.process(None, now());
+        hconn.process(out.dgram(), now());
+
+        let mut request = None;
+        while let Some(event) = hconn.next_event() {
+            if let Http3ServerEvent::Headers { request: r, .. } = event {
+                request = Some(r);
+            }
+        }
+        let mut request = request.expect("the request should be received");
+
+        hconn.goaway();
+        let going_away = |e| {
+            matches!(
+                e,
+                Http3ServerEvent::StateChange {
+                    state: Http3State::GoingAway(4),
+                    ..
+                }
+            )
+        };
+        assert!(hconn.events().any(going_away));
+        request
+            .set_response(
+                &[(String::from(":status"), String::from("200"))],
+                RESPONSE_BODY,
+            )
+            .unwrap();
+        let out = hconn.process(None, now());
+        let _ = peer_conn.process(out.dgram(), now());
+
+        let mut goaway_received = false;
+        let mut response_received = false;
+        while let Some(e) = peer_conn.next_event() {
+            if let ConnectionEvent::RecvStreamReadable { stream_id: id } = e {
+                let mut buf = [0_u8; 100];
+                let (amount, _) = peer_conn.stream_recv(id, &mut buf).unwrap();
+                if id == SERVER_SIDE_CONTROL_STREAM_ID {
+                    assert_eq!(&buf[..amount], &[0x7, 0x1, 0x4]);
+                    goaway_received = true;
+                } else if id == stream_id {
+                    response_received = true;
+                }
+            }
+        }
+        assert!(goaway_received);
+        assert!(response_received);
+
+        // A new request is rejected.
+        let stream_id = peer_conn.stream_create(StreamType::BiDi).unwrap();
+        peer_conn.stream_send(stream_id, REQUEST_WITH_BODY).unwrap();
+        let out = peer_conn.process(None, now());
+        let out = hconn.process(out.dgram(), now());
+        let _ = peer_conn.process(out.dgram(), now());
+        let out = hconn.process(None, now());
+        let _ = peer_conn.process(out.dgram(), now());
+        assert!(!hconn
+            .events()
+            .any(|e| matches!(e, Http3ServerEvent::Headers { .. })));
+        let rejected = |e| {
+            matches!(e, ConnectionEvent::RecvStreamReset { stream_id: id, app_error }
+                if id == stream_id && app_error == Error::HttpRequestRejected.code())
+        };
+        assert!(peer_conn.events().any(rejected));
+    }
+
     #[test]
     fn test_server_request_with_body_send_stop_sending() {
         let (mut hconn, mut peer_conn) = connect();
@@ -6,6 +6,7 @@
 
 use crate::connection::Http3State;
 use crate::send_message::SendMessageEvents;
+use crate::webtransport::{WebTransportEvent, WebTransportEvents};
 use crate::Header;
 use crate::RecvMessageEvents;
 
@@ -25,11 +26,27 @@ pub(crate) enum Http3ServerConnEvent {
     },
     /// Request data is ready.
     DataReadable { stream_id: u64 },
-    //TODO: This is never used. Do we need it?
-    // Peer reset the stream.
-    //Reset { stream_id: u64, error: AppError },
+    /// More response data can be sent.
+    DataWritable { stream_id: u64 },
+    /// Trailers are ready.
+    Trailers {
+        stream_id: u64,
+        trailers: Vec<Header>,
+    },
+    /// An HTTP Datagram has been received for a request.
+    Datagram { stream_id: u64, datagram: Vec<u8> },
+    /// The client has acknowledged the whole response.
+    ResponseComplete { stream_id: u64 },
+    /// The stream has been reset by the peer or because of a parsing error.
+    Reset {
+        stream_id: u64,
+        error: AppError,
+        local: bool,
+    },
     /// Connection state change.
     StateChange(Http3State),
+    /// An event of a WebTransport session.
+    WebTransport(WebTransportEvent),
 }
 
 #[derive(Debug, Default, Clone)]
@@ -52,19 +69,47 @@ impl RecvMessageEvents for Http3ServerConnEvents {
         self.insert(Http3ServerConnEvent::DataReadable { stream_id });
     }
 
-    fn reset(&self, _stream_id: u64, _error: AppError, _local: bool) {}
+    /// Add a new `Trailers` event.
+    fn trailers_ready(&self, stream_id: u64, trailers: Vec<Header>) {
+        self.insert(Http3ServerConnEvent::Trailers {
+            stream_id,
+            trailers,
+        });
+    }
+
+    /// Add a new `Reset` event.
+    fn reset(&self, stream_id: u64, error: AppError, local: bool) {
+        self.remove_events_for_stream_id(stream_id);
+        self.insert(Http3ServerConnEvent::Reset {
+            stream_id,
+            error,
+            local,
+        });
+    }
 }
 
 impl SendMessageEvents for Http3ServerConnEvents {
-    fn data_writable(&self, _stream_id: u64) {
-        // Curently not used on the server side.
+    /// Add a new `DataWritable` event.
+    fn data_writable(&self, stream_id: u64) {
+        self.insert(Http3ServerConnEvent::DataWritable { stream_id });
     }
 
-    fn remove_send_side_event(&self, _stream_id: u64) {}
+    fn remove_send_side_event(&self, stream_id: u64) {
+        self.remove(|evt| {
+            matches!(evt, Http3ServerConnEvent::DataWritable { stream_id: x } if *x == stream_id)
+        });
+    }
 
     fn stop_sending(&self, _stream_id: u64, _app_err: AppError) {}
 }
 
+impl WebTransportEvents for Http3ServerConnEvents {
+    /// Add a new `WebTransport` event.
+    fn webtransport_event(&self, event: WebTransportEvent) {
+        self.insert(Http3ServerConnEvent::WebTransport(event));
+    }
+}
+
 impl Http3ServerConnEvents {
     fn insert(&self, event: Http3ServerConnEvent) {
         self.events.borrow_mut().push_back(event);
@@ -85,6 +130,17 @@ impl Http3ServerConnEvents {
         self.events.borrow_mut().pop_front()
     }
 
+    pub fn datagram(&self, stream_id: u64, datagram: Vec<u8>) {
+        self.insert(Http3ServerConnEvent::Datagram {
+            stream_id,
+            datagram,
+        });
+    }
+
+    pub fn response_complete(&self, stream_id: u64) {
+        self.insert(Http3ServerConnEvent::ResponseComplete { stream_id });
+    }
+
     pub fn connection_state_change(&self, state: Http3State) {
         self.insert(Http3ServerConnEvent::StateChange(state));
     }
@@ -92,7 +148,13 @@ impl Http3ServerConnEvents {
     pub fn remove_events_for_stream_id(&self, stream_id: u64) {
         self.remove(|evt| {
             matches!(evt,
-                Http3ServerConnEvent::Headers { stream_id: x, .. } | Http3ServerConnEvent::DataReadable { stream_id: x, .. } if *x == stream_id)
+                Http3ServerConnEvent::Headers { stream_id: x, .. }
+                | Http3ServerConnEvent::DataReadable { stream_id: x, .. }
+                | Http3ServerConnEvent::DataWritable { stream_id: x }
+                | Http3ServerConnEvent::Trailers { stream_id: x, .. }
+                | Http3ServerConnEvent::Datagram { stream_id: x, .. }
+                | Http3ServerConnEvent::ResponseComplete { stream_id: x }
+                | Http3ServerConnEvent::Reset { stream_id: x, .. } if *x == stream_id)
         });
     }
 }
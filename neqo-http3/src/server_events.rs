@@ -11,11 +11,13 @@ use crate::connection_server::Http3ServerHandler;
 use crate::{Header, Res};
 use neqo_common::{qdebug, qinfo};
 use neqo_transport::server::ActiveConnectionRef;
-use neqo_transport::{AppError, Connection};
+use neqo_transport::{AppError, Connection, StreamType};
 
 use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
 use std::rc::Rc;
+use std::time::Instant;
 
 #[derive(Debug, Clone)]
 pub struct ClientRequestStream {
@@ -35,6 +37,22 @@ impl ::std::fmt::Display for ClientRequestStream {
     }
 }
 
+/// Two values are the same request if they are for the same stream of the same connection.
+impl PartialEq for ClientRequestStream {
+    fn eq(&self, other: &Self) -> bool {
+        self.conn == other.conn && self.stream_id == other.stream_id
+    }
+}
+
+impl Eq for ClientRequestStream {}
+
+impl Hash for ClientRequestStream {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.conn.hash(state);
+        self.stream_id.hash(state);
+    }
+}
+
 impl ClientRequestStream {
     pub(crate) fn new(
         conn: ActiveConnectionRef,
@@ -48,12 +66,64 @@ impl ClientRequestStream {
         }
     }
 
+    /// The ID of the request stream.
+    #[must_use]
+    pub fn stream_id(&self) -> u64 {
+        self.stream_id
+    }
+
     /// Supply a response to a request.
+    /// # Errors
+    /// `FieldSectionTooLarge` if the headers exceed the limit the client has advertised.
     pub fn set_response(&mut self, headers: &[Header], data: &[u8]) -> Res<()> {
         qinfo!([self], "Set new response.");
         self.handler
             .borrow_mut()
-            .set_response(self.stream_id, headers, data)
+            .set_response(self.stream_id, headers, data, None)
+    }
+
+    /// Send an interim (1xx) response, e.g. 103 Early Hints, before the final response. This
+    /// may be called several times, but not after the final response has been supplied.
+    /// # Errors
+    /// `InvalidInput` if `headers` do not carry a 1xx status other than 101,
+    /// `AlreadyInitialized` if the final response has already been supplied,
+    /// `FieldSectionTooLarge` if the headers exceed the limit the client has advertised.
+    pub fn set_interim_response(&mut self, headers: &[Header]) -> Res<()> {
+        qinfo!([self], "Set interim response.");
+        self.handler
+            .borrow_mut()
+            .set_interim_response(self.stream_id, headers)
+    }
+
+    /// Supply a response to a request, followed by trailers.
+    pub fn set_response_with_trailers(
+        &mut self,
+        headers: &[Header],
+        data: &[u8],
+        trailers: &[Header],
+    ) -> Res<()> {
+        qinfo!([self], "Set new response with trailers.");
+        self.handler
+            .borrow_mut()
+            .set_response(self.stream_id, headers, data, Some(trailers))
+    }
+
+    /// Read request data into `buf`. This is used if the server has been configured with
+    /// `set_manual_request_reads`; a `DataReadable` event is posted when data is available.
+    /// Data that is not read stays in the transport and is not acknowledged with flow control
+    /// credit, which limits how much more the client can send. Returns the number of bytes
+    /// read and whether the request body is complete.
+    /// # Errors
+    /// `InvalidStreamId` if the request body has already been read completely; other errors
+    /// happen if the request is malformed and close the connection.
+    pub fn read_data(&mut self, now: Instant, buf: &mut [u8]) -> Res<(usize, bool)> {
+        qdebug!([self], "Read data.");
+        self.handler.borrow_mut().read_request_data(
+            &mut self.conn.borrow_mut(),
+            now,
+            self.stream_id,
+            buf,
+        )
     }
 
     /// Request a peer to stop sending a request.
@@ -70,6 +140,87 @@ impl ClientRequestStream {
         Ok(())
     }
 
+    /// Supply response headers to a request and keep the stream open. The response body is
+    /// then sent with `send_data`. This is used to accept a CONNECT request, after which the
+    /// stream is a tunnel: the request data are the bytes sent by the client and `send_data`
+    /// sends bytes to the client. If the connection to the target fails, the stream should be
+    /// reset with `Error::HttpConnect.code()`; if the client resets either direction of the
+    /// tunnel, the other direction is reset with `H3_CONNECT_ERROR` and a `Reset` event is
+    /// posted.
+    pub fn set_response_headers(&mut self, headers: &[Header]) -> Res<()> {
+        qinfo!([self], "Set new response headers.");
+        self.handler
+            .borrow_mut()
+            .set_response_headers(self.stream_id, headers)
+    }
+
+    /// Send response data, see `set_response_headers`. Returns the number of bytes that have
+    /// been accepted, which may be less than the size of `buf` if flow control does not allow
+    /// sending more. A `DataWritable` event is posted when more data can be sent.
+    pub fn send_data(&mut self, buf: &[u8]) -> Res<usize> {
+        qdebug!([self], "Send {} bytes of data.", buf.len());
+        self.handler
+            .borrow_mut()
+            .send_data(&mut self.conn.borrow_mut(), self.stream_id, buf)
+    }
+
+    /// Send trailers after the response data sent with `send_data`. This closes the sending
+    /// side of the response, i.e. `stream_close_send` does not need to be called.
+    /// # Errors
+    /// `InvalidStreamId` if the request is not open anymore,
+    /// `InvalidState` if the response headers have not been sent yet (wait for `DataWritable`),
+    /// `AlreadyClosed` if the response has already been closed.
+    pub fn send_trailers(&mut self, trailers: &[Header]) -> Res<()> {
+        qinfo!([self], "Send response trailers.");
+        self.handler
+            .borrow_mut()
+            .send_response_trailers(self.stream_id, trailers)
+    }
+
+    /// Send a UDP payload on a connect-udp session that has been accepted with
+    /// `set_response_headers`. It is sent in an HTTP Datagram if they have been negotiated and
+    /// in a DATAGRAM capsule otherwise. The payload is dropped if it cannot be sent at once;
+    /// the return value tells whether it has been sent.
+    pub fn send_udp_payload(&mut self, payload: &[u8]) -> Res<bool> {
+        qdebug!([self], "Send UDP payload of {} bytes.", payload.len());
+        self.handler.borrow_mut().send_udp_payload(
+            &mut self.conn.borrow_mut(),
+            self.stream_id,
+            payload,
+        )
+    }
+
+    /// Send an HTTP Datagram (RFC 9297) that belongs to this request. HTTP Datagrams are used
+    /// if they are enabled with `Http3Server::set_enable_h3_datagram` and by the client. An
+    /// HTTP Datagram is not retransmitted if it is lost.
+    /// # Errors
+    /// `Unavailable` if HTTP Datagrams have not been negotiated,
+    /// `InvalidStreamId` if the request is not open anymore,
+    /// `InvalidInput` if `buf` is larger than `max_datagram_size`.
+    pub fn send_datagram(&mut self, buf: &[u8]) -> Res<()> {
+        qdebug!([self], "Send a datagram of {} bytes.", buf.len());
+        self.handler
+            .borrow_mut()
+            .send_datagram(&mut self.conn.borrow_mut(), self.stream_id, buf)
+    }
+
+    /// The largest HTTP Datagram that can be sent with `send_datagram`.
+    /// # Errors
+    /// The same as `send_datagram`.
+    pub fn max_datagram_size(&self) -> Res<u64> {
+        self.handler
+            .borrow()
+            .max_datagram_size(&self.conn.borrow(), self.stream_id)
+    }
+
+    /// Close the sending side of a response, i.e. close a tunnel in the direction of the client.
+    pub fn stream_close_send(&mut self) -> Res<()> {
+        qdebug!([self], "Close sending side.");
+        self.handler
+            .borrow_mut()
+            .stream_close_send(&mut self.conn.borrow_mut(), self.stream_id)
+    }
+
     /// Reset a stream/request.
     pub fn stream_reset(&mut self, app_error: AppError) -> Res<()> {
         qdebug!([self], "reset error:{}.", app_error);
@@ -81,6 +232,153 @@ impl ClientRequestStream {
     }
 }
 
+/// A WebTransport session on the server. The session ID is the stream ID of the CONNECT request
+/// that has opened it.
+#[derive(Debug, Clone)]
+pub struct WebTransportSession {
+    conn: ActiveConnectionRef,
+    handler: Rc<RefCell<Http3ServerHandler>>,
+    session_id: u64,
+}
+
+impl ::std::fmt::Display for WebTransportSession {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let conn: &Connection = &self.conn.borrow();
+        write!(
+            f,
+            "WebTransport session conn={:?} session_id={}",
+            conn, self.session_id
+        )
+    }
+}
+
+impl WebTransportSession {
+    pub(crate) fn new(
+        conn: ActiveConnectionRef,
+        handler: Rc<RefCell<Http3ServerHandler>>,
+        session_id: u64,
+    ) -> Self {
+        Self {
+            conn,
+            handler,
+            session_id,
+        }
+    }
+
+    #[must_use]
+    pub fn session_id(&self) -> u64 {
+        self.session_id
+    }
+
+    /// Accept a new session with a 200 response or reject it with a 404 response.
+    pub fn response(&mut self, accept: bool) -> Res<()> {
+        qinfo!([self], "Set session response accept={}.", accept);
+        self.handler.borrow_mut().webtransport_session_response(
+            &mut self.conn.borrow_mut(),
+            self.session_id,
+            accept,
+        )
+    }
+
+    /// Close the session. `error` and `message` are sent to the client if flow control allows
+    /// it. The streams of the session are reset.
+    pub fn close_session(&mut self, error: u32, message: &str) -> Res<()> {
+        qinfo!([self], "Close session error={}.", error);
+        self.handler.borrow_mut().webtransport_close_session(
+            &mut self.conn.borrow_mut(),
+            self.session_id,
+            error,
+            message,
+        )
+    }
+
+    /// Open a stream of the session.
+    pub fn create_stream(&mut self, stream_type: StreamType) -> Res<u64> {
+        qdebug!([self], "Create a stream.");
+        self.handler.borrow_mut().webtransport_create_stream(
+            &mut self.conn.borrow_mut(),
+            self.session_id,
+            stream_type,
+        )
+    }
+
+    /// Send a datagram. The datagram is dropped if it cannot be sent at once; the return value
+    /// tells whether it has been sent.
+    pub fn send_datagram(&mut self, datagram: &[u8]) -> Res<bool> {
+        qdebug!([self], "Send datagram of {} bytes.", datagram.len());
+        self.handler.borrow_mut().webtransport_send_datagram(
+            &mut self.conn.borrow_mut(),
+            self.session_id,
+            datagram,
+        )
+    }
+
+    /// Send data on a stream of the session.
+    pub fn stream_send(&mut self, stream_id: u64, buf: &[u8]) -> Res<usize> {
+        self.handler.borrow_mut().webtransport_stream_send(
+            &mut self.conn.borrow_mut(),
+            stream_id,
+            buf,
+        )
+    }
+
+    /// Read data from a stream of the session.
+    pub fn stream_recv(&mut self, stream_id: u64, buf: &mut [u8]) -> Res<(usize, bool)> {
+        self.handler.borrow_mut().webtransport_stream_recv(
+            &mut self.conn.borrow_mut(),
+            stream_id,
+            buf,
+        )
+    }
+
+    /// Close the sending side of a stream of the session.
+    pub fn stream_close_send(&mut self, stream_id: u64) -> Res<()> {
+        self.handler
+            .borrow_mut()
+            .webtransport_stream_close_send(&mut self.conn.borrow_mut(), stream_id)
+    }
+
+    /// Reset both sides of a stream of the session.
+    pub fn stream_reset(&mut self, stream_id: u64, error: AppError) -> Res<()> {
+        self.handler.borrow_mut().webtransport_stream_reset(
+            &mut self.conn.borrow_mut(),
+            stream_id,
+            error,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum WebTransportServerEvent {
+    /// A client has requested a new session. It is accepted or rejected with `response`.
+    NewSession {
+        session: WebTransportSession,
+        headers: Vec<Header>,
+    },
+    /// The client has closed the session. The error code and the message are taken from its
+    /// CLOSE_WEBTRANSPORT_SESSION capsule, if one has been received.
+    SessionClosed {
+        session: WebTransportSession,
+        error: Option<u32>,
+        message: String,
+    },
+    /// The client has opened a stream of the session. The stream may already have data to read.
+    NewStream {
+        session: WebTransportSession,
+        stream_id: u64,
+    },
+    /// New bytes are available on a stream of the session.
+    DataReadable {
+        session: WebTransportSession,
+        stream_id: u64,
+    },
+    /// A datagram has been received on the session.
+    Datagram {
+        session: WebTransportSession,
+        datagram: Vec<u8>,
+    },
+}
+
 #[derive(Debug, Clone)]
 pub enum Http3ServerEvent {
     /// Headers are ready.
@@ -89,12 +387,41 @@ pub enum Http3ServerEvent {
         headers: Vec<Header>,
         fin: bool,
     },
-    /// Request data is ready.
+    /// Request data is ready. This event is not used if the server has been configured with
+    /// `set_manual_request_reads`. `data` is empty if only the end of the request has been
+    /// received, so that the end is seen even if it arrives after the last data, e.g. when the
+    /// client closes a CONNECT tunnel.
     Data {
         request: ClientRequestStream,
         data: Vec<u8>,
         fin: bool,
     },
+    /// New request data is available and can be read with `ClientRequestStream::read_data`.
+    /// This is only used if the server has been configured with `set_manual_request_reads`.
+    DataReadable { request: ClientRequestStream },
+    /// Request trailers are ready.
+    Trailers {
+        request: ClientRequestStream,
+        trailers: Vec<Header>,
+    },
+    /// More response data can be sent with `send_data`.
+    DataWritable { request: ClientRequestStream },
+    /// An HTTP Datagram has been received for the request.
+    Datagram {
+        request: ClientRequestStream,
+        datagram: Vec<u8>,
+    },
+    /// The client has acknowledged the whole response; the request is done.
+    ResponseComplete { request: ClientRequestStream },
+    /// The request has been reset by the client or because of an error. For a CONNECT request
+    /// this means that the tunnel has failed.
+    Reset {
+        request: ClientRequestStream,
+        error: AppError,
+        local: bool,
+    },
+    /// An event of a WebTransport session.
+    WebTransport(WebTransportServerEvent),
     /// When individual connection change state. It is only used for tests.
     StateChange {
         conn: ActiveConnectionRef,
@@ -145,4 +472,43 @@ impl Http3ServerEvents {
     pub(crate) fn data(&self, request: ClientRequestStream, data: Vec<u8>, fin: bool) {
         self.insert(Http3ServerEvent::Data { request, data, fin });
     }
+
+    /// Insert a `DataReadable` event.
+    pub(crate) fn data_readable(&self, request: ClientRequestStream) {
+        self.insert(Http3ServerEvent::DataReadable { request });
+    }
+
+    /// Insert a `DataWritable` event.
+    pub(crate) fn data_writable(&self, request: ClientRequestStream) {
+        self.insert(Http3ServerEvent::DataWritable { request });
+    }
+
+    /// Insert a `Datagram` event.
+    pub(crate) fn datagram(&self, request: ClientRequestStream, datagram: Vec<u8>) {
+        self.insert(Http3ServerEvent::Datagram { request, datagram });
+    }
+
+    /// Insert a `ResponseComplete` event.
+    pub(crate) fn response_complete(&self, request: ClientRequestStream) {
+        self.insert(Http3ServerEvent::ResponseComplete { request });
+    }
+
+    /// Insert a `Reset` event.
+    pub(crate) fn reset(&self, request: ClientRequestStream, error: AppError, local: bool) {
+        self.insert(Http3ServerEvent::Reset {
+            request,
+            error,
+            local,
+        });
+    }
+
+    /// Insert a `WebTransport` event.
+    pub(crate) fn webtransport(&self, event: WebTransportServerEvent) {
+        self.insert(Http3ServerEvent::WebTransport(event));
+    }
+
+    /// Insert a `Trailers` event.
+    pub(crate) fn trailers(&self, request: ClientRequestStream, trailers: Vec<Header>) {
+        self.insert(Http3ServerEvent::Trailers { request, trailers });
+    }
 }
@@ -21,21 +21,74 @@ const SETTINGS_ZERO_RTT_VERSION: u64 = 1;
 const SETTINGS_MAX_HEADER_LIST_SIZE: SettingsType = 0x6;
 const SETTINGS_QPACK_MAX_TABLE_CAPACITY: SettingsType = 0x1;
 const SETTINGS_QPACK_BLOCKED_STREAMS: SettingsType = 0x7;
+const SETTINGS_ENABLE_CONNECT_PROTOCOL: SettingsType = 0x8;
+const SETTINGS_ENABLE_WEBTRANSPORT: SettingsType = 0x2b60_3742;
+const SETTINGS_H3_DATAGRAM: SettingsType = 0x33;
 
 pub const H3_RESERVED_SETTINGS: &[SettingsType] = &[0x2, 0x3, 0x4, 0x5];
 
+/// The number of unknown settings that are kept for extensions. A peer can send any number
+/// of settings that we do not understand; the ones above this limit are ignored.
+pub const MAX_EXTENSION_SETTINGS: usize = 16;
+
 #[derive(Clone, PartialEq, Debug, Copy)]
 pub enum HSettingType {
     MaxHeaderListSize,
     MaxTableCapacity,
     BlockedStreams,
+    EnableConnectProtocol,
+    EnableWebTransport,
+    EnableH3Datagram,
+    /// A setting that is not managed by neqo-http3, e.g. one of an extension, with its
+    /// identifier.
+    Extension(u64),
 }
 
 fn hsetting_default(setting_type: HSettingType) -> u64 {
     match setting_type {
         HSettingType::MaxHeaderListSize => 1 << 62,
-        HSettingType::MaxTableCapacity | HSettingType::BlockedStreams => 0,
+        HSettingType::MaxTableCapacity
+        | HSettingType::BlockedStreams
+        | HSettingType::EnableConnectProtocol
+        | HSettingType::EnableWebTransport
+        | HSettingType::EnableH3Datagram
+        | HSettingType::Extension(_) => 0,
+    }
+}
+
+// Reserved identifiers of the form 0x1f * N + 0x21 are used for greasing and must be ignored.
+fn is_grease(setting_type: SettingsType) -> bool {
+    setting_type >= 0x21 && (setting_type - 0x21) % 0x1f == 0
+}
+
+/// Check settings that an application wants to send in addition to the settings managed by
+/// neqo-http3. An identifier must be used only once and must not be one of the managed or
+/// reserved settings.
+/// # Errors
+/// `InvalidInput` if a setting cannot be sent.
+pub(crate) fn extension_settings(settings: &[(SettingsType, u64)]) -> Res<Vec<HSetting>> {
+    let mut ext: Vec<HSetting> = Vec::new();
+    for (id, value) in settings {
+        if H3_RESERVED_SETTINGS.contains(id)
+            || is_grease(*id)
+            || [
+                SETTINGS_MAX_HEADER_LIST_SIZE,
+                SETTINGS_QPACK_MAX_TABLE_CAPACITY,
+                SETTINGS_QPACK_BLOCKED_STREAMS,
+                SETTINGS_ENABLE_CONNECT_PROTOCOL,
+                SETTINGS_ENABLE_WEBTRANSPORT,
+                SETTINGS_H3_DATAGRAM,
+            ]
+            .contains(id)
+            || ext
+                .iter()
+                .any(|s| s.setting_type == HSettingType::Extension(*id))
+        {
+            return Err(Error::InvalidInput);
+        }
+        ext.push(HSetting::new(HSettingType::Extension(*id), *value));
     }
+    Ok(ext)
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -72,6 +125,24 @@ impl HSettings {
         }
     }
 
+    /// The settings that are not managed by neqo-http3, as identifier and value pairs.
+    pub fn extensions(&self) -> Vec<(u64, u64)> {
+        self.settings
+            .iter()
+            .filter_map(|s| match s.setting_type {
+                HSettingType::Extension(id) => Some((id, s.value)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn extensions_count(&self) -> usize {
+        self.settings
+            .iter()
+            .filter(|s| matches!(s.setting_type, HSettingType::Extension(_)))
+            .count()
+    }
+
     pub fn encode_frame_contents(&self, enc: &mut Encoder) {
         enc.encode_vvec_with(|enc_inner| {
             for iter in &self.settings {
@@ -88,6 +159,22 @@ impl HSettings {
                         enc_inner.encode_varint(SETTINGS_QPACK_BLOCKED_STREAMS as u64);
                         enc_inner.encode_varint(iter.value);
                     }
+                    HSettingType::EnableConnectProtocol => {
+                        enc_inner.encode_varint(SETTINGS_ENABLE_CONNECT_PROTOCOL as u64);
+                        enc_inner.encode_varint(iter.value);
+                    }
+                    HSettingType::EnableWebTransport => {
+                        enc_inner.encode_varint(SETTINGS_ENABLE_WEBTRANSPORT as u64);
+                        enc_inner.encode_varint(iter.value);
+                    }
+                    HSettingType::EnableH3Datagram => {
+                        enc_inner.encode_varint(SETTINGS_H3_DATAGRAM as u64);
+                        enc_inner.encode_varint(iter.value);
+                    }
+                    HSettingType::Extension(id) => {
+                        enc_inner.encode_varint(id);
+                        enc_inner.encode_varint(iter.value);
+                    }
                 }
             }
         });
@@ -113,8 +200,24 @@ impl HSettings {
                 (Some(SETTINGS_QPACK_BLOCKED_STREAMS), Some(value)) => self
                     .settings
                     .push(HSetting::new(HSettingType::BlockedStreams, value)),
+                (Some(SETTINGS_ENABLE_CONNECT_PROTOCOL), Some(value)) => self
+                    .settings
+                    .push(HSetting::new(HSettingType::EnableConnectProtocol, value)),
+                (Some(SETTINGS_ENABLE_WEBTRANSPORT), Some(value)) => self
+                    .settings
+                    .push(HSetting::new(HSettingType::EnableWebTransport, value)),
+                (Some(SETTINGS_H3_DATAGRAM), Some(value)) => self
+                    .settings
+                    .push(HSetting::new(HSettingType::EnableH3Datagram, value)),
                 // other supported settings here
-                (Some(_), Some(_)) => {} // ignore unknown setting, it is fine.
+                (Some(t), Some(_)) if is_grease(t) => {}
+                // Unknown settings are kept for extensions, up to a limit.
+                (Some(t), Some(value)) => {
+                    if self.extensions_count() < MAX_EXTENSION_SETTINGS {
+                        self.settings
+                            .push(HSetting::new(HSettingType::Extension(t), value));
+                    }
+                }
                 _ => return Err(Error::NotEnoughData),
             };
         }
@@ -175,7 +278,10 @@ impl ZeroRttChecker for HttpZeroRttChecker {
                 u64::from(self.settings.max_blocked_streams) >= setting.value
             }
             HSettingType::MaxTableCapacity => self.settings.max_table_size_decoder >= setting.value,
-            HSettingType::MaxHeaderListSize => true,
+            HSettingType::MaxHeaderListSize
+            | HSettingType::EnableConnectProtocol
+            | HSettingType::EnableWebTransport
+            | HSettingType::Extension(_) => true,
         }) {
             ZeroRttCheckResult::Accept
         } else {
@@ -0,0 +1,484 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// WebTransport over HTTP/3.
+//
+// A session is an extended CONNECT request with the `webtransport` protocol; the session ID is
+// the stream ID of the request. Streams of a session start with a signal (a stream type for
+// unidirectional streams, a frame type for bidirectional streams) followed by the session ID.
+// As for connect-udp, datagrams are sent as HTTP Datagrams once SETTINGS_H3_DATAGRAM has been
+// negotiated, and as DATAGRAM capsules on the CONNECT stream otherwise.
+
+#![allow(clippy::module_name_repetitions)]
+
+use crate::capsule::{encode_capsule, CapsuleReader, CAPSULE_TYPE_DATAGRAM};
+use crate::client_events::Http3ClientEvents;
+use crate::connection::Http3Connection;
+use crate::stream_type_reader::NewStreamTypeReader;
+use crate::{Error, Header, RecvMessageEvents, Res};
+use neqo_common::{qdebug, Decoder, Encoder};
+use neqo_transport::{AppError, Connection, StreamType};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::mem;
+
+pub const WEBTRANSPORT_PROTOCOL: &str = "webtransport";
+pub(crate) const WEBTRANSPORT_UNI_STREAM_TYPE: u64 = 0x54;
+const WEBTRANSPORT_BIDI_SIGNAL: u64 = 0x41;
+const CAPSULE_TYPE_CLOSE_WEBTRANSPORT_SESSION: u64 = 0x2843;
+/// The error code the streams of a session are reset with when the session is closed.
+pub const WEBTRANSPORT_SESSION_GONE: AppError = 0x170d_7b68;
+// Streams that refer to a session that does not exist are reset with this error code.
+const WEBTRANSPORT_BUFFERED_STREAM_REJECTED: AppError = 0x3994_bd84;
+const MAX_READ_SIZE: usize = 4096;
+
+#[derive(Debug, PartialOrd, Ord, PartialEq, Eq, Clone)]
+pub enum WebTransportEvent {
+    /// The response to a session request has been received (client only). The session has
+    /// been established if the status is 2xx.
+    Session { session_id: u64, status: u16 },
+    /// The peer has closed the session. The error code and the message are taken from its
+    /// CLOSE_WEBTRANSPORT_SESSION capsule, if one has been received.
+    SessionClosed {
+        session_id: u64,
+        error: Option<u32>,
+        message: String,
+    },
+    /// The peer has opened a stream of a session. The stream may already have data to read.
+    NewStream { stream_id: u64, session_id: u64 },
+    /// New bytes are available on a stream of a session.
+    DataReadable { stream_id: u64, session_id: u64 },
+    /// A datagram has been received on a session.
+    Datagram { session_id: u64, datagram: Vec<u8> },
+}
+
+pub(crate) trait WebTransportEvents {
+    fn webtransport_event(&self, event: WebTransportEvent);
+}
+
+/// Reads the session ID of a stream opened by the peer.
+#[derive(Debug)]
+pub(crate) struct WebTransportStreamReader {
+    // Whether the signal of a bidirectional stream has been read. The type of a unidirectional
+    // stream has already been read when the reader is created.
+    signal_read: bool,
+    reader: NewStreamTypeReader,
+}
+
+impl WebTransportStreamReader {
+    pub fn new(stream_type: StreamType) -> Self {
+        Self {
+            signal_read: stream_type == StreamType::UniDi,
+            reader: NewStreamTypeReader::new(),
+        }
+    }
+
+    /// Returns the session ID or `None` if more data are needed.
+    /// # Errors
+    /// `HttpStreamCreation` if a bidirectional stream does not start with the WebTransport
+    /// signal.
+    pub fn get_session_id(&mut self, conn: &mut Connection, stream_id: u64) -> Res<Option<u64>> {
+        loop {
+            match self.reader.get_type(conn, stream_id) {
+                None => return Ok(None),
+                Some(session_id) if self.signal_read => return Ok(Some(session_id)),
+                Some(WEBTRANSPORT_BIDI_SIGNAL) => {
+                    self.signal_read = true;
+                    self.reader = NewStreamTypeReader::new();
+                }
+                Some(_) => return Err(Error::HttpStreamCreation),
+            }
+        }
+    }
+
+    pub fn fin(&self) -> bool {
+        self.reader.fin()
+    }
+}
+
+/// Reports the response to a session request on the client. The data of the session are
+/// read by `WebTransportSessions::process`.
+#[derive(Debug)]
+pub(crate) struct WebTransportSessionListener {
+    events: Http3ClientEvents,
+}
+
+impl WebTransportSessionListener {
+    pub fn new(events: Http3ClientEvents) -> Self {
+        Self { events }
+    }
+}
+
+impl RecvMessageEvents for WebTransportSessionListener {
+    fn header_ready(&self, stream_id: u64, headers: Vec<Header>, interim: bool, _fin: bool) {
+        if interim {
+            return;
+        }
+        let status = headers
+            .iter()
+            .find(|(name, _)| name == ":status")
+            .and_then(|(_, value)| value.parse::<u16>().ok())
+            .unwrap_or(0);
+        self.events.webtransport_event(WebTransportEvent::Session {
+            session_id: stream_id,
+            status,
+        });
+    }
+
+    fn data_readable(&self, _stream_id: u64) {}
+
+    fn trailers_ready(&self, _stream_id: u64, _trailers: Vec<Header>) {}
+
+    fn reset(&self, _stream_id: u64, _error: AppError, _local: bool) {}
+}
+
+#[derive(Debug, Default)]
+struct Session {
+    capsules: CapsuleReader,
+    // The error code and the message of a received CLOSE_WEBTRANSPORT_SESSION capsule.
+    close_info: Option<(u32, String)>,
+    // The session has been closed or rejected locally; the rest of the CONNECT stream is
+    // discarded.
+    closed_locally: bool,
+}
+
+impl Session {
+    fn take_capsules(&mut self, session_id: u64, events: &dyn WebTransportEvents) -> Res<()> {
+        while let Some((capsule_type, value)) = self.capsules.next_capsule()? {
+            if self.closed_locally {
+                continue;
+            }
+            match capsule_type {
+                CAPSULE_TYPE_DATAGRAM => events.webtransport_event(WebTransportEvent::Datagram {
+                    session_id,
+                    datagram: value,
+                }),
+                CAPSULE_TYPE_CLOSE_WEBTRANSPORT_SESSION => {
+                    let mut dec = Decoder::from(&value[..]);
+                    if let Some(error) = dec.decode_uint(4).and_then(|e| u32::try_from(e).ok()) {
+                        let message = String::from_utf8_lossy(dec.decode_remainder()).into_owned();
+                        self.close_info = Some((error, message));
+                    }
+                }
+                // Unknown capsules are ignored.
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The WebTransport sessions of a connection and their streams.
+#[derive(Debug, Default)]
+pub(crate) struct WebTransportSessions {
+    sessions: HashMap<u64, Session>,
+    // Maps the streams of the sessions to their session IDs.
+    streams: HashMap<u64, u64>,
+    // Streams, with their session IDs, that have arrived before the request that opens their
+    // session has been handled.
+    buffered_streams: Vec<(u64, u64)>,
+}
+
+impl WebTransportSessions {
+    pub fn add_session(&mut self, session_id: u64) {
+        self.sessions.insert(session_id, Session::default());
+    }
+
+    /// Whether the CONNECT stream `stream_id` belongs to a session, i.e. its data are capsules
+    /// that are read by `process`.
+    pub fn is_session(&self, stream_id: u64) -> bool {
+        self.sessions.contains_key(&stream_id)
+    }
+
+    /// Report an HTTP Datagram that has been received on a session, unless the session has been
+    /// closed locally.
+    pub fn datagram(&self, events: &impl WebTransportEvents, session_id: u64, datagram: Vec<u8>) {
+        if self
+            .sessions
+            .get(&session_id)
+            .map_or(false, |s| !s.closed_locally)
+        {
+            events.webtransport_event(WebTransportEvent::Datagram {
+                session_id,
+                datagram,
+            });
+        }
+    }
+
+    pub fn stream_session(&self, stream_id: u64) -> Option<u64> {
+        self.streams.get(&stream_id).copied()
+    }
+
+    fn check_session(&self, session_id: u64) -> Res<()> {
+        match self.sessions.get(&session_id) {
+            Some(s) if !s.closed_locally => Ok(()),
+            _ => Err(Error::InvalidStreamId),
+        }
+    }
+
+    fn check_stream(&self, stream_id: u64) -> Res<()> {
+        if self.streams.contains_key(&stream_id) {
+            Ok(())
+        } else {
+            Err(Error::InvalidStreamId)
+        }
+    }
+
+    /// Open a stream of a session.
+    pub fn create_stream(
+        &mut self,
+        conn: &mut Connection,
+        session_id: u64,
+        stream_type: StreamType,
+    ) -> Res<u64> {
+        self.check_session(session_id)?;
+        let stream_id = conn
+            .stream_create(stream_type)
+            .map_err(|e| Error::map_stream_create_errors(&e))?;
+        let mut enc = Encoder::default();
+        if stream_type == StreamType::UniDi {
+            enc.encode_varint(WEBTRANSPORT_UNI_STREAM_TYPE);
+        } else {
+            enc.encode_varint(WEBTRANSPORT_BIDI_SIGNAL);
+        }
+        enc.encode_varint(session_id);
+        let prefix: Vec<u8> = enc.into();
+        let sent = conn
+            .stream_send(stream_id, &prefix)
+            .map_err(|e| Error::map_stream_send_errors(&e))?;
+        if sent != prefix.len() {
+            // The peer does not allow sending a few bytes on a new stream.
+            let _ = conn.stream_reset_send(stream_id, Error::HttpInternal.code());
+            return Err(Error::Unavailable);
+        }
+        self.streams.insert(stream_id, session_id);
+        Ok(stream_id)
+    }
+
+    /// Send a datagram on a session. It is sent in an HTTP Datagram if they have been
+    /// negotiated and in a DATAGRAM capsule otherwise. The datagram is dropped if it cannot be
+    /// sent at once.
+    pub fn send_datagram(
+        &self,
+        conn: &mut Connection,
+        base_handler: &mut Http3Connection,
+        session_id: u64,
+        datagram: &[u8],
+    ) -> Res<bool> {
+        self.check_session(session_id)?;
+        if base_handler.h3_datagram_negotiated() {
+            return base_handler.send_unreliable_datagram(conn, session_id, datagram);
+        }
+        base_handler
+            .send_streams
+            .get_mut(&session_id)
+            .ok_or(Error::InvalidStreamId)?
+            .send_body_atomic(
+                conn,
+                &encode_capsule(CAPSULE_TYPE_DATAGRAM, |enc| {
+                    enc.encode(datagram);
+                }),
+            )
+    }
+
+    /// Close a session: a CLOSE_WEBTRANSPORT_SESSION capsule is sent if possible, the CONNECT
+    /// stream is closed and the streams of the session are reset.
+    pub fn close_session(
+        &mut self,
+        conn: &mut Connection,
+        base_handler: &mut Http3Connection,
+        session_id: u64,
+        error: u32,
+        message: &str,
+    ) -> Res<()> {
+        self.check_session(session_id)?;
+        if let Some(s) = base_handler.send_streams.get_mut(&session_id) {
+            let capsule = encode_capsule(CAPSULE_TYPE_CLOSE_WEBTRANSPORT_SESSION, |enc| {
+                enc.encode_uint(4, error);
+                enc.encode(message.as_bytes());
+            });
+            // The session is closed even if the capsule cannot be sent.
+            let _ = s.send_body_atomic(conn, &capsule);
+        }
+        // The stream may already be closed.
+        let _ = base_handler.stream_close_send(conn, session_id);
+        self.close_locally(conn, session_id);
+        Ok(())
+    }
+
+    /// Stop handling a session, e.g. after it has been rejected. The CONNECT stream is read
+    /// until its end, but its data are discarded.
+    pub fn close_locally(&mut self, conn: &mut Connection, session_id: u64) {
+        if let Some(s) = self.sessions.get_mut(&session_id) {
+            s.closed_locally = true;
+        }
+        self.reset_streams(conn, session_id);
+    }
+
+    fn reset_streams(&mut self, conn: &mut Connection, session_id: u64) {
+        self.streams.retain(|stream_id, s| {
+            if *s == session_id {
+                // The stream may already be closed, errors are ignored.
+                let _ = conn.stream_reset_send(*stream_id, WEBTRANSPORT_SESSION_GONE);
+                let _ = conn.stream_stop_sending(*stream_id, WEBTRANSPORT_SESSION_GONE);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    pub fn stream_send(&self, conn: &mut Connection, stream_id: u64, buf: &[u8]) -> Res<usize> {
+        self.check_stream(stream_id)?;
+        conn.stream_send(stream_id, buf)
+            .map_err(|e| Error::map_stream_send_errors(&e))
+    }
+
+    pub fn stream_recv(
+        &self,
+        conn: &mut Connection,
+        stream_id: u64,
+        buf: &mut [u8],
+    ) -> Res<(usize, bool)> {
+        self.check_stream(stream_id)?;
+        conn.stream_recv(stream_id, buf)
+            .map_err(|e| Error::map_stream_recv_errors(&e))
+    }
+
+    pub fn stream_close_send(&self, conn: &mut Connection, stream_id: u64) -> Res<()> {
+        self.check_stream(stream_id)?;
+        conn.stream_close_send(stream_id)
+            .map_err(|e| Error::map_stream_send_errors(&e))
+    }
+
+    /// Reset both sides of a stream.
+    pub fn stream_reset(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: u64,
+        error: AppError,
+    ) -> Res<()> {
+        self.streams
+            .remove(&stream_id)
+            .ok_or(Error::InvalidStreamId)?;
+        // The stream may already be closed, errors are ignored.
+        let _ = conn.stream_reset_send(stream_id, error);
+        let _ = conn.stream_stop_sending(stream_id, error);
+        Ok(())
+    }
+
+    /// Add the streams whose session ID has been read and read the CONNECT streams of the
+    /// sessions. New streams, datagrams and sessions closed by the peer are reported to
+    /// `events`.
+    pub fn process(
+        &mut self,
+        conn: &mut Connection,
+        base_handler: &mut Http3Connection,
+        events: &dyn WebTransportEvents,
+    ) -> Res<()> {
+        let mut new_streams = mem::replace(&mut self.buffered_streams, Vec::new());
+        new_streams.append(&mut base_handler.take_new_webtransport_streams());
+        for (stream_id, session_id) in new_streams {
+            if self.check_session(session_id).is_ok() {
+                self.streams.insert(stream_id, session_id);
+                events.webtransport_event(WebTransportEvent::NewStream {
+                    stream_id,
+                    session_id,
+                });
+            } else if !self.sessions.contains_key(&session_id)
+                && base_handler.recv_streams.contains_key(&session_id)
+            {
+                // The request may still turn out to open the session.
+                self.buffered_streams.push((stream_id, session_id));
+            } else {
+                qdebug!(
+                    "WebTransport stream {} refers to an unknown session {}.",
+                    stream_id,
+                    session_id
+                );
+                let _ = conn.stream_stop_sending(stream_id, WEBTRANSPORT_BUFFERED_STREAM_REJECTED);
+                let _ = conn.stream_reset_send(stream_id, WEBTRANSPORT_BUFFERED_STREAM_REJECTED);
+            }
+        }
+
+        let session_ids: Vec<u64> = self.sessions.keys().copied().collect();
+        for session_id in session_ids {
+            let closed = match self.read_session(conn, base_handler, session_id, events) {
+                Ok(closed) => closed,
+                // A bad capsule ends the session, not the connection.
+                Err(e) if e.stream_reset_error() => {
+                    qdebug!("WebTransport session {} failed: {:?}", session_id, e);
+                    // The stream may already be closed.
+                    let _ = base_handler.stream_reset(conn, session_id, e.code());
+                    true
+                }
+                Err(e) => return Err(e),
+            };
+            if !closed {
+                continue;
+            }
+            if let Some(session) = self.sessions.remove(&session_id) {
+                if session.closed_locally {
+                    continue;
+                }
+                let (error, message) = match session.close_info {
+                    Some((error, message)) => (Some(error), message),
+                    None => (None, String::new()),
+                };
+                events.webtransport_event(WebTransportEvent::SessionClosed {
+                    session_id,
+                    error,
+                    message,
+                });
+                // The stream may already be closed.
+                let _ = base_handler.stream_close_send(conn, session_id);
+                self.reset_streams(conn, session_id);
+            }
+        }
+        Ok(())
+    }
+
+    // Read the capsules of a session. Returns true if the CONNECT stream has been closed.
+    fn read_session(
+        &mut self,
+        conn: &mut Connection,
+        base_handler: &mut Http3Connection,
+        session_id: u64,
+        events: &dyn WebTransportEvents,
+    ) -> Res<bool> {
+        let session = match self.sessions.get_mut(&session_id) {
+            Some(s) => s,
+            None => return Ok(false),
+        };
+
+        let mut closed = false;
+        if let Some(recv_stream) = base_handler.recv_streams.get_mut(&session_id) {
+            let mut buf = vec![0; MAX_READ_SIZE];
+            loop {
+                let (amount, fin) =
+                    recv_stream.read_data(conn, &mut base_handler.qpack_decoder, &mut buf)?;
+                // Capsules are taken after every read, so that only an incomplete capsule is
+                // kept.
+                session.capsules.receive(&buf[..amount])?;
+                session.take_capsules(session_id, events)?;
+                if fin {
+                    closed = true;
+                    break;
+                }
+                if amount == 0 {
+                    break;
+                }
+            }
+            if recv_stream.done() {
+                base_handler.recv_streams.remove(&session_id);
+            }
+        } else {
+            // The stream has been reset or its end has already been read.
+            closed = true;
+        }
+        Ok(closed)
+    }
+}
@@ -8,7 +8,15 @@
 
 use neqo_common::{event::Provider, Datagram};
 use neqo_crypto::AuthenticationStatus;
-use neqo_http3::{Http3Client, Http3ClientEvent, Http3Server, Http3ServerEvent, Http3State};
+use neqo_http3::{
+    ClientRequestStream, ConnectUdpReader, ConnectUdpTarget, Error, Header, Http3Client,
+    Http3ClientEvent, Http3Parameters, Http3Server, Http3ServerEvent, Http3State, Request,
+    RequestHandler, WebTransportEvent, WebTransportServerEvent, WebTransportSession,
+};
+use neqo_qpack::QpackSettings;
+use neqo_transport::StreamType;
+use std::convert::TryFrom;
+use std::time::Duration;
 use test_fixture::*;
 
 const RESPONSE_DATA: &[u8] = &[0x61, 0x62, 0x63];
@@ -79,9 +87,17 @@ fn process_client_events(conn: &mut Http3Client) {
 }
 
 fn connect() -> (Http3Client, Http3Server, Option<Datagram>) {
-    let mut hconn_c = default_http3_client();
-    let mut hconn_s = default_http3_server();
+    connect_with_server(default_http3_server())
+}
+
+fn connect_with_server(hconn_s: Http3Server) -> (Http3Client, Http3Server, Option<Datagram>) {
+    connect_with(default_http3_client(), hconn_s)
+}
 
+fn connect_with(
+    mut hconn_c: Http3Client,
+    mut hconn_s: Http3Server,
+) -> (Http3Client, Http3Server, Option<Datagram>) {
     assert_eq!(hconn_c.state(), Http3State::Initializing);
     let out = hconn_c.process(None, now()); // Initial
     let out = hconn_s.process(out.dgram(), now()); // Initial + Handshake
@@ -130,3 +146,915 @@ fn test_fetch() {
     let _ = hconn_c.process(out.dgram(), now());
     process_client_events(&mut hconn_c);
 }
+
+#[test]
+fn test_fetch_with_grease() {
+    let mut hconn_c = default_http3_client();
+    hconn_c.set_grease(true).unwrap();
+    let mut hconn_s = default_http3_server();
+    hconn_s.set_grease(true);
+    let (mut hconn_c, mut hconn_s, dgram) = connect_with(hconn_c, hconn_s);
+
+    // Both sides have opened a stream of a reserved type and the peer has ignored it.
+    assert_eq!(hconn_c.state(), Http3State::Connected);
+    assert_eq!(hconn_c.set_grease(false), Err(Error::AlreadyInitialized));
+
+    // The request and the response carry a frame of a reserved type before the headers.
+    let req = hconn_c
+        .fetch(now(), "GET", "https", "something.com", "/", &[])
+        .unwrap();
+    assert_eq!(req, 0);
+    hconn_c.stream_close_send(req).unwrap();
+    let out = hconn_c.process(dgram, now());
+    let out = hconn_s.process(out.dgram(), now());
+    let _ = hconn_c.process(out.dgram(), now());
+    process_server_events(&mut hconn_s);
+    let out = hconn_s.process(None, now());
+
+    let _ = hconn_c.process(out.dgram(), now());
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    process_client_events(&mut hconn_c);
+    assert_eq!(hconn_c.state(), Http3State::Connected);
+}
+
+/// Open a CONNECT tunnel and let the server accept it.
+fn open_connect_tunnel(
+    hconn_c: &mut Http3Client,
+    hconn_s: &mut Http3Server,
+    dgram: Option<Datagram>,
+) -> (u64, ClientRequestStream) {
+    let req = hconn_c
+        .connect_tunnel(now(), "something.com:443", &[])
+        .unwrap();
+    let out = hconn_c.process(dgram, now());
+    let out = hconn_s.process(out.dgram(), now());
+    let _ = hconn_c.process(out.dgram(), now());
+
+    // The server accepts the tunnel.
+    let mut request = None;
+    while let Some(event) = hconn_s.next_event() {
+        if let Http3ServerEvent::Headers {
+            request: mut r,
+            headers,
+            fin,
+        } = event
+        {
+            assert_eq!(
+                headers,
+                vec![
+                    (String::from(":method"), String::from("CONNECT")),
+                    (
+                        String::from(":authority"),
+                        String::from("something.com:443")
+                    ),
+                ]
+            );
+            assert_eq!(fin, false);
+            r.set_response_headers(&[(String::from(":status"), String::from("200"))])
+                .unwrap();
+            request = Some(r);
+        }
+    }
+    let request = request.expect("the server should receive the CONNECT request");
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+
+    let header_ready = |e| matches!(e, Http3ClientEvent::HeaderReady { stream_id, fin: false, .. } if stream_id == req);
+    assert!(hconn_c.events().any(header_ready));
+    (req, request)
+}
+
+#[test]
+fn test_connect_tunnel() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect();
+    let (req, mut request) = open_connect_tunnel(&mut hconn_c, &mut hconn_s, dgram);
+
+    // Send data through the tunnel in both directions.
+    assert_eq!(hconn_c.send_request_body(req, b"ping").unwrap(), 4);
+    let out = hconn_c.process(None, now());
+    let _ = hconn_s.process(out.dgram(), now());
+    let data_received =
+        |e| matches!(e, Http3ServerEvent::Data { ref data, fin: false, .. } if data == b"ping");
+    assert!(hconn_s.events().any(data_received));
+
+    assert_eq!(request.send_data(b"pong").unwrap(), 4);
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    let mut buf = [0_u8; 100];
+    assert_eq!(
+        hconn_c.read_response_data(now(), req, &mut buf).unwrap(),
+        (4, false)
+    );
+    assert_eq!(&buf[..4], b"pong");
+
+    // Close the tunnel; each side sees the fin of the other.
+    hconn_c.stream_close_send(req).unwrap();
+    let out = hconn_c.process(None, now());
+    let _ = hconn_s.process(out.dgram(), now());
+    let fin_received =
+        |e| matches!(e, Http3ServerEvent::Data { ref data, fin: true, .. } if data.is_empty());
+    assert!(hconn_s.events().any(fin_received));
+
+    request.stream_close_send().unwrap();
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    assert_eq!(
+        hconn_c.read_response_data(now(), req, &mut buf).unwrap(),
+        (0, true)
+    );
+}
+
+/// A reset of one direction of a tunnel fails the whole tunnel with H3_CONNECT_ERROR.
+#[test]
+fn test_connect_tunnel_reset() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect();
+    let (req, _request) = open_connect_tunnel(&mut hconn_c, &mut hconn_s, dgram);
+
+    // Only the client to server direction is reset.
+    hconn_c
+        .conn()
+        .stream_reset_send(req, Error::HttpConnect.code())
+        .unwrap();
+    let out = hconn_c.process(None, now());
+    let out = hconn_s.process(out.dgram(), now());
+    let server_reset = |e| {
+        matches!(e, Http3ServerEvent::Reset { error, local: false, .. }
+            if error == Error::HttpConnect.code())
+    };
+    assert!(hconn_s.events().any(server_reset));
+
+    // The server resets the other direction with H3_CONNECT_ERROR.
+    let _ = hconn_c.process(out.dgram(), now());
+    let client_reset = |e| {
+        matches!(e, Http3ClientEvent::Reset { stream_id, error, local: false }
+            if stream_id == req && error == Error::HttpConnect.code())
+    };
+    assert!(hconn_c.events().any(client_reset));
+}
+
+/// Open a connect-udp session and let the proxy accept it.
+fn open_connect_udp(
+    hconn_c: &mut Http3Client,
+    hconn_s: &mut Http3Server,
+    dgram: Option<Datagram>,
+) -> (u64, ClientRequestStream) {
+    let target = ConnectUdpTarget::new("192.0.2.6", 53);
+    let req = hconn_c
+        .connect_udp(now(), "proxy.example", &target)
+        .unwrap();
+    let out = hconn_c.process(dgram, now());
+    let out = hconn_s.process(out.dgram(), now());
+    let _ = hconn_c.process(out.dgram(), now());
+
+    // The proxy accepts the session.
+    let mut request = None;
+    while let Some(event) = hconn_s.next_event() {
+        if let Http3ServerEvent::Headers {
+            request: mut r,
+            headers,
+            fin,
+        } = event
+        {
+            assert_eq!(
+                ConnectUdpTarget::from_headers(&headers),
+                Some(target.clone())
+            );
+            assert_eq!(fin, false);
+            r.set_response_headers(&[
+                (String::from(":status"), String::from("200")),
+                (String::from("capsule-protocol"), String::from("?1")),
+            ])
+            .unwrap();
+            request = Some(r);
+        }
+    }
+    let request = request.expect("the proxy should receive the connect-udp request");
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+
+    let header_ready =
+        |e| matches!(e, Http3ClientEvent::HeaderReady { stream_id, .. } if stream_id == req);
+    assert!(hconn_c.events().any(header_ready));
+    (req, request)
+}
+
+#[test]
+fn test_connect_udp() {
+    let mut hconn_s = default_http3_server();
+    hconn_s.set_enable_connect_protocol(true);
+    let (mut hconn_c, mut hconn_s, dgram) = connect_with_server(hconn_s);
+    let (req, mut request) = open_connect_udp(&mut hconn_c, &mut hconn_s, dgram);
+
+    // Exchange UDP payloads.
+    assert!(hconn_c.send_udp_payload(req, b"query").unwrap());
+    let out = hconn_c.process(None, now());
+    let _ = hconn_s.process(out.dgram(), now());
+    let mut reader = ConnectUdpReader::default();
+    while let Some(event) = hconn_s.next_event() {
+        if let Http3ServerEvent::Data { data, .. } = event {
+            reader.receive(&data).unwrap();
+        }
+    }
+    assert_eq!(reader.next_payload(), Ok(Some(b"query".to_vec())));
+
+    assert!(request.send_udp_payload(b"answer").unwrap());
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    let mut buf = [0_u8; 100];
+    let (amount, fin) = hconn_c.read_response_data(now(), req, &mut buf).unwrap();
+    assert_eq!(fin, false);
+    let mut reader = ConnectUdpReader::default();
+    reader.receive(&buf[..amount]).unwrap();
+    assert_eq!(reader.next_payload(), Ok(Some(b"answer".to_vec())));
+}
+
+#[test]
+fn test_connect_udp_h3_datagram() {
+    let hconn_c = http3_client_with_params(
+        &Http3Parameters::new(
+            QpackSettings {
+                max_table_size_encoder: 100,
+                max_table_size_decoder: 100,
+                max_blocked_streams: 100,
+            },
+            10,
+        )
+        .http3_datagram(true),
+    );
+    let mut hconn_s = default_http3_server();
+    hconn_s.set_enable_h3_datagram(true);
+    hconn_s.set_enable_connect_protocol(true);
+    let (mut hconn_c, mut hconn_s, dgram) = connect_with(hconn_c, hconn_s);
+    let (req, mut request) = open_connect_udp(&mut hconn_c, &mut hconn_s, dgram);
+
+    // With HTTP Datagrams negotiated, UDP payloads are not sent on the request stream.
+    assert!(hconn_c.send_udp_payload(req, b"query").unwrap());
+    let out = hconn_c.process(None, now());
+    let _ = hconn_s.process(out.dgram(), now());
+    let mut payloads = Vec::new();
+    while let Some(event) = hconn_s.next_event() {
+        match event {
+            Http3ServerEvent::Datagram { datagram, .. } => {
+                payloads.push(ConnectUdpReader::payload_from_datagram(&datagram).unwrap());
+            }
+            Http3ServerEvent::Data { .. } => panic!("a UDP payload should not be sent as data"),
+            _ => {}
+        }
+    }
+    assert_eq!(payloads, vec![Some(b"query".to_vec())]);
+
+    assert!(request.send_udp_payload(b"answer").unwrap());
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    let payload = hconn_c.events().find_map(|e| match e {
+        Http3ClientEvent::Datagram {
+            stream_id,
+            datagram,
+        } if stream_id == req => ConnectUdpReader::payload_from_datagram(&datagram).unwrap(),
+        _ => None,
+    });
+    assert_eq!(payload, Some(b"answer".to_vec()));
+
+    // A payload that does not fit into a QUIC datagram is dropped.
+    let too_big = vec![0; usize::try_from(hconn_c.max_datagram_size(req).unwrap()).unwrap()];
+    assert!(!hconn_c.send_udp_payload(req, &too_big).unwrap());
+}
+
+#[test]
+fn test_extended_connect_not_enabled() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect();
+
+    let target = ConnectUdpTarget::new("192.0.2.6", 53);
+    assert_eq!(
+        hconn_c.connect_udp(now(), "proxy.example", &target),
+        Err(Error::Unavailable)
+    );
+
+    // A server that has not enabled extended CONNECT resets such requests.
+    let req = hconn_c
+        .fetch(
+            now(),
+            "CONNECT",
+            "https",
+            "proxy.example",
+            &target.path(),
+            &[(String::from(":protocol"), String::from("connect-udp"))],
+        )
+        .unwrap();
+    let out = hconn_c.process(dgram, now());
+    let out = hconn_s.process(out.dgram(), now());
+    let events = hconn_s.events().collect::<Vec<_>>();
+    assert!(!events
+        .iter()
+        .any(|e| matches!(e, Http3ServerEvent::Headers { .. })));
+    assert!(events.iter().any(|e| matches!(e,
+        Http3ServerEvent::Reset { error, local: true, .. }
+          if *error == Error::HttpMessageError.code())));
+    let _ = hconn_c.process(out.dgram(), now());
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+
+    let reset = |e| matches!(e, Http3ClientEvent::Reset { stream_id, error, .. } if stream_id == req && error == Error::HttpMessageError.code());
+    assert!(hconn_c.events().any(reset));
+}
+
+#[test]
+fn test_goaway() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect();
+
+    // The first request reaches the server, the second one is sent after the GOAWAY.
+    let req_1 = hconn_c
+        .fetch(now(), "GET", "https", "something.com", "/", &[])
+        .unwrap();
+    hconn_c.stream_close_send(req_1).unwrap();
+    let out = hconn_c.process(dgram, now());
+    let _ = hconn_s.process(out.dgram(), now());
+    let req_2 = hconn_c
+        .fetch(now(), "GET", "https", "something.com", "/", &[])
+        .unwrap();
+    hconn_c.stream_close_send(req_2).unwrap();
+
+    hconn_s.goaway();
+    let mut request = None;
+    while let Some(event) = hconn_s.next_event() {
+        if let Http3ServerEvent::Headers { request: r, .. } = event {
+            request = Some(r);
+        }
+    }
+    let mut request = request.expect("the server should receive the first request");
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+
+    // The second request is rejected and can be retried on a new connection.
+    let rejected = |e| {
+        matches!(e, Http3ClientEvent::Reset { stream_id, error, local: false }
+            if stream_id == req_2 && error == Error::HttpRequestRejected.code())
+    };
+    assert!(hconn_c.events().any(rejected));
+    assert_eq!(hconn_c.state(), Http3State::GoingAway(req_2));
+    assert_eq!(
+        hconn_c.fetch(now(), "GET", "https", "something.com", "/", &[]),
+        Err(Error::AlreadyClosed)
+    );
+
+    // The first request still completes.
+    request
+        .set_response(
+            &[(String::from(":status"), String::from("200"))],
+            RESPONSE_DATA,
+        )
+        .unwrap();
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    let mut buf = [0_u8; 100];
+    assert_eq!(
+        hconn_c.read_response_data(now(), req_1, &mut buf).unwrap(),
+        (RESPONSE_DATA.len(), true)
+    );
+}
+
+fn connect_webtransport() -> (Http3Client, Http3Server, Option<Datagram>) {
+    let hconn_c = http3_client_with_params(
+        &Http3Parameters::new(
+            QpackSettings {
+                max_table_size_encoder: 100,
+                max_table_size_decoder: 100,
+                max_blocked_streams: 100,
+            },
+            10,
+        )
+        .webtransport(true),
+    );
+    let mut hconn_s = default_http3_server();
+    hconn_s.set_enable_webtransport(true);
+    connect_with(hconn_c, hconn_s)
+}
+
+fn exchange_packets(hconn_c: &mut Http3Client, hconn_s: &mut Http3Server) {
+    let out = hconn_c.process(None, now());
+    let out = hconn_s.process(out.dgram(), now());
+    let _ = hconn_c.process(out.dgram(), now());
+}
+
+/// Open a WebTransport session and let the server accept it.
+fn open_webtransport_session(
+    hconn_c: &mut Http3Client,
+    hconn_s: &mut Http3Server,
+    dgram: Option<Datagram>,
+) -> (u64, WebTransportSession) {
+    let session_id = hconn_c
+        .webtransport_create_session(now(), "something.com", "/wt", &[])
+        .unwrap();
+    let out = hconn_c.process(dgram, now());
+    let out = hconn_s.process(out.dgram(), now());
+    let _ = hconn_c.process(out.dgram(), now());
+
+    // The server accepts the session.
+    let mut session = None;
+    while let Some(event) = hconn_s.next_event() {
+        if let Http3ServerEvent::WebTransport(WebTransportServerEvent::NewSession {
+            session: mut s,
+            headers,
+        }) = event
+        {
+            assert!(headers
+                .iter()
+                .any(|(n, v)| n == ":protocol" && v == "webtransport"));
+            s.response(true).unwrap();
+            session = Some(s);
+        }
+    }
+    let session: WebTransportSession =
+        session.expect("the server should receive the session request");
+    assert_eq!(session.session_id(), session_id);
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    let established = |e| matches!(e, Http3ClientEvent::WebTransport(WebTransportEvent::Session { session_id: id, status: 200 }) if id == session_id);
+    assert!(hconn_c.events().any(established));
+    (session_id, session)
+}
+
+#[test]
+fn test_webtransport() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect_webtransport();
+    let (session_id, mut session) = open_webtransport_session(&mut hconn_c, &mut hconn_s, dgram);
+
+    // The client opens a bidirectional stream and the server echoes the data.
+    let stream_id = hconn_c
+        .webtransport_create_stream(session_id, StreamType::BiDi)
+        .unwrap();
+    assert_eq!(
+        hconn_c
+            .webtransport_stream_send(stream_id, b"ping")
+            .unwrap(),
+        4
+    );
+    exchange_packets(&mut hconn_c, &mut hconn_s);
+    let new_stream = |e| matches!(e, Http3ServerEvent::WebTransport(WebTransportServerEvent::NewStream { stream_id: id, .. }) if id == stream_id);
+    assert!(hconn_s.events().any(new_stream));
+    let mut buf = [0_u8; 100];
+    assert_eq!(
+        session.stream_recv(stream_id, &mut buf).unwrap(),
+        (4, false)
+    );
+    assert_eq!(&buf[..4], b"ping");
+    assert_eq!(session.stream_send(stream_id, b"pong").unwrap(), 4);
+    session.stream_close_send(stream_id).unwrap();
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    let data_readable = |e| matches!(e, Http3ClientEvent::WebTransport(WebTransportEvent::DataReadable { stream_id: id, .. }) if id == stream_id);
+    assert!(hconn_c.events().any(data_readable));
+    assert_eq!(
+        hconn_c
+            .webtransport_stream_recv(stream_id, &mut buf)
+            .unwrap(),
+        (4, true)
+    );
+    assert_eq!(&buf[..4], b"pong");
+
+    // The server opens a unidirectional stream.
+    let uni_stream_id = session.create_stream(StreamType::UniDi).unwrap();
+    assert_eq!(session.stream_send(uni_stream_id, b"hello").unwrap(), 5);
+    session.stream_close_send(uni_stream_id).unwrap();
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    let new_stream = |e| matches!(e, Http3ClientEvent::WebTransport(WebTransportEvent::NewStream { stream_id: id, session_id: s }) if id == uni_stream_id && s == session_id);
+    assert!(hconn_c.events().any(new_stream));
+    assert_eq!(
+        hconn_c
+            .webtransport_stream_recv(uni_stream_id, &mut buf)
+            .unwrap(),
+        (5, true)
+    );
+    assert_eq!(&buf[..5], b"hello");
+
+    // Datagrams in both directions.
+    assert!(hconn_c
+        .webtransport_send_datagram(session_id, b"dgram")
+        .unwrap());
+    exchange_packets(&mut hconn_c, &mut hconn_s);
+    let datagram = |e| matches!(e, Http3ServerEvent::WebTransport(WebTransportServerEvent::Datagram { ref datagram, .. }) if datagram == b"dgram");
+    assert!(hconn_s.events().any(datagram));
+
+    assert!(session.send_datagram(b"reply").unwrap());
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    let datagram = |e| matches!(e, Http3ClientEvent::WebTransport(WebTransportEvent::Datagram { session_id: id, ref datagram }) if id == session_id && datagram == b"reply");
+    assert!(hconn_c.events().any(datagram));
+
+    // The client closes the session.
+    hconn_c
+        .webtransport_close_session(session_id, 7, "done")
+        .unwrap();
+    exchange_packets(&mut hconn_c, &mut hconn_s);
+    let closed = |e| matches!(e, Http3ServerEvent::WebTransport(WebTransportServerEvent::SessionClosed { error: Some(7), ref message, .. }) if message == "done");
+    assert!(hconn_s.events().any(closed));
+}
+
+#[test]
+fn test_webtransport_capsule_too_long() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect_webtransport();
+    let (session_id, _session) = open_webtransport_session(&mut hconn_c, &mut hconn_s, dgram);
+
+    // A DATAGRAM capsule that says it is 1 MiB long. The server gives up on the session as
+    // soon as it has read the length.
+    let capsule = [0x00, 0x80, 0x10, 0x00, 0x00];
+    assert_eq!(
+        hconn_c.send_request_body(session_id, &capsule).unwrap(),
+        capsule.len()
+    );
+    exchange_packets(&mut hconn_c, &mut hconn_s);
+    let closed = |e| matches!(e, Http3ServerEvent::WebTransport(WebTransportServerEvent::SessionClosed { ref session, error: None, .. }) if session.session_id() == session_id);
+    assert!(hconn_s.events().any(closed));
+
+    // Only the session is closed, not the connection.
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    assert_eq!(hconn_c.state(), Http3State::Connected);
+}
+
+#[test]
+fn test_webtransport_h3_datagram() {
+    let hconn_c = http3_client_with_params(
+        &Http3Parameters::new(
+            QpackSettings {
+                max_table_size_encoder: 100,
+                max_table_size_decoder: 100,
+                max_blocked_streams: 100,
+            },
+            10,
+        )
+        .webtransport(true)
+        .http3_datagram(true),
+    );
+    let mut hconn_s = default_http3_server();
+    hconn_s.set_enable_webtransport(true);
+    hconn_s.set_enable_h3_datagram(true);
+    let (mut hconn_c, mut hconn_s, dgram) = connect_with(hconn_c, hconn_s);
+    let (session_id, mut session) = open_webtransport_session(&mut hconn_c, &mut hconn_s, dgram);
+
+    // With HTTP Datagrams negotiated, datagrams are not sent on the CONNECT stream.
+    let before = hconn_c.conn().stats().frame_tx;
+    assert!(hconn_c
+        .webtransport_send_datagram(session_id, b"dgram")
+        .unwrap());
+    exchange_packets(&mut hconn_c, &mut hconn_s);
+    let after = hconn_c.conn().stats().frame_tx;
+    assert_eq!(after.datagram, before.datagram + 1);
+    assert_eq!(after.stream, before.stream);
+    let datagram = |e| matches!(e, Http3ServerEvent::WebTransport(WebTransportServerEvent::Datagram { ref datagram, .. }) if datagram == b"dgram");
+    assert!(hconn_s.events().any(datagram));
+
+    assert!(session.send_datagram(b"reply").unwrap());
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    let events = hconn_c.events().collect::<Vec<_>>();
+    assert!(events.iter().any(|e| matches!(e, Http3ClientEvent::WebTransport(WebTransportEvent::Datagram { session_id: id, ref datagram }) if *id == session_id && datagram == b"reply")));
+    // The datagram is reported for the session only, not as an HTTP Datagram of the request.
+    assert!(!events
+        .iter()
+        .any(|e| matches!(e, Http3ClientEvent::Datagram { .. })));
+}
+#[test]
+fn test_webtransport_not_enabled() {
+    let (mut hconn_c, _hconn_s, _dgram) = connect();
+    assert_eq!(
+        hconn_c.webtransport_create_session(now(), "something.com", "/wt", &[]),
+        Err(Error::Unavailable)
+    );
+}
+
+#[derive(Default)]
+struct StreamingHandler {
+    requests: Vec<(String, String)>,
+    writable: Vec<ClientRequestStream>,
+    complete: usize,
+}
+
+impl RequestHandler for StreamingHandler {
+    fn on_request(
+        &mut self,
+        mut request: ClientRequestStream,
+        method: &str,
+        path: &str,
+        _headers: &[Header],
+        fin: bool,
+    ) {
+        assert!(fin);
+        self.requests.push((method.to_string(), path.to_string()));
+        request
+            .set_response_headers(&[(String::from(":status"), String::from("200"))])
+            .unwrap();
+    }
+
+    fn on_data_writable(&mut self, request: ClientRequestStream) {
+        self.writable.push(request);
+    }
+
+    fn on_complete(&mut self, _request: ClientRequestStream) {
+        self.complete += 1;
+    }
+}
+
+#[test]
+fn test_request_handler() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect();
+    let req = hconn_c
+        .fetch(now(), "GET", "https", "something.com", "/stream", &[])
+        .unwrap();
+    hconn_c.stream_close_send(req).unwrap();
+    let out = hconn_c.process(dgram, now());
+    let _ = hconn_s.process(out.dgram(), now());
+
+    let mut handler = StreamingHandler::default();
+    hconn_s.dispatch_events(&mut handler);
+    assert_eq!(
+        handler.requests,
+        vec![(String::from("GET"), String::from("/stream"))]
+    );
+
+    // Send the response headers, then stream the body followed by trailers.
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    hconn_s.dispatch_events(&mut handler);
+    let mut request = handler.writable.pop().unwrap();
+    assert_eq!(request.stream_id(), req);
+    assert_eq!(
+        request.send_data(RESPONSE_DATA).unwrap(),
+        RESPONSE_DATA.len()
+    );
+    request
+        .send_trailers(&[(String::from("something"), String::from("3"))])
+        .unwrap();
+    exchange_packets(&mut hconn_c, &mut hconn_s);
+
+    let mut buf = [0_u8; 100];
+    assert_eq!(
+        hconn_c.read_response_data(now(), req, &mut buf).unwrap(),
+        (RESPONSE_DATA.len(), false)
+    );
+    let trailers =
+        |e| matches!(e, Http3ClientEvent::TrailersReady { stream_id, .. } if stream_id == req);
+    assert!(hconn_c.events().any(trailers));
+
+    // The client acknowledges the response once its ACK delay has passed.
+    let out = hconn_c.process(None, now() + Duration::from_millis(100));
+    let _ = hconn_s.process(out.dgram(), now());
+    hconn_s.dispatch_events(&mut handler);
+    assert_eq!(handler.complete, 1);
+}
+
+fn connect_h3_datagram() -> (Http3Client, Http3Server, Option<Datagram>) {
+    let hconn_c = http3_client_with_params(
+        &Http3Parameters::new(
+            QpackSettings {
+                max_table_size_encoder: 100,
+                max_table_size_decoder: 100,
+                max_blocked_streams: 100,
+            },
+            10,
+        )
+        .http3_datagram(true),
+    );
+    let mut hconn_s = default_http3_server();
+    hconn_s.set_enable_h3_datagram(true);
+    connect_with(hconn_c, hconn_s)
+}
+
+#[test]
+fn test_h3_datagram() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect_h3_datagram();
+
+    let req = hconn_c
+        .fetch(now(), "GET", "https", "something.com", "/", &[])
+        .unwrap();
+    let out = hconn_c.process(dgram, now());
+    let _ = hconn_s.process(out.dgram(), now());
+    let mut request = hconn_s
+        .events()
+        .find_map(|e| {
+            if let Http3ServerEvent::Headers { request, .. } = e {
+                Some(request)
+            } else {
+                None
+            }
+        })
+        .unwrap();
+
+    assert!(hconn_c.max_datagram_size(req).unwrap() > 1000);
+    hconn_c.send_datagram(req, b"ping").unwrap();
+    let out = hconn_c.process(None, now());
+    let _ = hconn_s.process(out.dgram(), now());
+    let datagram =
+        |e| matches!(e, Http3ServerEvent::Datagram { ref datagram, .. } if datagram == b"ping");
+    assert!(hconn_s.events().any(datagram));
+
+    request.send_datagram(b"pong").unwrap();
+    let out = hconn_s.process(None, now());
+    let _ = hconn_c.process(out.dgram(), now());
+    let datagram = |e| matches!(e, Http3ClientEvent::Datagram { stream_id, ref datagram } if stream_id == req && datagram == b"pong");
+    assert!(hconn_c.events().any(datagram));
+
+    // A datagram must fit into a single packet.
+    let too_big = vec![0; usize::try_from(hconn_c.max_datagram_size(req).unwrap()).unwrap() + 1];
+    assert_eq!(
+        hconn_c.send_datagram(req, &too_big),
+        Err(Error::InvalidInput)
+    );
+    // Datagrams are bound to client-initiated bidirectional streams.
+    assert_eq!(
+        hconn_c.send_datagram(req + 1, b"ping"),
+        Err(Error::InvalidStreamId)
+    );
+}
+
+#[test]
+fn test_h3_datagram_not_enabled() {
+    let (mut hconn_c, _hconn_s, _dgram) = connect();
+    let req = hconn_c
+        .fetch(now(), "GET", "https", "something.com", "/", &[])
+        .unwrap();
+    assert_eq!(hconn_c.send_datagram(req, b"ping"), Err(Error::Unavailable));
+}
+
+#[test]
+fn test_origin() {
+    let mut hconn_s = default_http3_server();
+    assert_eq!(
+        hconn_s.set_origins(&["https://a.com/path"]),
+        Err(Error::InvalidInput)
+    );
+    hconn_s
+        .set_origins(&[
+            "https://a.com",
+            "https://B.example.org:443",
+            "https://c.com:8443",
+        ])
+        .unwrap();
+    let (mut hconn_c, mut hconn_s, d) = connect_with(default_http3_client(), hconn_s);
+    let out = hconn_s.process(d, now());
+    hconn_c.process(out.dgram(), now());
+
+    assert!(hconn_c
+        .events()
+        .any(|e| matches!(e, Http3ClientEvent::OriginReceived)));
+    assert_eq!(
+        hconn_c.origins(),
+        &[
+            String::from("https://a.com"),
+            String::from("https://b.example.org"),
+            String::from("https://c.com:8443")
+        ]
+    );
+    assert!(hconn_c.is_origin_advertised("https://A.com:443"));
+    assert!(hconn_c.is_origin_advertised("https://b.example.org"));
+    assert!(!hconn_c.is_origin_advertised("https://c.com"));
+    assert!(!hconn_c.is_origin_advertised("http://a.com"));
+}
+
+#[test]
+fn test_no_origin() {
+    let (hconn_c, _hconn_s, _d) = connect();
+    assert!(hconn_c.origins().is_empty());
+    assert!(!hconn_c.is_origin_advertised("https://something.com"));
+}
+
+#[test]
+fn test_extension_settings() {
+    let mut hconn_c = default_http3_client();
+    // Settings managed by neqo-http3 cannot be set and identifiers cannot be repeated.
+    assert_eq!(
+        hconn_c.set_extension_settings(&[(0x8, 1)]),
+        Err(Error::InvalidInput)
+    );
+    assert_eq!(
+        hconn_c.set_extension_settings(&[(0x4242, 1), (0x4242, 2)]),
+        Err(Error::InvalidInput)
+    );
+    hconn_c.set_extension_settings(&[(0x4242, 7)]).unwrap();
+    let mut hconn_s = default_http3_server();
+    hconn_s.set_extension_settings(&[(0x4243, 9)]).unwrap();
+
+    let (mut hconn_c, mut hconn_s, d) = connect_with(hconn_c, hconn_s);
+    let out = hconn_s.process(d, now());
+    hconn_c.process(out.dgram(), now());
+
+    assert_eq!(hconn_c.peer_extension_settings(), vec![(0x4243, 9)]);
+    let conn = hconn_s
+        .events()
+        .find_map(|e| {
+            if let Http3ServerEvent::StateChange { conn, .. } = e {
+                Some(conn)
+            } else {
+                None
+            }
+        })
+        .unwrap();
+    assert_eq!(hconn_s.peer_extension_settings(&conn), vec![(0x4242, 7)]);
+
+    // The settings cannot be changed once they have been sent.
+    assert_eq!(
+        hconn_c.set_extension_settings(&[(0x4242, 8)]),
+        Err(Error::AlreadyInitialized)
+    );
+}
+
+#[test]
+fn test_send_request() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect();
+    let request = Request::post("https://something.com/upload", b"hello")
+        .unwrap()
+        .header("Content-Type", "text/plain");
+    assert_eq!(hconn_c.send_request(now(), &request).unwrap(), 0);
+    let out = hconn_c.process(dgram, now());
+    let out = hconn_s.process(out.dgram(), now());
+    let _ = hconn_c.process(out.dgram(), now());
+
+    let mut headers_received = false;
+    let mut data_received = Vec::new();
+    let mut fin_received = false;
+    while let Some(event) = hconn_s.next_event() {
+        match event {
+            Http3ServerEvent::Headers { headers, fin, .. } => {
+                assert_eq!(
+                    headers,
+                    vec![
+                        (String::from(":method"), String::from("POST")),
+                        (String::from(":scheme"), String::from("https")),
+                        (String::from(":authority"), String::from("something.com")),
+                        (String::from(":path"), String::from("/upload")),
+                        (String::from("content-type"), String::from("text/plain")),
+                        (String::from("content-length"), String::from("5")),
+                    ]
+                );
+                assert!(!fin);
+                headers_received = true;
+            }
+            Http3ServerEvent::Data { data, fin, .. } => {
+                data_received.extend_from_slice(&data);
+                fin_received = fin;
+            }
+            _ => {}
+        }
+    }
+    assert!(headers_received);
+    assert_eq!(data_received, b"hello");
+    assert!(fin_received);
+}
+
+#[test]
+fn test_max_field_section_size() {
+    let mut hconn_c = default_http3_client();
+    hconn_c.set_max_field_section_size(100).unwrap();
+    let mut hconn_s = default_http3_server();
+    hconn_s.set_max_field_section_size(200);
+    let (mut hconn_c, mut hconn_s, d) = connect_with(hconn_c, hconn_s);
+    let out = hconn_s.process(d, now());
+    hconn_c.process(out.dgram(), now());
+
+    // The request headers are limited by the server.
+    let large = vec![(String::from("my-header"), "a".repeat(30))];
+    assert_eq!(
+        hconn_c.fetch(now(), "GET", "https", "something.com", "/", &large),
+        Err(Error::FieldSectionTooLarge)
+    );
+    let req = hconn_c
+        .fetch(now(), "GET", "https", "something.com", "/", &[])
+        .unwrap();
+    hconn_c.stream_close_send(req).unwrap();
+    let out = hconn_c.process(None, now());
+    hconn_s.process(out.dgram(), now());
+
+    // The response headers are limited by the client.
+    let mut request = hconn_s
+        .events()
+        .find_map(|e| {
+            if let Http3ServerEvent::Headers { request, .. } = e {
+                Some(request)
+            } else {
+                None
+            }
+        })
+        .unwrap();
+    let mut headers = vec![(String::from(":status"), String::from("200"))];
+    headers.extend(large);
+    assert_eq!(
+        request.set_response(&headers, RESPONSE_DATA),
+        Err(Error::FieldSectionTooLarge)
+    );
+    request
+        .set_response(
+            &[
+                (String::from(":status"), String::from("200")),
+                (String::from("content-length"), String::from("3")),
+            ],
+            RESPONSE_DATA,
+        )
+        .unwrap();
+    let out = hconn_s.process(None, now());
+    hconn_c.process(out.dgram(), now());
+    let out = hconn_s.process(None, now());
+    hconn_c.process(out.dgram(), now());
+    process_client_events(&mut hconn_c);
+}
@@ -7,18 +7,19 @@
 #![cfg_attr(feature = "deny-warnings", deny(warnings))]
 #![warn(clippy::use_self)]
 
-use neqo_common::{event::Provider, hex, Datagram};
+use neqo_common::{event::Provider, hex, Datagram, Decoder};
 use neqo_crypto::{init, AuthenticationStatus, ResumptionToken};
 use neqo_http3::{Header, Http3Client, Http3ClientEvent, Http3Parameters, Http3State};
 use neqo_qpack::QpackSettings;
 use neqo_transport::{
     CongestionControlAlgorithm, Connection, ConnectionError, ConnectionEvent, Error,
-    FixedConnectionIdManager, Output, QuicVersion, State, StreamType,
+    FixedConnectionIdManager, Output, QuicVersion, State, StreamType, ZeroRttState,
 };
 
 use std::cell::RefCell;
 use std::cmp::min;
 use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
 use std::rc::Rc;
 // use std::path::PathBuf;
@@ -49,6 +50,33 @@ struct Args {
 
     #[structopt(long, default_value = "5")]
     timeout: u64,
+
+    #[structopt(long)]
+    /// A port where a peer always sends Retry, as LABEL=PORT, e.g. `local=4434`.
+    /// The Retry test only runs against peers that have one.
+    retry_port: Vec<RetryPort>,
+}
+
+/// A port to run the Retry test on, for the peer with `label`.
+#[derive(Debug, Clone)]
+struct RetryPort {
+    label: String,
+    port: u16,
+}
+
+impl FromStr for RetryPort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        match (parts.next(), parts.next().map(u16::from_str)) {
+            (Some(label), Some(Ok(port))) if !label.is_empty() => Ok(Self {
+                label: String::from(label),
+                port,
+            }),
+            _ => Err(format!("expected LABEL=PORT, not {}", s)),
+        }
+    }
 }
 
 trait Handler {
@@ -56,6 +84,45 @@ trait Handler {
     fn rewrite_out(&mut self, _dgram: &Datagram) -> Option<Datagram> {
         None
     }
+    /// Look at a datagram before the connection gets it.  Return `false` to stop
+    /// without passing the datagram on.
+    fn inspect_in(&mut self, _dgram: &Datagram) -> bool {
+        true
+    }
+}
+
+/// The parts of a long header packet that the tests look at.
+struct LongHeader<'a> {
+    /// The packet type bits from the first byte.
+    packet_type: u8,
+    version: u32,
+    dcid: &'a [u8],
+    scid: &'a [u8],
+    /// The remainder of the packet.
+    rest: Decoder<'a>,
+}
+
+impl<'a> LongHeader<'a> {
+    const INITIAL: u8 = 0;
+    const RETRY: u8 = 3;
+
+    fn decode(d: &'a [u8]) -> Option<Self> {
+        let mut dec = Decoder::from(d);
+        let first = dec.decode_byte()?;
+        if first & 0x80 == 0 {
+            return None;
+        }
+        let version = u32::try_from(dec.decode_uint(4)?).unwrap();
+        let dcid = dec.decode_vec(1)?;
+        let scid = dec.decode_vec(1)?;
+        Some(Self {
+            packet_type: (first >> 4) & 3,
+            version,
+            dcid,
+            scid,
+            rest: dec,
+        })
+    }
 }
 
 fn emit_datagram(socket: &UdpSocket, d: Datagram) {
@@ -147,6 +214,9 @@ fn process_loop(
         }
         if sz > 0 {
             let received = Datagram::new(nctx.remote_addr, nctx.local_addr, &buf[..sz]);
+            if !handler.inspect_in(&received) {
+                return Ok(client.state().clone());
+            }
             client.process_input(received, Instant::now());
         }
     }
@@ -371,10 +441,13 @@ impl H3Handler {
     }
 }
 
+#[derive(Clone, Copy)]
 struct Peer {
     label: &'static str,
     host: &'static str,
     port: u16,
+    /// A port where the peer always sends Retry, if it has one. `--retry-port` overrides this.
+    retry_port: Option<u16>,
 }
 
 impl Peer {
@@ -385,6 +458,15 @@ impl Peer {
             .expect("No remote addresses")
     }
 
+    /// The address to use for `test`.
+    fn test_addr(&self, test: &Test) -> SocketAddr {
+        let mut addr = self.addr();
+        if let (Test::Retry, Some(port)) = (test, self.retry_port) {
+            addr.set_port(port);
+        }
+        addr
+    }
+
     fn bind(&self) -> SocketAddr {
         match self.addr() {
             SocketAddr::V4(..) => SocketAddr::new(IpAddr::V4(Ipv4Addr::from([0; 4])), 0),
@@ -392,8 +474,11 @@ impl Peer {
         }
     }
 
-    fn test_enabled(&self, _test: &Test) -> bool {
-        true
+    fn test_enabled(&self, test: &Test) -> bool {
+        match test {
+            Test::Retry => self.retry_port.is_some(),
+            _ => true,
+        }
     }
 }
 
@@ -415,6 +500,7 @@ enum Test {
     R,
     Z,
     D,
+    Retry,
 }
 
 impl Test {
@@ -434,6 +520,7 @@ impl Test {
             Self::R => "r",
             Self::Z => "z",
             Self::D => "d",
+            Self::Retry => "retry",
         })
     }
 
@@ -446,6 +533,7 @@ impl Test {
             Self::R => vec!['R'],
             Self::Z => vec!['Z'],
             Self::D => vec!['d'],
+            Self::Retry => vec!['S'],
         }
     }
 }
@@ -456,7 +544,13 @@ struct NetworkCtx {
     socket: UdpSocket,
 }
 
-fn test_connect(nctx: &NetworkCtx, test: &Test, peer: &Peer) -> Result<Connection, String> {
+fn test_connect(
+    nctx: &NetworkCtx,
+    test: &Test,
+    peer: &Peer,
+    version: QuicVersion,
+    handler: &mut dyn Handler,
+) -> Result<Connection, String> {
     let mut client = Connection::new_client(
         peer.host,
         &test.alpn(),
@@ -464,12 +558,10 @@ fn test_connect(nctx: &NetworkCtx, test: &Test, peer: &Peer) -> Result<Connectio
         nctx.local_addr,
         nctx.remote_addr,
         &CongestionControlAlgorithm::NewReno,
-        QuicVersion::default(),
+        version,
     )
     .expect("must succeed");
-    // Temporary here to help out the type inference engine
-    let mut h = PreConnectHandler {};
-    let res = process_loop(nctx, &mut client, &mut h);
+    let res = process_loop(nctx, &mut client, handler);
 
     let st = match res {
         Ok(st) => st,
@@ -512,14 +604,14 @@ fn connect_h3(nctx: &NetworkCtx, peer: &Peer, client: Connection) -> Result<H3Ha
         streams: HashSet::new(),
         h3: Http3Client::new_with_conn(
             client,
-            &Http3Parameters {
-                qpack_settings: QpackSettings {
+            &Http3Parameters::new(
+                QpackSettings {
                     max_table_size_encoder: 16384,
                     max_table_size_decoder: 16384,
                     max_blocked_streams: 10,
                 },
-                max_concurrent_push_streams: 10,
-            },
+                10,
+            ),
         ),
         host: String::from(peer.host),
         path: String::from("/"),
@@ -576,13 +668,46 @@ fn test_h3(nctx: &NetworkCtx, peer: &Peer, client: Connection, test: &Test) -> R
     Ok(())
 }
 
-// Return true if 0RTT was negotiated.
+/// How the server treated 0-RTT data when the client resumed.
+#[derive(Debug, PartialEq)]
+enum EarlyData {
+    /// The client didn't try to send 0-RTT data.
+    NotAttempted,
+    /// The resumption token didn't allow 0-RTT.
+    NotAllowed,
+    Accepted,
+    Rejected,
+}
+
+/// What happened when the client reconnected with a resumption token.
+#[derive(Debug)]
+struct Resumption {
+    resumed: bool,
+    early_data: EarlyData,
+}
+
+impl Resumption {
+    /// The result of `test`, which reports resumption and 0-RTT separately
+    /// whether or not the test passed.
+    fn result(&self, test: &Test) -> String {
+        let ok = self.resumed && (*test != Test::Z || self.early_data == EarlyData::Accepted);
+        format!(
+            "{} (resumption {}, early data {:?})",
+            if ok { "OK" } else { "ERROR" },
+            if self.resumed { "accepted" } else { "rejected" },
+            self.early_data,
+        )
+    }
+}
+
+/// Make a full connection to get a resumption token, then reconnect with it.
+/// For `Test::Z`, a request is sent as 0-RTT data if the token allows it.
 fn test_h3_rz(
     nctx: &NetworkCtx,
     peer: &Peer,
     client: Connection,
     test: &Test,
-) -> Result<(), String> {
+) -> Result<Resumption, String> {
     let mut hc = connect_h3(nctx, peer, client)?;
 
     // Exchange some data to get http3 control streams and a resumption token.
@@ -607,14 +732,14 @@ fn test_h3_rz(
         nctx.remote_addr,
         &CongestionControlAlgorithm::NewReno,
         QuicVersion::default(),
-        &Http3Parameters {
-            qpack_settings: QpackSettings {
+        &Http3Parameters::new(
+            QpackSettings {
                 max_table_size_encoder: 16384,
                 max_table_size_decoder: 16384,
                 max_blocked_streams: 10,
             },
-            max_concurrent_push_streams: 0,
-        },
+            0,
+        ),
     );
     if handler.is_err() {
         return Err(String::from("ERROR: creating a client failed"));
@@ -630,13 +755,10 @@ fn test_h3_rz(
 
     hc.h3.enable_resumption(Instant::now(), res_token).unwrap();
 
-    if *test == Test::Z {
-        println!("Test 0RTT");
-        if Http3State::ZeroRtt != hc.h3.state() {
-            return Err("ERROR: zerortt not negotiated".into());
-        }
-
-        // SendH3 data during 0rtt
+    let early_data = if *test != Test::Z {
+        EarlyData::NotAttempted
+    } else if hc.h3.state() == Http3State::ZeroRtt {
+        // Send a request as 0-RTT data.
         let client_stream_id = hc
             .h3
             .fetch(Instant::now(), "GET", "https", &hc.host, &hc.path, &[])
@@ -646,28 +768,45 @@ fn test_h3_rz(
         if let Err(e) = process_loop_h3(nctx, &mut hc, false, true) {
             return Err(format!("ERROR: {}", e));
         }
-
-        let recvd_0rtt_reject = |e| e == Http3ClientEvent::ZeroRttRejected;
-        if hc.h3.events().any(recvd_0rtt_reject) {
-            return Err("ERROR: 0RTT rejected".into());
+        match hc.h3.conn().zero_rtt_state() {
+            ZeroRttState::AcceptedClient => EarlyData::Accepted,
+            ZeroRttState::Rejected => EarlyData::Rejected,
+            st => return Err(format!("ERROR: handshake incomplete, 0-RTT {:?}", st)),
         }
     } else {
-        println!("Test resumption");
+        EarlyData::NotAllowed
+    };
+
+    if early_data == EarlyData::NotAttempted || early_data == EarlyData::NotAllowed {
         if let Err(e) = process_loop_h3(nctx, &mut hc, true, true) {
             return Err(format!("ERROR: {}", e));
         }
     }
 
-    if !hc.h3.conn().stats().resumed {
-        return Err("ERROR: resumption failed".into());
-    }
-    Ok(())
+    Ok(Resumption {
+        resumed: hc.h3.conn().stats().resumed,
+        early_data,
+    })
 }
 
-struct VnHandler {}
+/// A reserved version, which peers have to answer with Version Negotiation.
+const UNKNOWN_VERSION: u32 = 0x1a2a_3a4a;
+
+/// Sends packets with an unknown version and collects the versions from the
+/// Version Negotiation packet that comes back. The packet is passed on to the
+/// client, which stops waiting once it has seen it.
+#[derive(Default)]
+struct VnHandler {
+    /// The connection IDs from the packets that were sent.
+    cids: Option<(Vec<u8>, Vec<u8>)>,
+    versions: Option<Vec<u32>>,
+}
 
 impl Handler for VnHandler {
     fn handle(&mut self, client: &mut Connection) -> bool {
+        if self.versions.is_some() {
+            return false;
+        }
         match client.state() {
             State::Connected => false,
             State::Closing { .. } => false,
@@ -676,13 +815,41 @@ impl Handler for VnHandler {
     }
 
     fn rewrite_out(&mut self, d: &Datagram) -> Option<Datagram> {
+        if let Some(hdr) = LongHeader::decode(&d[..]) {
+            self.cids = Some((hdr.dcid.to_vec(), hdr.scid.to_vec()));
+        }
         let mut payload = d[..].to_vec();
-        payload[1] = 0x1a;
+        payload[1..5].copy_from_slice(&UNKNOWN_VERSION.to_be_bytes());
         Some(Datagram::new(d.source(), d.destination(), payload))
     }
+
+    fn inspect_in(&mut self, d: &Datagram) -> bool {
+        let mut hdr = match LongHeader::decode(&d[..]) {
+            Some(hdr) if hdr.version == 0 => hdr,
+            _ => return true,
+        };
+        // Version Negotiation echoes the connection IDs from the client, swapped.
+        match &self.cids {
+            Some((dcid, scid)) if hdr.dcid == &scid[..] && hdr.scid == &dcid[..] => {}
+            _ => {
+                eprintln!("Version Negotiation with the wrong connection IDs");
+                return true;
+            }
+        }
+        let mut versions = Vec::new();
+        while let Some(v) = hdr.rest.decode_uint(4) {
+            versions.push(u32::try_from(v).unwrap());
+        }
+        self.versions = Some(versions);
+        true
+    }
 }
 
-fn test_vn(nctx: &NetworkCtx, peer: &Peer) -> Result<Connection, String> {
+/// Start with an unknown version and check the Version Negotiation packet and
+/// what the client makes of it. The client gives up on the connection, so
+/// connect again with a version that the peer offered.
+fn test_vn(nctx: &NetworkCtx, peer: &Peer) -> Result<(), String> {
+    let client_version = QuicVersion::default();
     let mut client = Connection::new_client(
         peer.host,
         &["hq-28"],
@@ -690,22 +857,110 @@ fn test_vn(nctx: &NetworkCtx, peer: &Peer) -> Result<Connection, String> {
         nctx.local_addr,
         nctx.remote_addr,
         &CongestionControlAlgorithm::NewReno,
-        QuicVersion::default(),
+        client_version,
     )
     .expect("must succeed");
-    // Temporary here to help out the type inference engine
-    let mut h = VnHandler {};
+    let mut h = VnHandler::default();
     let _res = process_loop(nctx, &mut client, &mut h);
 
-    Ok(client)
+    let versions = h.versions.ok_or("no Version Negotiation")?;
+    // A Version Negotiation packet that lists the version the client used
+    // is either broken or an attempt to downgrade.
+    if versions.contains(&UNKNOWN_VERSION) {
+        return Err(format!(
+            "Version Negotiation lists the version sent: {:x?}",
+            versions
+        ));
+    }
+
+    // The client believes it sent `client_version`. It has to ignore a Version
+    // Negotiation packet that lists that version and close the connection
+    // otherwise.
+    if versions.contains(&client_version.as_u32()) {
+        if *client.state() != State::WaitInitial {
+            return Err(format!(
+                "Version Negotiation listing {:x} was not ignored: {:?}",
+                client_version.as_u32(),
+                client.state()
+            ));
+        }
+    } else if *client.state()
+        != State::Closed(ConnectionError::Transport(Error::VersionNegotiation))
+    {
+        return Err(format!(
+            "Version Negotiation did not close the connection: {:?}",
+            client.state()
+        ));
+    }
+
+    let version = versions
+        .iter()
+        .find_map(|&v| QuicVersion::try_from(v).ok())
+        .ok_or_else(|| format!("no supported version in {:x?}", versions))?;
+    test_connect(nctx, &Test::VN, peer, version, &mut PreConnectHandler {})?;
+    Ok(())
+}
+
+/// Checks that the client uses the token and connection ID from a Retry.
+#[derive(Default)]
+struct RetryHandler {
+    /// The source connection ID and token from a Retry packet.
+    retry: Option<(Vec<u8>, Vec<u8>)>,
+    /// Set when an Initial is sent with the connection ID and token from the Retry.
+    token_used: bool,
+}
+
+impl Handler for RetryHandler {
+    fn handle(&mut self, client: &mut Connection) -> bool {
+        PreConnectHandler {}.handle(client)
+    }
+
+    fn rewrite_out(&mut self, d: &Datagram) -> Option<Datagram> {
+        if let (Some((scid, token)), Some(mut hdr)) = (&self.retry, LongHeader::decode(&d[..])) {
+            if hdr.packet_type == LongHeader::INITIAL
+                && hdr.dcid == &scid[..]
+                && hdr.rest.decode_vvec() == Some(&token[..])
+            {
+                self.token_used = true;
+            }
+        }
+        None
+    }
+
+    fn inspect_in(&mut self, d: &Datagram) -> bool {
+        if let Some(mut hdr) = LongHeader::decode(&d[..]) {
+            if hdr.version != 0 && hdr.packet_type == LongHeader::RETRY && self.retry.is_none() {
+                // The token is followed by a 16 byte integrity tag.
+                let token_len = hdr.rest.remaining().saturating_sub(16);
+                if let Some(token) = hdr.rest.decode(token_len) {
+                    self.retry = Some((hdr.scid.to_vec(), token.to_vec()));
+                }
+            }
+        }
+        true
+    }
+}
+
+fn test_retry(nctx: &NetworkCtx, peer: &Peer) -> Result<(), String> {
+    let mut h = RetryHandler::default();
+    test_connect(nctx, &Test::Retry, peer, QuicVersion::default(), &mut h)?;
+    if h.retry.is_none() {
+        return Err(String::from("no Retry"));
+    }
+    if !h.token_used {
+        return Err(String::from("Initial after Retry didn't use the token"));
+    }
+    Ok(())
 }
 
 fn run_test<'t>(peer: &Peer, test: &'t Test) -> (&'t Test, String) {
     let socket = UdpSocket::bind(peer.bind()).expect("Unable to bind UDP socket");
-    socket.connect(&peer).expect("Unable to connect UDP socket");
+    let remote_addr = peer.test_addr(test);
+    socket
+        .connect(remote_addr)
+        .expect("Unable to connect UDP socket");
 
     let local_addr = socket.local_addr().expect("Socket local address not bound");
-    let remote_addr = peer.addr();
 
     let nctx = NetworkCtx {
         socket,
@@ -713,20 +968,25 @@ fn run_test<'t>(peer: &Peer, test: &'t Test) -> (&'t Test, String) {
         remote_addr,
     };
 
-    if let Test::VN = test {
-        let res = test_vn(&nctx, peer);
+    let res = match test {
+        Test::VN => Some(test_vn(&nctx, peer)),
+        Test::Retry => Some(test_retry(&nctx, peer)),
+        _ => None,
+    };
+    if let Some(res) = res {
         return match res {
+            Ok(()) => (test, String::from("OK")),
             Err(e) => (test, format!("ERROR: {}", e)),
-            Ok(client) => match client.state() {
-                State::Closed(ConnectionError::Transport(Error::VersionNegotiation)) => {
-                    (test, String::from("OK"))
-                }
-                _ => (test, format!("ERROR: Wrong state {:?}", client.state())),
-            },
         };
     }
 
-    let mut client = match test_connect(&nctx, test, peer) {
+    let mut client = match test_connect(
+        &nctx,
+        test,
+        peer,
+        QuicVersion::default(),
+        &mut PreConnectHandler {},
+    ) {
         Ok(client) => client,
         Err(e) => return (test, e),
     };
@@ -737,9 +997,13 @@ fn run_test<'t>(peer: &Peer, test: &'t Test) -> (&'t Test, String) {
         }
         Test::H9 => test_h9(&nctx, &mut client),
         Test::H3 => test_h3(&nctx, peer, client, test),
-        Test::VN => unimplemented!(),
-        Test::R => test_h3_rz(&nctx, peer, client, test),
-        Test::Z => test_h3_rz(&nctx, peer, client, test),
+        Test::VN | Test::Retry => unreachable!(),
+        Test::R | Test::Z => {
+            return match test_h3_rz(&nctx, peer, client, test) {
+                Ok(resumption) => (test, resumption.result(test)),
+                Err(e) => (test, e),
+            };
+        }
         Test::D => test_h3(&nctx, peer, client, test),
     };
 
@@ -795,90 +1059,108 @@ const PEERS: &[Peer] = &[
         label: "quiche",
         host: "quic.tech",
         port: 4433,
+        retry_port: None,
     },
     Peer {
         label: "quiche2",
         host: "quic.tech",
         port: 8443,
+        retry_port: None,
     },
     Peer {
         label: "quiche3",
         host: "quic.tech",
         port: 8444,
+        retry_port: None,
     },
     Peer {
         label: "quant",
         host: "quant.eggert.org",
         port: 4433,
+        retry_port: None,
     },
     Peer {
         label: "quicly",
         host: "quic.examp1e.net",
         port: 443,
+        retry_port: None,
     },
     Peer {
         label: "quicly2",
         host: "quic.examp1e.net",
         port: 4433,
+        retry_port: None,
     },
     Peer {
         label: "local",
         host: "127.0.0.1",
         port: 4433,
+        // Run a second `neqo-server --retry` on this port.
+        retry_port: Some(4434),
     },
     Peer {
         label: "applequic",
         host: "[2a00:79e1:abc:301:fca8:166e:525f:9b5c]",
         port: 4433,
+        retry_port: None,
     },
     Peer {
         label: "f5",
         host: "f5quic.com",
         port: 4433,
+        retry_port: None,
     },
     Peer {
         label: "msft",
         host: "quic.westus.cloudapp.azure.com",
         port: 443,
+        retry_port: None,
     },
     Peer {
         label: "mvfst",
         host: "fb.mvfst.net",
         port: 443,
+        retry_port: None,
     },
     Peer {
         label: "google",
         host: "quic.rocks",
         port: 4433,
+        retry_port: None,
     },
     Peer {
         label: "ngtcp2",
         host: "nghttp2.org",
         port: 4433,
+        retry_port: None,
     },
     Peer {
         label: "picoquic",
         host: "test.privateoctopus.com",
         port: 4433,
+        retry_port: None,
     },
     Peer {
         label: "ats",
         host: "quic.ogre.com",
         port: 4433,
+        retry_port: None,
     },
     Peer {
         label: "cloudflare",
         host: "www.cloudflare.com",
         port: 443,
+        retry_port: None,
     },
     Peer {
         label: "litespeed",
         host: "http3-test.litespeedtech.com",
         port: 4433,
+        retry_port: None,
     },
 ];
 
-const TESTS: [Test; 7] = [
+const TESTS: [Test; 8] = [
     Test::Connect,
     Test::H9,
     Test::H3,
@@ -886,6 +1168,7 @@ const TESTS: [Test; 7] = [
     Test::R,
     Test::Z,
     Test::D,
+    Test::Retry,
 ];
 
 fn main() {
@@ -906,8 +1189,24 @@ fn main() {
             continue;
         }
 
+        let retry_port = args
+            .retry_port
+            .iter()
+            .rev()
+            .find(|r| r.label == peer.label)
+            .map(|r| r.port);
+        let peer: &'static Peer = if retry_port.is_some() {
+            // The tests borrow the peer from their threads, so this one lives until exit.
+            Box::leak(Box::new(Peer {
+                retry_port,
+                ..*peer
+            }))
+        } else {
+            peer
+        };
+
         let at = args.clone();
-        let child = thread::spawn(move || run_peer(&at, &peer));
+        let child = thread::spawn(move || run_peer(&at, peer));
         children.push((peer, child));
     }
 
@@ -917,7 +1216,7 @@ fn main() {
         let mut all_letters = HashSet::new();
         for r in &res {
             for l in r.0.letters() {
-                if r.1 == "OK" {
+                if r.1.starts_with("OK") {
                     all_letters.insert(l);
                 }
             }
@@ -13,8 +13,8 @@ case "$ROLE" in
   client)
     /wait-for-it.sh sim:57832 -s -t 30
     sleep 5
-    RUST_LOG=debug RUST_BACKTRACE=1 neqo-client --qns-test "$TESTCASE" --qlog-dir "$QLOGDIR" \
-        --output-dir /downloads $REQUESTS
+    # TESTCASE, QLOGDIR and REQUESTS are read from the environment.
+    RUST_LOG=debug RUST_BACKTRACE=1 neqo-client
     ;;
 
   server)
@@ -27,8 +27,7 @@ case "$ROLE" in
         -name "$CERT" -passout pass: -out "$P12CERT"
     pk12util -d "sql:$DB" -i "$P12CERT" -W ''
     certutil -L -d "sql:$DB" -n "$CERT"
-    RUST_LOG=info RUST_BACKTRACE=1 neqo-server --qns-test "$TESTCASE" --qlog-dir "$QLOGDIR" \
-        -d "$DB" -k "$CERT" [::]:443
+    RUST_LOG=info RUST_BACKTRACE=1 neqo-server -d "$DB" -k "$CERT" [::]:443
     ;;
 
   *)
@@ -0,0 +1,94 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Encoding of typical browser request headers. The dynamic table is not used, so this
+// measures the static table lookups and the encoding of field lines.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use neqo_qpack::{Header, QPackEncoder, QpackSettings};
+use neqo_transport::StreamType;
+use test_fixture::connect;
+
+fn h(name: &str, value: &str) -> Header {
+    (String::from(name), String::from(value))
+}
+
+fn navigation_request() -> Vec<Header> {
+    vec![
+        h(":method", "GET"),
+        h(":scheme", "https"),
+        h(":authority", "www.example.com"),
+        h(":path", "/"),
+        h(
+            "user-agent",
+            "Mozilla/5.0 (X11; Linux x86_64; rv:89.0) Gecko/20100101 Firefox/89.0",
+        ),
+        h(
+            "accept",
+            "text/html,application/xhtml+xml,application/xml;q=0.9,image/webp,*/*;q=0.8",
+        ),
+        h("accept-language", "en-US,en;q=0.5"),
+        h("accept-encoding", "gzip, deflate, br"),
+        h("upgrade-insecure-requests", "1"),
+        h("sec-fetch-dest", "document"),
+        h("sec-fetch-mode", "navigate"),
+        h("sec-fetch-site", "none"),
+        h("sec-fetch-user", "?1"),
+        h("te", "trailers"),
+    ]
+}
+
+fn subresource_request() -> Vec<Header> {
+    vec![
+        h(":method", "GET"),
+        h(":scheme", "https"),
+        h(":authority", "www.example.com"),
+        h(":path", "/images/logo.png"),
+        h(
+            "user-agent",
+            "Mozilla/5.0 (X11; Linux x86_64; rv:89.0) Gecko/20100101 Firefox/89.0",
+        ),
+        h("accept", "image/webp,*/*"),
+        h("accept-language", "en-US,en;q=0.5"),
+        h("accept-encoding", "gzip, deflate, br"),
+        h("referer", "https://www.example.com/"),
+        h("cookie", "session=0123456789abcdef"),
+        h("sec-fetch-dest", "image"),
+        h("sec-fetch-mode", "no-cors"),
+        h("sec-fetch-site", "same-origin"),
+        h("if-modified-since", "Mon, 07 Jun 2021 10:00:00 GMT"),
+        h("if-none-match", "\"5f3c-5c4a3b2a1d0e0\""),
+    ]
+}
+
+fn encode_headers(c: &mut Criterion) {
+    let (mut conn, _peer) = connect();
+    let mut encoder = QPackEncoder::new(
+        QpackSettings {
+            max_table_size_encoder: 0,
+            max_table_size_decoder: 0,
+            max_blocked_streams: 0,
+        },
+        true,
+    );
+    encoder.add_send_stream(conn.stream_create(StreamType::UniDi).unwrap());
+
+    for (name, headers) in &[
+        ("navigation request", navigation_request()),
+        ("subresource request", subresource_request()),
+    ] {
+        c.bench_function(&format!("encode {}", name), |b| {
+            b.iter(|| {
+                encoder
+                    .encode_header_block(&mut conn, black_box(headers), 0)
+                    .unwrap()
+            })
+        });
+    }
+}
+
+criterion_group!(benches, encode_headers);
+criterion_main!(benches);
@@ -8,7 +8,7 @@ use crate::decoder_instructions::DecoderInstruction;
 use crate::encoder_instructions::{DecodedEncoderInstruction, EncoderInstructionReader};
 use crate::header_block::{HeaderDecoder, HeaderDecoderResult};
 use crate::qpack_send_buf::QPData;
-use crate::reader::ReceiverConnWrapper;
+use crate::reader::{ReadByte, Reader, ReceiverConnWrapper};
 use crate::stats::Stats;
 use crate::table::HeaderTable;
 use crate::{Error, Header, QpackSettings, Res};
@@ -52,11 +52,19 @@ impl QPackDecoder {
         }
     }
 
+    /// The current capacity of the dynamic table, as set by the peer's encoder.
     #[must_use]
-    fn capacity(&self) -> u64 {
+    pub fn table_capacity(&self) -> u64 {
         self.table.capacity()
     }
 
+    /// The size of the entries in the dynamic table: the length of the name and value of each
+    /// entry plus 32 (RFC 9204, Section 3.2.1).
+    #[must_use]
+    pub fn table_used(&self) -> u64 {
+        self.table.used()
+    }
+
     #[must_use]
     pub fn get_max_table_size(&self) -> u64 {
         self.max_table_size
@@ -90,9 +98,12 @@ impl QPackDecoder {
     }
 
     fn read_instructions(&mut self, conn: &mut Connection, stream_id: u64) -> Res<()> {
-        let mut recv = ReceiverConnWrapper::new(conn, stream_id);
+        self.read_instructions_from(&mut ReceiverConnWrapper::new(conn, stream_id))
+    }
+
+    pub(crate) fn read_instructions_from<T: ReadByte + Reader>(&mut self, recv: &mut T) -> Res<()> {
         loop {
-            match self.instruction_reader.read_instructions(&mut recv) {
+            match self.instruction_reader.read_instructions(recv) {
                 Ok(instruction) => self.execute_instruction(instruction)?,
                 Err(Error::NeedMoreData) => break Ok(()),
                 Err(e) => break Err(e),
@@ -192,22 +203,23 @@ impl QPackDecoder {
 
         match decoder.decode_header_block(&self.table, self.max_entries, self.table.base()) {
             Ok(HeaderDecoderResult::Blocked(req_insert_cnt)) => {
-                if self.blocked_streams.len() > self.max_blocked_streams {
-                    Err(Error::DecompressionFailed)
-                } else {
-                    let r = self
-                        .blocked_streams
-                        .iter()
-                        .filter_map(|(id, req)| if *id == stream_id { Some(*req) } else { None })
-                        .collect::<Vec<_>>();
-                    if !r.is_empty() {
-                        debug_assert!(r.len() == 1);
-                        debug_assert!(r[0] == req_insert_cnt);
-                        return Ok(None);
-                    }
-                    self.blocked_streams.push((stream_id, req_insert_cnt));
-                    Ok(None)
+                let r = self
+                    .blocked_streams
+                    .iter()
+                    .filter_map(|(id, req)| if *id == stream_id { Some(*req) } else { None })
+                    .collect::<Vec<_>>();
+                if !r.is_empty() {
+                    debug_assert!(r.len() == 1);
+                    debug_assert!(r[0] == req_insert_cnt);
+                    return Ok(None);
                 }
+                // A new blocked stream must not exceed the limit announced in
+                // SETTINGS_QPACK_BLOCKED_STREAMS.
+                if self.blocked_streams.len() >= self.max_blocked_streams {
+                    return Err(Error::DecompressionFailed);
+                }
+                self.blocked_streams.push((stream_id, req_insert_cnt));
+                Ok(None)
             }
             Ok(HeaderDecoderResult::Headers(h)) => {
                 if decoder.get_req_insert_cnt() != 0 {
@@ -265,7 +277,7 @@ impl QPackDecoder {
 
 impl ::std::fmt::Display for QPackDecoder {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        write!(f, "QPackDecoder {}", self.capacity())
+        write!(f, "QPackDecoder {}", self.table_capacity())
     }
 }
 
@@ -294,6 +306,10 @@ mod tests {
     }
 
     fn connect() -> TestDecoder {
+        connect_with_blocked_streams(100)
+    }
+
+    fn connect_with_blocked_streams(max_blocked_streams: u16) -> TestDecoder {
         let (mut conn, mut peer_conn) = test_fixture::connect();
 
         // create a stream
@@ -304,7 +320,7 @@ mod tests {
         let mut decoder = QPackDecoder::new(QpackSettings {
             max_table_size_encoder: 0,
             max_table_size_decoder: 300,
-            max_blocked_streams: 100,
+            max_blocked_streams,
         });
         decoder.add_send_stream(send_stream_id);
 
@@ -378,7 +394,7 @@ mod tests {
         send_instructions_and_check(&mut decoder, decoder_instruction);
 
         if check_capacity > 0 {
-            assert_eq!(decoder.decoder.capacity(), check_capacity);
+            assert_eq!(decoder.decoder.table_capacity(), check_capacity);
         }
     }
 
@@ -805,4 +821,250 @@ mod tests {
 
         decode_headers(&mut decoder, HEADER_BLOCK, &headers, 0);
     }
+
+    const ENCODER_INST_HEADER_A: &[u8] = &[
+        0x4a, 0x6d, 0x79, 0x2d, 0x68, 0x65, 0x61, 0x64, 0x65, 0x72, 0x61, 0x09, 0x6d, 0x79, 0x2d,
+        0x76, 0x61, 0x6c, 0x75, 0x65, 0x61,
+    ];
+    const ENCODER_INST_HEADER_B: &[u8] = &[
+        0x4a, 0x6d, 0x79, 0x2d, 0x68, 0x65, 0x61, 0x64, 0x65, 0x72, 0x62, 0x09, 0x6d, 0x79, 0x2d,
+        0x76, 0x61, 0x6c, 0x75, 0x65, 0x62,
+    ];
+    // A header block that refers to the first entry, which has not been inserted yet.
+    const HEADER_BLOCK_REF_HEADER_A: &[u8] = &[0x02, 0x80, 0x10];
+    // A header block that refers to the second entry, which has not been inserted yet.
+    const HEADER_BLOCK_REF_HEADER_B: &[u8] = &[0x03, 0x81, 0x11];
+
+    // Deliver encoder instructions and return the streams that have been unblocked.
+    fn recv_instruction_unblock(decoder: &mut TestDecoder, encoder_instruction: &[u8]) -> Vec<u64> {
+        let _ = decoder
+            .peer_conn
+            .stream_send(decoder.recv_stream_id, encoder_instruction);
+        let out = decoder.peer_conn.process(None, now());
+        let _ = decoder.conn.process(out.dgram(), now());
+        decoder
+            .decoder
+            .receive(&mut decoder.conn, decoder.recv_stream_id)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_blocked_header_block() {
+        // A header block arrives before the encoder instruction it depends on. It is held until
+        // the instruction arrives and acknowledged only after it has been decoded.
+        let mut decoder = connect();
+        assert!(decoder.decoder.set_capacity(200).is_ok());
+
+        assert_eq!(
+            decoder
+                .decoder
+                .decode_header_block(HEADER_BLOCK_REF_HEADER_A, 0),
+            Ok(None)
+        );
+        // Decoding again while blocked does not count as another blocked stream.
+        assert_eq!(
+            decoder
+                .decoder
+                .decode_header_block(HEADER_BLOCK_REF_HEADER_A, 0),
+            Ok(None)
+        );
+
+        assert_eq!(
+            recv_instruction_unblock(&mut decoder, ENCODER_INST_HEADER_A),
+            vec![0]
+        );
+        let headers = vec![(String::from("my-headera"), String::from("my-valuea"))];
+        decode_headers(&mut decoder, HEADER_BLOCK_REF_HEADER_A, &headers, 0);
+        send_instructions_and_check(&mut decoder, &[0x03, 0x80]);
+    }
+
+    #[test]
+    fn test_blocked_header_blocks_unblocked_in_order_of_inserts() {
+        // Two streams are blocked on different entries. Each is released as soon as the entry
+        // it depends on arrives, regardless of the order in which the streams were blocked.
+        let mut decoder = connect();
+        assert!(decoder.decoder.set_capacity(200).is_ok());
+
+        assert_eq!(
+            decoder
+                .decoder
+                .decode_header_block(HEADER_BLOCK_REF_HEADER_B, 0),
+            Ok(None)
+        );
+        assert_eq!(
+            decoder
+                .decoder
+                .decode_header_block(HEADER_BLOCK_REF_HEADER_A, 4),
+            Ok(None)
+        );
+
+        assert_eq!(
+            recv_instruction_unblock(&mut decoder, ENCODER_INST_HEADER_A),
+            vec![4]
+        );
+        let headers = vec![(String::from("my-headera"), String::from("my-valuea"))];
+        decode_headers(&mut decoder, HEADER_BLOCK_REF_HEADER_A, &headers, 4);
+
+        assert_eq!(
+            recv_instruction_unblock(&mut decoder, ENCODER_INST_HEADER_B),
+            vec![0]
+        );
+        let headers = vec![(String::from("my-headerb"), String::from("my-valueb"))];
+        decode_headers(&mut decoder, HEADER_BLOCK_REF_HEADER_B, &headers, 0);
+    }
+
+    #[test]
+    fn test_blocked_streams_limit() {
+        let mut decoder = connect_with_blocked_streams(1);
+        assert!(decoder.decoder.set_capacity(200).is_ok());
+
+        assert_eq!(
+            decoder
+                .decoder
+                .decode_header_block(HEADER_BLOCK_REF_HEADER_A, 0),
+            Ok(None)
+        );
+        assert_eq!(
+            decoder
+                .decoder
+                .decode_header_block(HEADER_BLOCK_REF_HEADER_B, 4),
+            Err(Error::DecompressionFailed)
+        );
+    }
+
+    // The static table has 99 entries, index 99 does not exist.
+    #[test]
+    fn static_index_out_of_range() {
+        let mut decoder = connect();
+        assert_eq!(
+            decoder
+                .decoder
+                .decode_header_block(&[0x00, 0x00, 0xff, 0x23], 0),
+            Ok(Some(vec![(
+                String::from("x-frame-options"),
+                String::from("sameorigin")
+            )]))
+        );
+        assert_eq!(
+            decoder
+                .decoder
+                .decode_header_block(&[0x00, 0x00, 0xff, 0x24], 0),
+            Err(Error::DecompressionFailed)
+        );
+    }
+
+    #[test]
+    fn test_no_blocked_streams_allowed() {
+        let mut decoder = connect_with_blocked_streams(0);
+        assert!(decoder.decoder.set_capacity(200).is_ok());
+
+        assert_eq!(
+            decoder
+                .decoder
+                .decode_header_block(HEADER_BLOCK_REF_HEADER_A, 0),
+            Err(Error::DecompressionFailed)
+        );
+    }
+
+    #[test]
+    fn test_cancel_blocked_stream() {
+        // A reset stream is no longer blocked and a stream cancellation is sent.
+        let mut decoder = connect();
+        assert!(decoder.decoder.set_capacity(200).is_ok());
+
+        assert_eq!(
+            decoder
+                .decoder
+                .decode_header_block(HEADER_BLOCK_REF_HEADER_A, 0),
+            Ok(None)
+        );
+        decoder.decoder.cancel_stream(0);
+        assert!(recv_instruction_unblock(&mut decoder, ENCODER_INST_HEADER_A).is_empty());
+        send_instructions_and_check(&mut decoder, &[0x03, 0x40, 0x01]);
+    }
+
+    fn h(name: &str, value: &str) -> Header {
+        (String::from(name), String::from(value))
+    }
+
+    // The dynamic table examples of RFC 9204, Appendix B.2 to B.5. The decoder's maximum
+    // capacity is 300, so the Required Insert Counts decode the same as in the examples.
+    #[test]
+    fn rfc_examples_dynamic_table() {
+        let mut decoder = connect();
+
+        // B.2: Set Dynamic Table Capacity=220 and two inserts with static name references.
+        recv_instruction(
+            &mut decoder,
+            &[
+                0x3f, 0xbd, 0x01, 0xc0, 0x0f, 0x77, 0x77, 0x77, 0x2e, 0x65, 0x78, 0x61, 0x6d, 0x70,
+                0x6c, 0x65, 0x2e, 0x63, 0x6f, 0x6d, 0xc1, 0x0c, 0x2f, 0x73, 0x61, 0x6d, 0x70, 0x6c,
+                0x65, 0x2f, 0x70, 0x61, 0x74, 0x68,
+            ],
+            &Ok(()),
+        );
+        assert_eq!(decoder.decoder.table_capacity(), 220);
+        assert_eq!(decoder.decoder.table_used(), 106);
+        decode_headers(
+            &mut decoder,
+            &[0x03, 0x81, 0x10, 0x11],
+            &[
+                h(":authority", "www.example.com"),
+                h(":path", "/sample/path"),
+            ],
+            4,
+        );
+        // Section Acknowledgment for stream 4.
+        send_instructions_and_check(&mut decoder, &[0x03, 0x84]);
+
+        // B.3: Insert With Literal Name custom-key=custom-value.
+        recv_instruction(
+            &mut decoder,
+            &[
+                0x4a, 0x63, 0x75, 0x73, 0x74, 0x6f, 0x6d, 0x2d, 0x6b, 0x65, 0x79, 0x0c, 0x63, 0x75,
+                0x73, 0x74, 0x6f, 0x6d, 0x2d, 0x76, 0x61, 0x6c, 0x75, 0x65,
+            ],
+            &Ok(()),
+        );
+        assert_eq!(decoder.decoder.table_used(), 160);
+        // Insert Count Increment of 1.
+        send_instructions_and_check(&mut decoder, &[0x01]);
+
+        // B.4: Duplicate of the first entry.
+        recv_instruction(&mut decoder, &[0x02], &Ok(()));
+        assert_eq!(decoder.decoder.table_used(), 217);
+        // Required Insert Count=4 and Base=4 with two dynamic and one static reference. In the
+        // RFC the stream is cancelled before the block is acknowledged; here it is decoded.
+        decode_headers(
+            &mut decoder,
+            &[0x05, 0x00, 0x80, 0xc1, 0x81],
+            &[
+                h(":authority", "www.example.com"),
+                h(":path", "/"),
+                h("custom-key", "custom-value"),
+            ],
+            8,
+        );
+        send_instructions_and_check(&mut decoder, &[0x88]);
+
+        // B.5: Insert With Name Reference to the dynamic table. The table is full, so the
+        // oldest entry (57 bytes) is evicted to make space for the new one (55 bytes).
+        recv_instruction(
+            &mut decoder,
+            &[
+                0x81, 0x0d, 0x63, 0x75, 0x73, 0x74, 0x6f, 0x6d, 0x2d, 0x76, 0x61, 0x6c, 0x75, 0x65,
+                0x32,
+            ],
+            &Ok(()),
+        );
+        assert_eq!(decoder.decoder.table_used(), 215);
+        send_instructions_and_check(&mut decoder, &[0x01]);
+
+        // The evicted entry can no longer be referenced: Required Insert Count=5, Base=5 and a
+        // reference to the relative index 4, i.e. absolute index 0.
+        assert_eq!(
+            decoder.decoder.decode_header_block(&[0x06, 0x00, 0x84], 12),
+            Err(Error::DecompressionFailed)
+        );
+    }
 }
@@ -14,6 +14,7 @@ use crate::stats::Stats;
 use crate::table::{HeaderTable, LookupResult, ADDITIONAL_TABLE_ENTRY_SIZE};
 use crate::Header;
 use crate::{Error, QpackSettings, Res};
+use neqo_common::metrics::{self, MetricsRef};
 use neqo_common::{qdebug, qerror, qlog::NeqoQlog, qtrace};
 use neqo_transport::{Connection, Error as TransportError, StreamId};
 use std::collections::{HashMap, HashSet, VecDeque};
@@ -55,6 +56,7 @@ pub struct QPackEncoder {
     use_huffman: bool,
     next_capacity: Option<u64>,
     stats: Stats,
+    metrics: MetricsRef,
 }
 
 impl QPackEncoder {
@@ -73,6 +75,7 @@ impl QPackEncoder {
             use_huffman,
             next_capacity: None,
             stats: Stats::default(),
+            metrics: metrics::no_metrics(),
         }
     }
 
@@ -86,6 +89,10 @@ impl QPackEncoder {
             return Err(Error::EncoderStream);
         }
 
+        // The Required Insert Count is encoded using the maximum capacity of the peer's
+        // decoder (RFC 9204, Section 4.5.1.1), even if the encoder uses a smaller table.
+        self.max_entries = cap / 32;
+
         if cap == self.table.capacity() {
             return Ok(());
         }
@@ -251,25 +258,63 @@ impl QPackEncoder {
             return Err(Error::DynamicTableFull);
         }
 
-        let mut buf = QPData::default();
-        EncoderInstruction::InsertWithNameLiteral {
-            name: &name,
-            value: &value,
+        self.send_instruction(
+            conn,
+            &EncoderInstruction::InsertWithNameLiteral {
+                name: &name,
+                value: &value,
+            },
+        )?;
+
+        self.stats.dynamic_table_inserts += 1;
+        self.metrics
+            .counter_inc(metrics::QPACK_DYNAMIC_TABLE_INSERTS, 1);
+
+        match self.table.insert(name, value) {
+            Ok(inx) => Ok(inx),
+            Err(e) => {
+                debug_assert!(false);
+                Err(e)
+            }
         }
-        .marshal(&mut buf, self.use_huffman);
+    }
 
-        let stream_id = self.local_stream.stream_id().ok_or(Error::Internal)?;
+    /// Inserts a new entry whose name is the name of the dynamic table entry `name_index` and sends an
+    /// InsertWithNameRef instruction to a peer. As with `send_and_insert`, an entry is added only if the
+    /// instruction can be sent immediately.
+    fn send_and_insert_with_name_ref(
+        &mut self,
+        conn: &mut Connection,
+        name_index: u64,
+        name: &[u8],
+        value: &[u8],
+    ) -> Res<u64> {
+        qdebug!([self], "insert with name ref {} {:?}.", name_index, value);
 
-        let sent = conn
-            .stream_send_atomic(stream_id.as_u64(), &buf)
-            .map_err(|e| map_stream_send_atomic_error(&e))?;
-        if !sent {
-            return Err(Error::EncoderStreamBlocked);
+        let entry_size = name.len() + value.len() + ADDITIONAL_TABLE_ENTRY_SIZE;
+
+        if !self.table.insert_possible(entry_size) {
+            return Err(Error::DynamicTableFull);
         }
 
+        // On the encoder stream the index is relative to the number of inserts.
+        let relative_index = self.table.base() - name_index - 1;
+        self.send_instruction(
+            conn,
+            &EncoderInstruction::InsertWithNameRefDynamic {
+                index: relative_index,
+                value: &value,
+            },
+        )?;
+
         self.stats.dynamic_table_inserts += 1;
+        self.metrics
+            .counter_inc(metrics::QPACK_DYNAMIC_TABLE_INSERTS, 1);
 
-        match self.table.insert(name, value) {
+        match self
+            .table
+            .insert_with_name_ref(false, relative_index, value)
+        {
             Ok(inx) => Ok(inx),
             Err(e) => {
                 debug_assert!(false);
@@ -278,6 +323,26 @@ impl QPackEncoder {
         }
     }
 
+    fn send_instruction(
+        &mut self,
+        conn: &mut Connection,
+        instruction: &EncoderInstruction,
+    ) -> Res<()> {
+        let mut buf = QPData::default();
+        instruction.marshal(&mut buf, self.use_huffman);
+
+        let stream_id = self.local_stream.stream_id().ok_or(Error::Internal)?;
+
+        let sent = conn
+            .stream_send_atomic(stream_id.as_u64(), &buf)
+            .map_err(|e| map_stream_send_atomic_error(&e))?;
+        if sent {
+            Ok(())
+        } else {
+            Err(Error::EncoderStreamBlocked)
+        }
+    }
+
     fn change_capacity(&mut self, value: u64) {
         qdebug!([self], "change capacity: {}", value);
         self.next_capacity = Some(value);
@@ -305,7 +370,6 @@ impl QPackEncoder {
                 );
                 return Err(Error::InternalError);
             }
-            self.max_entries = cap / 32;
             self.next_capacity = None;
         }
         Ok(())
@@ -397,11 +461,34 @@ impl QPackEncoder {
                     if static_table { "static" } else { "dynamic" },
                     value_matches
                 );
+                if !value_matches && !static_table && can_block & !encoder_blocked {
+                    // The name has been inserted before, so it is likely to be used again. Insert the new value
+                    // using an InsertWithNameRef instruction.
+                    match self.send_and_insert_with_name_ref(conn, index, &name, &value) {
+                        Ok(new_index) => {
+                            encoded_h.encode_indexed_dynamic(new_index);
+                            ref_entries.insert(new_index);
+                            self.table.add_ref(new_index);
+                            continue;
+                        }
+                        Err(Error::EncoderStreamBlocked) | Err(Error::DynamicTableFull) => {
+                            encoder_blocked = true;
+                        }
+                        Err(e) => {
+                            // `InternalError`, `ClosedCriticalStream`
+                            return Err(e);
+                        }
+                    }
+                }
                 if value_matches {
                     if static_table {
                         encoded_h.encode_indexed_static(index);
+                        self.metrics
+                            .counter_inc(metrics::QPACK_STATIC_TABLE_HITS, 1);
                     } else {
                         encoded_h.encode_indexed_dynamic(index);
+                        self.metrics
+                            .counter_inc(metrics::QPACK_DYNAMIC_TABLE_HITS, 1);
                     }
                 } else {
                     encoded_h.encode_literal_with_name_ref(static_table, index, &value);
@@ -481,6 +568,25 @@ impl QPackEncoder {
         &self.stats
     }
 
+    /// Set where table hits and inserts are reported.
+    pub fn set_metrics(&mut self, metrics: MetricsRef) {
+        self.metrics = metrics;
+    }
+
+    /// The current capacity of the dynamic table. This changes only once the Set Dynamic Table
+    /// Capacity instruction has been sent.
+    #[must_use]
+    pub fn table_capacity(&self) -> u64 {
+        self.table.capacity()
+    }
+
+    /// The size of the entries in the dynamic table: the length of the name and value of each
+    /// entry plus 32 (RFC 9204, Section 3.2.1).
+    #[must_use]
+    pub fn table_used(&self) -> u64 {
+        self.table.used()
+    }
+
     #[must_use]
     pub fn local_stream_id(&self) -> Option<u64> {
         self.local_stream.stream_id().map(StreamId::as_u64)
@@ -734,13 +840,13 @@ mod tests {
                 header_block: ENCODE_INDEXED_REF_DYNAMIC,
                 encoder_inst: &[],
             },
-            // test encode_literal_with_name_ref.
+            // test adding a new header with a name ref to the dynamic table.
             TestElement {
                 headers: vec![(String::from("my-header"), String::from("my-value2"))],
-                header_block: &[
-                    0x02, 0x00, 0x40, 0x09, 0x6d, 0x79, 0x2d, 0x76, 0x61, 0x6c, 0x75, 0x65, 0x32,
+                header_block: &[0x03, 0x80, 0x10],
+                encoder_inst: &[
+                    0x80, 0x09, 0x6d, 0x79, 0x2d, 0x76, 0x61, 0x6c, 0x75, 0x65, 0x32,
                 ],
-                encoder_inst: &[],
             },
             // test multiple headers
             TestElement {
@@ -751,7 +857,7 @@ mod tests {
                     (String::from(":scheme"), String::from("https")),
                 ],
                 header_block: &[
-                    0x00, 0x01, 0xd1, 0x51, 0x0a, 0x2f, 0x73, 0x6f, 0x6d, 0x65, 0x77, 0x68, 0x65,
+                    0x00, 0x02, 0xd1, 0x51, 0x0a, 0x2f, 0x73, 0x6f, 0x6d, 0x65, 0x77, 0x68, 0x65,
                     0x72, 0x65, 0x50, 0x0b, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x2e, 0x63,
                     0x6f, 0x6d, 0xd7,
                 ],
@@ -809,13 +915,11 @@ mod tests {
                 header_block: ENCODE_INDEXED_REF_DYNAMIC,
                 encoder_inst: &[],
             },
-            // test encode_literal_with_name_ref.
+            // test adding a new header with a name ref to the dynamic table.
             TestElement {
                 headers: vec![(String::from("my-header"), String::from("my-value2"))],
-                header_block: &[
-                    0x02, 0x00, 0x40, 0x87, 0xa7, 0xd2, 0xdd, 0xc7, 0x45, 0xa5, 0x17,
-                ],
-                encoder_inst: &[],
+                header_block: &[0x03, 0x80, 0x10],
+                encoder_inst: &[0x80, 0x87, 0xa7, 0xd2, 0xdd, 0xc7, 0x45, 0xa5, 0x17],
             },
             // test multiple headers
             TestElement {
@@ -826,7 +930,7 @@ mod tests {
                     (String::from(":scheme"), String::from("https")),
                 ],
                 header_block: &[
-                    0x00, 0x01, 0xd1, 0x51, 0x87, 0x61, 0x07, 0xa4, 0xbe, 0x27, 0x2d, 0x85, 0x50,
+                    0x00, 0x02, 0xd1, 0x51, 0x87, 0x61, 0x07, 0xa4, 0xbe, 0x27, 0x2d, 0x85, 0x50,
                     0x88, 0x2f, 0x91, 0xd3, 0x5d, 0x05, 0x5c, 0x87, 0xa7, 0xd7,
                 ],
                 encoder_inst: &[],
@@ -1653,6 +1757,47 @@ mod tests {
         send_instructions(&mut encoder, CAP_INSTRUCTION_1500);
     }
 
+    // The encoder uses a smaller table than the peer allows. The Required Insert Count is
+    // still encoded with the peer's maximum capacity (MaxEntries = 1000 / 32 = 31).
+    #[test]
+    fn encoder_target_capacity_below_peer_max() {
+        let mut encoder = connect(false);
+        encoder.encoder = QPackEncoder::new(
+            QpackSettings {
+                max_table_size_encoder: 100,
+                max_table_size_decoder: 0,
+                max_blocked_streams: 0,
+            },
+            false,
+        );
+        encoder.encoder.add_send_stream(encoder.send_stream_id);
+
+        assert!(encoder.encoder.set_max_capacity(1000).is_ok());
+        send_instructions(&mut encoder, &[0x02, 0x3f, 0x45]);
+        assert_eq!(encoder.encoder.table_capacity(), 100);
+
+        // Only two entries of 34 bytes fit, older ones are evicted once they are acknowledged.
+        for i in 0..7 {
+            encoder
+                .encoder
+                .send_and_insert(&mut encoder.conn, b"a", i.to_string().as_bytes())
+                .unwrap();
+            recv_instruction(&mut encoder, &[0x01]);
+        }
+        assert_eq!(encoder.encoder.table_used(), 68);
+
+        // Required Insert Count 7 is encoded as 7 % (2 * 31) + 1.
+        let buf = encoder
+            .encoder
+            .encode_header_block(
+                &mut encoder.conn,
+                &[(String::from("a"), String::from("6"))],
+                1,
+            )
+            .unwrap();
+        assert_eq!(&buf[..], &[0x08, 0x00, 0x80]);
+    }
+
     #[test]
     fn test_do_not_evict_entry_that_are_referd_only_by_the_same_header_blocked_encoding() {
         let mut encoder = connect(false);
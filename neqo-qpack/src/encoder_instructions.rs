@@ -14,8 +14,8 @@ use crate::Res;
 use neqo_common::{qdebug, qtrace};
 use std::mem;
 
-// The encoder only uses InsertWithNameLiteral, therefore clippy is complaining about dead_code.
-// We may decide to use othe instruction in the future.
+// The encoder does not use InsertWithNameRefStatic and Duplicate, therefore clippy is complaining
+// about dead_code. We may decide to use them in the future.
 // All instructions are used for testing, therefore they are defined.
 #[allow(dead_code)]
 #[derive(Debug, PartialEq)]
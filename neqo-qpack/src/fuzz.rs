@@ -0,0 +1,69 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Entry points for fuzzing the QPACK decoders.
+// These are only built with the "fuzzing" feature; see fuzz/ for the targets.
+
+use crate::decoder_instructions::DecoderInstructionReader;
+use crate::reader::{ReadByte, Reader};
+use crate::{Error, QPackDecoder, QpackSettings, Res};
+use std::cmp::min;
+
+/// Reads from a buffer, as though it were all the data a stream has received so far.
+struct BufferReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> ReadByte for BufferReader<'a> {
+    fn read_byte(&mut self) -> Res<u8> {
+        let (b, rest) = self.buf.split_first().ok_or(Error::NeedMoreData)?;
+        self.buf = rest;
+        Ok(*b)
+    }
+}
+
+impl<'a> Reader for BufferReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> Res<usize> {
+        let amount = min(buf.len(), self.buf.len());
+        buf[..amount].copy_from_slice(&self.buf[..amount]);
+        self.buf = &self.buf[amount..];
+        Ok(amount)
+    }
+}
+
+/// Give encoder stream instructions to a decoder, then decode a header block using the
+/// dynamic table that the instructions built. The first two bytes of `data` are the
+/// length of the instructions; the header block is the rest.
+pub fn decode(data: &[u8]) {
+    if data.len() < 2 {
+        return;
+    }
+    let (len, data) = data.split_at(2);
+    let len = min(
+        usize::from(u16::from_be_bytes([len[0], len[1]])),
+        data.len(),
+    );
+    let (instructions, header_block) = data.split_at(len);
+
+    let mut decoder = QPackDecoder::new(QpackSettings {
+        max_table_size_decoder: 4096,
+        max_table_size_encoder: 0,
+        max_blocked_streams: 16,
+    });
+    if decoder
+        .read_instructions_from(&mut BufferReader { buf: instructions })
+        .is_ok()
+    {
+        let _ = decoder.decode_header_block(header_block, 0);
+    }
+}
+
+/// Read decoder stream instructions.
+pub fn decode_decoder_instructions(data: &[u8]) {
+    let mut reader = DecoderInstructionReader::new();
+    let mut recv = BufferReader { buf: data };
+    while reader.read_instructions(&mut recv).is_ok() {}
+}
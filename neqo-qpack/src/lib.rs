@@ -15,6 +15,8 @@ pub mod decoder;
 mod decoder_instructions;
 pub mod encoder;
 mod encoder_instructions;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
 mod header_block;
 pub mod huffman;
 mod huffman_decode_helper;
@@ -35,8 +37,13 @@ type Res<T> = Result<T, Error>;
 
 #[derive(Debug, PartialEq, PartialOrd, Ord, Eq, Clone, Copy)]
 pub struct QpackSettings {
+    /// The maximum capacity of the local decoder's dynamic table. It is advertised as
+    /// `SETTINGS_QPACK_MAX_TABLE_CAPACITY`; the peer's encoder may not use a larger table.
     pub max_table_size_decoder: u64,
+    /// The capacity that the local encoder uses for its dynamic table. The encoder uses the
+    /// smaller of this and the maximum capacity that the peer has advertised.
     pub max_table_size_encoder: u64,
+    /// The number of streams that may be blocked waiting for dynamic table inserts.
     pub max_blocked_streams: u16,
 }
 
@@ -74,8 +81,8 @@ impl Error {
             Self::EncoderStream => 0x201,
             Self::DecoderStream => 0x202,
             Self::ClosedCriticalStream => 0x104,
-            // These are all internal errors.
-            _ => 3,
+            // These are all internal errors, i.e. H3_INTERNAL_ERROR.
+            _ => 0x102,
         }
     }
 }
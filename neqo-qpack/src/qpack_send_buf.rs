@@ -63,9 +63,17 @@ impl QPData {
         written
     }
 
+    /// Encode a string literal. If `use_huffman` is set, the literal is Huffman encoded unless
+    /// that would make it longer, e.g. for binary values.
     pub fn encode_literal(&mut self, use_huffman: bool, prefix: Prefix, value: &[u8]) {
+        let encoded = if use_huffman {
+            Some(encode_huffman(value)).filter(|encoded| encoded.len() <= value.len())
+        } else {
+            None
+        };
+
         let real_prefix = Prefix::new(
-            if use_huffman {
+            if encoded.is_some() {
                 prefix.prefix() | (0x80 >> prefix.len())
             } else {
                 prefix.prefix()
@@ -73,8 +81,7 @@ impl QPData {
             prefix.len() + 1,
         );
 
-        if use_huffman {
-            let encoded = encode_huffman(value);
+        if let Some(encoded) = encoded {
             self.encode_prefixed_encoded_int(real_prefix, u64::try_from(encoded.len()).unwrap());
             self.write_bytes(&encoded);
         } else {
@@ -148,4 +155,15 @@ mod tests {
         d.encode_literal(true, Prefix::new(0xC0, 2), VALUE);
         assert_eq!(&&d[..], &LITERAL_HUFFMAN);
     }
+
+    // Huffman encoding would make this value longer, so it is sent as is.
+    const VALUE_BINARY: &[u8] = &[0x00, 0x01, 0x02];
+    const LITERAL_BINARY: &[u8] = &[0xc3, 0x00, 0x01, 0x02];
+
+    #[test]
+    fn test_encode_literal_huffman_longer() {
+        let mut d = QPData::default();
+        d.encode_literal(true, Prefix::new(0xC0, 2), VALUE_BINARY);
+        assert_eq!(&&d[..], &LITERAL_BINARY);
+    }
 }
@@ -7,7 +7,7 @@
 use crate::huffman::decode_huffman;
 use crate::prefix::Prefix;
 use crate::{Error, Res};
-use neqo_common::{qdebug, qerror};
+use neqo_common::{qdebug, qerror, Decoder};
 use neqo_transport::Connection;
 use std::convert::TryInto;
 use std::mem;
@@ -62,37 +62,31 @@ impl<'a> ReceiverConnWrapper<'a> {
 /// A header block is read entirely before decoding it, therefore if there is not enough
 /// data in the buffer an error `DecompressionFailed` will be return.
 pub(crate) struct ReceiverBufferWrapper<'a> {
-    buf: &'a [u8],
-    offset: usize,
+    buf: Decoder<'a>,
 }
 
 impl<'a> ReadByte for ReceiverBufferWrapper<'a> {
     fn read_byte(&mut self) -> Res<u8> {
-        if self.offset == self.buf.len() {
-            Err(Error::DecompressionFailed)
-        } else {
-            let b = self.buf[self.offset];
-            self.offset += 1;
-            Ok(b)
-        }
+        self.buf.decode_byte().ok_or(Error::DecompressionFailed)
     }
 }
 
 impl<'a> ReceiverBufferWrapper<'a> {
     pub fn new(buf: &'a [u8]) -> Self {
-        Self { buf, offset: 0 }
+        Self {
+            buf: Decoder::new(buf),
+        }
     }
 
     pub fn peek(&self) -> Res<u8> {
-        if self.offset == self.buf.len() {
-            Err(Error::DecompressionFailed)
-        } else {
-            Ok(self.buf[self.offset])
-        }
+        self.buf
+            .peek(1)
+            .map(|b| b[0])
+            .ok_or(Error::DecompressionFailed)
     }
 
     pub fn done(&self) -> bool {
-        self.offset == self.buf.len()
+        self.buf.remaining() == 0
     }
 
     /// The function decodes varint with a prefixed, i.e. ignores `prefix_len` bits of the first
@@ -136,14 +130,8 @@ impl<'a> ReceiverBufferWrapper<'a> {
         }
     }
 
-    fn slice(&mut self, len: usize) -> Res<&[u8]> {
-        if self.offset + len > self.buf.len() {
-            Err(Error::DecompressionFailed)
-        } else {
-            let start = self.offset;
-            self.offset += len;
-            Ok(&self.buf[start..self.offset])
-        }
+    fn slice(&mut self, len: usize) -> Res<&'a [u8]> {
+        self.buf.decode(len).ok_or(Error::DecompressionFailed)
     }
 }
 
@@ -4,6 +4,9 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+
 #[derive(Debug)]
 pub struct StaticTableEntry {
     index: u64,
@@ -132,3 +135,24 @@ pub const HEADER_STATIC_TABLE: &[StaticTableEntry] = static_table_entries![
     97, b"x-frame-options", b"deny";
     98, b"x-frame-options", b"sameorigin";
 ];
+
+lazy_static! {
+    // The entries of the static table grouped by name, in the order of the table. Most names
+    // have a single entry, the longest group (`:status`) has 14.
+    static ref STATIC_TABLE_BY_NAME: HashMap<&'static [u8], Vec<&'static StaticTableEntry>> = {
+        let mut by_name: HashMap<&'static [u8], Vec<&'static StaticTableEntry>> = HashMap::new();
+        for entry in HEADER_STATIC_TABLE {
+            by_name.entry(entry.name).or_default().push(entry);
+        }
+        by_name
+    };
+}
+
+/// Find a header in the static table. This returns the index of the first entry with the same
+/// name and value and `true`, or else the index of the first entry with the same name and
+/// `false`.
+pub fn lookup_static(name: &[u8], value: &[u8]) -> Option<(u64, bool)> {
+    let entries = STATIC_TABLE_BY_NAME.get(name)?;
+    let found = entries.iter().find(|e| e.value == value);
+    Some(found.map_or((entries[0].index, false), |e| (e.index, true)))
+}
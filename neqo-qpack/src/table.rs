@@ -4,7 +4,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::static_table::{StaticTableEntry, HEADER_STATIC_TABLE};
+use crate::static_table::{lookup_static, StaticTableEntry, HEADER_STATIC_TABLE};
 use crate::{Error, Res};
 use neqo_common::qtrace;
 use std::collections::VecDeque;
@@ -105,6 +105,12 @@ impl HeaderTable {
         self.capacity
     }
 
+    /// Returns the size of all entries in the dynamic table. The size of an entry is the length
+    /// of its name and value plus 32.
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
     /// Change the dynamic table capacity.
     /// ### Errors
     /// `ChangeCapacity` if table capacity cannot be reduced.
@@ -123,7 +129,7 @@ impl HeaderTable {
     /// `HeaderLookup` if the index does not exist in the static table.
     pub fn get_static(index: u64) -> Res<&'static StaticTableEntry> {
         let inx = usize::try_from(index).or(Err(Error::HeaderLookup))?;
-        if inx > HEADER_STATIC_TABLE.len() {
+        if inx >= HEADER_STATIC_TABLE.len() {
             return Err(Error::HeaderLookup);
         }
         Ok(&HEADER_STATIC_TABLE[inx])
@@ -197,24 +203,16 @@ impl HeaderTable {
             can_block
         );
         let mut name_match = None;
-        for iter in HEADER_STATIC_TABLE.iter() {
-            if iter.name() == name {
-                if iter.value() == value {
-                    return Some(LookupResult {
-                        index: iter.index(),
-                        static_table: true,
-                        value_matches: true,
-                    });
-                }
-
-                if name_match.is_none() {
-                    name_match = Some(LookupResult {
-                        index: iter.index(),
-                        static_table: true,
-                        value_matches: false,
-                    });
-                }
+        if let Some((index, value_matches)) = lookup_static(name, value) {
+            let result = LookupResult {
+                index,
+                static_table: true,
+                value_matches,
+            };
+            if value_matches {
+                return Some(result);
             }
+            name_match = Some(result);
         }
 
         for iter in &mut self.dynamic {
@@ -371,3 +369,155 @@ impl HeaderTable {
         self.acked_inserts_cnt
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::HeaderTable;
+    use crate::static_table::HEADER_STATIC_TABLE;
+    use crate::Error;
+
+    // The table of RFC 9204, Appendix B.2 to B.4, as kept by the encoder.
+    fn rfc_example_table() -> HeaderTable {
+        let mut table = HeaderTable::new(true);
+        table.set_capacity(220).unwrap();
+        assert_eq!(table.insert(b":authority", b"www.example.com"), Ok(0));
+        assert_eq!(table.insert(b":path", b"/sample/path"), Ok(1));
+        assert_eq!(table.used(), 106);
+        assert_eq!(table.insert(b"custom-key", b"custom-value"), Ok(2));
+        assert_eq!(table.used(), 160);
+        // Duplicate the first entry, relative index 2.
+        assert_eq!(table.duplicate(2), Ok(3));
+        assert_eq!(table.used(), 217);
+        table
+    }
+
+    #[test]
+    fn entry_size() {
+        let mut table = HeaderTable::new(false);
+        table.set_capacity(100).unwrap();
+        table.insert(b"", b"").unwrap();
+        assert_eq!(table.used(), 32);
+        table.insert(b"name", b"value").unwrap();
+        assert_eq!(table.used(), 32 + 41);
+        // An entry larger than the capacity never fits.
+        assert_eq!(
+            table.insert(&[0x61; 40], &[0x62; 40]),
+            Err(Error::DynamicTableFull)
+        );
+        assert_eq!(table.used(), 73);
+    }
+
+    // RFC 9204, Appendix B.5: an insert that needs space evicts the oldest entry.
+    #[test]
+    fn evict_oldest() {
+        let mut table = rfc_example_table();
+        table.increment_acked(4).unwrap();
+        assert_eq!(table.insert(b"custom-key", b"custom-value2"), Ok(4));
+        assert_eq!(table.used(), 215);
+        // The first entry is gone, relative index 4 does not exist any more.
+        assert!(table.get_dynamic(4, 5, false).is_err());
+        assert_eq!(
+            table.get_dynamic(0, 5, false).unwrap().value(),
+            b"custom-value2"
+        );
+    }
+
+    // The encoder cannot evict an entry that has not been acknowledged or that is referenced.
+    #[test]
+    fn evict_only_unreferenced() {
+        let mut table = rfc_example_table();
+        assert_eq!(
+            table.insert(b"custom-key", b"custom-value2"),
+            Err(Error::DynamicTableFull)
+        );
+
+        table.increment_acked(4).unwrap();
+        table.add_ref(0);
+        assert!(!table.insert_possible(55));
+        assert_eq!(
+            table.insert(b"custom-key", b"custom-value2"),
+            Err(Error::DynamicTableFull)
+        );
+        assert_eq!(table.set_capacity(160), Err(Error::ChangeCapacity));
+        assert_eq!(table.used(), 217);
+
+        table.remove_ref(0);
+        assert!(table.insert_possible(55));
+        assert_eq!(table.insert(b"custom-key", b"custom-value2"), Ok(4));
+        assert_eq!(table.used(), 215);
+    }
+
+    #[test]
+    fn reduce_capacity() {
+        let mut table = rfc_example_table();
+        table.increment_acked(4).unwrap();
+        // Entries are evicted from the oldest until the used size fits.
+        table.set_capacity(111).unwrap();
+        assert_eq!(table.used(), 111);
+        assert_eq!(table.capacity(), 111);
+        table.set_capacity(0).unwrap();
+        assert_eq!(table.used(), 0);
+    }
+
+    fn lookup(table: &mut HeaderTable, name: &[u8], value: &[u8]) -> Option<(u64, bool, bool)> {
+        table
+            .lookup(name, value, true)
+            .map(|r| (r.index, r.static_table, r.value_matches))
+    }
+
+    #[test]
+    fn lookup_static() {
+        let mut table = HeaderTable::new(true);
+        assert_eq!(
+            lookup(&mut table, b":method", b"GET"),
+            Some((17, true, true))
+        );
+        // A name-only match refers to the first entry with the name.
+        assert_eq!(
+            lookup(&mut table, b":method", b"PATCH"),
+            Some((15, true, false))
+        );
+        assert_eq!(
+            lookup(&mut table, b":status", b"418"),
+            Some((24, true, false))
+        );
+        assert_eq!(lookup(&mut table, b"my-header", b""), None);
+        // Every entry is found with its own index.
+        for entry in HEADER_STATIC_TABLE {
+            assert_eq!(
+                lookup(&mut table, entry.name(), entry.value()),
+                Some((entry.index(), true, true))
+            );
+        }
+    }
+
+    #[test]
+    fn lookup_prefers_static() {
+        let mut table = HeaderTable::new(true);
+        table.set_capacity(200).unwrap();
+        table.insert(b":method", b"GET").unwrap();
+        table.insert(b"my-header", b"a").unwrap();
+        assert_eq!(
+            lookup(&mut table, b":method", b"GET"),
+            Some((17, true, true))
+        );
+        assert_eq!(
+            lookup(&mut table, b":method", b"PATCH"),
+            Some((15, true, false))
+        );
+        assert_eq!(
+            lookup(&mut table, b"my-header", b"b"),
+            Some((1, false, false))
+        );
+    }
+
+    #[test]
+    fn get_static() {
+        assert_eq!(HeaderTable::get_static(0).unwrap().name(), b":authority");
+        assert_eq!(HeaderTable::get_static(98).unwrap().value(), b"sameorigin");
+        assert!(matches!(
+            HeaderTable::get_static(99),
+            Err(Error::HeaderLookup)
+        ));
+    }
+}
@@ -8,42 +8,75 @@
 #![warn(clippy::use_self)]
 
 use std::cell::RefCell;
-use std::collections::HashSet;
-use std::fmt::Display;
-use std::fs::OpenOptions;
-use std::io;
-use std::io::Read;
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io::{self, LineWriter, Write};
 use std::mem;
 use std::net::{SocketAddr, ToSocketAddrs};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::rc::Rc;
+use std::str::FromStr;
+use std::thread;
 use std::time::{Duration, Instant};
 
 use mio::net::UdpSocket;
 use mio::{Events, Poll, PollOpt, Ready, Token};
+use mio_extras::channel::channel;
 use mio_extras::timer::{Builder, Timeout, Timer};
 use structopt::StructOpt;
 
-use neqo_common::{qdebug, qinfo, Datagram};
+use neqo_common::{
+    clock::SystemClock,
+    metrics::{self, Metrics, MetricsRef},
+    qdebug, qinfo, Datagram,
+};
 use neqo_crypto::{
     constants::{TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256},
-    init_db, AntiReplay, Cipher,
+    init_db, random, set_key_log_file, AntiReplay, Cipher,
 };
-use neqo_http3::{Error, Http3Server, Http3ServerEvent};
+use neqo_http3::{ClientRequestStream, Error, Header, Http3Server, Http3ServerEvent};
 use neqo_qpack::QpackSettings;
 use neqo_transport::{
-    server::ValidateAddress, FixedConnectionIdManager as RandomConnectionIdGenerator, Output,
+    server::ValidateAddress, ConnectionIdManager,
+    FixedConnectionIdManager as RandomConnectionIdGenerator, Output,
 };
 
 use crate::old_https::Http09Server;
+use crate::response::{Endpoint, Response, MAX_ECHO_BODY};
+use crate::worker::{Worker, WorkerConnectionIdGenerator, CID_LEN};
 
 const TIMER_TOKEN: Token = Token(0xffff_ffff);
+const FORWARD_TOKEN: Token = Token(0xffff_fffe);
 const ANTI_REPLAY_WINDOW: Duration = Duration::from_secs(10);
 
 mod old_https;
+mod response;
+mod worker;
+
+/// A key for address validation tokens, given as 64 hex digits.
+#[derive(Clone, Debug)]
+struct TokenKey(Vec<u8>);
+
+impl FromStr for TokenKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 64 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(String::from("expected 64 hex digits"));
+        }
+        let key = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect();
+        Ok(Self(key))
+    }
+}
 
-#[derive(Debug, StructOpt)]
+#[derive(Clone, Debug, StructOpt)]
 #[structopt(name = "neqo-server", about = "A basic HTTP3 server.")]
 struct Args {
     /// List of IP:port to listen on
@@ -78,11 +111,16 @@ struct Args {
     /// This server still only does HTTP3 no matter what the ALPN says.
     alpn: String,
 
-    #[structopt(name = "qlog-dir", long)]
-    /// Enable QLOG logging and QLOG traces to this directory
+    #[structopt(name = "qlog-dir", long, env = "QLOGDIR")]
+    /// Write a qlog trace of each connection to `<original DCID>-server.qlog` in this directory
     qlog_dir: Option<PathBuf>,
 
-    #[structopt(name = "qns-test", long)]
+    #[structopt(name = "packet-dump-dir", long, parse(from_os_str))]
+    /// Write every packet of each connection, decrypted and with the fields of its frames,
+    /// to `<original DCID>-server.dump` in this directory. This is slow.
+    packet_dump_dir: Option<PathBuf>,
+
+    #[structopt(name = "qns-test", long, env = "TESTCASE")]
     /// Enable special behavior for use with QUIC Network Simulator
     qns_test: Option<String>,
 
@@ -94,11 +132,53 @@ struct Args {
     /// Force a retry
     retry: bool,
 
+    #[structopt(name = "retry-above", long)]
+    /// Send Retry when more than this many connection attempts without a valid token
+    /// arrive in a second. Ignored with --retry.
+    retry_above: Option<usize>,
+
+    #[structopt(name = "token-lifetime", long)]
+    /// How many seconds the tokens sent in NEW_TOKEN frames can be used for.
+    token_lifetime: Option<u64>,
+
+    #[structopt(name = "token-key", long)]
+    /// The key that protects address validation tokens, as 64 hex digits.
+    /// Servers with the same key accept each other's tokens.
+    /// Without this, a random key is used.
+    token_key: Option<TokenKey>,
+
     #[structopt(short = "c", long, number_of_values = 1)]
     /// The set of TLS cipher suites to enable.
     /// From: TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256.
     ciphers: Vec<String>,
 
+    #[structopt(name = "root", long, parse(from_os_str))]
+    /// Serve files from this directory; directories are served from their index.html.
+    /// The QUIC Network Simulator tests use /www.
+    root: Option<PathBuf>,
+
+    #[structopt(name = "synthetic", long)]
+    /// Respond to requests for `/N`, where N is a number, with N bytes. Over HTTP/3, also
+    /// answer a POST to `/upload` with the size of its body, `/echo` with the header fields
+    /// and body of the request (up to 1 MiB), and `/delay/N` after N milliseconds (up to 60 s).
+    /// This is always on when there is no document root.
+    synthetic: bool,
+
+    #[structopt(name = "key-log-file", long, env = "SSLKEYLOGFILE", parse(from_os_str))]
+    /// Write TLS secrets to this file in the NSS key log format.
+    key_log_file: Option<PathBuf>,
+
+    #[structopt(name = "workers", short = "w", long, default_value = "1")]
+    /// The number of threads that serve connections, at most 256.
+    /// More than one needs SO_REUSEPORT and a fixed port for each address.
+    workers: usize,
+
+    #[structopt(name = "csv", long, parse(from_os_str))]
+    /// Write the samples of the RTT and the congestion window of every connection to this
+    /// file, in the format of `neqo-client --bench --csv`. With more than one worker, each
+    /// writes to its own file, with the index of the worker appended to the name.
+    csv: Option<PathBuf>,
+
     #[structopt(name = "preferred-address-v4", long)]
     /// An IPv4 address for the server preferred address.
     preferred_address_v4: Option<String>,
@@ -121,6 +201,30 @@ impl Args {
             .collect::<Vec<_>>()
     }
 
+    fn document_root(&self) -> Option<PathBuf> {
+        self.root
+            .clone()
+            .or_else(|| self.qns_test.as_ref().map(|_| PathBuf::from("/www")))
+    }
+
+    fn synthetic(&self) -> bool {
+        self.synthetic || self.document_root().is_none()
+    }
+
+    /// The response to a request for `path`.
+    fn response(&self, path: &str) -> Response {
+        Response::for_path(self.document_root().as_deref(), self.synthetic(), path)
+    }
+
+    /// The endpoint that a request is for, if it is for one that only HTTP/3 has.
+    fn endpoint(&self, method: &str, path: &str) -> Option<Endpoint> {
+        if self.synthetic() {
+            Endpoint::for_request(method, path)
+        } else {
+            None
+        }
+    }
+
     fn listen_addresses(&self) -> Vec<SocketAddr> {
         self.hosts
             .iter()
@@ -158,45 +262,148 @@ fn emit_packet(socket: &mut UdpSocket, out_dgram: Datagram) {
     }
 }
 
-fn qns_read_response(filename: &str) -> Option<Vec<u8>> {
-    let mut file_path = PathBuf::from("/www");
-    file_path.push(filename.trim_matches(|p| p == '/'));
-
-    OpenOptions::new()
-        .read(true)
-        .open(&file_path)
-        .map_err(|_e| eprintln!("Could not open {}", file_path.display()))
-        .ok()
-        .and_then(|mut f| {
-            let mut data = Vec::new();
-            match f.read_to_end(&mut data) {
-                Ok(sz) => {
-                    println!("{} bytes read from {}", sz, file_path.display());
-                    Some(data)
-                }
-                Err(e) => {
-                    eprintln!("Error reading data: {:?}", e);
-                    None
-                }
-            }
-        })
-}
-
 trait HttpServer: Display {
     fn process(&mut self, dgram: Option<Datagram>, now: Instant) -> Output;
     fn process_events(&mut self, args: &Args, now: Instant);
     fn set_qlog_dir(&mut self, dir: Option<PathBuf>);
+    fn set_packet_dump_dir(&mut self, dir: Option<PathBuf>);
+    fn set_metrics(&mut self, metrics: MetricsRef);
     fn set_ciphers(&mut self, ciphers: &[Cipher]);
     fn validate_address(&mut self, when: ValidateAddress);
+    fn set_token_lifetime(&mut self, lifetime: Duration);
+    fn set_token_key(&mut self, key: &[u8]);
+}
+
+/// Writes the RTT and congestion window that connections report as they change, so that
+/// a download with `neqo-client --bench` can be looked at from the sending side.
+#[derive(Debug)]
+struct SampleLog {
+    start: Instant,
+    file: RefCell<LineWriter<File>>,
+}
+
+impl SampleLog {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut file = LineWriter::new(File::create(path)?);
+        writeln!(file, "time,metric,value")?;
+        Ok(Self {
+            start: Instant::now(),
+            file: RefCell::new(file),
+        })
+    }
+}
+
+impl Metrics for SampleLog {
+    fn gauge_set(&self, name: &'static str, value: f64) {
+        if name == metrics::RTT || name == metrics::CWND {
+            let time = self.start.elapsed().as_secs_f64();
+            // A sample that can't be written isn't worth failing a connection over.
+            let _ = writeln!(self.file.borrow_mut(), "{:.6},{},{}", time, name, value);
+        }
+    }
+}
+
+/// What is kept of a request body until all of it has arrived.
+enum Body {
+    /// An upload, of which only the size matters.
+    Counted(usize),
+    /// A request to `/echo`, with its header fields.
+    Echoed(Vec<Header>, Vec<u8>),
+}
+
+impl Body {
+    /// Add `data` to the body. This is false if the body has become too large to keep.
+    fn add(&mut self, data: &[u8]) -> bool {
+        match self {
+            Self::Counted(received) => *received += data.len(),
+            Self::Echoed(_, body) => {
+                if body.len() + data.len() > MAX_ECHO_BODY {
+                    return false;
+                }
+                body.extend_from_slice(data);
+            }
+        }
+        true
+    }
+
+    fn into_response(self) -> Response {
+        match self {
+            Self::Counted(received) => Response::uploaded(received),
+            Self::Echoed(headers, body) => Response::echo(&headers, body),
+        }
+    }
+}
+
+/// An HTTP/3 server, along with the requests whose responses have to wait.
+struct Http3Handler {
+    server: Http3Server,
+    /// Requests that are answered once their body is complete.
+    bodies: HashMap<ClientRequestStream, Body>,
+    /// Requests to `/delay/N`, with the time at which they are answered.
+    delayed: Vec<(Instant, ClientRequestStream, Response)>,
 }
 
-impl HttpServer for Http3Server {
+impl Http3Handler {
+    fn new(server: Http3Server) -> Self {
+        Self {
+            server,
+            bodies: HashMap::new(),
+            delayed: Vec::new(),
+        }
+    }
+
+    /// Start collecting the body of a request, or respond now if it has none.
+    fn read_body(&mut self, mut request: ClientRequestStream, fin: bool, body: Body) {
+        if fin {
+            respond(&mut request, &body.into_response());
+        } else {
+            self.bodies.insert(request, body);
+        }
+    }
+
+    /// Send the delayed responses that are due.
+    fn send_delayed(&mut self, now: Instant) {
+        let (due, waiting): (Vec<_>, Vec<_>) = mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(time, _, _)| *time <= now);
+        self.delayed = waiting;
+        for (_, mut request, response) in due {
+            respond(&mut request, &response);
+        }
+    }
+}
+
+impl Display for Http3Handler {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Display::fmt(&self.server, f)
+    }
+}
+
+fn respond(request: &mut ClientRequestStream, response: &Response) {
+    // The client can be gone by the time that a response is ready.
+    if let Err(e) = request.set_response(&response.headers(), &response.body) {
+        eprintln!("Unable to respond to {}: {:?}", request, e);
+    }
+}
+
+impl HttpServer for Http3Handler {
     fn process(&mut self, dgram: Option<Datagram>, now: Instant) -> Output {
-        self.process(dgram, now)
+        let output = self.server.process(dgram, now);
+        // Ask to be called again when the next delayed response is due.
+        let next = match self.delayed.iter().map(|(time, _, _)| *time).min() {
+            Some(time) => time.saturating_duration_since(now),
+            None => return output,
+        };
+        match output {
+            Output::Callback(timeout) => Output::Callback(min(timeout, next)),
+            Output::None => Output::Callback(next),
+            dgram => dgram,
+        }
     }
 
-    fn process_events(&mut self, args: &Args, _now: Instant) {
-        while let Some(event) = self.next_event() {
+    fn process_events(&mut self, args: &Args, now: Instant) {
+        self.send_delayed(now);
+        while let Some(event) = self.server.next_event() {
             match event {
                 Http3ServerEvent::Headers {
                     mut request,
@@ -205,42 +412,58 @@ impl HttpServer for Http3Server {
                 } => {
                     println!("Headers (request={} fin={}): {:?}", request, fin, headers);
 
-                    let default_ret = b"Hello World".to_vec();
-
-                    let response =
-                        headers
-                            .iter()
-                            .find(|&(k, _)| k == ":path")
-                            .and_then(|(_, path)| {
-                                if args.qns_test.is_some() {
-                                    qns_read_response(path)
-                                } else {
-                                    match path.trim_matches(|p| p == '/').parse::<usize>() {
-                                        Ok(v) => Some(vec![b'a'; v]),
-                                        Err(_) => Some(default_ret),
-                                    }
-                                }
-                            });
-
-                    if response.is_none() {
-                        let _ = request.stream_reset(Error::HttpRequestIncomplete.code());
-                        continue;
+                    let path = match headers.iter().find(|&(k, _)| k == ":path") {
+                        Some((_, path)) => path,
+                        None => {
+                            let _ = request.stream_reset(Error::HttpRequestIncomplete.code());
+                            continue;
+                        }
+                    };
+                    let method = headers
+                        .iter()
+                        .find(|&(k, _)| k == ":method")
+                        .map_or("", |(_, method)| method);
+                    match args.endpoint(method, path) {
+                        None => respond(&mut request, &args.response(path)),
+                        Some(Endpoint::Upload) => {
+                            self.read_body(request, fin, Body::Counted(0));
+                        }
+                        Some(Endpoint::Echo) => {
+                            self.read_body(request, fin, Body::Echoed(headers, Vec::new()));
+                        }
+                        Some(Endpoint::Delay(delay)) => {
+                            self.delayed
+                                .push((now + delay, request, Response::delayed(delay)));
+                        }
+                        Some(Endpoint::BadDelay) => {
+                            respond(&mut request, &Response::bad_request());
+                        }
                     }
-
-                    let response = response.unwrap();
-
-                    request
-                        .set_response(
-                            &[
-                                (String::from(":status"), String::from("200")),
-                                (String::from("content-length"), response.len().to_string()),
-                            ],
-                            &response,
-                        )
-                        .unwrap();
                 }
-                Http3ServerEvent::Data { request, data, fin } => {
-                    println!("Data (request={} fin={}): {:?}", request, fin, data);
+                Http3ServerEvent::Data {
+                    mut request,
+                    data,
+                    fin,
+                } => match self.bodies.get_mut(&request) {
+                    Some(body) => {
+                        if !body.add(&data) {
+                            self.bodies.remove(&request);
+                            respond(&mut request, &Response::too_large());
+                            // The response is complete, so the rest of the body isn't needed.
+                            let _ = request.stream_stop_sending(Error::HttpNoError.code());
+                        } else if fin {
+                            if let Some(body) = self.bodies.remove(&request) {
+                                respond(&mut request, &body.into_response());
+                            }
+                        }
+                    }
+                    None => {
+                        println!("Data (request={} fin={}): {:?}", request, fin, data);
+                    }
+                },
+                Http3ServerEvent::Reset { request, .. } => {
+                    self.bodies.remove(&request);
+                    self.delayed.retain(|(_, r, _)| *r != request);
                 }
                 _ => {}
             }
@@ -248,15 +471,33 @@ impl HttpServer for Http3Server {
     }
 
     fn set_qlog_dir(&mut self, dir: Option<PathBuf>) {
-        Self::set_qlog_dir(self, dir)
+        self.server.set_qlog_dir(dir)
+    }
+
+    fn set_packet_dump_dir(&mut self, dir: Option<PathBuf>) {
+        self.server.set_packet_dump_dir(dir);
+    }
+
+    fn set_metrics(&mut self, metrics: MetricsRef) {
+        self.server.set_metrics(metrics);
     }
 
     fn validate_address(&mut self, v: ValidateAddress) {
-        self.set_validation(v);
+        self.server.set_validation(v);
+    }
+
+    fn set_token_lifetime(&mut self, lifetime: Duration) {
+        self.server.set_token_lifetime(lifetime);
+    }
+
+    fn set_token_key(&mut self, key: &[u8]) {
+        self.server
+            .set_token_key(key)
+            .expect("unable to set the token key");
     }
 
     fn set_ciphers(&mut self, ciphers: &[Cipher]) {
-        Self::set_ciphers(self, ciphers);
+        self.server.set_ciphers(ciphers);
     }
 }
 
@@ -295,13 +536,16 @@ struct ServersRunner {
     sockets: Vec<UdpSocket>,
     active_sockets: HashSet<usize>,
     timer: Timer<usize>,
+    /// Set when this is one of several worker threads.
+    worker: Option<Worker>,
 }
 
 impl ServersRunner {
-    pub fn new(args: Args) -> Result<Self, io::Error> {
-        let server = Self::create_server(&args);
+    pub fn new(args: Args, worker: Option<Worker>) -> Result<Self, io::Error> {
+        let server = Self::create_server(&args, worker.as_ref());
         let mut runner = Self {
             args,
+            worker,
             poll: Poll::new()?,
             hosts: Vec::new(),
             server,
@@ -326,7 +570,12 @@ impl ServersRunner {
         }
 
         for (i, host) in self.hosts.iter().enumerate() {
-            let socket = match UdpSocket::bind(&host) {
+            let socket = if self.worker.is_some() {
+                worker::bind_shared(host)
+            } else {
+                UdpSocket::bind(&host)
+            };
+            let socket = match socket {
                 Err(err) => {
                     eprintln!("Unable to bind UDP socket: {}", err);
                     return Err(err);
@@ -364,15 +613,28 @@ impl ServersRunner {
 
         self.poll
             .register(&self.timer, TIMER_TOKEN, Ready::readable(), PollOpt::edge())?;
+        if let Some(worker) = &self.worker {
+            self.poll.register(
+                &worker.forwarded,
+                FORWARD_TOKEN,
+                Ready::readable(),
+                PollOpt::edge(),
+            )?;
+        }
 
         Ok(())
     }
 
-    fn create_server(args: &Args) -> Box<dyn HttpServer> {
+    fn create_server(args: &Args, worker: Option<&Worker>) -> Box<dyn HttpServer> {
         // Note: this is the exception to the case where we use `Args::now`.
-        let anti_replay = AntiReplay::new(Instant::now(), ANTI_REPLAY_WINDOW, 7, 14)
+        let anti_replay = AntiReplay::new(&SystemClock, ANTI_REPLAY_WINDOW, 7, 14)
             .expect("unable to setup anti-replay");
-        let cid_mgr = Rc::new(RefCell::new(RandomConnectionIdGenerator::new(10)));
+        let cid_mgr: Rc<RefCell<dyn ConnectionIdManager>> = if let Some(w) = worker {
+            let index = u8::try_from(w.index).expect("too many workers");
+            Rc::new(RefCell::new(WorkerConnectionIdGenerator::new(index)))
+        } else {
+            Rc::new(RefCell::new(RandomConnectionIdGenerator::new(CID_LEN)))
+        };
 
         let mut svr: Box<dyn HttpServer> = if args.use_old_http {
             Box::new(
@@ -399,12 +661,40 @@ impl ServersRunner {
                 },
             )
             .expect("We cannot make a server!");
-            Box::new(server)
+            Box::new(Http3Handler::new(server))
         };
         svr.set_ciphers(&args.get_ciphers());
+        for dir in args.qlog_dir.iter().chain(args.packet_dump_dir.iter()) {
+            if let Err(e) = fs::create_dir_all(dir) {
+                eprintln!("Unable to create {}: {}", dir.display(), e);
+            }
+        }
         svr.set_qlog_dir(args.qlog_dir.clone());
+        svr.set_packet_dump_dir(args.packet_dump_dir.clone());
+        if let Some(path) = &args.csv {
+            let path = match worker {
+                Some(w) if args.workers > 1 => {
+                    let mut name = path.clone().into_os_string();
+                    name.push(format!(".{}", w.index));
+                    PathBuf::from(name)
+                }
+                _ => path.clone(),
+            };
+            match SampleLog::create(&path) {
+                Ok(log) => svr.set_metrics(Rc::new(log)),
+                Err(e) => eprintln!("Unable to create {}: {}", path.display(), e),
+            }
+        }
         if args.retry {
             svr.validate_address(ValidateAddress::Always);
+        } else if let Some(rate) = args.retry_above {
+            svr.validate_address(ValidateAddress::Busy(rate));
+        }
+        if let Some(secs) = args.token_lifetime {
+            svr.set_token_lifetime(Duration::from_secs(secs));
+        }
+        if let Some(TokenKey(key)) = &args.token_key {
+            svr.set_token_key(key);
         }
         svr
     }
@@ -453,11 +743,18 @@ impl ServersRunner {
             if read_socket {
                 loop {
                     let socket = self.sockets.get_mut(inx).unwrap();
-                    let dgram = read_dgram(socket, &self.hosts[inx])?;
-                    if dgram.is_none() {
-                        break;
+                    let dgram = match read_dgram(socket, &self.hosts[inx])? {
+                        Some(d) => d,
+                        None => break,
+                    };
+                    if let Some(w) = &self.worker {
+                        if let Some(owner) = w.other_owner(&dgram) {
+                            qdebug!("Passing datagram to worker {}", owner);
+                            let _ = w.peers[owner].send((inx, dgram));
+                            continue;
+                        }
                     }
-                    let _ = self.process(inx, dgram);
+                    let _ = self.process(inx, Some(dgram));
                 }
             } else {
                 let _ = self.process(inx, None);
@@ -478,6 +775,22 @@ impl ServersRunner {
         Ok(())
     }
 
+    /// Process the datagrams that other workers passed to this one.
+    fn process_forwarded(&mut self) -> Result<(), io::Error> {
+        loop {
+            let forwarded = match &self.worker {
+                Some(w) => w.forwarded.try_recv(),
+                None => return Ok(()),
+            };
+            let (inx, dgram) = match forwarded {
+                Ok(f) => f,
+                Err(_) => return Ok(()),
+            };
+            let _ = self.process(inx, Some(dgram));
+            self.process_datagrams_and_events(inx, false)?;
+        }
+    }
+
     fn process_timeout(&mut self) -> Result<(), io::Error> {
         while let Some(inx) = self.timer.poll() {
             qinfo!("Timer expired for {:?}", inx);
@@ -502,6 +815,8 @@ impl ServersRunner {
             for event in &events {
                 if event.token() == TIMER_TOKEN {
                     self.process_timeout()?;
+                } else if event.token() == FORWARD_TOKEN {
+                    self.process_forwarded()?;
                 } else {
                     if !event.readiness().is_readable() {
                         continue;
@@ -518,12 +833,15 @@ fn main() -> Result<(), io::Error> {
     let mut args = Args::from_args();
     assert!(!args.key.is_empty(), "Need at least one key");
 
+    if let Some(path) = &args.key_log_file {
+        set_key_log_file(path);
+    }
     init_db(args.db.clone());
 
     if let Some(testcase) = args.qns_test.as_ref() {
         match testcase.as_str() {
             "http3" => (),
-            "handshake" | "transfer" | "resumption" | "zerortt" | "multiconnect" => {
+            "handshake" | "transfer" | "resumption" | "zerortt" | "multiconnect" | "keyupdate" => {
                 args.use_old_http = true;
                 args.alpn = "hq-29".into();
             }
@@ -543,6 +861,36 @@ fn main() -> Result<(), io::Error> {
         }
     }
 
-    let mut servers_runner = ServersRunner::new(args)?;
-    servers_runner.run()
+    if args.workers <= 1 {
+        return ServersRunner::new(args, None)?.run();
+    }
+    if args.workers > 256 {
+        eprintln!("At most 256 workers are supported");
+        exit(1);
+    }
+
+    // Workers share a key so that a token from one is good at the others.
+    if args.token_key.is_none() {
+        args.token_key = Some(TokenKey(random(32)));
+    }
+
+    let (peers, receivers): (Vec<_>, Vec<_>) = (0..args.workers).map(|_| channel()).unzip();
+    let mut threads = Vec::new();
+    for (index, forwarded) in receivers.into_iter().enumerate() {
+        let args = args.clone();
+        let worker = Worker {
+            index,
+            peers: peers.clone(),
+            forwarded,
+        };
+        threads.push(
+            thread::Builder::new()
+                .name(format!("worker-{}", index))
+                .spawn(move || ServersRunner::new(args, Some(worker))?.run())?,
+        );
+    }
+    for t in threads {
+        t.join().expect("worker thread panicked")?;
+    }
+    Ok(())
 }
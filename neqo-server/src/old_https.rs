@@ -12,11 +12,11 @@ use std::collections::HashMap;
 use std::fmt::Display;
 use std::path::PathBuf;
 use std::rc::Rc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use regex::Regex;
 
-use neqo_common::{event::Provider, hex, qdebug, Datagram};
+use neqo_common::{event::Provider, hex, metrics::MetricsRef, qdebug, Datagram};
 use neqo_crypto::{AllowZeroRtt, AntiReplay, Cipher};
 use neqo_http3::Error;
 use neqo_transport::{
@@ -24,7 +24,7 @@ use neqo_transport::{
     ConnectionEvent, ConnectionIdManager as ConnectionIdGenerator, Output, State,
 };
 
-use super::{qns_read_response, Args, HttpServer};
+use super::{Args, HttpServer, Response};
 
 #[derive(Default)]
 struct Http09StreamState {
@@ -129,11 +129,7 @@ impl Http09Server {
             return;
         };
 
-        let re = if args.qns_test.is_some() {
-            Regex::new(r"GET +/(\S+)(?:\r)?\n").unwrap()
-        } else {
-            Regex::new(r"GET +/(\d+)(?:\r)?\n").unwrap()
-        };
+        let re = Regex::new(r"GET +(/\S*)(?:\r)?\n").unwrap();
         let m = re.captures(&msg);
         let resp = match m.and_then(|m| m.get(1)) {
             None => {
@@ -143,12 +139,10 @@ impl Http09Server {
             Some(path) => {
                 let path = path.as_str();
                 eprintln!("Path = '{}'", path);
-                if args.qns_test.is_some() {
-                    qns_read_response(path)
-                } else {
-                    let count = usize::from_str_radix(path, 10).unwrap();
-                    Some(vec![b'a'; count])
-                }
+                // HTTP/0.9 has no status, so anything but success gets the error text.
+                Some(args.response(path))
+                    .filter(Response::is_ok)
+                    .map(|r| r.body)
             }
         };
         self.write(stream_id, resp, conn);
@@ -225,10 +219,28 @@ impl HttpServer for Http09Server {
         self.server.set_qlog_dir(dir)
     }
 
+    fn set_packet_dump_dir(&mut self, dir: Option<PathBuf>) {
+        self.server.set_packet_dump_dir(dir);
+    }
+
+    fn set_metrics(&mut self, metrics: MetricsRef) {
+        self.server.set_metrics(metrics);
+    }
+
     fn validate_address(&mut self, v: ValidateAddress) {
         self.server.set_validation(v);
     }
 
+    fn set_token_lifetime(&mut self, lifetime: Duration) {
+        self.server.set_token_lifetime(lifetime);
+    }
+
+    fn set_token_key(&mut self, key: &[u8]) {
+        self.server
+            .set_token_key(key)
+            .expect("unable to set the token key");
+    }
+
     fn set_ciphers(&mut self, ciphers: &[Cipher]) {
         self.server.set_ciphers(ciphers);
     }
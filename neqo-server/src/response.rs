@@ -0,0 +1,381 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// What the server sends in response to a request: a file from the document root,
+// a synthetic body of a requested size, the size of an upload, an echo of the request,
+// or a 404.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use neqo_http3::Header;
+
+/// The file that is served when a directory is requested.
+const INDEX: &str = "index.html";
+/// The longest that `/delay/N` waits.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+/// The most of a request body that `/echo` keeps.
+pub const MAX_ECHO_BODY: usize = 1 << 20;
+
+/// A synthetic endpoint whose response depends on more than the path. These are only
+/// served over HTTP/3.
+#[derive(Debug, PartialEq)]
+pub enum Endpoint {
+    /// A POST to `/upload`, which is answered with the size of the request body.
+    Upload,
+    /// `/echo`, which is answered with the header fields and the body of the request.
+    Echo,
+    /// `/delay/N`, which is answered after N milliseconds.
+    Delay(Duration),
+    /// `/delay/N` where N is not a number or is longer than `MAX_DELAY`.
+    BadDelay,
+}
+
+impl Endpoint {
+    pub fn for_request(method: &str, path: &str) -> Option<Self> {
+        let path = without_query(path);
+        match path {
+            "/upload" if method == "POST" => Some(Self::Upload),
+            "/echo" => Some(Self::Echo),
+            _ => {
+                path.strip_prefix("/delay/")
+                    .map(|ms| match ms.parse().map(Duration::from_millis) {
+                        Ok(delay) if delay <= MAX_DELAY => Self::Delay(delay),
+                        _ => Self::BadDelay,
+                    })
+            }
+        }
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    /// The header fields other than `:status` and `content-length`.
+    fields: Vec<Header>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: u16, content_type: &str, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            fields: vec![(String::from("content-type"), String::from(content_type))],
+            body,
+        }
+    }
+
+    fn ok(content_type: &str, body: Vec<u8>) -> Self {
+        Self::new(200, content_type, body)
+    }
+
+    fn not_found() -> Self {
+        Self::new(404, "text/plain", b"404 Not Found\r\n".to_vec())
+    }
+
+    pub fn bad_request() -> Self {
+        Self::new(400, "text/plain", b"400 Bad Request\r\n".to_vec())
+    }
+
+    pub fn too_large() -> Self {
+        Self::new(413, "text/plain", b"413 Payload Too Large\r\n".to_vec())
+    }
+
+    /// Work out the response to a request for `path`. With `synthetic`, a path that is a
+    /// number, like `/1000`, gets that many bytes. Otherwise the file at `path` under `root`
+    /// is served. Without a `root`, anything else gets a short greeting.
+    pub fn for_path(root: Option<&Path>, synthetic: bool, path: &str) -> Self {
+        let path = without_query(path);
+        if synthetic {
+            if let Ok(size) = path.trim_matches('/').parse::<usize>() {
+                return Self::ok("application/octet-stream", vec![b'a'; size]);
+            }
+        }
+        match root {
+            Some(root) => file_path(root, path).map_or_else(Self::not_found, |p| read_file(&p)),
+            None => Self::ok("text/plain", b"Hello World".to_vec()),
+        }
+    }
+
+    /// The response to an upload, once all `received` bytes of its body have arrived.
+    pub fn uploaded(received: usize) -> Self {
+        Self::ok("text/plain", format!("{}\r\n", received).into_bytes())
+    }
+
+    /// The response to `/echo`: the body of the request, with its header fields other than
+    /// the pseudo-header fields and `content-length`.
+    pub fn echo(headers: &[Header], body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            fields: headers
+                .iter()
+                .filter(|(name, _)| !name.starts_with(':') && name != "content-length")
+                .cloned()
+                .collect(),
+            body,
+        }
+    }
+
+    /// The response to `/delay/N`, once the delay is over.
+    pub fn delayed(delay: Duration) -> Self {
+        Self::ok(
+            "text/plain",
+            format!("{} ms\r\n", delay.as_millis()).into_bytes(),
+        )
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status == 200
+    }
+
+    pub fn headers(&self) -> Vec<Header> {
+        let mut headers = vec![(String::from(":status"), self.status.to_string())];
+        headers.extend(self.fields.iter().cloned());
+        headers.push((String::from("content-length"), self.body.len().to_string()));
+        headers
+    }
+}
+
+/// Neither the query nor the fragment say anything about which response to send.
+fn without_query(path: &str) -> &str {
+    path.split(|c| c == '?' || c == '#').next().unwrap_or("")
+}
+
+/// The file under `root` that `path` names. Paths that would leave `root` are refused.
+fn file_path(root: &Path, path: &str) -> Option<PathBuf> {
+    let mut file_path = root.to_path_buf();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            s => file_path.push(s),
+        }
+    }
+    if file_path.is_dir() {
+        file_path.push(INDEX);
+    }
+    Some(file_path)
+}
+
+fn read_file(file_path: &Path) -> Response {
+    match fs::read(file_path) {
+        Ok(data) => {
+            println!("{} bytes read from {}", data.len(), file_path.display());
+            Response::ok(content_type(file_path), data)
+        }
+        Err(e) => {
+            eprintln!("Could not read {}: {}", file_path.display(), e);
+            Response::not_found()
+        }
+    }
+}
+
+fn content_type(file_path: &Path) -> &'static str {
+    match file_path.extension().and_then(OsStr::to_str) {
+        Some("html") | Some("htm") => "text/html",
+        Some("txt") => "text/plain",
+        Some("css") => "text/css",
+        Some("js") => "application/javascript",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{content_type, file_path, Endpoint, Response, INDEX, MAX_DELAY};
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process;
+    use std::time::Duration;
+
+    fn h(name: &str, value: &str) -> (String, String) {
+        (String::from(name), String::from(value))
+    }
+
+    /// A document root with `a.txt` and `dir/index.html`, which is removed when dropped.
+    struct Root(PathBuf);
+
+    impl Root {
+        fn new(name: &str) -> Self {
+            let root = env::temp_dir().join(format!("neqo-server-{}-{}", name, process::id()));
+            fs::create_dir_all(root.join("dir")).unwrap();
+            fs::write(root.join("a.txt"), "a").unwrap();
+            fs::write(root.join("dir").join(INDEX), "<p>index</p>").unwrap();
+            Self(root)
+        }
+    }
+
+    impl Drop for Root {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn file_paths() {
+        let root = Path::new("/nonexistent");
+        assert_eq!(
+            file_path(root, "/a/./b.txt"),
+            Some(root.join("a").join("b.txt"))
+        );
+        assert_eq!(file_path(root, "//a.txt"), Some(root.join("a.txt")));
+        assert_eq!(file_path(root, "/../etc/passwd"), None);
+        assert_eq!(file_path(root, "/a/../../etc/passwd"), None);
+        assert_eq!(file_path(root, "/a/.."), None);
+    }
+
+    #[test]
+    fn content_types() {
+        assert_eq!(content_type(Path::new("index.html")), "text/html");
+        assert_eq!(content_type(Path::new("a/b.txt")), "text/plain");
+        assert_eq!(content_type(Path::new("logo.svg")), "image/svg+xml");
+        assert_eq!(
+            content_type(Path::new("a.tar.gz")),
+            "application/octet-stream"
+        );
+        assert_eq!(
+            content_type(Path::new("README")),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn files() {
+        let root = Root::new("files");
+        let response = Response::for_path(Some(&root.0), false, "/a.txt?x=1");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"a");
+        assert_eq!(
+            response.headers(),
+            vec![
+                h(":status", "200"),
+                h("content-type", "text/plain"),
+                h("content-length", "1"),
+            ]
+        );
+
+        // A directory gets its index.html, with or without a trailing slash.
+        for path in &["/dir", "/dir/"] {
+            let response = Response::for_path(Some(&root.0), false, path);
+            assert_eq!(response.status, 200);
+            assert_eq!(response.body, b"<p>index</p>");
+            assert_eq!(response.fields, vec![h("content-type", "text/html")]);
+        }
+
+        assert_eq!(
+            Response::for_path(Some(&root.0), false, "/missing.txt").status,
+            404
+        );
+        // A directory without an index.html.
+        fs::remove_file(root.0.join("dir").join(INDEX)).unwrap();
+        assert_eq!(Response::for_path(Some(&root.0), false, "/dir").status, 404);
+        // Even a file that exists can't be reached from outside the root.
+        assert_eq!(
+            Response::for_path(Some(&root.0.join("dir")), false, "/../a.txt").status,
+            404
+        );
+    }
+
+    #[test]
+    fn synthetic_with_root() {
+        let root = Root::new("synthetic");
+        let response = Response::for_path(Some(&root.0), true, "/3");
+        assert_eq!(response.body, b"aaa");
+        assert_eq!(Response::for_path(Some(&root.0), true, "/a.txt").body, b"a");
+        assert_eq!(Response::for_path(Some(&root.0), false, "/3").status, 404);
+    }
+
+    #[test]
+    fn endpoints() {
+        assert_eq!(
+            Endpoint::for_request("POST", "/upload"),
+            Some(Endpoint::Upload)
+        );
+        assert_eq!(Endpoint::for_request("GET", "/upload"), None);
+        assert_eq!(Endpoint::for_request("GET", "/echo"), Some(Endpoint::Echo));
+        assert_eq!(
+            Endpoint::for_request("POST", "/echo?x=1"),
+            Some(Endpoint::Echo)
+        );
+        assert_eq!(
+            Endpoint::for_request("GET", "/delay/250"),
+            Some(Endpoint::Delay(Duration::from_millis(250)))
+        );
+        assert_eq!(Endpoint::for_request("GET", "/100"), None);
+        assert_eq!(Endpoint::for_request("GET", "/echo/more"), None);
+    }
+
+    #[test]
+    fn delay_limit() {
+        let max = format!("/delay/{}", MAX_DELAY.as_millis());
+        assert_eq!(
+            Endpoint::for_request("GET", &max),
+            Some(Endpoint::Delay(MAX_DELAY))
+        );
+        let over = format!("/delay/{}", MAX_DELAY.as_millis() + 1);
+        assert_eq!(
+            Endpoint::for_request("GET", &over),
+            Some(Endpoint::BadDelay)
+        );
+        // This would overflow an `Instant`.
+        assert_eq!(
+            Endpoint::for_request("GET", "/delay/18446744073709551615"),
+            Some(Endpoint::BadDelay)
+        );
+        assert_eq!(
+            Endpoint::for_request("GET", "/delay/99999999999999999999"),
+            Some(Endpoint::BadDelay)
+        );
+        assert_eq!(
+            Endpoint::for_request("GET", "/delay/soon"),
+            Some(Endpoint::BadDelay)
+        );
+        assert_eq!(
+            Endpoint::for_request("GET", "/delay/"),
+            Some(Endpoint::BadDelay)
+        );
+    }
+
+    #[test]
+    fn echo() {
+        let request = [
+            h(":method", "POST"),
+            h(":path", "/echo"),
+            h("content-type", "text/plain"),
+            h("content-length", "5"),
+            h("x-test", "1"),
+        ];
+        let response = Response::echo(&request, b"hello".to_vec());
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"hello");
+        assert_eq!(
+            response.headers(),
+            vec![
+                h(":status", "200"),
+                h("content-type", "text/plain"),
+                h("x-test", "1"),
+                h("content-length", "5"),
+            ]
+        );
+    }
+
+    #[test]
+    fn echo_empty() {
+        let response = Response::echo(&[h(":method", "GET"), h(":path", "/echo")], Vec::new());
+        assert_eq!(
+            response.headers(),
+            vec![h(":status", "200"), h("content-length", "0")]
+        );
+    }
+}
@@ -0,0 +1,109 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Sharing connections between worker threads. Each worker has its own server and its own
+// sockets, which are bound to the same addresses with SO_REUSEPORT. The kernel picks a
+// socket for each datagram by its addresses, which keeps a connection on one worker only
+// until the client address changes. So each worker puts its index in the first byte of the
+// connection IDs it chooses, and a datagram that arrives at the wrong worker is passed on.
+
+use std::io;
+use std::net::SocketAddr;
+
+use mio::net::UdpSocket;
+use mio_extras::channel::{Receiver, Sender};
+
+use neqo_common::{Datagram, Decoder};
+use neqo_crypto::random;
+use neqo_transport::{ConnectionId, ConnectionIdDecoder, ConnectionIdManager, ConnectionIdRef};
+
+/// The length of the connection IDs that workers choose.
+pub const CID_LEN: usize = 10;
+
+/// A datagram that a worker passes on, with the index of the address that it arrived on.
+pub type Forwarded = (usize, Datagram);
+
+/// What a worker thread needs to know about the others.
+pub struct Worker {
+    pub index: usize,
+    /// The channels to every worker, this one included, in order.
+    pub peers: Vec<Sender<Forwarded>>,
+    /// Datagrams that other workers received for connections that this one owns.
+    pub forwarded: Receiver<Forwarded>,
+}
+
+impl Worker {
+    /// The worker that owns the connection that `d` is for, if that isn't this worker.
+    pub fn other_owner(&self, d: &[u8]) -> Option<usize> {
+        owner(d).filter(|&o| o != self.index && o < self.peers.len())
+    }
+}
+
+/// The worker that chose the destination connection ID of a datagram. There is none for
+/// Initial and 0-RTT packets, which can use a connection ID that the client chose.
+fn owner(d: &[u8]) -> Option<usize> {
+    let first = *d.first()?;
+    let dcid = if first & 0x80 == 0 {
+        d.get(1)
+    } else {
+        if matches!((first >> 4) & 0x3, 0 | 1) || d.get(5) != Some(&(CID_LEN as u8)) {
+            return None;
+        }
+        d.get(6)
+    };
+    dcid.map(|&b| usize::from(b))
+}
+
+/// Bind a socket that other workers can bind to the same address.
+#[cfg(unix)]
+pub fn bind_shared(addr: &SocketAddr) -> io::Result<UdpSocket> {
+    use net2::{unix::UnixUdpBuilderExt, UdpBuilder};
+
+    let builder = if addr.is_ipv4() {
+        UdpBuilder::new_v4()?
+    } else {
+        UdpBuilder::new_v6()?
+    };
+    builder.reuse_port(true)?;
+    UdpSocket::from_socket(builder.bind(addr)?)
+}
+
+#[cfg(not(unix))]
+pub fn bind_shared(_addr: &SocketAddr) -> io::Result<UdpSocket> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "multiple workers need SO_REUSEPORT",
+    ))
+}
+
+/// Makes random connection IDs that start with the index of a worker.
+pub struct WorkerConnectionIdGenerator {
+    worker: u8,
+}
+
+impl WorkerConnectionIdGenerator {
+    pub fn new(worker: u8) -> Self {
+        Self { worker }
+    }
+}
+
+impl ConnectionIdDecoder for WorkerConnectionIdGenerator {
+    fn decode_cid<'a>(&self, dec: &mut Decoder<'a>) -> Option<ConnectionIdRef<'a>> {
+        dec.decode(CID_LEN).map(ConnectionIdRef::from)
+    }
+}
+
+impl ConnectionIdManager for WorkerConnectionIdGenerator {
+    fn generate_cid(&mut self) -> ConnectionId {
+        let mut cid = random(CID_LEN);
+        cid[0] = self.worker;
+        ConnectionId::from(&cid[..])
+    }
+
+    fn as_decoder(&self) -> &dyn ConnectionIdDecoder {
+        self
+    }
+}
@@ -6,7 +6,7 @@
 
 // This file implements functions necessary for address validation.
 
-use neqo_common::{qinfo, qtrace, Decoder, Encoder, Role};
+use neqo_common::{clock::Clock, qinfo, qtrace, Decoder, Encoder, Role};
 use neqo_crypto::{
     constants::{TLS_AES_128_GCM_SHA256, TLS_VERSION_1_3},
     selfencrypt::SelfEncrypt,
@@ -21,7 +21,7 @@ use crate::Res;
 use smallvec::SmallVec;
 use std::convert::TryFrom;
 use std::net::{IpAddr, SocketAddr};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// A prefix we add to Retry tokens to distinguish them from NEW_TOKEN tokens.
 const TOKEN_IDENTIFIER_RETRY: &[u8] = &[0x52, 0x65, 0x74, 0x72, 0x79];
@@ -37,6 +37,12 @@ const MAX_NEW_TOKEN: usize = 4;
 /// This is based on how many might be received over a period where could be
 /// retransmissions.  It should be at least `MAX_NEW_TOKEN`.
 const MAX_SAVED_TOKENS: usize = 8;
+/// How long a Retry token is valid for.
+const EXPIRATION_RETRY: Duration = Duration::from_secs(5);
+/// How long a NEW_TOKEN token is valid for, unless configured otherwise.
+const EXPIRATION_NEW_TOKEN: Duration = Duration::from_secs(60 * 60 * 24);
+/// The period over which `ValidateAddress::Busy` counts connection attempts.
+const BUSY_WINDOW: Duration = Duration::from_secs(1);
 
 /// `ValidateAddress` determines what sort of address validation is performed.
 /// In short, this determines when a Retry packet is sent.
@@ -48,6 +54,10 @@ pub enum ValidateAddress {
     NoToken,
     /// Require address validation even if a NEW_TOKEN token is provided.
     Always,
+    /// Require address validation unless a NEW_TOKEN token is provided, but only
+    /// once more than this many connection attempts without a valid token arrive
+    /// in a one second period.
+    Busy(usize),
 }
 
 pub enum AddressValidationResult {
@@ -64,17 +74,56 @@ pub struct AddressValidation {
     self_encrypt: SelfEncrypt,
     /// When this object was created.
     start_time: Instant,
+    /// The system time at `start_time`.  Token expiry is in system time so that
+    /// other servers that share the key can check it.
+    start_system: SystemTime,
+    /// How long NEW_TOKEN tokens are valid for.
+    new_token_lifetime: Duration,
+    /// The start of the period over which connection attempts are counted.
+    window_start: Instant,
+    /// The number of connection attempts without a valid token in that period.
+    attempts: usize,
 }
 
 impl AddressValidation {
-    pub fn new(now: Instant, validation: ValidateAddress) -> Res<Self> {
+    /// `clock` provides the system time that corresponds to `now`; it is `SystemClock`
+    /// outside of tests.
+    pub fn new(now: Instant, clock: &dyn Clock, validation: ValidateAddress) -> Res<Self> {
         Ok(Self {
             validation,
             self_encrypt: SelfEncrypt::new(TLS_VERSION_1_3, TLS_AES_128_GCM_SHA256)?,
             start_time: now,
+            start_system: clock.system_time(),
+            new_token_lifetime: EXPIRATION_NEW_TOKEN,
+            window_start: now,
+            attempts: 0,
         })
     }
 
+    /// Use a fixed key for tokens, rather than a random one.  Servers that share a key
+    /// accept each other's tokens.
+    pub fn set_key(&mut self, key: &[u8]) -> Res<()> {
+        self.self_encrypt = SelfEncrypt::with_key(TLS_VERSION_1_3, TLS_AES_128_GCM_SHA256, key)?;
+        Ok(())
+    }
+
+    /// Take the system time from `clock`, rather than the clock passed to `new`.  This
+    /// lets tests control when tokens expire.
+    pub fn set_clock(&mut self, clock: &dyn Clock) {
+        self.start_time = clock.now();
+        self.start_system = clock.system_time();
+    }
+
+    /// Set how long NEW_TOKEN tokens are valid for.
+    pub fn set_new_token_lifetime(&mut self, lifetime: Duration) {
+        self.new_token_lifetime = lifetime;
+    }
+
+    /// The system time that corresponds to `now`.
+    fn system_time(&self, now: Instant) -> SystemTime {
+        self.start_system + now.saturating_duration_since(self.start_time)
+    }
+
     fn encode_aad(peer_address: SocketAddr, retry: bool) -> Encoder {
         // Let's be "clever" by putting the peer's address in the AAD.
         // We don't need to encode these into the token as they should be
@@ -107,9 +156,6 @@ impl AddressValidation {
         peer_address: SocketAddr,
         now: Instant,
     ) -> Res<Vec<u8>> {
-        const EXPIRATION_RETRY: Duration = Duration::from_secs(5);
-        const EXPIRATION_NEW_TOKEN: Duration = Duration::from_secs(60 * 60 * 24);
-
         // TODO(mt) rotate keys on a fixed schedule.
         let retry = dcid.is_some();
         let mut data = Encoder::default();
@@ -117,10 +163,13 @@ impl AddressValidation {
             + if retry {
                 EXPIRATION_RETRY
             } else {
-                EXPIRATION_NEW_TOKEN
+                self.new_token_lifetime
             };
-        let end_millis = u32::try_from(end.duration_since(self.start_time).as_millis())?;
-        data.encode_uint(4, end_millis);
+        let end = self
+            .system_time(end)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        data.encode_uint(8, u64::try_from(end.as_millis())?);
         if let Some(dcid) = dcid {
             data.encode(dcid);
         }
@@ -171,9 +220,10 @@ impl AddressValidation {
             return None;
         };
         let mut dec = Decoder::new(&data);
-        match dec.decode_uint(4) {
+        match dec.decode_uint(8) {
             Some(d) => {
-                let end = self.start_time + Duration::from_millis(d);
+                let end = UNIX_EPOCH + Duration::from_millis(d);
+                let now = self.system_time(now);
                 if end < now {
                     qtrace!("Expired token: {:?} vs. {:?}", end, now);
                     return None;
@@ -198,8 +248,25 @@ impl AddressValidation {
         usize::try_from(difference).unwrap() < TOKEN_IDENTIFIER_RETRY.len()
     }
 
+    /// Count a connection attempt without a valid token and decide whether
+    /// it needs to be validated.
+    fn unvalidated_attempt(&mut self, now: Instant) -> bool {
+        match self.validation {
+            ValidateAddress::Never => false,
+            ValidateAddress::NoToken | ValidateAddress::Always => true,
+            ValidateAddress::Busy(limit) => {
+                if now.saturating_duration_since(self.window_start) >= BUSY_WINDOW {
+                    self.window_start = now;
+                    self.attempts = 0;
+                }
+                self.attempts += 1;
+                self.attempts > limit
+            }
+        }
+    }
+
     pub fn validate(
-        &self,
+        &mut self,
         token: &[u8],
         peer_address: SocketAddr,
         now: Instant,
@@ -211,12 +278,12 @@ impl AddressValidation {
         );
 
         if token.is_empty() {
-            if self.validation == ValidateAddress::Never {
-                qinfo!("AddressValidation: no token; accepting");
-                return AddressValidationResult::Pass;
-            } else {
+            if self.unvalidated_attempt(now) {
                 qinfo!("AddressValidation: no token; validating");
                 return AddressValidationResult::Validate;
+            } else {
+                qinfo!("AddressValidation: no token; accepting");
+                return AddressValidationResult::Pass;
             }
         }
         if token.len() <= TOKEN_IDENTIFIER_RETRY.len() {
@@ -256,15 +323,15 @@ impl AddressValidation {
                 // If this looked like a Retry, treat it as being bad.
                 qinfo!("AddressValidation: invalid Retry token; rejecting");
                 AddressValidationResult::Invalid
-            } else if self.validation == ValidateAddress::Never {
-                // We don't require validation, so OK.
-                qinfo!("AddressValidation: invalid NEW_TOKEN token; accepting");
-                AddressValidationResult::Pass
-            } else {
+            } else if self.unvalidated_attempt(now) {
                 // This might be an invalid NEW_TOKEN token, or a valid one
                 // for which we have since lost the keys.  Check again.
                 qinfo!("AddressValidation: invalid NEW_TOKEN token; validating again");
                 AddressValidationResult::Validate
+            } else {
+                // We don't require validation, so OK.
+                qinfo!("AddressValidation: invalid NEW_TOKEN token; accepting");
+                AddressValidationResult::Pass
             }
         }
     }
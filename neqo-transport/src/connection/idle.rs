@@ -83,8 +83,4 @@ impl IdleTimeout {
             self.state = IdleTimeoutState::PacketReceived(now);
         }
     }
-
-    pub fn expired(&self, now: Instant, pto: Duration) -> bool {
-        now >= self.expiry(now, pto)
-    }
 }
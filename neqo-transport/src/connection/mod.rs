@@ -10,17 +10,16 @@ use std::cell::RefCell;
 use std::cmp::max;
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::fmt::{self, Debug};
+use std::fmt::{self, Debug, Display};
 use std::mem;
 use std::net::SocketAddr;
 use std::rc::{Rc, Weak};
 use std::time::{Duration, Instant};
 
-use smallvec::SmallVec;
-
+use neqo_common::metrics::{self, MetricsRef};
 use neqo_common::{
-    event::Provider as EventProvider, hex, hex_snip_middle, qdebug, qerror, qinfo, qlog::NeqoQlog,
-    qtrace, qwarn, Datagram, Decoder, Encoder, Role,
+    event::Provider as EventProvider, hex, hex_snip_middle, log, qdebug, qerror, qinfo,
+    qlog::NeqoQlog, qtrace, qwarn, timer::TimerQueue, Datagram, Decoder, Encoder, Role,
 };
 use neqo_crypto::agent::CertificateInfo;
 use neqo_crypto::{
@@ -36,7 +35,7 @@ use crate::dump::*;
 use crate::events::{ConnectionEvent, ConnectionEvents};
 use crate::flow_mgr::FlowMgr;
 use crate::frame::{
-    AckRange, CloseError, Frame, FrameType, StreamType, FRAME_TYPE_CONNECTION_CLOSE_APPLICATION,
+    AckRange, CloseError, ErrorContext, Frame, StreamType, FRAME_TYPE_CONNECTION_CLOSE_APPLICATION,
     FRAME_TYPE_CONNECTION_CLOSE_TRANSPORT,
 };
 use crate::packet::{
@@ -44,9 +43,10 @@ use crate::packet::{
 };
 use crate::path::Path;
 use crate::qlog;
+use crate::quic_datagrams::{datagram_frame_size, QuicDatagrams, SHORT_PACKET_OVERHEAD};
 use crate::recovery::{LossRecovery, RecoveryToken, SendProfile, GRANULARITY};
 use crate::recv_stream::{RecvStream, RecvStreams, RECV_BUFFER_SIZE};
-use crate::send_stream::{SendStream, SendStreams};
+use crate::send_stream::{SendStream, SendStreams, StreamPriority};
 use crate::stats::{Stats, StatsCell};
 use crate::stream_id::{StreamId, StreamIndex, StreamIndexes};
 use crate::tparams::{
@@ -285,12 +285,37 @@ pub struct Connection {
     loss_recovery: LossRecovery,
     events: ConnectionEvents,
     new_token: NewTokenState,
+    /// QUIC DATAGRAM frames waiting to be sent.
+    quic_datagrams: QuicDatagrams,
     stats: StatsCell,
     qlog: NeqoQlog,
+    metrics: MetricsRef,
+    packet_dump: Option<PacketDump>,
+    /// When the first Initial was sent or received, for reporting the handshake duration.
+    handshake_start: Option<Instant>,
+    /// Where the error that closed the connection was found.
+    error_context: Option<ErrorContext>,
     /// A session ticket was received without NEW_TOKEN,
     /// this is when that turns into an event without NEW_TOKEN.
     release_resumption_token_timer: Option<Instant>,
     quic_version: QuicVersion,
+    /// The deadlines of the connection timers. `next_delay` picks the earliest one and
+    /// `process_timer` handles those that have expired.
+    timers: TimerQueue<ConnectionTimer>,
+    /// The context that log lines are tagged with while the connection is processing, i.e.
+    /// the `Display` form of the connection. It changes only with the original destination
+    /// connection ID, so it is formatted once rather than for every packet.
+    log_context: Rc<dyn Display>,
+}
+
+/// The timers of a connection, see `Connection::next_delay` and `Connection::process_timer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConnectionTimer {
+    Ack,
+    Idle,
+    LossRecovery,
+    KeyUpdate,
+    Pacing,
 }
 
 impl Debug for Connection {
@@ -326,6 +351,7 @@ impl Connection {
         )?;
         c.crypto.states.init(quic_version, Role::Client, &dcid);
         c.original_destination_cid = Some(dcid);
+        c.update_log_context();
         c.initialize_path(local_addr, remote_addr);
         Ok(c)
     }
@@ -427,11 +453,19 @@ impl Connection {
             loss_recovery: LossRecovery::new(cc_algorithm, stats.clone()),
             events: ConnectionEvents::default(),
             new_token: NewTokenState::new(role),
+            quic_datagrams: QuicDatagrams::default(),
             stats,
             qlog: NeqoQlog::disabled(),
+            metrics: metrics::no_metrics(),
+            packet_dump: None,
+            handshake_start: None,
+            error_context: None,
             release_resumption_token_timer: None,
             quic_version,
+            timers: TimerQueue::default(),
+            log_context: Rc::new(String::new()),
         };
+        c.update_log_context();
         c.stats.borrow_mut().init(format!("{}", c));
         Ok(c)
     }
@@ -447,6 +481,28 @@ impl Connection {
         self.qlog = qlog;
     }
 
+    /// Set where the metrics of this connection are reported.
+    pub fn set_metrics(&mut self, metrics: MetricsRef) {
+        self.metrics = metrics;
+    }
+
+    /// Write every packet that is sent or received from now on to `dump`, or stop writing
+    /// them if it is `None`.
+    pub fn set_packet_dump(&mut self, dump: Option<PacketDump>) {
+        self.packet_dump = dump;
+    }
+
+    fn write_packet_dump(&mut self, dir: &str, pt: PacketType, pn: PacketNumber, payload: &[u8]) {
+        let res = match &mut self.packet_dump {
+            Some(dump) => dump.packet(dir, pt, pn, payload),
+            None => return,
+        };
+        if let Err(e) = res {
+            qwarn!([self], "Stopping the packet dump: {}", e);
+            self.packet_dump = None;
+        }
+    }
+
     /// Get the qlog (if any) for this connection.
     pub fn qlog_mut(&mut self) -> &mut NeqoQlog {
         &mut self.qlog
@@ -459,6 +515,10 @@ impl Connection {
         self.original_destination_cid.as_ref()
     }
 
+    fn update_log_context(&mut self) {
+        self.log_context = Rc::new(self.to_string());
+    }
+
     /// Set a local transport parameter, possibly overriding a default value.
     pub fn set_local_tparam(&self, tp: TransportParameterId, value: TransportParameter) -> Res<()> {
         if *self.state() == State::Init {
@@ -629,9 +689,8 @@ impl Connection {
             Duration::from_millis(dec.decode_varint().ok_or(Error::InvalidResumptionToken)?);
         qtrace!([self], "  RTT {:?}", smoothed_rtt);
 
-        let tp_slice = dec.decode_vvec().ok_or(Error::InvalidResumptionToken)?;
-        qtrace!([self], "  transport parameters {}", hex(&tp_slice));
-        let mut dec_tp = Decoder::from(tp_slice);
+        let mut dec_tp = dec.decode_vvec_sub().ok_or(Error::InvalidResumptionToken)?;
+        qtrace!([self], "  transport parameters {}", hex(&dec_tp[..]));
         let tp =
             TransportParameters::decode(&mut dec_tp).map_err(|_| Error::InvalidResumptionToken)?;
 
@@ -740,6 +799,12 @@ impl Connection {
         &self.state
     }
 
+    /// Where the error that closed the connection was found, if the connection was closed
+    /// because of an error that was detected locally.
+    pub fn error_context(&self) -> Option<&ErrorContext> {
+        self.error_context.as_ref()
+    }
+
     /// Get the 0-RTT state of the connection.
     pub fn zero_rtt_state(&self) -> &ZeroRttState {
         &self.zero_rtt_state
@@ -752,18 +817,28 @@ impl Connection {
 
     // This function wraps a call to another function and sets the connection state
     // properly if that call fails.
-    fn capture_error<T>(&mut self, now: Instant, frame_type: FrameType, res: Res<T>) -> Res<T> {
+    fn capture_error<T>(&mut self, now: Instant, context: ErrorContext, res: Res<T>) -> Res<T> {
         if let Err(v) = &res {
+            let context = if let Error::CryptoAlert(alert) = v {
+                ErrorContext {
+                    alert: Some(*alert),
+                    ..context
+                }
+            } else {
+                context
+            };
             #[cfg(debug_assertions)]
-            let msg = format!("{:?}", v);
+            let msg = format!("{:?} ({})", v, context);
             #[cfg(not(debug_assertions))]
             let msg = "";
             let error = ConnectionError::Transport(v.clone());
+            let frame_type = context.frame_type;
             match &self.state {
                 State::Closing { error: err, .. }
                 | State::Draining { error: err, .. }
                 | State::Closed(err) => {
                     qwarn!([self], "Closing again after error {:?}", err);
+                    return res;
                 }
                 State::Init => {
                     // We have not even sent anything just close the connection without sending any error.
@@ -788,6 +863,8 @@ impl Connection {
                     }
                 }
             }
+            qinfo!([self], "Closing on error {:?} ({})", v, context);
+            self.error_context = Some(context);
         }
         res
     }
@@ -795,7 +872,19 @@ impl Connection {
     /// For use with process_input(). Errors there can be ignored, but this
     /// needs to ensure that the state is updated.
     fn absorb_error<T>(&mut self, now: Instant, res: Res<T>) -> Option<T> {
-        self.capture_error(now, 0, res).ok()
+        self.capture_error(now, ErrorContext::default(), res).ok()
+    }
+
+    /// Decode a frame. A frame that can't be decoded closes the connection, noting the
+    /// frame type if that much could be read.
+    fn decode_frame<'a>(&mut self, d: &mut Decoder<'a>, now: Instant) -> Res<Frame<'a>> {
+        let frame_type = d.peek_varint().unwrap_or(0);
+        let res = Frame::decode(d).map_err(|e| match e {
+            // A frame that ends early is badly encoded.
+            Error::NoMoreData => Error::FrameEncodingError,
+            e => e,
+        });
+        self.capture_error(now, ErrorContext::frame_type(frame_type), res)
     }
 
     fn process_timer(&mut self, now: Instant) {
@@ -814,8 +903,13 @@ impl Connection {
             return;
         }
 
-        let pto = self.loss_recovery.pto_raw(PNSpace::ApplicationData);
-        if self.idle_timeout.expired(now, pto) {
+        // Every timer that has expired is taken from the queue and handled.
+        self.update_timers(now);
+        if self
+            .timers
+            .get(&ConnectionTimer::Idle)
+            .map_or(false, |t| t <= now)
+        {
             qinfo!([self], "idle timeout expired");
             self.set_state(State::Closed(ConnectionError::Transport(
                 Error::IdleTimeout,
@@ -825,12 +919,22 @@ impl Connection {
 
         self.cleanup_streams();
 
-        let res = self.crypto.states.check_key_update(now);
-        self.absorb_error(now, res);
-
-        let lost = self.loss_recovery.timeout(now);
-        self.handle_lost_packets(&lost);
-        qlog::packets_lost(&mut self.qlog, &lost);
+        while let Some((timer, _)) = self.timers.pop_expired(now) {
+            qtrace!([self], "{:?} timer expired", timer);
+            match timer {
+                ConnectionTimer::KeyUpdate => {
+                    let res = self.crypto.states.check_key_update(now);
+                    self.absorb_error(now, res);
+                }
+                ConnectionTimer::LossRecovery => {
+                    let lost = self.loss_recovery.timeout(now);
+                    self.handle_lost_packets(&lost);
+                    qlog::packets_lost(&mut self.qlog, &lost);
+                }
+                // The idle timer is checked above, ACKs and paced packets are sent by `output`.
+                ConnectionTimer::Idle | ConnectionTimer::Ack | ConnectionTimer::Pacing => {}
+            }
+        }
 
         if self.release_resumption_token_timer.is_some() {
             self.create_resumption_token(now);
@@ -839,12 +943,34 @@ impl Connection {
 
     /// Process new input datagrams on the connection.
     pub fn process_input(&mut self, d: Datagram, now: Instant) {
+        let _context = log::enter_context(Rc::clone(&self.log_context));
         let res = self.input(d, now);
         self.absorb_error(now, res);
         self.process_saved(now);
         self.cleanup_streams();
     }
 
+    /// Bring the deadlines in `self.timers` up to date. Only those that have changed are moved.
+    /// The pacing timer is left alone, it is only armed by `next_delay`.
+    fn update_timers(&mut self, now: Instant) {
+        let ack_time = self.acks.ack_time(now);
+        qtrace!([self], "Delayed ACK timer {:?}", ack_time);
+        self.timers.set(ConnectionTimer::Ack, ack_time);
+
+        let pto = self.loss_recovery.pto_raw(PNSpace::ApplicationData);
+        let idle_time = self.idle_timeout.expiry(now, pto);
+        qtrace!([self], "Idle timer {:?}", idle_time);
+        self.timers.set(ConnectionTimer::Idle, Some(idle_time));
+
+        let lr_time = self.loss_recovery.next_timeout();
+        qtrace!([self], "Loss recovery timer {:?}", lr_time);
+        self.timers.set(ConnectionTimer::LossRecovery, lr_time);
+
+        let key_update_time = self.crypto.states.update_time();
+        qtrace!([self], "Key update timer {:?}", key_update_time);
+        self.timers.set(ConnectionTimer::KeyUpdate, key_update_time);
+    }
+
     /// Get the time that we next need to be called back, relative to `now`.
     fn next_delay(&mut self, now: Instant, paced: bool) -> Duration {
         qtrace!([self], "Get callback delay {:?}", now);
@@ -854,46 +980,29 @@ impl Connection {
             return timeout.duration_since(now);
         }
 
-        let mut delays = SmallVec::<[_; 6]>::new();
-        if let Some(ack_time) = self.acks.ack_time(now) {
-            qtrace!([self], "Delayed ACK timer {:?}", ack_time);
-            delays.push(ack_time);
-        }
-
-        let pto = self.loss_recovery.pto_raw(PNSpace::ApplicationData);
-        let idle_time = self.idle_timeout.expiry(now, pto);
-        qtrace!([self], "Idle timer {:?}", idle_time);
-        delays.push(idle_time);
-
-        if let Some(lr_time) = self.loss_recovery.next_timeout() {
-            qtrace!([self], "Loss recovery timer {:?}", lr_time);
-            delays.push(lr_time);
-        }
-
-        if let Some(key_update_time) = self.crypto.states.update_time() {
-            qtrace!([self], "Key update timer {:?}", key_update_time);
-            delays.push(key_update_time);
-        }
-
-        if paced {
-            if let Some(pace_time) = self.loss_recovery.next_paced() {
-                qtrace!([self], "Pacing timer {:?}", pace_time);
-                delays.push(pace_time);
-            }
-        }
+        self.update_timers(now);
+        let pace_time = if paced {
+            self.loss_recovery.next_paced()
+        } else {
+            None
+        };
+        qtrace!([self], "Pacing timer {:?}", pace_time);
+        self.timers.set(ConnectionTimer::Pacing, pace_time);
 
         // `release_resumption_token_timer` is not considered here, because
         // it is not important enough to force the application to set a
         // timeout for it  It is expected thatt other activities will
         // drive it.
 
-        let earliest = delays.into_iter().min().unwrap();
+        // There is always an idle timer.
+        let earliest = self.timers.next_expiry().unwrap();
         // TODO(agrover, mt) - need to analyze and fix #47
         // rather than just clamping to zero here.
         qdebug!(
             [self],
-            "delay duration {:?}",
-            max(now, earliest).duration_since(now)
+            "delay duration {:?} for {:?} timer",
+            max(now, earliest).duration_since(now),
+            self.timers.next_key().unwrap()
         );
         debug_assert!(earliest > now);
         max(now, earliest).duration_since(now)
@@ -905,6 +1014,7 @@ impl Connection {
     /// even if no incoming packets.
     #[must_use = "Output of the process_output function must be handled"]
     pub fn process_output(&mut self, now: Instant) -> Output {
+        let _context = log::enter_context(Rc::clone(&self.log_context));
         qtrace!([self], "process_output {:?} {:?}", self.state, now);
 
         if self.state == State::Init {
@@ -931,6 +1041,7 @@ impl Connection {
     /// Process input and generate output.
     #[must_use = "Output of the process function must be handled"]
     pub fn process(&mut self, dgram: Option<Datagram>, now: Instant) -> Output {
+        let _context = log::enter_context(Rc::clone(&self.log_context));
         if let Some(d) = dgram {
             let res = self.input(d, now);
             self.absorb_error(now, res);
@@ -1216,7 +1327,7 @@ impl Connection {
                 match PublicPacket::decode(slc, self.cid_manager.borrow().as_decoder()) {
                     Ok((packet, remainder)) => (packet, remainder),
                     Err(e) => {
-                        qinfo!([self], "Garbage packet: {}", e);
+                        qinfo!(limit: "garbage", [self], "Garbage packet: {}", e);
                         qtrace!([self], "Garbage packet contents: {}", hex(slc));
                         self.stats.borrow_mut().pkt_dropped("Garbage packet");
                         break;
@@ -1245,6 +1356,7 @@ impl Connection {
                         payload.pn(),
                         &payload[..],
                     );
+                    self.write_packet_dump("RX", payload.packet_type(), payload.pn(), &payload[..]);
                     qlog::packet_received(&mut self.qlog, &packet, &payload);
                     let res = self.process_packet(&payload, now);
                     if res.is_err() && self.path.is_none() {
@@ -1255,6 +1367,7 @@ impl Connection {
                     }
                     res?;
                     if self.state == State::WaitInitial {
+                        self.handshake_start.get_or_insert(now);
                         self.start_handshake(&packet, &d)?;
                     }
                     self.process_migrations(&d)?;
@@ -1306,12 +1419,12 @@ impl Connection {
         let mut d = Decoder::from(&packet[..]);
         let mut consecutive_padding = 0;
         while d.remaining() > 0 {
-            let mut f = Frame::decode(&mut d)?;
+            let mut f = self.decode_frame(&mut d, now)?;
 
             // Skip padding
             while f == Frame::Padding && d.remaining() > 0 {
                 consecutive_padding += 1;
-                f = Frame::decode(&mut d)?;
+                f = self.decode_frame(&mut d, now)?;
             }
             if consecutive_padding > 0 {
                 qdebug!(
@@ -1323,9 +1436,9 @@ impl Connection {
             }
 
             ack_eliciting |= f.ack_eliciting();
-            let t = f.get_type();
+            let context = ErrorContext::from(&f);
             let res = self.input_frame(packet.packet_type(), f, now);
-            self.capture_error(now, t, res)?;
+            self.capture_error(now, context, res)?;
         }
         self.acks
             .get_mut(space)
@@ -1360,6 +1473,7 @@ impl Connection {
             // A server needs to accept the client's selected CID during the handshake.
             self.valid_cids.push(ConnectionId::from(packet.dcid()));
             self.original_destination_cid = Some(ConnectionId::from(packet.dcid()));
+            self.update_log_context();
             // Install a path.
             self.initialize_path(d.destination(), d.source());
 
@@ -1583,6 +1697,8 @@ impl Connection {
                 .borrow_mut()
                 .write_frames(builder, &mut tokens, stats);
 
+            self.quic_datagrams
+                .write_frames(builder, &mut tokens, stats);
             self.send_streams.write_frames(builder, &mut tokens, stats);
             self.new_token.write_frames(builder, &mut tokens, stats);
         }
@@ -1676,6 +1792,7 @@ impl Connection {
             }
 
             dump_packet(self, "TX ->", pt, pn, &builder[payload_start..]);
+            self.write_packet_dump("TX", pt, pn, &builder[payload_start..]);
             qlog::packet_sent(
                 &mut self.qlog,
                 pt,
@@ -1685,6 +1802,7 @@ impl Connection {
             );
 
             self.stats.borrow_mut().packets_tx += 1;
+            self.metrics.counter_inc(metrics::PACKETS_SENT, 1);
             encoder = builder.build(self.crypto.states.tx(cspace).unwrap())?;
             debug_assert!(encoder.len() <= path.mtu());
             self.crypto.states.auto_update()?;
@@ -1765,6 +1883,7 @@ impl Connection {
         debug_assert_eq!(self.role, Role::Client);
         qlog::client_connection_started(&mut self.qlog, self.path.as_ref().unwrap());
         self.loss_recovery.start_pacer(now);
+        self.handshake_start = Some(now);
 
         self.handshake(now, PNSpace::Initial, None)?;
         self.set_state(State::WaitInitial);
@@ -2209,6 +2328,20 @@ impl Connection {
                 self.set_state(State::Confirmed);
                 self.discard_keys(PNSpace::Handshake, now);
             }
+            Frame::Datagram { data, fill } => {
+                self.stats.borrow_mut().frame_rx.datagram += 1;
+                let max_frame_size = self
+                    .tps
+                    .borrow()
+                    .local
+                    .get_integer(tparams::MAX_DATAGRAM_FRAME_SIZE);
+                let frame_size = u64::try_from(datagram_frame_size(data.len(), fill)).unwrap();
+                if frame_size > max_frame_size {
+                    qinfo!([self], "DATAGRAM frame of {} bytes not allowed", frame_size);
+                    return Err(Error::ProtocolViolation);
+                }
+                self.events.datagram(data.to_vec());
+            }
         };
 
         Ok(())
@@ -2218,6 +2351,10 @@ impl Connection {
     /// is told that they are lost.  This gives the frame generation code a chance
     /// to retransmit the frame as needed.
     fn handle_lost_packets(&mut self, lost_packets: &[SentPacket]) {
+        if !lost_packets.is_empty() {
+            self.metrics
+                .counter_inc(metrics::PACKETS_LOST, lost_packets.len() as u64);
+        }
         for lost in lost_packets {
             for token in lost.tokens.as_ref() {
                 qdebug!([self], "Lost: {:?}", token);
@@ -2233,6 +2370,7 @@ impl Connection {
                     ),
                     RecoveryToken::HandshakeDone => self.state_signaling.handshake_done(),
                     RecoveryToken::NewToken(seqno) => self.new_token.lost(*seqno),
+                    RecoveryToken::Datagram => {}
                 }
             }
         }
@@ -2285,13 +2423,19 @@ impl Connection {
                     RecoveryToken::Flow(ft) => {
                         self.flow_mgr.borrow_mut().acked(ft, &mut self.send_streams)
                     }
-                    RecoveryToken::HandshakeDone => (),
+                    RecoveryToken::HandshakeDone | RecoveryToken::Datagram => (),
                     RecoveryToken::NewToken(seqno) => self.new_token.acked(*seqno),
                 }
             }
         }
         self.handle_lost_packets(&lost_packets);
         qlog::packets_lost(&mut self.qlog, &lost_packets);
+        self.metrics
+            .gauge_set(metrics::RTT, self.loss_recovery.rtt().as_secs_f64());
+        // A congestion window is nowhere near large enough to lose precision.
+        #[allow(clippy::cast_precision_loss)]
+        let cwnd = self.loss_recovery.cwnd() as f64;
+        self.metrics.gauge_set(metrics::CWND, cwnd);
         let stats = &mut self.stats.borrow_mut().frame_rx;
         stats.ack += 1;
         stats.largest_acknowledged = max(stats.largest_acknowledged, largest_acknowledged);
@@ -2312,6 +2456,7 @@ impl Connection {
         self.send_streams.clear();
         self.recv_streams.clear();
         self.indexes = StreamIndexes::new();
+        self.quic_datagrams.clear();
         self.crypto.states.discard_0rtt_keys();
         self.events.client_0rtt_rejected();
     }
@@ -2347,6 +2492,12 @@ impl Connection {
         self.saved_datagrams
             .make_available(CryptoSpace::ApplicationData);
         self.stats.borrow_mut().resumed = self.crypto.tls.info().unwrap().resumed();
+        if let Some(start) = self.handshake_start {
+            self.metrics.histogram_observe(
+                metrics::HANDSHAKE_DURATION,
+                now.saturating_duration_since(start).as_secs_f64(),
+            );
+        }
         if self.role == Role::Server {
             self.state_signaling.handshake_done();
             self.set_state(State::Confirmed);
@@ -2698,6 +2849,30 @@ impl Connection {
         Ok(())
     }
 
+    /// Set the priority of a send stream, which determines the order in which
+    /// streams with data to send are served.
+    /// # Errors
+    /// `InvalidStreamId` if the stream does not exist.
+    pub fn stream_priority(&mut self, stream_id: u64, priority: StreamPriority) -> Res<()> {
+        self.send_streams
+            .get_mut(stream_id.into())?
+            .set_priority(priority);
+        Ok(())
+    }
+
+    /// Whether the peer is allowed to open the stream `stream_id` under the stream limits
+    /// that have been sent to it. This includes the streams that it has opened already.
+    #[must_use]
+    pub fn peer_stream_allowed(&self, stream_id: u64) -> bool {
+        let stream_id = StreamId::from(stream_id);
+        let max = if stream_id.is_bidi() {
+            self.indexes.local_max_stream_bidi
+        } else {
+            self.indexes.local_max_stream_uni
+        };
+        stream_id.is_remote_initiated(self.role()) && StreamIndex::from(stream_id) <= max
+    }
+
     /// Read buffered data from stream. bool says whether read bytes includes
     /// the final data on stream.
     /// # Errors
@@ -2724,6 +2899,47 @@ impl Connection {
         Ok(())
     }
 
+    /// The largest datagram that can be sent with `send_datagram`. This is limited by
+    /// the `max_datagram_frame_size` transport parameter of the peer and by the path MTU.
+    /// # Errors
+    /// `NotAvailable` if the peer does not accept QUIC datagrams, or if its transport
+    /// parameters are not known yet.
+    pub fn max_datagram_size(&self) -> Res<u64> {
+        let max_frame_size = {
+            let tps = self.tps.borrow();
+            tps.remote
+                .as_ref()
+                .or_else(|| tps.remote_0rtt.as_ref())
+                .map_or(0, |r| r.get_integer(tparams::MAX_DATAGRAM_FRAME_SIZE))
+        };
+        let path = self.path.as_ref().ok_or(Error::NotAvailable)?;
+        let packet_limit = u64::try_from(
+            path.mtu()
+                .saturating_sub(SHORT_PACKET_OVERHEAD + path.remote_cid().len()),
+        )?;
+        let frame_size = max_frame_size.min(packet_limit);
+        // Leave space for the frame type and the length.
+        let overhead = 1 + u64::try_from(Encoder::varint_len(frame_size))?;
+        if frame_size <= overhead {
+            return Err(Error::NotAvailable);
+        }
+        Ok(frame_size - overhead)
+    }
+
+    /// Queue a QUIC DATAGRAM frame (RFC 9221) for sending. Datagrams are not
+    /// retransmitted if they are lost; if too many are waiting to be sent, the
+    /// oldest ones are dropped.
+    /// # Errors
+    /// `NotAvailable` if the peer does not accept QUIC datagrams,
+    /// `TooMuchData` if `buf` is larger than `max_datagram_size()`.
+    pub fn send_datagram(&mut self, buf: &[u8]) -> Res<()> {
+        if u64::try_from(buf.len())? > self.max_datagram_size()? {
+            return Err(Error::TooMuchData);
+        }
+        self.quic_datagrams.add_datagram(buf);
+        Ok(())
+    }
+
     #[cfg(test)]
     pub fn get_pto(&self) -> Duration {
         self.loss_recovery.pto_raw(PNSpace::ApplicationData)
@@ -6,6 +6,7 @@
 
 use super::super::{Connection, Output, State};
 use super::{connect, connect_force_idle, default_client, default_server, send_something};
+use crate::frame::{ErrorContext, FRAME_TYPE_CRYPTO};
 use crate::tparams::{self, TransportParameter};
 use crate::{AppError, ConnectionError, Error, ERROR_APPLICATION_CLOSE};
 
@@ -81,6 +82,16 @@ fn bad_tls_version() {
         *server.state(),
         State::Closed(ConnectionError::Transport(Error::ProtocolViolation))
     );
+    // The error is attributed to the CRYPTO frame that carried the ClientHello.
+    assert_eq!(
+        server.error_context(),
+        Some(&ErrorContext {
+            frame_type: FRAME_TYPE_CRYPTO,
+            stream_id: None,
+            offset: Some(0),
+            alert: None,
+        })
+    );
     assert!(dgram.is_some());
     client.process_input(dgram.unwrap(), now());
     assert_draining(&client, &Error::PeerError(Error::ProtocolViolation.code()));
@@ -0,0 +1,118 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::super::Connection;
+use super::{connect, default_client, default_server};
+use crate::events::ConnectionEvent;
+use crate::tparams::{self, TransportParameter};
+use crate::Error;
+
+use neqo_common::event::Provider;
+use std::convert::TryFrom;
+use test_fixture::now;
+
+const DATAGRAM_FRAME_SIZE: u64 = 1200;
+const DATA: &[u8] = &[1, 2, 3, 4, 5];
+
+fn enable_datagrams(c: &Connection) {
+    c.set_local_tparam(
+        tparams::MAX_DATAGRAM_FRAME_SIZE,
+        TransportParameter::Integer(DATAGRAM_FRAME_SIZE),
+    )
+    .unwrap();
+}
+
+#[test]
+fn datagram_not_available() {
+    let mut client = default_client();
+    let mut server = default_server();
+    enable_datagrams(&client);
+    connect(&mut client, &mut server);
+
+    // The server has not enabled datagrams.
+    assert_eq!(client.max_datagram_size(), Err(Error::NotAvailable));
+    assert_eq!(client.send_datagram(DATA), Err(Error::NotAvailable));
+}
+
+#[test]
+fn datagram_exchange() {
+    let mut client = default_client();
+    let mut server = default_server();
+    enable_datagrams(&client);
+    enable_datagrams(&server);
+    connect(&mut client, &mut server);
+
+    client.send_datagram(DATA).unwrap();
+    client.send_datagram(DATA).unwrap();
+    let out = client.process(None, now());
+    let _ = server.process(out.dgram(), now());
+
+    // Both datagrams are delivered, even though they are the same.
+    let datagrams = server
+        .events()
+        .filter(|e| matches!(e, ConnectionEvent::Datagram(d) if d == DATA))
+        .count();
+    assert_eq!(datagrams, 2);
+    assert_eq!(server.stats().frame_rx.datagram, 2);
+
+    server.send_datagram(DATA).unwrap();
+    let out = server.process(None, now());
+    let _ = client.process(out.dgram(), now());
+    assert!(client
+        .events()
+        .any(|e| matches!(e, ConnectionEvent::Datagram(d) if d == DATA)));
+}
+
+#[test]
+fn datagram_too_big() {
+    let mut client = default_client();
+    let mut server = default_server();
+    enable_datagrams(&client);
+    enable_datagrams(&server);
+    connect(&mut client, &mut server);
+
+    let max = usize::try_from(client.max_datagram_size().unwrap()).unwrap();
+    assert!(max < usize::try_from(DATAGRAM_FRAME_SIZE).unwrap());
+    assert_eq!(
+        client.send_datagram(&vec![0; max + 1]),
+        Err(Error::TooMuchData)
+    );
+
+    // A datagram of the largest size fits into a packet.
+    client.send_datagram(&vec![0; max]).unwrap();
+    let out = client.process(None, now());
+    let _ = server.process(out.dgram(), now());
+    assert!(server
+        .events()
+        .any(|e| matches!(e, ConnectionEvent::Datagram(d) if d.len() == max)));
+}
+
+#[test]
+fn datagram_skipped_when_it_does_not_fit() {
+    let mut client = default_client();
+    let mut server = default_server();
+    enable_datagrams(&client);
+    enable_datagrams(&server);
+    connect(&mut client, &mut server);
+
+    let max = usize::try_from(client.max_datagram_size().unwrap()).unwrap();
+    client.send_datagram(DATA).unwrap();
+    client.send_datagram(&vec![0; max]).unwrap();
+    client.send_datagram(DATA).unwrap();
+
+    // The large datagram does not fit behind the first small one, the second small one does.
+    let out = client.process(None, now());
+    let _ = server.process(out.dgram(), now());
+    assert_eq!(server.stats().frame_rx.datagram, 2);
+
+    // The large datagram follows in the next packet.
+    let out = client.process(None, now());
+    let _ = server.process(out.dgram(), now());
+    assert_eq!(server.stats().frame_rx.datagram, 3);
+    assert!(server
+        .events()
+        .any(|e| matches!(e, ConnectionEvent::Datagram(d) if d.len() == max)));
+}
@@ -11,14 +11,17 @@ use super::{
 };
 use crate::connection::AddressValidation;
 use crate::events::ConnectionEvent;
-use crate::frame::StreamType;
+use crate::frame::{ErrorContext, StreamType, FRAME_TYPE_CRYPTO};
 use crate::path::PATH_MTU_V6;
 use crate::server::ValidateAddress;
 use crate::{CongestionControlAlgorithm, ConnectionError, Error, QuicVersion};
 
+use neqo_common::metrics::{self, Metrics};
 use neqo_common::{event::Provider, qdebug, Datagram};
 use neqo_crypto::{constants::TLS_CHACHA20_POLY1305_SHA256, AuthenticationStatus};
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::rc::Rc;
 use std::time::Duration;
 use test_fixture::{self, assertions, fixture_init, loopback, now, split_datagram};
@@ -95,6 +98,14 @@ fn handshake_failed_authentication() {
     assert!(out.as_dgram_ref().is_some());
     assert_error(&client, &ConnectionError::Transport(Error::CryptoAlert(44)));
     assert_error(&server, &ConnectionError::Transport(Error::PeerError(300)));
+    // The alert isn't raised by a frame.
+    assert_eq!(
+        client.error_context(),
+        Some(&ErrorContext {
+            alert: Some(44),
+            ..ErrorContext::default()
+        })
+    );
 }
 
 #[test]
@@ -120,6 +131,9 @@ fn no_alpn() {
         &server,
         &ConnectionError::Transport(Error::CryptoAlert(120)),
     );
+    let context = server.error_context().unwrap();
+    assert_eq!(context.frame_type, FRAME_TYPE_CRYPTO);
+    assert_eq!(context.alert, Some(120));
 }
 
 #[test]
@@ -328,7 +342,8 @@ fn reorder_05rtt_with_0rtt() {
 
     let mut client = default_client();
     let mut server = default_server();
-    let validation = AddressValidation::new(now(), ValidateAddress::NoToken).unwrap();
+    let validation =
+        AddressValidation::new(now(), &*test_fixture::clock(), ValidateAddress::NoToken).unwrap();
     let validation = Rc::new(RefCell::new(validation));
     server.set_validation(Rc::clone(&validation));
     let mut now = connect_with_rtt(&mut client, &mut server, now(), RTT);
@@ -698,3 +713,56 @@ fn extra_initial_invalid_cid() {
     let nothing = client.process(Some(dgram_copy), now).dgram();
     assert!(nothing.is_none());
 }
+
+/// Keeps the total of each counter, the last value of each gauge and all observations.
+#[derive(Debug, Default)]
+struct RecordedMetrics {
+    counters: RefCell<HashMap<&'static str, u64>>,
+    gauges: RefCell<HashMap<&'static str, f64>>,
+    observations: RefCell<Vec<(&'static str, f64)>>,
+}
+
+impl Metrics for RecordedMetrics {
+    fn counter_inc(&self, name: &'static str, value: u64) {
+        *self.counters.borrow_mut().entry(name).or_insert(0) += value;
+    }
+
+    fn gauge_set(&self, name: &'static str, value: f64) {
+        self.gauges.borrow_mut().insert(name, value);
+    }
+
+    fn histogram_observe(&self, name: &'static str, value: f64) {
+        self.observations.borrow_mut().push((name, value));
+    }
+}
+
+#[test]
+fn handshake_metrics() {
+    let mut client = default_client();
+    let mut server = default_server();
+    let client_metrics = Rc::new(RecordedMetrics::default());
+    let server_metrics = Rc::new(RecordedMetrics::default());
+    client.set_metrics(Rc::clone(&client_metrics) as _);
+    server.set_metrics(Rc::clone(&server_metrics) as _);
+    connect_with_rtt(&mut client, &mut server, now(), DEFAULT_RTT);
+
+    for (c, m) in &[(&client, &client_metrics), (&server, &server_metrics)] {
+        assert_eq!(
+            m.counters.borrow().get(metrics::PACKETS_SENT).copied(),
+            Some(c.stats().packets_tx as u64)
+        );
+        assert_eq!(m.counters.borrow().get(metrics::PACKETS_LOST), None);
+        assert_eq!(
+            m.gauges.borrow().get(metrics::RTT).copied(),
+            Some(c.loss_recovery.rtt().as_secs_f64())
+        );
+        assert_eq!(
+            m.gauges.borrow().get(metrics::CWND).copied(),
+            Some(f64::from(u32::try_from(c.loss_recovery.cwnd()).unwrap()))
+        );
+        let observations = m.observations.borrow();
+        assert_eq!(observations.len(), 1);
+        assert_eq!(observations[0].0, metrics::HANDSHAKE_DURATION);
+        assert!(observations[0].1 >= DEFAULT_RTT.as_secs_f64());
+    }
+}
@@ -29,6 +29,7 @@ use test_fixture::{self, fixture_init, loopback, now};
 // All the tests.
 mod cc;
 mod close;
+mod datagram;
 mod handshake;
 mod idle;
 mod keys;
@@ -152,7 +153,8 @@ fn exchange_ticket(
     server: &mut Connection,
     now: Instant,
 ) -> ResumptionToken {
-    let validation = AddressValidation::new(now, ValidateAddress::NoToken).unwrap();
+    let validation =
+        AddressValidation::new(now, &*test_fixture::clock(), ValidateAddress::NoToken).unwrap();
     let validation = Rc::new(RefCell::new(validation));
     server.set_validation(Rc::clone(&validation));
     server.send_ticket(now, &[]).expect("can send ticket");
@@ -68,7 +68,8 @@ fn address_validation_token_resume() {
 
     let mut client = default_client();
     let mut server = default_server();
-    let validation = AddressValidation::new(now(), ValidateAddress::Always).unwrap();
+    let validation =
+        AddressValidation::new(now(), &*test_fixture::clock(), ValidateAddress::Always).unwrap();
     let validation = Rc::new(RefCell::new(validation));
     server.set_validation(Rc::clone(&validation));
     let mut now = connect_with_rtt(&mut client, &mut server, now(), RTT);
@@ -139,7 +140,8 @@ fn two_tickets_on_timer() {
 fn two_tickets_with_new_token() {
     let mut client = default_client();
     let mut server = default_server();
-    let validation = AddressValidation::new(now(), ValidateAddress::Always).unwrap();
+    let validation =
+        AddressValidation::new(now(), &*test_fixture::clock(), ValidateAddress::Always).unwrap();
     let validation = Rc::new(RefCell::new(validation));
     server.set_validation(Rc::clone(&validation));
     connect(&mut client, &mut server);
@@ -4,7 +4,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use super::super::State;
+use super::super::{State, LOCAL_STREAM_LIMIT_BIDI};
 use super::{
     connect, default_client, default_server, maybe_authenticate, send_something,
     DEFAULT_STREAM_DATA,
@@ -13,11 +13,12 @@ use crate::events::ConnectionEvent;
 use crate::frame::StreamType;
 use crate::recv_stream::RECV_BUFFER_SIZE;
 use crate::send_stream::SEND_BUFFER_SIZE;
+use crate::stream_id::StreamIndex;
 use crate::tparams::{self, TransportParameter};
 use crate::tracking::MAX_UNACKED_PKTS;
 use crate::{Error, StreamId};
 
-use neqo_common::{event::Provider, qdebug};
+use neqo_common::{event::Provider, qdebug, Role};
 use std::convert::TryFrom;
 use test_fixture::now;
 
@@ -578,3 +579,20 @@ fn no_dupdata_readable_events_empty_last_frame() {
     let _ = server.process(out_second_data_frame.dgram(), now());
     assert!(!server.events().any(stream_readable));
 }
+
+#[test]
+fn peer_stream_allowed() {
+    let mut client = default_client();
+    let mut server = default_server();
+    connect(&mut client, &mut server);
+
+    let last = StreamIndex::new(LOCAL_STREAM_LIMIT_BIDI)
+        .to_stream_id(StreamType::BiDi, Role::Client)
+        .as_u64();
+    assert!(server.peer_stream_allowed(0));
+    assert!(server.peer_stream_allowed(last));
+    assert!(!server.peer_stream_allowed(last + 4));
+    // The client cannot open streams of the server.
+    assert!(!server.peer_stream_allowed(1));
+    assert!(!client.peer_stream_allowed(0));
+}
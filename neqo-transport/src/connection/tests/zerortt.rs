@@ -139,8 +139,13 @@ fn zero_rtt_send_reject() {
     .unwrap();
     // Using a freshly initialized anti-replay context
     // should result in the server rejecting 0-RTT.
-    let ar =
-        AntiReplay::new(now(), test_fixture::ANTI_REPLAY_WINDOW, 1, 3).expect("setup anti-replay");
+    let ar = AntiReplay::new(
+        &*test_fixture::clock(),
+        test_fixture::ANTI_REPLAY_WINDOW,
+        1,
+        3,
+    )
+    .expect("setup anti-replay");
     server
         .server_enable_0rtt(&ar, AllowZeroRtt {})
         .expect("enable 0-RTT");
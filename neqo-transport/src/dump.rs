@@ -6,11 +6,14 @@
 
 // Enable just this file for logging to just see packets.
 // e.g. "RUST_LOG=neqo_transport::dump neqo-client ..."
+// For more detail, `PacketDump` writes the packets of one connection to a file.
 
 use crate::connection::Connection;
 use crate::frame::Frame;
 use crate::packet::{PacketNumber, PacketType};
-use neqo_common::{qdebug, Decoder};
+use neqo_common::{hex, qdebug, Decoder};
+
+use std::io::{self, Write};
 
 #[allow(clippy::module_name_repetitions)]
 pub fn dump_packet(conn: &Connection, dir: &str, pt: PacketType, pn: PacketNumber, payload: &[u8]) {
@@ -30,3 +33,99 @@ pub fn dump_packet(conn: &Connection, dir: &str, pt: PacketType, pn: PacketNumbe
     }
     qdebug!([conn], "pn={} type={:?}{}", pn, pt, s);
 }
+
+/// Writes every packet that a connection sends or receives, in the clear, with the fields of
+/// each frame and the hex of anything that doesn't parse as a frame. This works without qlog
+/// or logging, and it shows packets that never result in an event. It is slow, so it is for
+/// debugging only. See `Connection::set_packet_dump`.
+#[allow(clippy::module_name_repetitions)]
+pub struct PacketDump {
+    out: Box<dyn Write>,
+}
+
+impl PacketDump {
+    #[must_use]
+    pub fn new(out: impl Write + 'static) -> Self {
+        Self { out: Box::new(out) }
+    }
+
+    pub(crate) fn packet(
+        &mut self,
+        dir: &str,
+        pt: PacketType,
+        pn: PacketNumber,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        fn end_padding(s: &mut String, padding: &mut usize) {
+            if *padding > 0 {
+                s.push_str(&format!("  Padding {{ len: {} }}\n", padding));
+                *padding = 0;
+            }
+        }
+
+        let mut s = format!("{} {:?} pn={} len={}\n", dir, pt, pn, payload.len());
+        let mut d = Decoder::from(payload);
+        let mut padding = 0;
+        while d.remaining() > 0 {
+            let start = d.offset();
+            match Frame::decode(&mut d) {
+                Ok(Frame::Padding) => padding += 1,
+                Ok(f) => {
+                    end_padding(&mut s, &mut padding);
+                    if let Some(x) = f.dump() {
+                        s.push_str(&format!("  {}\n", x));
+                    }
+                }
+                Err(_) => {
+                    end_padding(&mut s, &mut padding);
+                    s.push_str(&format!("  unparsed {}\n", hex(&payload[start..])));
+                    break;
+                }
+            }
+        }
+        end_padding(&mut s, &mut padding);
+        self.out.write_all(s.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PacketDump;
+    use crate::packet::PacketType;
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    /// Collects what is written, so that it can be read while a `PacketDump` holds it.
+    #[derive(Clone, Default)]
+    struct Sink(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn frames() {
+        let sink = Sink::default();
+        let mut dump = PacketDump::new(sink.clone());
+        // PING, three PADDING, then a frame type that doesn't exist.
+        dump.packet(
+            "RX",
+            PacketType::Short,
+            7,
+            &[0x01, 0, 0, 0, 0x40, 0x99, 0xab],
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(sink.0.borrow().clone()).unwrap(),
+            "RX Short pn=7 len=7\n  Ping\n  Padding { len: 3 }\n  unparsed 4099ab\n"
+        );
+    }
+}
@@ -58,6 +58,8 @@ pub enum ConnectionEvent {
     /// Any data written to streams needs to be written again.
     ZeroRttRejected,
     ResumptionToken(ResumptionToken),
+    /// A QUIC DATAGRAM frame has been received.
+    Datagram(Vec<u8>),
 }
 
 #[derive(Debug, Default, Clone)]
@@ -139,6 +141,10 @@ impl ConnectionEvents {
         self.insert(ConnectionEvent::ZeroRttRejected);
     }
 
+    pub fn datagram(&self, data: Vec<u8>) {
+        self.insert(ConnectionEvent::Datagram(data));
+    }
+
     pub fn recv_stream_complete(&self, stream_id: StreamId) {
         // If stopped, no longer readable.
         self.remove(|evt| matches!(evt, ConnectionEvent::RecvStreamReadable { stream_id: x } if *x == stream_id.as_u64()));
@@ -158,6 +164,8 @@ impl ConnectionEvents {
                 matches!(evt, ConnectionEvent::RecvStreamReset { stream_id: x, .. }
 		                    if *x == *stream_id)
             }),
+            // Datagrams with the same content are not duplicates.
+            ConnectionEvent::Datagram(_) => false,
             _ => q.contains(&event),
         };
         if !already_present {
@@ -6,13 +6,14 @@
 
 // Directly relating to QUIC frames.
 
-use neqo_common::{qtrace, Decoder};
+use neqo_common::{qtrace, Decoder, MAX_VARINT};
 
 use crate::cid::MAX_CONNECTION_ID_LEN;
 use crate::packet::PacketType;
 use crate::stream_id::{StreamId, StreamIndex};
 use crate::{AppError, ConnectionError, Error, Res, TransportError, ERROR_APPLICATION_CLOSE};
 
+use std::cmp::min;
 use std::convert::TryFrom;
 use std::ops::RangeInclusive;
 
@@ -44,6 +45,8 @@ const FRAME_TYPE_PATH_RESPONSE: FrameType = 0x1b;
 pub const FRAME_TYPE_CONNECTION_CLOSE_TRANSPORT: FrameType = 0x1c;
 pub const FRAME_TYPE_CONNECTION_CLOSE_APPLICATION: FrameType = 0x1d;
 const FRAME_TYPE_HANDSHAKE_DONE: FrameType = 0x1e;
+const FRAME_TYPE_DATAGRAM: FrameType = 0x30;
+pub const FRAME_TYPE_DATAGRAM_WITH_LEN: FrameType = 0x31;
 
 const STREAM_FRAME_BIT_FIN: u64 = 0x01;
 const STREAM_FRAME_BIT_LEN: u64 = 0x02;
@@ -119,6 +122,71 @@ impl From<ConnectionError> for CloseError {
     }
 }
 
+/// Where an error was found. The frame type is sent in the CONNECTION_CLOSE frame; the rest
+/// is only for diagnostics.
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct ErrorContext {
+    /// The type of the frame that caused the error, or 0 if the error wasn't caused by a frame.
+    pub frame_type: FrameType,
+    pub stream_id: Option<StreamId>,
+    /// The offset of the data in a STREAM or CRYPTO frame, or the final size in RESET_STREAM.
+    pub offset: Option<u64>,
+    /// The TLS alert, if the error is one.
+    pub alert: Option<u8>,
+}
+
+impl ErrorContext {
+    #[must_use]
+    pub fn frame_type(frame_type: FrameType) -> Self {
+        Self {
+            frame_type,
+            ..Self::default()
+        }
+    }
+}
+
+impl<'a> From<&Frame<'a>> for ErrorContext {
+    fn from(frame: &Frame<'a>) -> Self {
+        let (stream_id, offset) = match frame {
+            Frame::Stream {
+                stream_id, offset, ..
+            } => (Some(*stream_id), Some(*offset)),
+            Frame::ResetStream {
+                stream_id,
+                final_size,
+                ..
+            } => (Some(*stream_id), Some(*final_size)),
+            Frame::StopSending { stream_id, .. }
+            | Frame::MaxStreamData { stream_id, .. }
+            | Frame::StreamDataBlocked { stream_id, .. } => (Some(*stream_id), None),
+            Frame::Crypto { offset, .. } => (None, Some(*offset)),
+            _ => (None, None),
+        };
+        Self {
+            frame_type: frame.get_type(),
+            stream_id,
+            offset,
+            alert: None,
+        }
+    }
+}
+
+impl ::std::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "frame type {:#x}", self.frame_type)?;
+        if let Some(stream_id) = self.stream_id {
+            write!(f, ", stream {}", stream_id)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, ", offset {}", offset)?;
+        }
+        if let Some(alert) = self.alert {
+            write!(f, ", alert {}", alert)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(PartialEq, Debug, Default, Clone)]
 pub struct AckRange {
     pub(crate) gap: u64,
@@ -203,6 +271,10 @@ pub enum Frame<'a> {
         reason_phrase: Vec<u8>,
     },
     HandshakeDone,
+    Datagram {
+        data: &'a [u8],
+        fill: bool,
+    },
 }
 
 impl<'a> Frame<'a> {
@@ -236,6 +308,13 @@ impl<'a> Frame<'a> {
                 FRAME_TYPE_CONNECTION_CLOSE_TRANSPORT + error_code.frame_type_bit()
             }
             Self::HandshakeDone => FRAME_TYPE_HANDSHAKE_DONE,
+            Self::Datagram { fill, .. } => {
+                if *fill {
+                    FRAME_TYPE_DATAGRAM
+                } else {
+                    FRAME_TYPE_DATAGRAM_WITH_LEN
+                }
+            }
         }
     }
 
@@ -379,7 +458,11 @@ impl<'a> Frame<'a> {
                 let ad = dv(dec)?;
                 let nr = dv(dec)?;
                 let fa = dv(dec)?;
-                let mut arr: Vec<AckRange> = Vec::with_capacity(nr as usize);
+                // Each range takes at least two bytes, so `nr` can't be trusted for sizing.
+                let mut arr: Vec<AckRange> = Vec::with_capacity(min(
+                    usize::try_from(nr).unwrap_or(usize::MAX),
+                    dec.remaining() / 2,
+                ));
                 for _ in 0..nr {
                     let ar = AckRange {
                         gap: dv(dec)?,
@@ -409,7 +492,7 @@ impl<'a> Frame<'a> {
             FRAME_TYPE_CRYPTO => {
                 let offset = dv(dec)?;
                 let data = d(dec.decode_vvec())?;
-                if offset + u64::try_from(data.len()).unwrap() > ((1 << 62) - 1) {
+                if offset + u64::try_from(data.len()).unwrap() > MAX_VARINT {
                     return Err(Error::FrameEncodingError);
                 }
                 Ok(Self::Crypto { offset, data })
@@ -436,7 +519,7 @@ impl<'a> Frame<'a> {
                     qtrace!("STREAM frame, with length");
                     d(dec.decode_vvec())?
                 };
-                if o + u64::try_from(data.len()).unwrap() > ((1 << 62) - 1) {
+                if o + u64::try_from(data.len()).unwrap() > MAX_VARINT {
                     return Err(Error::FrameEncodingError);
                 }
                 Ok(Self::Stream {
@@ -525,6 +608,17 @@ impl<'a> Frame<'a> {
                 })
             }
             FRAME_TYPE_HANDSHAKE_DONE => Ok(Self::HandshakeDone),
+            FRAME_TYPE_DATAGRAM | FRAME_TYPE_DATAGRAM_WITH_LEN => {
+                let fill = t == FRAME_TYPE_DATAGRAM;
+                let data = if fill {
+                    qtrace!("DATAGRAM frame, extends to the end of the packet");
+                    dec.decode_remainder()
+                } else {
+                    qtrace!("DATAGRAM frame, with length");
+                    d(dec.decode_vvec())?
+                };
+                Ok(Self::Datagram { data, fill })
+            }
             _ => Err(Error::UnknownFrameType),
         }
     }
@@ -534,6 +628,8 @@ impl<'a> Frame<'a> {
 mod tests {
     use super::*;
     use neqo_common::{Decoder, Encoder};
+    use proptest::collection::vec;
+    use proptest::prelude::*;
 
     fn just_dec(f: &Frame, s: &str) {
         let encoded = Encoder::from_hex(s);
@@ -742,6 +838,36 @@ mod tests {
         just_dec(&f, "1852340002010209090909090909090909090909090909");
     }
 
+    #[test]
+    fn error_context() {
+        let f = Frame::Stream {
+            stream_id: StreamId::from(4),
+            offset: 10,
+            data: &[1, 2, 3],
+            fin: true,
+            fill: false,
+        };
+        let context = ErrorContext::from(&f);
+        assert_eq!(context.frame_type, f.get_type());
+        assert_eq!(context.stream_id, Some(StreamId::from(4)));
+        assert_eq!(context.offset, Some(10));
+        assert_eq!(
+            context.to_string(),
+            format!("frame type {:#x}, stream 4, offset 10", f.get_type())
+        );
+
+        assert_eq!(
+            ErrorContext::from(&Frame::Ping),
+            ErrorContext::frame_type(FRAME_TYPE_PING)
+        );
+        assert_eq!(ErrorContext::default().to_string(), "frame type 0x0");
+        let alert = ErrorContext {
+            alert: Some(120),
+            ..ErrorContext::frame_type(FRAME_TYPE_CRYPTO)
+        };
+        assert_eq!(alert.to_string(), "frame type 0x6, alert 120");
+    }
+
     #[test]
     fn too_large_new_connection_id() {
         let mut enc = Encoder::from_hex("18523400"); // up to the CID
@@ -798,6 +924,22 @@ mod tests {
         just_dec(&f, "1d8000567803010203");
     }
 
+    #[test]
+    fn datagram() {
+        let f = Frame::Datagram {
+            data: &[1, 2, 3],
+            fill: false,
+        };
+        just_dec(&f, "3103010203");
+
+        // Without a length, the frame extends to the end of the packet.
+        let f = Frame::Datagram {
+            data: &[1, 2, 3],
+            fill: true,
+        };
+        just_dec(&f, "30010203");
+    }
+
     #[test]
     fn test_compare() {
         let f1 = Frame::Padding;
@@ -832,4 +974,255 @@ mod tests {
         assert!(res.is_ok());
         assert_eq!(res.unwrap(), vec![5..=7, 0..=3]);
     }
+
+    /// Encode a frame the same way that the connection does.
+    fn encode(frame: &Frame, enc: &mut Encoder) {
+        enc.encode_varint(frame.get_type());
+        match frame {
+            Frame::Padding | Frame::Ping | Frame::HandshakeDone => {}
+            Frame::Ack {
+                largest_acknowledged,
+                ack_delay,
+                first_ack_range,
+                ack_ranges,
+            } => {
+                enc.encode_varint(*largest_acknowledged)
+                    .encode_varint(*ack_delay)
+                    .encode_varint(u64::try_from(ack_ranges.len()).unwrap())
+                    .encode_varint(*first_ack_range);
+                for r in ack_ranges {
+                    enc.encode_varint(r.gap).encode_varint(r.range);
+                }
+            }
+            Frame::ResetStream {
+                stream_id,
+                application_error_code,
+                final_size,
+            } => {
+                enc.encode_varint(stream_id.as_u64())
+                    .encode_varint(*application_error_code)
+                    .encode_varint(*final_size);
+            }
+            Frame::StopSending {
+                stream_id,
+                application_error_code,
+            } => {
+                enc.encode_varint(stream_id.as_u64())
+                    .encode_varint(*application_error_code);
+            }
+            Frame::Crypto { offset, data } => {
+                enc.encode_varint(*offset).encode_vvec(data);
+            }
+            Frame::NewToken { token } => {
+                enc.encode_vvec(token);
+            }
+            Frame::Stream {
+                stream_id,
+                offset,
+                data,
+                fill,
+                ..
+            } => {
+                enc.encode_varint(stream_id.as_u64());
+                if *offset > 0 {
+                    enc.encode_varint(*offset);
+                }
+                if *fill {
+                    enc.encode(data);
+                } else {
+                    enc.encode_vvec(data);
+                }
+            }
+            Frame::MaxData { maximum_data } => {
+                enc.encode_varint(*maximum_data);
+            }
+            Frame::MaxStreamData {
+                stream_id,
+                maximum_stream_data,
+            } => {
+                enc.encode_varint(stream_id.as_u64())
+                    .encode_varint(*maximum_stream_data);
+            }
+            Frame::MaxStreams {
+                maximum_streams, ..
+            } => {
+                enc.encode_varint(maximum_streams.as_u64());
+            }
+            Frame::DataBlocked { data_limit } => {
+                enc.encode_varint(*data_limit);
+            }
+            Frame::StreamDataBlocked {
+                stream_id,
+                stream_data_limit,
+            } => {
+                enc.encode_varint(stream_id.as_u64())
+                    .encode_varint(*stream_data_limit);
+            }
+            Frame::StreamsBlocked { stream_limit, .. } => {
+                enc.encode_varint(stream_limit.as_u64());
+            }
+            Frame::NewConnectionId {
+                sequence_number,
+                retire_prior,
+                connection_id,
+                stateless_reset_token,
+            } => {
+                enc.encode_varint(*sequence_number)
+                    .encode_varint(*retire_prior)
+                    .encode_vec(1, connection_id)
+                    .encode(&stateless_reset_token[..]);
+            }
+            Frame::RetireConnectionId { sequence_number } => {
+                enc.encode_varint(*sequence_number);
+            }
+            Frame::PathChallenge { data } | Frame::PathResponse { data } => {
+                enc.encode(data);
+            }
+            Frame::ConnectionClose {
+                error_code,
+                frame_type,
+                reason_phrase,
+            } => {
+                enc.encode_varint(error_code.code());
+                if let CloseError::Transport(_) = error_code {
+                    enc.encode_varint(*frame_type);
+                }
+                enc.encode_vvec(reason_phrase);
+            }
+            Frame::Datagram { data, fill } => {
+                if *fill {
+                    enc.encode(data);
+                } else {
+                    enc.encode_vvec(data);
+                }
+            }
+        }
+    }
+
+    /// Build a frame of the type chosen by `selector` from the values that proptest picked,
+    /// adjusting them so that the frame is valid.
+    fn make_frame<'a>(
+        selector: u8,
+        v: [u64; 3],
+        data: &'a [u8],
+        token: &'a [u8; 16],
+        flags: [bool; 2],
+    ) -> Frame<'a> {
+        let stream_type = if flags[0] {
+            StreamType::BiDi
+        } else {
+            StreamType::UniDi
+        };
+        let len = u64::try_from(data.len()).unwrap();
+        match selector % 22 {
+            0 => Frame::Padding,
+            1 => Frame::Ping,
+            2 => Frame::Ack {
+                largest_acknowledged: v[0],
+                ack_delay: v[1],
+                first_ack_range: v[2],
+                ack_ranges: data
+                    .chunks(2)
+                    .map(|c| AckRange {
+                        gap: u64::from(c[0]),
+                        range: u64::from(*c.last().unwrap()),
+                    })
+                    .collect(),
+            },
+            3 => Frame::ResetStream {
+                stream_id: StreamId::from(v[0]),
+                application_error_code: v[1],
+                final_size: v[2],
+            },
+            4 => Frame::StopSending {
+                stream_id: StreamId::from(v[0]),
+                application_error_code: v[1],
+            },
+            5 => Frame::Crypto {
+                offset: min(v[0], MAX_VARINT - len),
+                data,
+            },
+            6 if !data.is_empty() => Frame::NewToken { token: data },
+            7 => Frame::Stream {
+                stream_id: StreamId::from(v[0]),
+                offset: min(v[1], MAX_VARINT - len),
+                data,
+                fin: flags[0],
+                fill: flags[1],
+            },
+            8 => Frame::MaxData { maximum_data: v[0] },
+            9 => Frame::MaxStreamData {
+                stream_id: StreamId::from(v[0]),
+                maximum_stream_data: v[1],
+            },
+            10 => Frame::MaxStreams {
+                stream_type,
+                maximum_streams: StreamIndex::new(min(v[0], 1 << 60)),
+            },
+            11 => Frame::DataBlocked { data_limit: v[0] },
+            12 => Frame::StreamDataBlocked {
+                stream_id: StreamId::from(v[0]),
+                stream_data_limit: v[1],
+            },
+            13 => Frame::StreamsBlocked {
+                stream_type,
+                stream_limit: StreamIndex::new(v[0]),
+            },
+            14 => Frame::NewConnectionId {
+                sequence_number: v[0],
+                retire_prior: v[1],
+                connection_id: &data[..min(data.len(), MAX_CONNECTION_ID_LEN)],
+                stateless_reset_token: token,
+            },
+            15 => Frame::RetireConnectionId {
+                sequence_number: v[0],
+            },
+            16 => Frame::PathChallenge {
+                data: <[u8; 8]>::try_from(&token[..8]).unwrap(),
+            },
+            17 => Frame::PathResponse {
+                data: <[u8; 8]>::try_from(&token[8..]).unwrap(),
+            },
+            18 => Frame::ConnectionClose {
+                error_code: CloseError::Transport(v[0]),
+                frame_type: v[1],
+                reason_phrase: data.to_vec(),
+            },
+            19 => Frame::ConnectionClose {
+                error_code: CloseError::Application(v[0]),
+                frame_type: 0,
+                reason_phrase: data.to_vec(),
+            },
+            20 => Frame::HandshakeDone,
+            _ => Frame::Datagram {
+                data,
+                fill: flags[1],
+            },
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(
+            selector in any::<u8>(),
+            v in [0..=MAX_VARINT, 0..=MAX_VARINT, 0..=MAX_VARINT],
+            data in vec(any::<u8>(), 0..100),
+            token in any::<[u8; 16]>(),
+            flags in any::<[bool; 2]>(),
+        ) {
+            let frame = make_frame(selector, v, &data, &token, flags);
+            let mut enc = Encoder::default();
+            encode(&frame, &mut enc);
+            let mut dec = enc.as_decoder();
+            prop_assert_eq!(Frame::decode(&mut dec), Ok(frame));
+            prop_assert_eq!(dec.remaining(), 0);
+        }
+
+        #[test]
+        fn decode_arbitrary(data in vec(any::<u8>(), 0..100)) {
+            // Any input is either decoded or rejected, without panicking.
+            let mut dec = Decoder::from(&data[..]);
+            while dec.remaining() > 0 && Frame::decode(&mut dec).is_ok() {}
+        }
+    }
 }
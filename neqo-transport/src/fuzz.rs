@@ -0,0 +1,50 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Entry points for fuzzing the parsers of bytes that come from the network.
+// These are only built with the "fuzzing" feature; see fuzz/ for the targets.
+
+use neqo_common::Decoder;
+
+use crate::connection::FixedConnectionIdManager;
+use crate::frame::Frame;
+use crate::packet::{PacketType, PublicPacket};
+use crate::tparams::TransportParameters;
+
+/// The connection ID length that short header packets are decoded with.
+const CID_LEN: usize = 8;
+
+/// Decode the public parts of the packets in a datagram.
+pub fn decode_packets(data: &[u8]) {
+    let cid_decoder = FixedConnectionIdManager::new(CID_LEN);
+    let mut slc = data;
+    while !slc.is_empty() {
+        let (packet, remainder) = match PublicPacket::decode(slc, &cid_decoder) {
+            Ok(res) => res,
+            Err(_) => return,
+        };
+        if packet.packet_type() == PacketType::VersionNegotiation {
+            let _ = packet.supported_versions();
+        }
+        let _ = packet.version();
+        slc = remainder;
+    }
+}
+
+/// Decode frames, as though `data` were the payload of a packet.
+pub fn decode_frames(data: &[u8]) {
+    let mut dec = Decoder::from(data);
+    while dec.remaining() > 0 {
+        if Frame::decode(&mut dec).is_err() {
+            return;
+        }
+    }
+}
+
+/// Decode the value of the transport parameters extension.
+pub fn decode_transport_parameters(data: &[u8]) {
+    let _ = TransportParameters::decode(&mut Decoder::from(data));
+}
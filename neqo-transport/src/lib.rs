@@ -18,10 +18,13 @@ mod dump;
 mod events;
 mod flow_mgr;
 mod frame;
+#[cfg(feature = "fuzzing")]
+pub mod fuzz;
 mod pace;
 mod packet;
 mod path;
 mod qlog;
+mod quic_datagrams;
 mod recovery;
 mod recv_stream;
 mod send_stream;
@@ -33,18 +36,19 @@ pub mod tparams;
 mod tracking;
 
 pub use self::cc::CongestionControlAlgorithm;
-pub use self::cid::{ConnectionId, ConnectionIdManager};
+pub use self::cid::{ConnectionId, ConnectionIdDecoder, ConnectionIdManager, ConnectionIdRef};
 pub use self::connection::{Connection, FixedConnectionIdManager, Output, State, ZeroRttState};
+pub use self::dump::PacketDump;
 pub use self::events::{ConnectionEvent, ConnectionEvents};
-pub use self::frame::CloseError;
 pub use self::frame::StreamType;
+pub use self::frame::{CloseError, ErrorContext};
 pub use self::packet::QuicVersion;
 pub use self::sender::PacketSender;
 pub use self::stats::Stats;
 pub use self::stream_id::StreamId;
 
 pub use self::recv_stream::RECV_BUFFER_SIZE;
-pub use self::send_stream::SEND_BUFFER_SIZE;
+pub use self::send_stream::{StreamPriority, SEND_BUFFER_SIZE};
 
 type TransportError = u64;
 const ERROR_APPLICATION_CLOSE: TransportError = 12;
@@ -93,6 +97,8 @@ pub enum Error {
     /// a packet sent with the current keys hasn't been acknowledged.
     KeyUpdateBlocked,
     NoMoreData,
+    /// The peer does not accept QUIC datagrams.
+    NotAvailable,
     NotConnected,
     PacketNumberOverlap,
     PeerApplicationError(AppError),
@@ -117,7 +123,7 @@ impl Error {
             Self::StreamLimitError => 4,
             Self::StreamStateError => 5,
             Self::FinalSizeError => 6,
-            Self::FrameEncodingError => 7,
+            Self::FrameEncodingError | Self::DecodingFrame | Self::UnknownFrameType => 7,
             Self::TransportParameterError => 8,
             Self::ProtocolViolation => 10,
             Self::InvalidToken => 11,
@@ -469,7 +469,8 @@ impl<'a> PublicPacket<'a> {
         if packet_type == PacketType::Retry {
             let header_len = decoder.offset();
             let expansion = retry::expansion(quic_version);
-            let token = Self::opt(decoder.decode(decoder.remaining() - expansion))?;
+            let token_len = Self::opt(decoder.remaining().checked_sub(expansion))?;
+            let token = Self::opt(decoder.decode(token_len))?;
             if token.is_empty() {
                 return Err(Error::InvalidPacket);
             }
@@ -795,6 +796,8 @@ mod tests {
     use crate::crypto::{CryptoDxState, CryptoStates};
     use crate::{FixedConnectionIdManager, QuicVersion};
     use neqo_common::Encoder;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
     use test_fixture::{fixture_init, now};
 
     const CLIENT_CID: &[u8] = &[0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08];
@@ -1330,4 +1333,46 @@ mod tests {
         assert_eq!(decrypted.pn(), 654_360_564);
         assert_eq!(&decrypted[..], &[0x01]);
     }
+
+    /// A Retry packet that is too short for the integrity tag is rejected.
+    #[test]
+    fn decode_short_retry() {
+        fixture_init();
+        let mut enc = Encoder::default();
+        enc.encode_byte(PACKET_BIT_LONG | PACKET_BIT_FIXED_QUIC | (PACKET_TYPE_RETRY << 4))
+            .encode_uint(4, QuicVersion::default().as_u32())
+            .encode_vec(1, CLIENT_CID)
+            .encode_vec(1, SERVER_CID)
+            .encode(&[0; 8]);
+        assert!(PublicPacket::decode(&enc, &FixedConnectionIdManager::new(5)).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn vn_round_trip(
+            dcid in vec(any::<u8>(), 0..=MAX_CONNECTION_ID_LEN),
+            scid in vec(any::<u8>(), 0..=MAX_CONNECTION_ID_LEN),
+        ) {
+            fixture_init();
+            let vn = PacketBuilder::version_negotiation(&dcid, &scid);
+            let (packet, remainder) =
+                PublicPacket::decode(&vn, &FixedConnectionIdManager::new(5)).unwrap();
+            prop_assert!(remainder.is_empty());
+            prop_assert_eq!(packet.packet_type(), PacketType::VersionNegotiation);
+            prop_assert_eq!(&packet.dcid()[..], &dcid[..]);
+            prop_assert_eq!(&packet.scid()[..], &scid[..]);
+        }
+
+        #[test]
+        fn decode_arbitrary(data in vec(any::<u8>(), 0..100)) {
+            // Any input is either decoded or rejected, without panicking.
+            fixture_init();
+            let cid_decoder = FixedConnectionIdManager::new(5);
+            if let Ok((packet, _)) = PublicPacket::decode(&data, &cid_decoder) {
+                if packet.packet_type() == PacketType::VersionNegotiation {
+                    let _ = packet.supported_versions();
+                }
+            }
+        }
+    }
 }
@@ -11,9 +11,13 @@ use std::ops::RangeInclusive;
 use std::string::String;
 use std::time::Duration;
 
-use qlog::{self, event::Event, PacketHeader, QuicFrame};
+use qlog::{self, PacketHeader, QuicFrame};
 
-use neqo_common::{hex, qinfo, qlog::NeqoQlog, Decoder};
+use neqo_common::{
+    hex, qinfo,
+    qlog::{Event, NeqoQlog},
+    Decoder,
+};
 
 use crate::connection::State;
 use crate::frame::{self, Frame};
@@ -426,6 +430,8 @@ fn frame_to_qlogframe(frame: &Frame) -> QuicFrame {
             Some(frame_type.to_string()),
         ),
         Frame::HandshakeDone => QuicFrame::handshake_done(),
+        // qlog has no DATAGRAM frame yet.
+        Frame::Datagram { .. } => QuicFrame::unknown(frame.get_type()),
     }
 }
 
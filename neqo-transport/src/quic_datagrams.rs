@@ -0,0 +1,103 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// QUIC DATAGRAM frames (RFC 9221) that are waiting to be sent.
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+use neqo_common::{qdebug, Encoder};
+
+use crate::frame::FRAME_TYPE_DATAGRAM_WITH_LEN;
+use crate::packet::PacketBuilder;
+use crate::recovery::RecoveryToken;
+use crate::stats::FrameStats;
+
+/// The number of datagrams that can be queued. When more are added the oldest one is
+/// dropped; datagrams are not reliable and late ones are of little use anyway.
+const MAX_QUEUED_DATAGRAMS: usize = 10;
+
+/// The overhead of a short header packet, apart from the connection ID: the first byte,
+/// the largest packet number encoding and the AEAD expansion.
+pub const SHORT_PACKET_OVERHEAD: usize = 1 + 4 + 16;
+
+/// The size of a DATAGRAM frame that carries `len` bytes.
+pub fn datagram_frame_size(len: usize, fill: bool) -> usize {
+    let len_size = if fill {
+        0
+    } else {
+        Encoder::varint_len(u64::try_from(len).unwrap())
+    };
+    1 + len_size + len
+}
+
+#[derive(Debug, Default)]
+pub struct QuicDatagrams {
+    datagrams: VecDeque<Vec<u8>>,
+}
+
+impl QuicDatagrams {
+    /// Queue a datagram for sending.
+    pub fn add_datagram(&mut self, buf: &[u8]) {
+        if self.datagrams.len() == MAX_QUEUED_DATAGRAMS {
+            qdebug!("Too many datagrams queued, dropping the oldest one");
+            self.datagrams.pop_front();
+        }
+        self.datagrams.push_back(buf.to_vec());
+    }
+
+    /// Write as many datagrams as fit into the packet. A datagram that does not fit is
+    /// left for the next packet, but the smaller ones behind it are still written.
+    pub fn write_frames(
+        &mut self,
+        builder: &mut PacketBuilder,
+        tokens: &mut Vec<RecoveryToken>,
+        stats: &mut FrameStats,
+    ) {
+        let mut i = 0;
+        while i < self.datagrams.len() {
+            if datagram_frame_size(self.datagrams[i].len(), false) > builder.remaining() {
+                i += 1;
+                continue;
+            }
+            let dgram = self.datagrams.remove(i).unwrap();
+            builder.encode_varint(FRAME_TYPE_DATAGRAM_WITH_LEN);
+            builder.encode_vvec(&dgram);
+            // Datagrams are not retransmitted, the token only makes the packet ack-eliciting.
+            tokens.push(RecoveryToken::Datagram);
+            stats.datagram += 1;
+        }
+    }
+
+    /// Drop all queued datagrams.
+    pub fn clear(&mut self) {
+        self.datagrams.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{datagram_frame_size, QuicDatagrams, MAX_QUEUED_DATAGRAMS};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn frame_size() {
+        assert_eq!(datagram_frame_size(3, true), 4);
+        assert_eq!(datagram_frame_size(3, false), 5);
+        assert_eq!(datagram_frame_size(64, false), 67);
+    }
+
+    #[test]
+    fn queue_limit() {
+        let mut d = QuicDatagrams::default();
+        for i in 0..=MAX_QUEUED_DATAGRAMS {
+            d.add_datagram(&[u8::try_from(i).unwrap()]);
+        }
+        assert_eq!(d.datagrams.len(), MAX_QUEUED_DATAGRAMS);
+        // The oldest datagram is gone.
+        assert_eq!(d.datagrams.front(), Some(&vec![1]));
+    }
+}
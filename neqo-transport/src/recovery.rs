@@ -52,6 +52,7 @@ pub enum RecoveryToken {
     Flow(FlowControlRecoveryToken),
     HandshakeDone,
     NewToken(usize),
+    Datagram,
 }
 
 #[derive(Debug)]
@@ -617,7 +618,6 @@ impl LossRecovery {
         }
     }
 
-    #[cfg(test)]
     pub fn cwnd(&self) -> usize {
         self.packet_sender.cwnd()
     }
@@ -451,6 +451,46 @@ impl SendStreamState {
     }
 }
 
+/// The transmission priority of a send stream.
+///
+/// Streams with a lower urgency are sent before streams with a higher urgency.
+/// Streams of the same urgency are sent one at a time, in the order they were
+/// created, unless they are incremental, in which case they take turns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamPriority {
+    urgency: u8,
+    incremental: bool,
+}
+
+impl StreamPriority {
+    /// The urgency used for streams that have no explicit priority.
+    pub const DEFAULT_URGENCY: u8 = 3;
+
+    #[must_use]
+    pub const fn new(urgency: u8, incremental: bool) -> Self {
+        Self {
+            urgency,
+            incremental,
+        }
+    }
+
+    #[must_use]
+    pub fn urgency(self) -> u8 {
+        self.urgency
+    }
+
+    #[must_use]
+    pub fn incremental(self) -> bool {
+        self.incremental
+    }
+}
+
+impl Default for StreamPriority {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_URGENCY, false)
+    }
+}
+
 /// Implement a QUIC send stream.
 #[derive(Debug)]
 pub struct SendStream {
@@ -459,6 +499,7 @@ pub struct SendStream {
     state: SendStreamState,
     flow_mgr: Rc<RefCell<FlowMgr>>,
     conn_events: ConnectionEvents,
+    priority: StreamPriority,
 }
 
 impl SendStream {
@@ -474,6 +515,7 @@ impl SendStream {
             state: SendStreamState::Ready,
             flow_mgr,
             conn_events,
+            priority: StreamPriority::default(),
         };
         if ss.avail() > 0 {
             ss.conn_events.send_stream_writable(stream_id);
@@ -481,6 +523,20 @@ impl SendStream {
         ss
     }
 
+    #[must_use]
+    pub fn priority(&self) -> StreamPriority {
+        self.priority
+    }
+
+    pub fn set_priority(&mut self, priority: StreamPriority) {
+        qtrace!(
+            "SendStream {} priority {:?}",
+            self.stream_id.as_u64(),
+            priority
+        );
+        self.priority = priority;
+    }
+
     /// Return the next range to be sent, if any.
     pub fn next_bytes(&mut self) -> Option<(u64, &[u8])> {
         match self.state {
@@ -869,10 +925,32 @@ impl SendStreams {
         tokens: &mut Vec<RecoveryToken>,
         stats: &mut FrameStats,
     ) {
-        for (_, stream) in self {
+        // Visit streams in order of urgency.  Streams with the same urgency
+        // keep the order of the map, which is the order of creation, except
+        // that incremental streams move to the back once they have sent.
+        let mut order = self
+            .0
+            .values()
+            .enumerate()
+            .map(|(i, stream)| (stream.priority.urgency(), i))
+            .collect::<Vec<_>>();
+        order.sort_unstable();
+
+        let mut served = Vec::new();
+        for (_, i) in order {
+            let (id, stream) = self.0.get_index_mut(i).unwrap();
             if let Some(t) = stream.write_frame(builder) {
                 tokens.push(t);
                 stats.stream += 1;
+                if stream.priority.incremental() {
+                    served.push(*id);
+                }
+            }
+        }
+
+        for id in served {
+            if let Some(stream) = self.0.shift_remove(&id) {
+                self.0.insert(id, stream);
             }
         }
     }
@@ -900,7 +978,7 @@ mod tests {
     use super::*;
 
     use crate::events::ConnectionEvent;
-    use neqo_common::{event::Provider, hex_with_len, qtrace};
+    use neqo_common::{event::Provider, hex_with_len, qtrace, MAX_VARINT};
 
     #[test]
     fn test_mark_range() {
@@ -1557,8 +1635,6 @@ mod tests {
     /// Create a `SendStream` and force it into a state where it believes that
     /// `offset` bytes have already been sent and acknowledged.
     fn stream_with_sent(stream: u64, offset: usize) -> SendStream {
-        const MAX_VARINT: u64 = (1 << 62) - 1;
-
         let mut flow_mgr = FlowMgr::default();
         flow_mgr.conn_increase_max_credit(MAX_VARINT);
 
@@ -1713,6 +1789,69 @@ mod tests {
         );
     }
 
+    fn write_stream_ids(streams: &mut SendStreams, space: usize) -> Vec<u64> {
+        let mut builder = PacketBuilder::short(Encoder::new(), false, &[]);
+        let header_len = builder.len();
+        builder.set_limit(header_len + space);
+        let mut tokens = Vec::new();
+        streams.write_frames(&mut builder, &mut tokens, &mut FrameStats::default());
+        tokens
+            .iter()
+            .map(|t| match t {
+                RecoveryToken::Stream(st) => st.id.as_u64(),
+                _ => panic!("unexpected recovery token"),
+            })
+            .collect()
+    }
+
+    fn streams_with_data(ids: &[u64]) -> SendStreams {
+        let mut streams = SendStreams::default();
+        for id in ids {
+            let mut s = stream_with_sent(*id, 0);
+            s.send(&[0x42; 100]).unwrap();
+            streams.insert(StreamId::from(*id), s);
+        }
+        streams
+    }
+
+    #[test]
+    fn priority_urgency() {
+        let mut streams = streams_with_data(&[0, 4, 8]);
+        streams
+            .get_mut(StreamId::from(8))
+            .unwrap()
+            .set_priority(StreamPriority::new(0, false));
+        // Only one stream fits; the most urgent stream goes first.
+        assert_eq!(write_stream_ids(&mut streams, 50), vec![8]);
+        // It is not incremental, so it keeps the connection until its data is sent.
+        assert_eq!(write_stream_ids(&mut streams, 50), vec![8]);
+        // Once it is the least urgent, streams of the default urgency go first, in order
+        // of creation.
+        streams
+            .get_mut(StreamId::from(8))
+            .unwrap()
+            .set_priority(StreamPriority::new(7, false));
+        assert_eq!(write_stream_ids(&mut streams, 50), vec![0]);
+        assert_eq!(write_stream_ids(&mut streams, 50), vec![0]);
+        assert_eq!(write_stream_ids(&mut streams, 50), vec![0, 4]);
+    }
+
+    #[test]
+    fn priority_incremental() {
+        let mut streams = streams_with_data(&[0, 4, 8]);
+        for id in &[0, 4, 8] {
+            streams
+                .get_mut(StreamId::from(*id))
+                .unwrap()
+                .set_priority(StreamPriority::new(3, true));
+        }
+        // Incremental streams of the same urgency take turns.
+        assert_eq!(write_stream_ids(&mut streams, 50), vec![0]);
+        assert_eq!(write_stream_ids(&mut streams, 50), vec![4]);
+        assert_eq!(write_stream_ids(&mut streams, 50), vec![8]);
+        assert_eq!(write_stream_ids(&mut streams, 50), vec![0]);
+    }
+
     #[test]
     fn stream_frame_64() {
         const DATA64: &[u8] = &[0x43; 64];
@@ -6,9 +6,11 @@
 
 // This file implements a server that can handle multiple connections.
 
+use neqo_common::clock::{Clock, SystemClock};
+use neqo_common::metrics::{self, MetricsRef};
 use neqo_common::{
-    self as common, event::Provider, hex, qdebug, qerror, qinfo, qlog::NeqoQlog, qtrace, qwarn,
-    timer::Timer, Datagram, Decoder, Role,
+    event::Provider, hex, qdebug, qerror, qinfo, qlog::NeqoQlog, qtrace, qwarn, timer::Timer,
+    Datagram, Decoder, Role,
 };
 use neqo_crypto::{AntiReplay, Cipher, ZeroRttCheckResult, ZeroRttChecker};
 
@@ -17,7 +19,9 @@ use crate::addr_valid::{AddressValidation, AddressValidationResult};
 use crate::cc::CongestionControlAlgorithm;
 use crate::cid::{ConnectionId, ConnectionIdDecoder, ConnectionIdManager, ConnectionIdRef};
 use crate::connection::{Connection, Output, State};
+use crate::dump::PacketDump;
 use crate::packet::{PacketBuilder, PacketType, PublicPacket};
+use crate::tparams::{self, TransportParameter};
 use crate::{QuicVersion, Res};
 
 use std::cell::RefCell;
@@ -146,6 +150,13 @@ pub struct Server {
     address_validation: Rc<RefCell<AddressValidation>>,
     /// Directory to create qlog traces in
     qlog_dir: Option<PathBuf>,
+    /// Directory to create packet dumps in
+    packet_dump_dir: Option<PathBuf>,
+    /// Where the metrics of new connections are reported.
+    metrics: MetricsRef,
+    /// The `max_datagram_frame_size` transport parameter of new connections, 0 if QUIC
+    /// datagrams are not accepted.
+    max_datagram_frame_size: u64,
 }
 
 impl Server {
@@ -167,7 +178,7 @@ impl Server {
         zero_rtt_checker: Box<dyn ZeroRttChecker>,
         cid_manager: CidMgr,
     ) -> Res<Self> {
-        let validation = AddressValidation::new(now, ValidateAddress::Never)?;
+        let validation = AddressValidation::new(now, &SystemClock, ValidateAddress::Never)?;
         Ok(Self {
             certs: certs.iter().map(|x| String::from(x.as_ref())).collect(),
             protocols: protocols.iter().map(|x| String::from(x.as_ref())).collect(),
@@ -182,25 +193,73 @@ impl Server {
             timers: Timer::new(now, TIMER_GRANULARITY, TIMER_CAPACITY),
             address_validation: Rc::new(RefCell::new(validation)),
             qlog_dir: None,
+            packet_dump_dir: None,
+            metrics: metrics::no_metrics(),
+            max_datagram_frame_size: 0,
         })
     }
 
     /// Set or clear directory to create logs of connection events in QLOG format.
+    /// Each connection is logged to a file named `<original DCID>-server.qlog`.
     pub fn set_qlog_dir(&mut self, dir: Option<PathBuf>) {
         self.qlog_dir = dir;
     }
 
+    /// Set or clear the directory to dump the packets of connections to, see `PacketDump`.
+    /// Each connection is written to a file named `<original DCID>-server.dump`.
+    pub fn set_packet_dump_dir(&mut self, dir: Option<PathBuf>) {
+        self.packet_dump_dir = dir;
+    }
+
+    /// Set where the metrics of connections are reported. This applies to connections that
+    /// are created afterwards.
+    pub fn set_metrics(&mut self, metrics: MetricsRef) {
+        self.metrics = metrics;
+    }
+
     /// Set the policy for address validation.
     pub fn set_validation(&mut self, v: ValidateAddress) {
         self.address_validation.borrow_mut().set_validation(v);
     }
 
+    /// Set the clock that gives the system time used for address validation tokens.
+    /// By default, this is `SystemClock`.  Tokens made by other servers that share the
+    /// key are checked against this clock, so tests can use a `MockClock` to make them
+    /// expire.
+    pub fn set_clock(&mut self, clock: &dyn Clock) {
+        self.address_validation.borrow_mut().set_clock(clock);
+    }
+
+    /// Set how long the tokens that are sent in NEW_TOKEN frames can be used for.
+    /// Tokens sent in Retry packets are always short-lived.
+    pub fn set_token_lifetime(&mut self, lifetime: Duration) {
+        self.address_validation
+            .borrow_mut()
+            .set_new_token_lifetime(lifetime);
+    }
+
+    /// Set the key that protects address validation tokens.  By default, a random key is
+    /// used, so only this server can use the tokens that it makes.  Servers that are set
+    /// with the same key accept each other's tokens.
+    ///
+    /// # Errors
+    /// When the key is not 32 bytes long, or when it can't be imported.
+    pub fn set_token_key(&mut self, key: &[u8]) -> Res<()> {
+        self.address_validation.borrow_mut().set_key(key)
+    }
+
     /// Set the cipher suites that should be used.  Set an empty value to use
     /// default values.
     pub fn set_ciphers(&mut self, ciphers: impl AsRef<[Cipher]>) {
         self.ciphers = Vec::from(ciphers.as_ref());
     }
 
+    /// Accept QUIC DATAGRAM frames up to the given size on new connections.  Set 0 to
+    /// disable QUIC datagrams, which is the default.
+    pub fn set_max_datagram_frame_size(&mut self, size: u64) {
+        self.max_datagram_frame_size = size;
+    }
+
     fn remove_timer(&mut self, c: &StateRef) {
         let last = c.borrow().last_timer;
         self.timers.remove(last, |t| Rc::ptr_eq(t, c));
@@ -246,6 +305,7 @@ impl Server {
 
         if matches!(c.borrow().state(), State::Closed(_)) {
             c.borrow_mut().set_qlog(NeqoQlog::disabled());
+            c.borrow_mut().set_packet_dump(None);
             self.connections
                 .borrow_mut()
                 .retain(|_, v| !Rc::ptr_eq(v, &c));
@@ -268,10 +328,10 @@ impl Server {
         now: Instant,
     ) -> Option<Datagram> {
         qdebug!([self], "Handle initial");
-        let res = self
-            .address_validation
-            .borrow()
-            .validate(&initial.token, dgram.source(), now);
+        let res =
+            self.address_validation
+                .borrow_mut()
+                .validate(&initial.token, dgram.source(), now);
         match res {
             AddressValidationResult::Invalid => None,
             AddressValidationResult::Pass => self.connection_attempt(initial, dgram, None, now),
@@ -339,7 +399,7 @@ impl Server {
         if let Some(qlog_dir) = &self.qlog_dir {
             let mut qlog_path = qlog_dir.to_path_buf();
 
-            qlog_path.push(format!("{}.qlog", attempt_key.odcid));
+            qlog_path.push(format!("{}-server.qlog", attempt_key.odcid));
 
             // The original DCID is chosen by the client. Using create_new()
             // prevents attackers from overwriting existing logs.
@@ -351,16 +411,7 @@ impl Server {
                 Ok(f) => {
                     qinfo!("Qlog output to {}", qlog_path.display());
 
-                    let streamer = ::qlog::QlogStreamer::new(
-                        qlog::QLOG_VERSION.to_string(),
-                        Some("Neqo server qlog".to_string()),
-                        Some("Neqo server qlog".to_string()),
-                        None,
-                        std::time::Instant::now(),
-                        common::qlog::new_trace(Role::Server),
-                        Box::new(f),
-                    );
-                    let n_qlog = NeqoQlog::enabled(streamer, qlog_path);
+                    let n_qlog = NeqoQlog::enabled_with_file(f, qlog_path, Role::Server);
                     match n_qlog {
                         Ok(nql) => nql,
                         Err(e) => {
@@ -384,6 +435,26 @@ impl Server {
         }
     }
 
+    fn create_packet_dump(&self, attempt_key: &AttemptKey) -> Option<PacketDump> {
+        let mut path = self.packet_dump_dir.as_ref()?.to_path_buf();
+        path.push(format!("{}-server.dump", attempt_key.odcid));
+        // As with qlog, don't let a client choose a file to overwrite.
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(f) => {
+                qinfo!("Packet dump to {}", path.display());
+                Some(PacketDump::new(f))
+            }
+            Err(e) => {
+                qerror!(
+                    "Could not open file {} for a packet dump: {}",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
     fn accept_connection(
         &mut self,
         attempt_key: AttemptKey,
@@ -421,7 +492,18 @@ impl Server {
                 c.set_retry_cids(odcid, initial.src_cid, initial.dst_cid);
             }
             c.set_validation(Rc::clone(&self.address_validation));
+            if self.max_datagram_frame_size > 0
+                && c.set_local_tparam(
+                    tparams::MAX_DATAGRAM_FRAME_SIZE,
+                    TransportParameter::Integer(self.max_datagram_frame_size),
+                )
+                .is_err()
+            {
+                qwarn!([self], "Unable to enable QUIC datagrams");
+            }
             c.set_qlog(self.create_qlog_trace(&attempt_key));
+            c.set_packet_dump(self.create_packet_dump(&attempt_key));
+            c.set_metrics(Rc::clone(&self.metrics));
             let c = Rc::new(RefCell::new(ServerConnectionState {
                 c,
                 last_timer: now,
@@ -47,6 +47,8 @@ pub struct FrameStats {
     pub connection_close: usize,
     pub handshake_done: usize,
     pub new_token: usize,
+
+    pub datagram: usize,
 }
 
 impl Debug for FrameStats {
@@ -83,7 +85,8 @@ impl Debug for FrameStats {
             self.retire_connection_id,
             self.path_challenge,
             self.path_response,
-        )
+        )?;
+        writeln!(f, "    datagram {}", self.datagram)
     }
 }
 
@@ -133,6 +136,7 @@ impl Stats {
     pub fn pkt_dropped(&mut self, reason: impl AsRef<str>) {
         self.dropped_rx += 1;
         qinfo!(
+            limit: "dropped",
             [self.info],
             "Dropped received packet: {}; Total: {}",
             reason.as_ref(),
@@ -44,6 +44,7 @@ tpids! {
     ACTIVE_CONNECTION_ID_LIMIT = 0x0e,
     INITIAL_SOURCE_CONNECTION_ID = 0x0f,
     RETRY_SOURCE_CONNECTION_ID = 0x10,
+    MAX_DATAGRAM_FRAME_SIZE = 0x20,
     GREASE_QUIC_BIT = 0x2ab2,
 }
 
@@ -78,12 +79,11 @@ impl TransportParameter {
             Some(v) => v,
             _ => return Err(Error::NoMoreData),
         };
-        let content = match dec.decode_vvec() {
+        let mut d = match dec.decode_vvec_sub() {
             Some(v) => v,
             _ => return Err(Error::NoMoreData),
         };
-        qtrace!("TP {:x} length {:x}", tp, content.len());
-        let mut d = Decoder::from(content);
+        qtrace!("TP {:x} length {:x}", tp, d.remaining());
         let value = match tp {
             ORIGINAL_DESTINATION_CONNECTION_ID
             | INITIAL_SOURCE_CONNECTION_ID
@@ -99,7 +99,8 @@ impl TransportParameter {
             | INITIAL_MAX_STREAM_DATA_BIDI_LOCAL
             | INITIAL_MAX_STREAM_DATA_BIDI_REMOTE
             | INITIAL_MAX_STREAM_DATA_UNI
-            | MAX_ACK_DELAY => match d.decode_varint() {
+            | MAX_ACK_DELAY
+            | MAX_DATAGRAM_FRAME_SIZE => match d.decode_varint() {
                 Some(v) => Self::Integer(v),
                 None => return Err(Error::TransportParameterError),
             },
@@ -184,7 +185,8 @@ impl TransportParameters {
             | INITIAL_MAX_STREAM_DATA_BIDI_REMOTE
             | INITIAL_MAX_STREAM_DATA_UNI
             | INITIAL_MAX_STREAMS_BIDI
-            | INITIAL_MAX_STREAMS_UNI => 0,
+            | INITIAL_MAX_STREAMS_UNI
+            | MAX_DATAGRAM_FRAME_SIZE => 0,
             MAX_UDP_PAYLOAD_SIZE => 65527,
             ACK_DELAY_EXPONENT => 3,
             MAX_ACK_DELAY => 25,
@@ -211,7 +213,8 @@ impl TransportParameters {
             | MAX_UDP_PAYLOAD_SIZE
             | ACK_DELAY_EXPONENT
             | MAX_ACK_DELAY
-            | ACTIVE_CONNECTION_ID_LIMIT => {
+            | ACTIVE_CONNECTION_ID_LIMIT
+            | MAX_DATAGRAM_FRAME_SIZE => {
                 self.set(tp, TransportParameter::Integer(value));
             }
             _ => panic!("Transport parameter not known"),
@@ -389,13 +392,12 @@ where
             return ZeroRttCheckResult::Reject;
         }
         let mut dec = Decoder::from(token);
-        let tpslice = if let Some(v) = dec.decode_vvec() {
+        let mut dec_tp = if let Some(v) = dec.decode_vvec_sub() {
             v
         } else {
             qinfo!("0-RTT: token code error");
             return ZeroRttCheckResult::Fail;
         };
-        let mut dec_tp = Decoder::from(tpslice);
         let remembered = if let Ok(v) = TransportParameters::decode(&mut dec_tp) {
             v
         } else {
@@ -417,6 +419,9 @@ where
 #[allow(unused_variables)]
 mod tests {
     use super::*;
+    use neqo_common::MAX_VARINT;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
 
     #[test]
     fn basic_tps() {
@@ -521,4 +526,68 @@ mod tests {
             assert!(!tps_b.ok_for_0rtt(&tps_a));
         }
     }
+
+    /// Integer parameters and the range of values that are valid for each.
+    const INTEGER_RANGES: &[(TransportParameterId, u64, u64)] = &[
+        (IDLE_TIMEOUT, 0, MAX_VARINT),
+        (MAX_UDP_PAYLOAD_SIZE, 1200, MAX_VARINT),
+        (INITIAL_MAX_DATA, 0, MAX_VARINT),
+        (INITIAL_MAX_STREAM_DATA_BIDI_LOCAL, 0, MAX_VARINT),
+        (INITIAL_MAX_STREAM_DATA_BIDI_REMOTE, 0, MAX_VARINT),
+        (INITIAL_MAX_STREAM_DATA_UNI, 0, MAX_VARINT),
+        (INITIAL_MAX_STREAMS_BIDI, 0, 1 << 60),
+        (INITIAL_MAX_STREAMS_UNI, 0, 1 << 60),
+        (ACK_DELAY_EXPONENT, 0, 20),
+        (MAX_ACK_DELAY, 0, MAX_VARINT),
+        (ACTIVE_CONNECTION_ID_LIMIT, 2, MAX_VARINT),
+        (MAX_DATAGRAM_FRAME_SIZE, 0, MAX_VARINT),
+    ];
+
+    /// Connection ID parameters, which can hold any value up to 20 bytes.
+    const CID_KEYS: &[TransportParameterId] = &[
+        ORIGINAL_DESTINATION_CONNECTION_ID,
+        INITIAL_SOURCE_CONNECTION_ID,
+        RETRY_SOURCE_CONNECTION_ID,
+    ];
+
+    proptest! {
+        #[test]
+        fn round_trip(
+            present in any::<u32>(),
+            values in vec(0..=MAX_VARINT, INTEGER_RANGES.len()),
+            cids in vec(vec(any::<u8>(), 0..=20), CID_KEYS.len()),
+            reset_token in any::<[u8; 16]>(),
+        ) {
+            // Each bit of `present` decides whether a parameter is included.
+            let mut bits = (0..).map(|i| (present & (1 << i)) != 0);
+            let mut tps = TransportParameters::default();
+            for ((k, low, high), v) in INTEGER_RANGES.iter().zip(values) {
+                if bits.next().unwrap() {
+                    tps.set_integer(*k, v.max(*low).min(*high));
+                }
+            }
+            for (k, cid) in CID_KEYS.iter().zip(cids) {
+                if bits.next().unwrap() {
+                    tps.set_bytes(*k, cid);
+                }
+            }
+            if bits.next().unwrap() {
+                tps.set_bytes(STATELESS_RESET_TOKEN, reset_token.to_vec());
+            }
+            if bits.next().unwrap() {
+                tps.set_empty(DISABLE_MIGRATION);
+            }
+
+            let mut enc = Encoder::default();
+            tps.encode(&mut enc);
+            let decoded = TransportParameters::decode(&mut enc.as_decoder());
+            prop_assert_eq!(decoded, Ok(tps));
+        }
+
+        #[test]
+        fn decode_arbitrary(data in vec(any::<u8>(), 0..100)) {
+            // Any input is either decoded or rejected, without panicking.
+            let _ = TransportParameters::decode(&mut Decoder::from(&data[..]));
+        }
+    }
 }
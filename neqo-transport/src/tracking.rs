@@ -15,7 +15,7 @@ use std::ops::{Index, IndexMut};
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 
-use neqo_common::{qdebug, qinfo, qtrace, qwarn};
+use neqo_common::{qdebug, qinfo, qtrace, qwarn, MAX_VARINT};
 use neqo_crypto::{Epoch, TLS_EPOCH_HANDSHAKE, TLS_EPOCH_INITIAL};
 
 use crate::packet::{PacketBuilder, PacketNumber, PacketType};
@@ -545,7 +545,7 @@ impl RecvdPackets {
         let elapsed = now.duration_since(self.largest_pn_time.unwrap());
         // We use the default exponent, so delay is in multiples of 8 microseconds.
         let ack_delay = u64::try_from(elapsed.as_micros() / 8).unwrap_or(u64::MAX);
-        let ack_delay = min((1 << 62) - 1, ack_delay);
+        let ack_delay = min(MAX_VARINT, ack_delay);
         builder.encode_varint(ack_delay);
         builder.encode_varint(u64::try_from(ranges.len() - 1).unwrap()); // extra ranges
         builder.encode_varint(first.len() - 1); // first range
@@ -7,17 +7,18 @@
 #![cfg_attr(feature = "deny-warnings", deny(warnings))]
 #![warn(clippy::pedantic)]
 
-#[macro_use]
-mod sim;
-
 use neqo_transport::{ConnectionError, Error, State};
-use sim::{
-    connection::{ConnectionNode, ReachState, ReceiveData, SendData},
-    network::{Delay, Drop, TailDrop},
-    Simulator,
-};
 use std::ops::Range;
 use std::time::Duration;
+use test_fixture::{
+    boxed,
+    sim::{
+        connection::{ConnectionNode, ReachState, ReceiveData, SendData},
+        network::{Delay, Drop, DropPattern, TailDrop},
+        Simulator,
+    },
+    simulate,
+};
 
 /// The amount of transfer.  Much more than this takes a surprising amount of time.
 const TRANSFER_AMOUNT: usize = 1 << 20; // 1M
@@ -74,6 +75,28 @@ simulate!(
     ],
 );
 
+simulate!(
+    connect_lose_client_initial,
+    [
+        ConnectionNode::new_client(boxed![ReachState::new(State::Confirmed)]),
+        Delay::new(DELAY..DELAY),
+        DropPattern::new(vec![0]),
+        ConnectionNode::new_server(boxed![ReachState::new(State::Confirmed)]),
+        Delay::new(DELAY..DELAY),
+    ],
+);
+
+simulate!(
+    connect_lose_server_flight,
+    [
+        ConnectionNode::new_client(boxed![ReachState::new(State::Confirmed)]),
+        Delay::new(DELAY..DELAY),
+        ConnectionNode::new_server(boxed![ReachState::new(State::Confirmed)]),
+        Delay::new(DELAY..DELAY),
+        DropPattern::burst(0..3),
+    ],
+);
+
 simulate!(
     transfer,
     [
@@ -126,6 +149,16 @@ simulate!(
     ],
 );
 
+simulate!(
+    transfer_jitter_in_order,
+    [
+        ConnectionNode::new_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        Delay::in_order(DELAY_RANGE),
+        ConnectionNode::new_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+        Delay::in_order(DELAY_RANGE),
+    ],
+);
+
 simulate!(
     transfer_taildrop,
     [
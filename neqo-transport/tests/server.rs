@@ -7,7 +7,9 @@
 #![cfg_attr(feature = "deny-warnings", deny(warnings))]
 #![warn(clippy::pedantic)]
 
-use neqo_common::{event::Provider, hex_with_len, qdebug, qtrace, Datagram, Decoder, Encoder};
+use neqo_common::{
+    clock::MockClock, event::Provider, hex_with_len, qdebug, qtrace, Datagram, Decoder, Encoder,
+};
 use neqo_crypto::{
     aead::Aead,
     constants::{TLS_AES_128_GCM_SHA256, TLS_VERSION_1_3},
@@ -540,6 +542,94 @@ fn retry_bad_token() {
     assert!(dgram.is_none());
 }
 
+// With a shared key, a server accepts a Retry token from another server.
+#[test]
+fn retry_shared_key() {
+    const KEY: &[u8] = &[0x5a; 32];
+    let mut client = default_client();
+    let mut retry_server = default_server();
+    retry_server.set_validation(ValidateAddress::Always);
+    retry_server.set_token_key(KEY).unwrap();
+    let mut server = default_server();
+    server.set_validation(ValidateAddress::Always);
+    server.set_token_key(KEY).unwrap();
+
+    let client_initial1 = client.process(None, now()).dgram();
+    assert!(client_initial1.is_some());
+    let retry = retry_server.process(client_initial1, now()).dgram();
+    assert!(retry.is_some());
+    let client_initial2 = client.process(retry, now()).dgram();
+    assert!(client_initial2.is_some());
+
+    let dgram = server.process(client_initial2, now()).dgram(); // Initial, HS
+    assertions::assert_initial(dgram.as_ref().unwrap(), false);
+}
+
+// A server that shares the key checks the expiry of a NEW_TOKEN token with its own clock.
+#[test]
+fn new_token_shared_key_clock() {
+    const KEY: &[u8] = &[0x5a; 32];
+    // At `now()`, this clock is a few days behind the one from the fixture.
+    let behind = || {
+        let clock = MockClock::new(now() + Duration::from_secs(60 * 60 * 24 * 3));
+        clock.set(now());
+        clock
+    };
+    let mut server = default_server();
+    server.set_token_key(KEY).unwrap();
+    server.set_clock(&behind());
+    let token = get_ticket(&mut server);
+
+    let client_initial = |server: &mut Server| {
+        let mut client = default_client();
+        client.enable_resumption(now(), &token).unwrap();
+        let dgram = client.process(None, now()).dgram();
+        server.process(dgram, now()).dgram()
+    };
+
+    // With the same time, the token is accepted.
+    let mut other = default_server();
+    other.set_validation(ValidateAddress::NoToken);
+    other.set_token_key(KEY).unwrap();
+    other.set_clock(&behind());
+    let dgram = client_initial(&mut other);
+    assertions::assert_initial(dgram.as_ref().unwrap(), false);
+
+    // With a clock that is ahead, the token has expired.
+    let mut other = default_server();
+    other.set_validation(ValidateAddress::NoToken);
+    other.set_token_key(KEY).unwrap();
+    other.set_clock(&*test_fixture::clock());
+    let dgram = client_initial(&mut other);
+    assertions::assert_retry(dgram.as_ref().unwrap());
+}
+
+#[test]
+fn retry_busy() {
+    let mut server = default_server();
+    server.set_validation(ValidateAddress::Busy(1));
+    let mut now = now();
+
+    // The first connection attempt is under the limit.
+    let mut client = default_client();
+    let dgram = client.process(None, now).dgram();
+    let dgram = server.process(dgram, now).dgram();
+    assertions::assert_initial(dgram.as_ref().unwrap(), false);
+
+    // The second is over it.
+    let mut client = default_client();
+    let dgram = client.process(None, now).dgram();
+    let dgram = server.process(dgram, now).dgram();
+    assertions::assert_retry(dgram.as_ref().unwrap());
+
+    // Once the period is over, the count starts again.
+    now += Duration::from_secs(1);
+    let mut client = default_client();
+    let dgram = client.process(None, now).dgram();
+    let dgram = server.process(dgram, now).dgram();
+    assertions::assert_initial(dgram.as_ref().unwrap(), false);
+}
+
 // This is really a client test, but we need a server with Retry to test it.
 // In this test, the client sends Initial on PTO.  The Retry should cause
 // all loss recovery timers to be reset, but we had a bug where the PTO timer
@@ -0,0 +1,37 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Platforms without support for per-packet information use the plain socket.
+
+use crate::{Ecn, RecvMeta};
+use neqo_common::Datagram;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+pub fn configure(_socket: &UdpSocket, _local: SocketAddr) -> io::Result<()> {
+    Ok(())
+}
+
+pub fn recv(
+    socket: &UdpSocket,
+    local: SocketAddr,
+    buf: &mut [u8],
+) -> io::Result<(usize, RecvMeta)> {
+    let (len, source) = socket.recv_from(buf)?;
+    Ok((
+        len,
+        RecvMeta {
+            source,
+            destination: local,
+            ecn: Ecn::NotEct,
+            ttl: None,
+        },
+    ))
+}
+
+pub fn send(socket: &UdpSocket, _local: SocketAddr, d: &Datagram, _ecn: Ecn) -> io::Result<usize> {
+    socket.send_to(d, d.destination())
+}
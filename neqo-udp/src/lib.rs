@@ -0,0 +1,252 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// A UDP socket that reports and sets the per-packet information that
+// `std::net::UdpSocket` does not expose: the ECN codepoint, the local address a packet
+// was received on, and the TTL or hop limit.
+
+#![cfg_attr(feature = "deny-warnings", deny(warnings))]
+#![warn(clippy::pedantic)]
+
+use neqo_common::Datagram;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod fallback;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod linux;
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+use fallback as sys;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use linux as sys;
+
+/// The ECN codepoint of a packet. This is carried in the two low bits of the IPv4 TOS field
+/// or of the IPv6 traffic class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ecn {
+    NotEct,
+    Ect1,
+    Ect0,
+    Ce,
+}
+
+impl Ecn {
+    #[must_use]
+    pub fn from_tos(tos: u8) -> Self {
+        match tos & 0b11 {
+            0b01 => Self::Ect1,
+            0b10 => Self::Ect0,
+            0b11 => Self::Ce,
+            _ => Self::NotEct,
+        }
+    }
+
+    #[must_use]
+    pub fn tos(self) -> u8 {
+        match self {
+            Self::NotEct => 0b00,
+            Self::Ect1 => 0b01,
+            Self::Ect0 => 0b10,
+            Self::Ce => 0b11,
+        }
+    }
+}
+
+impl Default for Ecn {
+    fn default() -> Self {
+        Self::NotEct
+    }
+}
+
+/// Information about a received packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvMeta {
+    pub source: SocketAddr,
+    /// The address that the packet was sent to. If the platform does not report it, this is
+    /// the local address of the socket, which is unspecified for a socket that is bound to
+    /// all addresses.
+    pub destination: SocketAddr,
+    /// `Ecn::NotEct` if the platform does not report the ECN codepoint.
+    pub ecn: Ecn,
+    /// The TTL or hop limit, if the platform reports it.
+    pub ttl: Option<u8>,
+}
+
+/// A UDP socket that reports the ECN codepoint, the destination address and the TTL of
+/// received packets, and that can set the ECN codepoint and the source address of packets
+/// it sends. This is supported on Linux and Android; on other platforms it works like
+/// `UdpSocket`.
+#[derive(Debug)]
+pub struct Socket {
+    socket: UdpSocket,
+    local_addr: SocketAddr,
+}
+
+impl Socket {
+    /// # Errors
+    /// If the socket cannot be bound or configured.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Self::new(UdpSocket::bind(addr)?)
+    }
+
+    /// Use an existing socket, e.g. one that has options set that `bind` does not set.
+    /// # Errors
+    /// If the socket cannot be configured.
+    pub fn new(socket: UdpSocket) -> io::Result<Self> {
+        let local_addr = socket.local_addr()?;
+        sys::configure(&socket, local_addr)?;
+        Ok(Self { socket, local_addr })
+    }
+
+    #[must_use]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The underlying socket, e.g. for registering it with an event loop.
+    #[must_use]
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Receive a packet into `buf`, returning its length.
+    /// # Errors
+    /// If the socket cannot be read, including `WouldBlock` for a non-blocking socket.
+    /// On Linux, `InvalidData` if the packet was larger than `buf`; the packet is dropped.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, RecvMeta)> {
+        sys::recv(&self.socket, self.local_addr, buf)
+    }
+
+    /// Receive a packet as a `Datagram`.
+    /// # Errors
+    /// If the socket cannot be read, including `WouldBlock` for a non-blocking socket.
+    pub fn recv_datagram(&self, buf: &mut [u8]) -> io::Result<(Datagram, RecvMeta)> {
+        let (len, meta) = self.recv(buf)?;
+        Ok((
+            Datagram::new(meta.source, meta.destination, &buf[..len]),
+            meta,
+        ))
+    }
+
+    /// Send `d` with the ECN codepoint `ecn`. If the source address of `d` is specified, the
+    /// packet is sent from that address, which lets a socket that is bound to all addresses
+    /// reply from the address a packet was received on.
+    /// # Errors
+    /// If the packet cannot be sent.
+    pub fn send(&self, d: &Datagram, ecn: Ecn) -> io::Result<usize> {
+        sys::send(&self.socket, self.local_addr, d, ecn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Ecn, Socket};
+    use neqo_common::Datagram;
+    use std::io::ErrorKind;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::time::Duration;
+
+    #[test]
+    fn ecn() {
+        for tos in 0..=255 {
+            assert_eq!(Ecn::from_tos(tos).tos(), tos & 0b11);
+        }
+        assert_eq!(Ecn::from_tos(0xb8 | 0b10), Ecn::Ect0);
+        assert_eq!(Ecn::default(), Ecn::NotEct);
+    }
+
+    fn exchange(bind: SocketAddr, ecn: Ecn) {
+        let a = Socket::bind(bind).unwrap();
+        let b = Socket::bind(bind).unwrap();
+        b.socket()
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let d = Datagram::new(a.local_addr(), b.local_addr(), vec![1, 2, 3]);
+        assert_eq!(a.send(&d, ecn).unwrap(), 3);
+
+        let mut buf = [0; 16];
+        let (received, meta) = b.recv_datagram(&mut buf).unwrap();
+        assert_eq!(&received[..], &[1, 2, 3]);
+        assert_eq!(received.source(), a.local_addr());
+        assert_eq!(meta.source, a.local_addr());
+        assert_eq!(meta.destination, b.local_addr());
+        if cfg!(any(target_os = "linux", target_os = "android")) {
+            assert_eq!(meta.ecn, ecn);
+            assert!(meta.ttl.is_some());
+        }
+    }
+
+    #[test]
+    fn exchange_v4() {
+        for ecn in &[Ecn::NotEct, Ecn::Ect0, Ecn::Ect1, Ecn::Ce] {
+            exchange(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)), *ecn);
+        }
+    }
+
+    #[test]
+    fn exchange_v6() {
+        // Not all test environments have IPv6.
+        if Socket::bind((Ipv6Addr::LOCALHOST, 0)).is_err() {
+            return;
+        }
+        for ecn in &[Ecn::NotEct, Ecn::Ect0, Ecn::Ect1, Ecn::Ce] {
+            exchange(SocketAddr::from((Ipv6Addr::LOCALHOST, 0)), *ecn);
+        }
+    }
+
+    /// A packet that does not fit in the buffer is dropped with an error rather than being
+    /// received in part.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn truncated() {
+        let a = Socket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let b = Socket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        b.socket()
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let large = Datagram::new(a.local_addr(), b.local_addr(), vec![1; 32]);
+        a.send(&large, Ecn::NotEct).unwrap();
+        let small = Datagram::new(a.local_addr(), b.local_addr(), vec![2; 3]);
+        a.send(&small, Ecn::NotEct).unwrap();
+
+        let mut buf = [0; 16];
+        let err = b.recv(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let (received, _) = b.recv_datagram(&mut buf).unwrap();
+        assert_eq!(&received[..], &[2; 3]);
+    }
+
+    /// A socket bound to all addresses learns which address a packet was sent to.
+    #[test]
+    fn destination_address() {
+        let a = Socket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let b = Socket::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        for s in &[&a, &b] {
+            s.socket()
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+        }
+        let to = SocketAddr::from((Ipv4Addr::LOCALHOST, b.local_addr().port()));
+        a.send(&Datagram::new(a.local_addr(), to, vec![1]), Ecn::NotEct)
+            .unwrap();
+
+        let mut buf = [0; 16];
+        let (_, meta) = b.recv(&mut buf).unwrap();
+        if cfg!(any(target_os = "linux", target_os = "android")) {
+            assert_eq!(meta.destination, to);
+        } else {
+            assert_eq!(meta.destination, b.local_addr());
+        }
+
+        // The reply is sent from the address the packet was received on.
+        b.send(&Datagram::new(to, a.local_addr(), vec![2]), Ecn::Ect0)
+            .unwrap();
+        let (_, meta) = a.recv(&mut buf).unwrap();
+        assert_eq!(meta.source, to);
+    }
+}
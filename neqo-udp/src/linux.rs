@@ -0,0 +1,293 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Per-packet information on Linux, using control messages with recvmsg and sendmsg.
+
+#![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+
+use crate::{Ecn, RecvMeta};
+use neqo_common::Datagram;
+use std::convert::TryFrom;
+use std::io;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket};
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+
+use libc::{c_int, c_void, cmsghdr, msghdr, sockaddr_storage, socklen_t};
+
+/// Room for the control messages of one packet: TOS, TTL and packet information.
+const CONTROL_LEN: usize = 128;
+
+/// A buffer for control messages, which has to be aligned like `cmsghdr`.
+#[repr(align(8))]
+struct Control([u8; CONTROL_LEN]);
+
+fn socklen<T>() -> socklen_t {
+    mem::size_of::<T>() as socklen_t
+}
+
+fn set_option(socket: &UdpSocket, level: c_int, name: c_int, value: c_int) -> io::Result<()> {
+    let rv = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            (&value as *const c_int).cast(),
+            socklen::<c_int>(),
+        )
+    };
+    if rv == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+pub fn configure(socket: &UdpSocket, local: SocketAddr) -> io::Result<()> {
+    if local.is_ipv6() {
+        set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_RECVTCLASS, 1)?;
+        set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO, 1)?;
+        set_option(socket, libc::IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT, 1)?;
+        // A dual-stack socket needs these for IPv4 packets. They fail on a socket that is
+        // IPv6 only, which doesn't need them.
+        let _ = set_option(socket, libc::IPPROTO_IP, libc::IP_RECVTOS, 1);
+        let _ = set_option(socket, libc::IPPROTO_IP, libc::IP_PKTINFO, 1);
+        let _ = set_option(socket, libc::IPPROTO_IP, libc::IP_RECVTTL, 1);
+    } else {
+        set_option(socket, libc::IPPROTO_IP, libc::IP_RECVTOS, 1)?;
+        set_option(socket, libc::IPPROTO_IP, libc::IP_PKTINFO, 1)?;
+        set_option(socket, libc::IPPROTO_IP, libc::IP_RECVTTL, 1)?;
+    }
+    Ok(())
+}
+
+/// The IPv4 address of `ip`, including IPv4-mapped IPv6 addresses.
+fn ipv4(ip: IpAddr) -> Option<Ipv4Addr> {
+    match ip {
+        IpAddr::V4(v4) => Some(v4),
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, ..] => v6.to_ipv4(),
+            _ => None,
+        },
+    }
+}
+
+fn from_sockaddr(addr: &sockaddr_storage) -> io::Result<SocketAddr> {
+    match c_int::from(addr.ss_family) {
+        libc::AF_INET => {
+            let a = unsafe { &*(addr as *const sockaddr_storage).cast::<libc::sockaddr_in>() };
+            Ok(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(a.sin_addr.s_addr)),
+                u16::from_be(a.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            let a = unsafe { &*(addr as *const sockaddr_storage).cast::<libc::sockaddr_in6>() };
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(a.sin6_addr.s6_addr),
+                u16::from_be(a.sin6_port),
+                a.sin6_flowinfo,
+                a.sin6_scope_id,
+            )))
+        }
+        _ => Err(io::Error::from(io::ErrorKind::InvalidData)),
+    }
+}
+
+fn to_sockaddr(addr: SocketAddr) -> (sockaddr_storage, socklen_t) {
+    let mut storage: sockaddr_storage = unsafe { mem::zeroed() };
+    match addr {
+        SocketAddr::V4(a) => {
+            let sin = unsafe {
+                &mut *(&mut storage as *mut sockaddr_storage).cast::<libc::sockaddr_in>()
+            };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = a.port().to_be();
+            sin.sin_addr.s_addr = u32::from(*a.ip()).to_be();
+            (storage, socklen::<libc::sockaddr_in>())
+        }
+        SocketAddr::V6(a) => {
+            let sin6 = unsafe {
+                &mut *(&mut storage as *mut sockaddr_storage).cast::<libc::sockaddr_in6>()
+            };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = a.port().to_be();
+            sin6.sin6_flowinfo = a.flowinfo();
+            sin6.sin6_addr.s6_addr = a.ip().octets();
+            sin6.sin6_scope_id = a.scope_id();
+            (storage, socklen::<libc::sockaddr_in6>())
+        }
+    }
+}
+
+unsafe fn read_cmsg<T>(cmsg: *const cmsghdr) -> T {
+    ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<T>())
+}
+
+pub fn recv(
+    socket: &UdpSocket,
+    local: SocketAddr,
+    buf: &mut [u8],
+) -> io::Result<(usize, RecvMeta)> {
+    let mut name: sockaddr_storage = unsafe { mem::zeroed() };
+    let mut control = Control([0; CONTROL_LEN]);
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let mut msg: msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = (&mut name as *mut sockaddr_storage).cast();
+    msg.msg_namelen = socklen::<sockaddr_storage>();
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.0.as_mut_ptr().cast();
+    msg.msg_controllen = CONTROL_LEN as _;
+
+    let len = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The rest of a packet that doesn't fit is discarded, so the packet is lost. Control
+    // messages that don't fit would leave the packet information incomplete.
+    if msg.msg_flags & libc::MSG_TRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "packet truncated",
+        ));
+    }
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "control messages truncated",
+        ));
+    }
+    let source = from_sockaddr(&name)?;
+
+    let mut ecn = Ecn::NotEct;
+    let mut ttl = None;
+    let mut destination = None;
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let (level, ty) = unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type) };
+        match (level, ty) {
+            (libc::IPPROTO_IP, libc::IP_TOS) => {
+                ecn = Ecn::from_tos(unsafe { read_cmsg::<u8>(cmsg) });
+            }
+            (libc::IPPROTO_IP, libc::IP_TTL) | (libc::IPPROTO_IPV6, libc::IPV6_HOPLIMIT) => {
+                ttl = u8::try_from(unsafe { read_cmsg::<c_int>(cmsg) }).ok();
+            }
+            (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                let info = unsafe { read_cmsg::<libc::in_pktinfo>(cmsg) };
+                let ip = Ipv4Addr::from(u32::from_be(info.ipi_addr.s_addr));
+                destination = Some(if local.is_ipv6() {
+                    IpAddr::V6(ip.to_ipv6_mapped())
+                } else {
+                    IpAddr::V4(ip)
+                });
+            }
+            (libc::IPPROTO_IPV6, libc::IPV6_TCLASS) => {
+                ecn = Ecn::from_tos(unsafe { read_cmsg::<c_int>(cmsg) } as u8);
+            }
+            (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                let info = unsafe { read_cmsg::<libc::in6_pktinfo>(cmsg) };
+                destination = Some(IpAddr::V6(Ipv6Addr::from(info.ipi6_addr.s6_addr)));
+            }
+            _ => {}
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    Ok((
+        len as usize,
+        RecvMeta {
+            source,
+            destination: SocketAddr::new(destination.unwrap_or_else(|| local.ip()), local.port()),
+            ecn,
+            ttl,
+        },
+    ))
+}
+
+/// Writes control messages into a `Control` buffer.
+struct ControlWriter<'a> {
+    control: &'a mut Control,
+    len: usize,
+}
+
+impl ControlWriter<'_> {
+    fn push<T>(&mut self, level: c_int, ty: c_int, value: T) {
+        let space = unsafe { libc::CMSG_SPACE(mem::size_of::<T>() as u32) } as usize;
+        assert!(self.len + space <= CONTROL_LEN);
+        unsafe {
+            // `Control` is aligned for `cmsghdr` and `len` is a multiple of that alignment.
+            #[allow(clippy::cast_ptr_alignment)]
+            let cmsg = self.control.0.as_mut_ptr().add(self.len).cast::<cmsghdr>();
+            (*cmsg).cmsg_level = level;
+            (*cmsg).cmsg_type = ty;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<T>() as u32) as _;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<T>(), value);
+        }
+        self.len += space;
+    }
+}
+
+pub fn send(socket: &UdpSocket, local: SocketAddr, d: &Datagram, ecn: Ecn) -> io::Result<usize> {
+    let mut destination = d.destination();
+    if let (SocketAddr::V6(_), SocketAddr::V4(a)) = (local, destination) {
+        // A dual-stack socket sends to IPv4-mapped addresses.
+        destination = SocketAddr::new(IpAddr::V6(a.ip().to_ipv6_mapped()), a.port());
+    }
+    let source = d.source().ip();
+
+    let mut control = Control([0; CONTROL_LEN]);
+    let mut writer = ControlWriter {
+        control: &mut control,
+        len: 0,
+    };
+    if ipv4(destination.ip()).is_some() {
+        writer.push(libc::IPPROTO_IP, libc::IP_TOS, c_int::from(ecn.tos()));
+        if let Some(src) = ipv4(source).filter(|a| !a.is_unspecified()) {
+            let mut info: libc::in_pktinfo = unsafe { mem::zeroed() };
+            info.ipi_spec_dst.s_addr = u32::from(src).to_be();
+            writer.push(libc::IPPROTO_IP, libc::IP_PKTINFO, info);
+        }
+    } else {
+        writer.push(
+            libc::IPPROTO_IPV6,
+            libc::IPV6_TCLASS,
+            c_int::from(ecn.tos()),
+        );
+        if let IpAddr::V6(src) = source {
+            if !src.is_unspecified() {
+                let mut info: libc::in6_pktinfo = unsafe { mem::zeroed() };
+                info.ipi6_addr.s6_addr = src.octets();
+                writer.push(libc::IPPROTO_IPV6, libc::IPV6_PKTINFO, info);
+            }
+        }
+    }
+    let control_len = writer.len;
+
+    let (mut name, namelen) = to_sockaddr(destination);
+    let mut iov = libc::iovec {
+        iov_base: d.as_ptr() as *mut c_void,
+        iov_len: d.len(),
+    };
+    let mut msg: msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = (&mut name as *mut sockaddr_storage).cast();
+    msg.msg_namelen = namelen;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.0.as_mut_ptr().cast();
+    msg.msg_controllen = control_len as _;
+
+    let len = unsafe { libc::sendmsg(socket.as_raw_fd(), &msg, 0) };
+    if len < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(len as usize)
+    }
+}
@@ -7,7 +7,7 @@
 #![cfg_attr(feature = "deny-warnings", deny(warnings))]
 #![warn(clippy::pedantic)]
 
-use neqo_common::{event::Provider, Datagram, Decoder};
+use neqo_common::{clock::MockClock, event::Provider, Datagram, Decoder};
 use neqo_crypto::{init_db, AllowZeroRtt, AntiReplay, AuthenticationStatus};
 use neqo_http3::{Http3Client, Http3Parameters, Http3Server};
 use neqo_qpack::QpackSettings;
@@ -25,6 +25,7 @@ use std::time::{Duration, Instant};
 use lazy_static::lazy_static;
 
 pub mod assertions;
+pub mod sim;
 
 /// The path for the database used in tests.
 pub const NSS_DB_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/db");
@@ -56,10 +57,17 @@ pub fn now() -> Instant {
     earlier().checked_add(ANTI_REPLAY_WINDOW).unwrap()
 }
 
+/// A clock that starts at `now()` and only moves when the test moves it.
+#[must_use]
+pub fn clock() -> Rc<MockClock> {
+    Rc::new(MockClock::new(now()))
+}
+
 // Create a default anti-replay context.
 #[must_use]
 pub fn anti_replay() -> AntiReplay {
-    AntiReplay::new(earlier(), ANTI_REPLAY_WINDOW, 1, 3).expect("setup anti-replay")
+    AntiReplay::new(&MockClock::new(earlier()), ANTI_REPLAY_WINDOW, 1, 3)
+        .expect("setup anti-replay")
 }
 
 pub const DEFAULT_SERVER_NAME: &str = "example.com";
@@ -158,6 +166,19 @@ pub fn connect() -> (Connection, Connection) {
 /// Create a http3 client with default configuration.
 #[must_use]
 pub fn default_http3_client() -> Http3Client {
+    http3_client_with_params(&Http3Parameters::new(
+        QpackSettings {
+            max_table_size_encoder: 100,
+            max_table_size_decoder: 100,
+            max_blocked_streams: 100,
+        },
+        10,
+    ))
+}
+
+/// Create a http3 client with the given parameters.
+#[must_use]
+pub fn http3_client_with_params(params: &Http3Parameters) -> Http3Client {
     fixture_init();
     Http3Client::new(
         DEFAULT_SERVER_NAME,
@@ -166,16 +187,9 @@ pub fn default_http3_client() -> Http3Client {
         loopback(),
         &CongestionControlAlgorithm::NewReno,
         QuicVersion::default(),
-        &Http3Parameters {
-            qpack_settings: QpackSettings {
-                max_table_size_encoder: 100,
-                max_table_size_decoder: 100,
-                max_blocked_streams: 100,
-            },
-            max_concurrent_push_streams: 10,
-        },
+        params,
     )
-    .expect("create a default client")
+    .expect("create a client")
 }
 
 /// Create a http3 server with default configuration.
@@ -45,26 +45,29 @@ pub struct ConnectionNode {
 }
 
 impl ConnectionNode {
-    pub fn new_client(goals: impl IntoIterator<Item = Box<dyn ConnectionGoal>>) -> Self {
+    /// Use a connection that was configured by the caller.
+    #[must_use]
+    pub fn new(c: Connection, goals: impl IntoIterator<Item = Box<dyn ConnectionGoal>>) -> Self {
         Self {
-            c: test_fixture::default_client(),
+            c,
             goals: goals.into_iter().collect(),
         }
     }
 
+    #[must_use]
+    pub fn new_client(goals: impl IntoIterator<Item = Box<dyn ConnectionGoal>>) -> Self {
+        Self::new(crate::default_client(), goals)
+    }
+
+    #[must_use]
     pub fn new_server(goals: impl IntoIterator<Item = Box<dyn ConnectionGoal>>) -> Self {
-        Self {
-            c: test_fixture::default_server(),
-            goals: goals.into_iter().collect(),
-        }
+        Self::new(crate::default_server(), goals)
     }
 
-    #[allow(dead_code)]
     pub fn clear_goals(&mut self) {
         self.goals.clear();
     }
 
-    #[allow(dead_code)]
     pub fn add_goal(&mut self, goal: Box<dyn ConnectionGoal>) {
         self.goals.push(goal);
     }
@@ -146,6 +149,7 @@ pub struct ReachState {
 }
 
 impl ReachState {
+    #[must_use]
     pub fn new(target: State) -> Self {
         Self { target }
     }
@@ -173,6 +177,7 @@ pub struct SendData {
 }
 
 impl SendData {
+    #[must_use]
     pub fn new(amount: usize) -> Self {
         Self {
             remaining: amount,
@@ -260,6 +265,7 @@ pub struct ReceiveData {
 }
 
 impl ReceiveData {
+    #[must_use]
     pub fn new(amount: usize) -> Self {
         Self { remaining: amount }
     }
@@ -9,6 +9,7 @@
 use super::{Node, Rng};
 use neqo_common::Datagram;
 use neqo_transport::Output;
+use std::cmp::max;
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::fmt::{self, Debug};
@@ -28,6 +29,7 @@ impl RandomDelay {
     /// is inverted (i.e., `bounds.start > bounds.end`), or spans 2^64
     /// or more nanoseconds.
     /// A zero-length range means that random values won't be taken from the Rng
+    #[must_use]
     pub fn new(bounds: Range<Duration>) -> Self {
         let max = u64::try_from((bounds.end - bounds.start).as_nanos()).unwrap();
         Self {
@@ -48,21 +50,44 @@ impl RandomDelay {
     }
 }
 
+/// Delays each datagram by a random amount.
 pub struct Delay {
     random: RandomDelay,
+    /// If set, datagrams are never delivered before those that arrived before them.
+    in_order: bool,
     queue: BTreeMap<Instant, Datagram>,
 }
 
 impl Delay {
+    /// Delay each datagram by a value taken from `bounds`.  If the range is wider than
+    /// the time between datagrams, they will be reordered.
+    #[must_use]
     pub fn new(bounds: Range<Duration>) -> Self {
         Self {
             random: RandomDelay::new(bounds),
+            in_order: false,
             queue: BTreeMap::default(),
         }
     }
 
+    /// Like `new`, but datagrams are always delivered in the order they arrive.  This adds
+    /// jitter without reordering: a datagram with a short delay waits for the datagrams
+    /// ahead of it.
+    #[must_use]
+    pub fn in_order(bounds: Range<Duration>) -> Self {
+        Self {
+            in_order: true,
+            ..Self::new(bounds)
+        }
+    }
+
     fn insert(&mut self, d: Datagram, now: Instant) {
         let mut t = now + self.random.next();
+        if self.in_order {
+            if let Some(&last) = self.queue.keys().next_back() {
+                t = max(t, last);
+            }
+        }
         while self.queue.contains_key(&t) {
             // This is a little inefficient, but it avoids drops on collisions,
             // which are super-common for a fixed delay.
@@ -9,7 +9,9 @@
 use super::{Node, Rng};
 use neqo_common::{qtrace, Datagram};
 use neqo_transport::Output;
+use std::collections::BTreeSet;
 use std::fmt::{self, Debug};
+use std::ops::Range;
 use std::time::Instant;
 
 /// A random dropper.
@@ -23,6 +25,7 @@ impl Drop {
     /// Make a new random drop generator.  Each `drop` is called, this generates a
     /// random value between 0 and `max` (exclusive).  If this value is less than
     /// `threshold` a value of `true` is returned.
+    #[must_use]
     pub fn new(threshold: u64, max: u64) -> Self {
         Self {
             threshold,
@@ -32,6 +35,7 @@ impl Drop {
     }
 
     /// Generate random drops with the given percentage.
+    #[must_use]
     pub fn percentage(pct: u8) -> Self {
         // Multiply by 10 so that the random number generator works more efficiently.
         Self::new(u64::from(pct) * 10, 1000)
@@ -69,3 +73,52 @@ impl Debug for Drop {
         f.write_str("drop")
     }
 }
+
+/// Drops the datagrams at chosen positions in the sequence of datagrams that pass
+/// through it.  Unlike `Drop`, this always drops the same datagrams, so it can be
+/// used to test recovery from the loss of a particular packet.
+pub struct DropPattern {
+    /// The indices of the datagrams to drop, counting from zero.
+    drop: BTreeSet<usize>,
+    /// The number of datagrams seen so far.
+    count: usize,
+}
+
+impl DropPattern {
+    /// Drop the datagrams with the given indices.
+    #[must_use]
+    pub fn new(drop: impl IntoIterator<Item = usize>) -> Self {
+        Self {
+            drop: drop.into_iter().collect(),
+            count: 0,
+        }
+    }
+
+    /// Drop a run of consecutive datagrams.
+    #[must_use]
+    pub fn burst(range: Range<usize>) -> Self {
+        Self::new(range)
+    }
+}
+
+impl Node for DropPattern {
+    fn process(&mut self, d: Option<Datagram>, _now: Instant) -> Output {
+        if let Some(dgram) = d {
+            let index = self.count;
+            self.count += 1;
+            if self.drop.remove(&index) {
+                qtrace!("drop {} at {}", dgram.len(), index);
+                return Output::None;
+            }
+            Output::Datagram(dgram)
+        } else {
+            Output::None
+        }
+    }
+}
+
+impl Debug for DropPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("drop pattern")
+    }
+}
@@ -4,9 +4,10 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-// Tests with simulated network
-#![cfg_attr(feature = "deny-warnings", deny(warnings))]
-#![warn(clippy::pedantic)]
+// A simulated network for testing connections.
+// Nodes are arranged in a ring: each node passes the datagrams it produces to the next,
+// and the last node passes its datagrams to the first.  Time is virtual and only
+// advances when every node is waiting, so simulations are fast and repeatable.
 
 pub mod connection;
 mod delay;
@@ -14,6 +15,7 @@ mod drop;
 pub mod rng;
 mod taildrop;
 
+use crate::now;
 use neqo_common::{qdebug, qinfo, qtrace, Datagram, Encoder};
 use neqo_transport::Output;
 use rng::Random;
@@ -23,17 +25,16 @@ use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::rc::Rc;
 use std::time::{Duration, Instant};
-use test_fixture::{self, now};
 
 use NodeState::{Active, Idle, Waiting};
 
 pub mod network {
     pub use super::delay::Delay;
-    pub use super::drop::Drop;
+    pub use super::drop::{Drop, DropPattern};
     pub use super::taildrop::TailDrop;
 }
 
-type Rng = Rc<RefCell<Random>>;
+pub type Rng = Rc<RefCell<Random>>;
 
 /// A macro that turns a list of values into boxed versions of the same.
 #[macro_export]
@@ -53,18 +54,18 @@ macro_rules! boxed {
 #[macro_export]
 macro_rules! simulate {
     ($n:ident, [ $($v:expr),+ $(,)? ] $(,)?) => {
-        simulate!($n, (), [ $(|_| $v),+ ]);
+        $crate::simulate!($n, (), [ $(|_| $v),+ ]);
     };
     ($n:ident, $setup:expr, [ $( $v:expr ),+ $(,)? ] $(,)?) => {
         #[test]
         fn $n() {
             let fixture = $setup;
-            let mut nodes: Vec<Box<dyn crate::sim::Node>> = Vec::new();
+            let mut nodes: Vec<Box<dyn $crate::sim::Node>> = Vec::new();
             $(
                 let f: Box<dyn FnOnce(&_) -> _> = Box::new($v);
                 nodes.push(Box::new(f(&fixture)));
             )*
-            let mut sim = Simulator::new(stringify!($n), nodes);
+            let mut sim = $crate::sim::Simulator::new(stringify!($n), nodes);
             if let Ok(seed) = std::env::var("SIMULATION_SEED") {
                 sim.seed_str(seed);
             }
@@ -108,7 +109,7 @@ impl NodeHolder {
     fn ready(&self, now: Instant) -> bool {
         match self.state {
             Active => true,
-            Waiting(t) => t >= now,
+            Waiting(t) => t <= now,
             Idle => false,
         }
     }
@@ -121,6 +122,7 @@ pub struct Simulator {
 }
 
 impl Simulator {
+    #[must_use]
     pub fn new(name: impl AsRef<str>, nodes: impl IntoIterator<Item = Box<dyn Node>>) -> Self {
         let name = String::from(name.as_ref());
         // The first node is marked as Active, the rest are idle.
@@ -164,7 +166,8 @@ impl Simulator {
         next.expect("a node cannot be idle and not done")
     }
 
-    /// Runs the simulation.
+    /// Runs the simulation, returning the amount of simulated time that passed.
+    #[allow(clippy::must_use_candidate)] // Most callers only care that it finishes.
     pub fn run(mut self) -> Duration {
         let start = now();
         let mut now = start;
@@ -14,6 +14,7 @@ pub struct Random {
 }
 
 impl Random {
+    #[must_use]
     pub fn new(seed: [u8; 32]) -> Self {
         assert!(seed.iter().any(|&x| x != 0));
         let mut dec = Decoder::from(&seed);
@@ -65,6 +66,7 @@ impl Random {
     }
 
     /// Get the seed necessary to continue from this point.
+    #[must_use]
     pub fn seed_str(&self) -> String {
         format!(
             "{:8x}{:8x}{:8x}{:8x}",
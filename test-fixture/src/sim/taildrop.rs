@@ -56,6 +56,7 @@ pub struct TailDrop {
 
 impl TailDrop {
     /// Make a new taildrop node with the given rate, queue capacity, and link delay.
+    #[must_use]
     pub fn new(rate: usize, capacity: usize, delay: Duration) -> Self {
         Self {
             overhead: 64,
@@ -76,11 +77,13 @@ impl TailDrop {
 
     /// A tail drop queue on a 10Mbps link (approximated to 1 million bytes per second)
     /// with a fat 32k buffer (about 30ms), and the default forward delay of 50ms.
+    #[must_use]
     pub fn dsl_uplink() -> Self {
         TailDrop::new(1_000_000, 32_768, Duration::from_millis(50))
     }
 
     /// Cut downlink to one fifth of the uplink (2Mbps), and reduce the buffer to 1/4.
+    #[must_use]
     pub fn dsl_downlink() -> Self {
         TailDrop::new(200_000, 8_192, Duration::from_millis(50))
     }